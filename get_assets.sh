@@ -1,15 +1,22 @@
 VERSION="1.16.3"
-DATA_FILES=("blocks.min.json" "entities.min.json" "block_entities.min.json" "models.min.json")
+DATA_FILES=("blocks.min.json" "entities.min.json" "block_entities.min.json" "models.min.json" "shapes.min.json" "items.min.json" "registries.min.json")
 
 mkdir assets
 for file in ${DATA_FILES[@]}; do
     curl "https://gitlab.bixilon.de/bixilon/pixlyzer-data/-/raw/master/version/${VERSION}/${file}" >> assets/${file}
 done
 
+# Only the block states are needed for the other versions the client knows about
+for version in "1.17.1" "1.18.1"; do
+    mkdir -p assets/${version}
+    curl "https://gitlab.bixilon.de/bixilon/pixlyzer-data/-/raw/master/version/${version}/blocks.min.json" >> assets/${version}/blocks.min.json
+done
+
 mkdir temp
 cd temp
 curl https://launcher.mojang.com/v1/objects/1321521b2caf934f7fc9665aab7e059a7b2bfcdf/client.jar >> ${VERSION}.jar
 unzip ${VERSION}.jar
 mv assets/minecraft/textures ../assets/textures
+mv assets/minecraft/lang/en_us.json ../assets/en_us.json
 cd ..
 rm -rf temp
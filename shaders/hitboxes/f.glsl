@@ -1,9 +1,9 @@
 #version 430
 
-in vec3 col;
+uniform vec3 colour;
 
 out vec4 color;
 
 void main() {
-    color = vec4(1.0);
+    color = vec4(colour, 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,9 @@
+#version 430
+
+in vec3 v_colour;
+
+out vec4 color;
+
+void main() {
+    color = vec4(v_colour, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,17 @@
+#version 430
+
+in vec3 position;
+in vec3 colour;
+
+out vec3 v_colour;
+
+uniform mat4 pvmat;
+uniform float size;
+
+void main() {
+    vec4 pos = pvmat * vec4(position, 1.0);
+    gl_Position = pos;
+    // Points shrink with distance like the billboards they stand in for
+    gl_PointSize = size / max(pos.w, 1.0);
+    v_colour = colour;
+}
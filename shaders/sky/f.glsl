@@ -0,0 +1,19 @@
+#version 430
+
+in vec2 ndc;
+
+// Inverse of the projection and view matrices without the camera's translation
+uniform mat4 invPvmat;
+uniform vec3 skyCol;
+uniform vec3 horizonCol;
+
+out vec4 color;
+
+void main() {
+    vec4 far = invPvmat * vec4(ndc, 1.0, 1.0);
+    vec3 dir = normalize(far.xyz / far.w);
+
+    // Fades from the fog colour at the horizon to the sky colour a little above it
+    float height = smoothstep(0.0, 0.4, dir.y);
+    color = vec4(mix(horizonCol, skyCol, height), 1.0);
+}
@@ -0,0 +1,10 @@
+#version 430
+
+in vec3 position;
+
+out vec2 ndc;
+
+void main() {
+    ndc = position.xy;
+    gl_Position = vec4(position.xy, 1.0, 1.0);
+}
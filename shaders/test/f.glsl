@@ -2,22 +2,28 @@
 
 in vec3 tex;
 in vec3 pos;
+in float shade;
 
 uniform sampler2DArray textures;
 uniform vec4 fogCol;
 uniform float fogNear;
 uniform float fogFar;
+// Fragments less opaque than this are discarded
+uniform float alphaCutoff;
 
 out vec4 color;
 
 void main() {
     vec4 texCol = texture(textures, tex);
     
-    if (texCol.a < 0.5) {
+    if (texCol.a < alphaCutoff) {
         discard;
     }
 
-    float fogDistance = length(pos);
+    texCol.rgb *= shade;
+
+    // Measured horizontally so fog lines up with the edge of the render distance
+    float fogDistance = length(pos.xz);
     float fogAmount = smoothstep(fogNear, fogFar, fogDistance);
 
     color = mix(texCol, fogCol, fogAmount);
@@ -2,17 +2,25 @@
 
 in vec3 position;
 in vec3 tex_coords;
+in float brightness;
+in vec2 light;
 
 out vec3 tex;
 out vec3 pos;
+out float shade;
 
 uniform mat4 pvmat;
 uniform mat4 tmat;
+uniform float daylight;
+uniform vec3 camPos;
 
 void main() {
     tex = tex_coords;
+    // Each light level is 80% as bright as the one above it
+    float level = max(light.x * daylight, light.y);
+    shade = brightness * mix(0.03, 1.0, pow(0.8, (1.0 - level) * 15.0));
     vec4 world_pos = tmat * vec4(position, 1.0);
     vec4 position = pvmat * world_pos;
-    pos = position.xyz;
+    pos = world_pos.xyz - camPos;
     gl_Position = position;
 }
@@ -0,0 +1,206 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use glam::Vec3;
+use log::{debug, warn};
+use rand::Rng;
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Source};
+use serde::Deserialize;
+
+use crate::sound::SoundEvent;
+
+// Distance in blocks a sound at full volume can be heard from, louder sounds carry further
+const ATTENUATION_DISTANCE: f32 = 16.0;
+// How many events referring to other events are followed before giving up on a sound
+const MAX_EVENT_DEPTH: u32 = 4;
+
+/// One of the files a sound event can play, as listed in `sounds.json`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum SoundEntry {
+    Path(String),
+    Detailed {
+        name: String,
+        #[serde(default = "one")]
+        volume: f32,
+        #[serde(default = "one")]
+        pitch: f32,
+        // `event` entries name another sound event to play rather than a file
+        #[serde(default, rename = "type")]
+        kind: Option<String>,
+    },
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct SoundDefinition {
+    #[serde(default)]
+    sounds: Vec<SoundEntry>,
+}
+
+/// Plays sound events from the server using the OGG files from a resource pack, quieter the further
+/// they are from the camera
+pub struct AudioPlayer {
+    // The stream stops playing when dropped, so it's kept for as long as the player is
+    _stream: OutputStream,
+    handle: OutputStreamHandle,
+    // Directory containing `sounds.json` and the `sounds` folder it refers to
+    dir: PathBuf,
+    definitions: HashMap<String, SoundDefinition>,
+}
+
+impl AudioPlayer {
+    /// Opens the default audio device and reads `sounds.json` from a resource pack directory
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Directory laid out like `assets/minecraft` in a resource pack
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<AudioPlayer, Box<dyn Error>> {
+        let (stream, handle) = OutputStream::try_default()?;
+        let dir = dir.as_ref().to_path_buf();
+        let definitions =
+            serde_json::from_reader(BufReader::new(File::open(dir.join("sounds.json"))?))?;
+
+        Ok(AudioPlayer {
+            _stream: stream,
+            handle,
+            dir,
+            definitions,
+        })
+    }
+
+    /// Plays a sound event heard from a position, sounds without a file or too far away to hear
+    /// are skipped
+    ///
+    /// # Arguments
+    ///
+    /// * `sound` - Sound the server asked to play
+    /// * `listener` - Where the sound is being heard from, normally the camera
+    /// * `master_volume` - Volume all sounds are scaled by, from 0 to 1
+    pub fn play(&self, sound: &SoundEvent, listener: Vec3, master_volume: f32) {
+        let gain =
+            attenuation(sound.position.distance(listener), sound.volume) * master_volume.max(0.0);
+        if gain <= 0.0 {
+            return;
+        }
+
+        let (path, volume, pitch) = match self.choose(&sound.name, 0) {
+            Some(file) => file,
+            None => {
+                debug!("No sound file for {}", sound.name);
+                return;
+            }
+        };
+
+        if let Err(e) = self.play_file(&path, gain * volume, sound.pitch * pitch) {
+            warn!("Couldn't play sound {}: {}", path.display(), e);
+        }
+    }
+
+    /// Picks one of the files for a sound event at random, returning its path along with the
+    /// volume and pitch it's played at
+    fn choose(&self, name: &str, depth: u32) -> Option<(PathBuf, f32, f32)> {
+        let name = name.strip_prefix("minecraft:").unwrap_or(name);
+        let sounds = &self.definitions.get(name)?.sounds;
+        if sounds.is_empty() || depth > MAX_EVENT_DEPTH {
+            return None;
+        }
+
+        match &sounds[rand::thread_rng().gen_range(0..sounds.len())] {
+            SoundEntry::Path(path) => Some((self.sound_path(path), 1.0, 1.0)),
+            SoundEntry::Detailed {
+                name,
+                kind: Some(kind),
+                ..
+            } if kind == "event" => self.choose(name, depth + 1),
+            SoundEntry::Detailed {
+                name,
+                volume,
+                pitch,
+                ..
+            } => Some((self.sound_path(name), *volume, *pitch)),
+        }
+    }
+
+    fn sound_path(&self, name: &str) -> PathBuf {
+        let name = name.strip_prefix("minecraft:").unwrap_or(name);
+        self.dir.join("sounds").join(format!("{}.ogg", name))
+    }
+
+    fn play_file(&self, path: &Path, volume: f32, pitch: f32) -> Result<(), Box<dyn Error>> {
+        let source = Decoder::new(BufReader::new(File::open(path)?))?
+            .speed(pitch)
+            .amplify(volume);
+        self.handle.play_raw(source.convert_samples())?;
+        Ok(())
+    }
+}
+
+/// Returns how loud a sound is from a distance away, fading linearly to silent at 16 blocks for
+/// sounds at full volume, further for louder ones
+///
+/// # Arguments
+///
+/// * `distance` - Distance in blocks between the sound and the listener
+/// * `volume` - Volume the server sent for the sound
+pub fn attenuation(distance: f32, volume: f32) -> f32 {
+    let range = ATTENUATION_DISTANCE * volume.max(1.0);
+    volume.clamp(0.0, 1.0) * (1.0 - distance / range).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gain_fades_with_distance() {
+        assert_eq!(attenuation(0.0, 1.0), 1.0);
+        assert_eq!(attenuation(8.0, 1.0), 0.5);
+        assert_eq!(attenuation(16.0, 1.0), 0.0);
+        assert_eq!(attenuation(100.0, 1.0), 0.0);
+
+        // Quiet sounds are quieter but don't carry any less far
+        assert_eq!(attenuation(8.0, 0.5), 0.25);
+        assert_eq!(attenuation(16.0, 0.5), 0.0);
+
+        // Loud sounds aren't louder up close but carry further
+        assert_eq!(attenuation(0.0, 4.0), 1.0);
+        assert_eq!(attenuation(32.0, 4.0), 0.5);
+        assert_eq!(attenuation(64.0, 4.0), 0.0);
+
+        assert_eq!(attenuation(0.0, -1.0), 0.0);
+    }
+
+    #[test]
+    fn sound_definitions() {
+        let definition: SoundDefinition = serde_json::from_str(
+            r#"{"sounds": [
+                "block/stone/break1",
+                {"name": "block/stone/break2", "volume": 0.5},
+                {"name": "block.stone.place", "type": "event"}
+            ]}"#,
+        )
+        .unwrap();
+
+        assert!(matches!(&definition.sounds[0], SoundEntry::Path(p) if p == "block/stone/break1"));
+        assert!(matches!(
+            &definition.sounds[1],
+            SoundEntry::Detailed { volume, pitch, kind: None, .. } if *volume == 0.5 && *pitch == 1.0
+        ));
+        assert!(matches!(
+            &definition.sounds[2],
+            SoundEntry::Detailed { kind: Some(kind), .. } if kind == "event"
+        ));
+
+        let empty: SoundDefinition = serde_json::from_str("{}").unwrap();
+        assert!(empty.sounds.is_empty());
+    }
+}
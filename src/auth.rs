@@ -0,0 +1,633 @@
+use std::{
+    error::Error,
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use simple_error::{bail, require_with};
+
+const DEVICE_CODE_URL: &str = "https://login.microsoftonline.com/consumers/oauth2/v2.0/devicecode";
+const TOKEN_URL: &str = "https://login.microsoftonline.com/consumers/oauth2/v2.0/token";
+const XBL_URL: &str = "https://user.auth.xboxlive.com/user/authenticate";
+const XSTS_URL: &str = "https://xsts.auth.xboxlive.com/xsts/authorize";
+const MC_LOGIN_URL: &str = "https://api.minecraftservices.com/authentication/login_with_xbox";
+const MC_PROFILE_URL: &str = "https://api.minecraftservices.com/minecraft/profile";
+const SESSION_JOIN_URL: &str = "https://sessionserver.mojang.com/session/minecraft/join";
+
+// Seconds added to the polling interval each time Microsoft asks us to slow down
+const SLOW_DOWN_INCREASE: u64 = 5;
+
+/// Minimal HTTP client used by the authentication flow, so it can be swapped out when testing the
+/// token exchange logic
+pub trait HttpClient {
+    /// POSTs a url encoded form and returns the json response, including for error statuses
+    fn post_form(&self, url: &str, form: &[(&str, &str)]) -> Result<Value, Box<dyn Error>>;
+
+    /// POSTs a json body with an optional bearer token and returns the json response
+    fn post_json(
+        &self,
+        url: &str,
+        body: &Value,
+        bearer: Option<&str>,
+    ) -> Result<Option<Value>, Box<dyn Error>>;
+
+    /// GETs a json response using a bearer token
+    fn get_json(&self, url: &str, bearer: &str) -> Result<Value, Box<dyn Error>>;
+}
+
+/// HttpClient making blocking requests with ureq
+pub struct UreqClient;
+
+impl HttpClient for UreqClient {
+    fn post_form(&self, url: &str, form: &[(&str, &str)]) -> Result<Value, Box<dyn Error>> {
+        match ureq::post(url).send_form(form) {
+            Ok(res) => Ok(res.into_json()?),
+            // OAuth reports pending and failed logins in the body of 400 responses
+            Err(ureq::Error::Status(_, res)) => Ok(res.into_json()?),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn post_json(
+        &self,
+        url: &str,
+        body: &Value,
+        bearer: Option<&str>,
+    ) -> Result<Option<Value>, Box<dyn Error>> {
+        let mut req = ureq::post(url).set("Accept", "application/json");
+        if let Some(token) = bearer {
+            req = req.set("Authorization", &format!("Bearer {}", token));
+        }
+
+        let res = req.send_json(body.clone())?;
+        // Some endpoints (the session server) respond with no content
+        if res.status() == 204 {
+            return Ok(None);
+        }
+        Ok(Some(res.into_json()?))
+    }
+
+    fn get_json(&self, url: &str, bearer: &str) -> Result<Value, Box<dyn Error>> {
+        let res = ureq::get(url)
+            .set("Authorization", &format!("Bearer {}", bearer))
+            .call()?;
+        Ok(res.into_json()?)
+    }
+}
+
+/// Code for the user to enter at the verification url to log in to their Microsoft account
+#[derive(Debug, Clone)]
+pub struct DeviceCode {
+    pub user_code: String,
+    pub verification_uri: String,
+    device_code: String,
+    interval: u64,
+    expires_in: u64,
+}
+
+/// Tokens returned by Microsoft once the user has logged in
+#[derive(Debug, Clone)]
+pub struct MicrosoftTokens {
+    pub access_token: String,
+    // Used to log in again without the user once the access token expires
+    pub refresh_token: Option<String>,
+}
+
+/// A Minecraft profile authenticated with Microsoft, used to join online mode servers
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub uuid: String,
+    pub access_token: String,
+    // Microsoft refresh token the profile was logged in with, so it can be refreshed on startup
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+/// Logs in to a Minecraft account through the Microsoft device code flow, then Xbox Live
+pub struct MicrosoftAuth<C: HttpClient> {
+    client: C,
+    client_id: String,
+}
+
+impl<C: HttpClient> MicrosoftAuth<C> {
+    /// # Arguments
+    ///
+    /// * `client` - The HttpClient to make requests with
+    /// * `client_id` - The client ID of the Azure application to log in through
+    pub fn new(client: C, client_id: String) -> MicrosoftAuth<C> {
+        MicrosoftAuth { client, client_id }
+    }
+
+    /// Requests a code for the user to log in with
+    pub fn request_device_code(&self) -> Result<DeviceCode, Box<dyn Error>> {
+        let res = self.client.post_form(
+            DEVICE_CODE_URL,
+            &[
+                ("client_id", self.client_id.as_str()),
+                ("scope", "XboxLive.signin offline_access"),
+            ],
+        )?;
+
+        Ok(DeviceCode {
+            user_code: get_str(&res, "user_code")?,
+            verification_uri: get_str(&res, "verification_uri")?,
+            device_code: get_str(&res, "device_code")?,
+            interval: res.get("interval").and_then(Value::as_u64).unwrap_or(5),
+            expires_in: res.get("expires_in").and_then(Value::as_u64).unwrap_or(900),
+        })
+    }
+
+    /// Checks if the user has logged in with the device code yet, increasing the polling interval
+    /// of the code if Microsoft asks us to slow down
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(tokens))` - The Microsoft tokens once the user has logged in
+    /// * `Ok(None)` - If the user hasn't finished logging in yet
+    /// * `Err` - If the login was declined, expired or the request failed
+    pub fn poll_token(
+        &self,
+        code: &mut DeviceCode,
+    ) -> Result<Option<MicrosoftTokens>, Box<dyn Error>> {
+        let res = self.client.post_form(
+            TOKEN_URL,
+            &[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+                ("client_id", self.client_id.as_str()),
+                ("device_code", code.device_code.as_str()),
+            ],
+        )?;
+
+        match res.get("error").and_then(Value::as_str) {
+            Some("authorization_pending") => Ok(None),
+            Some("slow_down") => {
+                code.interval += SLOW_DOWN_INCREASE;
+                Ok(None)
+            }
+            Some(e) => bail!("Microsoft login failed: {}", e),
+            None => Ok(Some(tokens(&res)?)),
+        }
+    }
+
+    /// Blocks until the user has logged in with the device code, polling at the interval
+    /// requested by Microsoft
+    pub fn wait_for_token(&self, code: &mut DeviceCode) -> Result<MicrosoftTokens, Box<dyn Error>> {
+        let start = Instant::now();
+        while (Instant::now() - start).as_secs() < code.expires_in {
+            if let Some(tokens) = self.poll_token(code)? {
+                return Ok(tokens);
+            }
+            thread::sleep(Duration::from_secs(code.interval));
+        }
+        bail!("Microsoft login code expired");
+    }
+
+    /// Gets new Microsoft tokens with the refresh token from an earlier login, without the user
+    /// having to log in again
+    pub fn refresh_token(&self, refresh_token: &str) -> Result<MicrosoftTokens, Box<dyn Error>> {
+        let res = self.client.post_form(
+            TOKEN_URL,
+            &[
+                ("grant_type", "refresh_token"),
+                ("client_id", self.client_id.as_str()),
+                ("refresh_token", refresh_token),
+                ("scope", "XboxLive.signin offline_access"),
+            ],
+        )?;
+
+        if let Some(e) = res.get("error").and_then(Value::as_str) {
+            bail!("Microsoft token refresh failed: {}", e);
+        }
+        tokens(&res)
+    }
+
+    /// Exchanges Microsoft tokens for a Minecraft access token and profile
+    pub fn login(&self, ms_tokens: &MicrosoftTokens) -> Result<Profile, Box<dyn Error>> {
+        let ms_token = &ms_tokens.access_token;
+        // Xbox Live
+        let xbl = require_with!(
+            self.client.post_json(
+                XBL_URL,
+                &json!({
+                    "Properties": {
+                        "AuthMethod": "RPS",
+                        "SiteName": "user.auth.xboxlive.com",
+                        "RpsTicket": format!("d={}", ms_token),
+                    },
+                    "RelyingParty": "http://auth.xboxlive.com",
+                    "TokenType": "JWT",
+                }),
+                None,
+            )?,
+            "Empty Xbox Live response"
+        );
+        let xbl_token = get_str(&xbl, "Token")?;
+        let user_hash = require_with!(
+            xbl.pointer("/DisplayClaims/xui/0/uhs")
+                .and_then(Value::as_str),
+            "Missing user hash in Xbox Live response"
+        )
+        .to_string();
+
+        // XSTS
+        let xsts = require_with!(
+            self.client.post_json(
+                XSTS_URL,
+                &json!({
+                    "Properties": {
+                        "SandboxId": "RETAIL",
+                        "UserTokens": [xbl_token],
+                    },
+                    "RelyingParty": "rp://api.minecraftservices.com/",
+                    "TokenType": "JWT",
+                }),
+                None,
+            )?,
+            "Empty XSTS response"
+        );
+        let xsts_token = get_str(&xsts, "Token")?;
+
+        // Minecraft
+        let mc = require_with!(
+            self.client.post_json(
+                MC_LOGIN_URL,
+                &json!({
+                    "identityToken": format!("XBL3.0 x={};{}", user_hash, xsts_token),
+                }),
+                None,
+            )?,
+            "Empty Minecraft login response"
+        );
+        let access_token = get_str(&mc, "access_token")?;
+
+        let profile = self.client.get_json(MC_PROFILE_URL, &access_token)?;
+        if profile.get("error").is_some() {
+            bail!("This account doesn't own Minecraft");
+        }
+
+        Ok(Profile {
+            name: get_str(&profile, "name")?,
+            uuid: get_str(&profile, "id")?,
+            access_token,
+            refresh_token: ms_tokens.refresh_token.clone(),
+        })
+    }
+}
+
+/// Tells the session server the profile is joining a server, must be done before responding to
+/// an encryption request from an online mode server
+pub fn join_server<C: HttpClient>(
+    client: &C,
+    profile: &Profile,
+    server_hash: &str,
+) -> Result<(), Box<dyn Error>> {
+    client.post_json(
+        SESSION_JOIN_URL,
+        &json!({
+            "accessToken": profile.access_token,
+            "selectedProfile": profile.uuid.replace('-', ""),
+            "serverId": server_hash,
+        }),
+        None,
+    )?;
+    Ok(())
+}
+
+/// Generates the server hash sent to the session server, which is Minecraft's signed hex digest
+/// of the sha1 hash of the server id, shared secret and server public key
+pub fn server_hash(server_id: &str, shared_secret: &[u8], public_key: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(server_id.as_bytes());
+    hasher.update(shared_secret);
+    hasher.update(public_key);
+    let mut hash: [u8; 20] = hasher.finalize().into();
+
+    // The digest is treated as a signed two's complement number
+    let negative = hash[0] & 0x80 != 0;
+    if negative {
+        let mut carry = true;
+        for byte in hash.iter_mut().rev() {
+            *byte = !*byte;
+            if carry {
+                let (val, overflow) = byte.overflowing_add(1);
+                *byte = val;
+                carry = overflow;
+            }
+        }
+    }
+
+    let hex: String = hash.iter().map(|b| format!("{:02x}", b)).collect();
+    let hex = hex.trim_start_matches('0');
+
+    if negative {
+        format!("-{}", hex)
+    } else {
+        hex.to_string()
+    }
+}
+
+/// Progress of a login running on another thread
+pub enum AuthEvent {
+    DeviceCode(DeviceCode),
+    LoggedIn(Profile),
+    Failed(String),
+}
+
+/// A Microsoft login running on a separate thread, so the GUI can keep rendering while the user
+/// logs in from their browser
+pub struct LoginFlow {
+    events: Receiver<AuthEvent>,
+    device_code: Option<DeviceCode>,
+}
+
+impl LoginFlow {
+    /// Starts logging in with a device code the user enters in their browser
+    pub fn start(client_id: String) -> LoginFlow {
+        LoginFlow::spawn(client_id, move |auth, send| {
+            auth.request_device_code().and_then(|mut code| {
+                send.send(AuthEvent::DeviceCode(code.clone())).ok();
+                let tokens = auth.wait_for_token(&mut code)?;
+                auth.login(&tokens)
+            })
+        })
+    }
+
+    /// Starts logging in again with the refresh token of a saved profile, which doesn't need the
+    /// user to do anything
+    pub fn refresh(client_id: String, refresh_token: String) -> LoginFlow {
+        LoginFlow::spawn(client_id, move |auth, _| {
+            let tokens = auth.refresh_token(&refresh_token)?;
+            auth.login(&tokens)
+        })
+    }
+
+    /// Runs a login on a new thread, sending the result once it's done
+    fn spawn<F>(client_id: String, login: F) -> LoginFlow
+    where
+        F: FnOnce(
+                &MicrosoftAuth<UreqClient>,
+                &Sender<AuthEvent>,
+            ) -> Result<Profile, Box<dyn Error>>
+            + Send
+            + 'static,
+    {
+        let (send, recv) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("MicrosoftLogin".to_string())
+            .spawn(move || {
+                let auth = MicrosoftAuth::new(UreqClient, client_id);
+                let result = login(&auth, &send);
+
+                send.send(match result {
+                    Ok(profile) => AuthEvent::LoggedIn(profile),
+                    Err(e) => AuthEvent::Failed(e.to_string()),
+                })
+                .ok();
+            })
+            .expect("Failed to start login thread");
+
+        LoginFlow {
+            events: recv,
+            device_code: None,
+        }
+    }
+
+    /// Returns the code the user needs to enter once it has been received
+    pub fn get_device_code(&self) -> Option<&DeviceCode> {
+        self.device_code.as_ref()
+    }
+
+    /// Checks on the progress of the login, returns the result once it has finished
+    pub fn poll(&mut self) -> Option<Result<Profile, String>> {
+        loop {
+            match self.events.try_recv() {
+                Ok(AuthEvent::DeviceCode(code)) => self.device_code = Some(code),
+                Ok(AuthEvent::LoggedIn(profile)) => return Some(Ok(profile)),
+                Ok(AuthEvent::Failed(e)) => return Some(Err(e)),
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    return Some(Err(String::from("Login thread stopped unexpectedly")))
+                }
+            }
+        }
+    }
+}
+
+/// Reads the access token and refresh token from a token response
+fn tokens(res: &Value) -> Result<MicrosoftTokens, Box<dyn Error>> {
+    Ok(MicrosoftTokens {
+        access_token: get_str(res, "access_token")?,
+        refresh_token: res
+            .get("refresh_token")
+            .and_then(Value::as_str)
+            .map(String::from),
+    })
+}
+
+fn get_str(json: &Value, key: &str) -> Result<String, Box<dyn Error>> {
+    Ok(require_with!(
+        json.get(key).and_then(Value::as_str),
+        "Missing {} in response",
+        key
+    )
+    .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap};
+
+    use super::*;
+
+    /// Answers each url with a canned response and records the forms that were posted
+    #[derive(Default)]
+    struct MockClient {
+        responses: HashMap<&'static str, Value>,
+        forms: RefCell<Vec<HashMap<String, String>>>,
+    }
+
+    impl MockClient {
+        fn respond(mut self, url: &'static str, res: Value) -> MockClient {
+            self.responses.insert(url, res);
+            self
+        }
+
+        fn response(&self, url: &str) -> Result<Value, Box<dyn Error>> {
+            match self.responses.get(url) {
+                Some(res) => Ok(res.clone()),
+                None => bail!("No response for {}", url),
+            }
+        }
+    }
+
+    impl HttpClient for MockClient {
+        fn post_form(&self, url: &str, form: &[(&str, &str)]) -> Result<Value, Box<dyn Error>> {
+            self.forms.borrow_mut().push(
+                form.iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            );
+            self.response(url)
+        }
+
+        fn post_json(
+            &self,
+            url: &str,
+            _body: &Value,
+            _bearer: Option<&str>,
+        ) -> Result<Option<Value>, Box<dyn Error>> {
+            self.response(url).map(Some)
+        }
+
+        fn get_json(&self, url: &str, _bearer: &str) -> Result<Value, Box<dyn Error>> {
+            self.response(url)
+        }
+    }
+
+    fn device_code() -> DeviceCode {
+        DeviceCode {
+            user_code: String::from("ABCD-EFGH"),
+            verification_uri: String::from("https://microsoft.com/link"),
+            device_code: String::from("device"),
+            interval: 5,
+            expires_in: 900,
+        }
+    }
+
+    fn token_response(res: Value) -> MicrosoftAuth<MockClient> {
+        MicrosoftAuth::new(
+            MockClient::default().respond(TOKEN_URL, res),
+            String::from("client"),
+        )
+    }
+
+    #[test]
+    fn poll_token_pending() {
+        let auth = token_response(json!({ "error": "authorization_pending" }));
+        let mut code = device_code();
+        assert!(auth.poll_token(&mut code).unwrap().is_none());
+        assert_eq!(code.interval, 5);
+    }
+
+    #[test]
+    fn poll_token_slow_down_increases_interval() {
+        let auth = token_response(json!({ "error": "slow_down" }));
+        let mut code = device_code();
+        assert!(auth.poll_token(&mut code).unwrap().is_none());
+        assert_eq!(code.interval, 10);
+        assert!(auth.poll_token(&mut code).unwrap().is_none());
+        assert_eq!(code.interval, 15);
+    }
+
+    #[test]
+    fn poll_token_declined() {
+        let auth = token_response(json!({ "error": "authorization_declined" }));
+        assert!(auth.poll_token(&mut device_code()).is_err());
+    }
+
+    #[test]
+    fn poll_token_success_keeps_refresh_token() {
+        let auth = token_response(json!({
+            "access_token": "ms access",
+            "refresh_token": "ms refresh",
+        }));
+        let tokens = auth.poll_token(&mut device_code()).unwrap().unwrap();
+        assert_eq!(tokens.access_token, "ms access");
+        assert_eq!(tokens.refresh_token.as_deref(), Some("ms refresh"));
+
+        let forms = auth.client.forms.borrow();
+        assert_eq!(forms[0]["device_code"], "device");
+        assert_eq!(forms[0]["client_id"], "client");
+    }
+
+    #[test]
+    fn refresh_token_posts_refresh_grant() {
+        let auth = token_response(json!({
+            "access_token": "new access",
+            "refresh_token": "new refresh",
+        }));
+        let tokens = auth.refresh_token("old refresh").unwrap();
+        assert_eq!(tokens.access_token, "new access");
+        assert_eq!(tokens.refresh_token.as_deref(), Some("new refresh"));
+
+        let forms = auth.client.forms.borrow();
+        assert_eq!(forms[0]["grant_type"], "refresh_token");
+        assert_eq!(forms[0]["refresh_token"], "old refresh");
+    }
+
+    fn xbox_client() -> MockClient {
+        MockClient::default()
+            .respond(
+                XBL_URL,
+                json!({
+                    "Token": "xbl token",
+                    "DisplayClaims": { "xui": [{ "uhs": "user hash" }] },
+                }),
+            )
+            .respond(XSTS_URL, json!({ "Token": "xsts token" }))
+            .respond(MC_LOGIN_URL, json!({ "access_token": "mc token" }))
+    }
+
+    #[test]
+    fn login_exchanges_tokens_for_profile() {
+        let client = xbox_client().respond(
+            MC_PROFILE_URL,
+            json!({ "id": "069a79f444e94726a5befca90e38aaf5", "name": "Notch" }),
+        );
+        let auth = MicrosoftAuth::new(client, String::from("client"));
+
+        let profile = auth
+            .login(&MicrosoftTokens {
+                access_token: String::from("ms access"),
+                refresh_token: Some(String::from("ms refresh")),
+            })
+            .unwrap();
+        assert_eq!(profile.name, "Notch");
+        assert_eq!(profile.uuid, "069a79f444e94726a5befca90e38aaf5");
+        assert_eq!(profile.access_token, "mc token");
+        assert_eq!(profile.refresh_token.as_deref(), Some("ms refresh"));
+    }
+
+    #[test]
+    fn login_without_minecraft() {
+        let client = xbox_client().respond(MC_PROFILE_URL, json!({ "error": "NOT_FOUND" }));
+        let auth = MicrosoftAuth::new(client, String::from("client"));
+
+        let tokens = MicrosoftTokens {
+            access_token: String::from("ms access"),
+            refresh_token: None,
+        };
+        assert!(auth.login(&tokens).is_err());
+    }
+
+    #[test]
+    fn server_hash_matches_known_digests() {
+        assert_eq!(
+            server_hash("Notch", &[], &[]),
+            "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48"
+        );
+        assert_eq!(
+            server_hash("jeb_", &[], &[]),
+            "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1"
+        );
+        assert_eq!(
+            server_hash("simon", &[], &[]),
+            "88e16a1019277b15d58faf0541e11910eb756f6"
+        );
+    }
+
+    #[test]
+    fn server_hash_covers_secret_and_key() {
+        // The parts are hashed one after the other, so splitting them differently is the same
+        assert_eq!(
+            server_hash("", b"Not", b"ch"),
+            "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48"
+        );
+    }
+}
@@ -1,25 +1,119 @@
-use mcproto_rs::v1_16_3::PlayServerChatMessageSpec;
+use std::collections::HashMap;
+
+use mcproto_rs::{
+    uuid::UUID4,
+    v1_16_3::{ChatPosition, PlayServerChatMessageSpec},
+};
+use serde_json::Value;
+
+use crate::resources::LANG;
+
+// Most received messages kept in the scrollback, older ones are dropped
+const MAX_HISTORY: usize = 100;
+// Most sent messages remembered for going back through with the arrow keys
+const MAX_SENT: usize = 100;
+// Colour of messages added by the client rather than the server
+const LOCAL_COLOR: [u8; 3] = [0xaa, 0xaa, 0xaa];
+
+/// A chat message received from the server
+#[derive(Debug, Clone)]
+pub struct ChatEvent {
+    pub sender: UUID4,
+    /// The message flattened to plain text
+    pub content: String,
+    pub spans: Vec<TextSpan>,
+    pub position: ChatPosition,
+}
+
+impl ChatEvent {
+    pub fn from_packet(pack: PlayServerChatMessageSpec) -> ChatEvent {
+        let spans = component_spans(&pack.message);
+        ChatEvent {
+            sender: pack.sender,
+            content: spans.iter().map(|s| s.text.as_str()).collect(),
+            spans,
+            position: pack.position,
+        }
+    }
+
+    /// Creates a message shown only to this client, such as an echo of a command that was sent
+    pub fn local(text: String) -> ChatEvent {
+        ChatEvent {
+            sender: UUID4::from(0),
+            content: text.clone(),
+            spans: vec![TextSpan {
+                text,
+                color: LOCAL_COLOR,
+                italic: true,
+                ..Default::default()
+            }],
+            position: ChatPosition::SystemMessage,
+        }
+    }
+}
+
+/// Flattens a chat component received in a packet into plain text
+pub fn component_text(chat: &mcproto_rs::types::Chat) -> String {
+    component_spans(chat).into_iter().map(|s| s.text).collect()
+}
+
+/// Flattens a chat component received in a packet into styled spans
+pub fn component_spans(chat: &mcproto_rs::types::Chat) -> Vec<TextSpan> {
+    match serde_json::to_value(chat) {
+        Ok(json) => Component::parse(&json).to_spans(),
+        Err(e) => {
+            log::error!("Couldn't convert chat component to json: {}", e);
+            vec![TextSpan::new(
+                chat.to_traditional().unwrap_or_default(),
+                &Style::default(),
+            )]
+        }
+    }
+}
 
 pub struct Chat {
-    history: Vec<(PlayServerChatMessageSpec, i64)>,
+    history: Vec<(ChatEvent, i64)>,
 
     input: String,
+
+    // Messages sent by the player, oldest first, and which one is in the input when going back
+    // through them
+    sent: Vec<String>,
+    sent_index: Option<usize>,
+}
+
+impl Default for Chat {
+    fn default() -> Self {
+        Chat::new()
+    }
 }
 
 impl Chat {
     pub fn new() -> Chat {
         Chat {
-            history: Vec::with_capacity(255),
+            history: Vec::with_capacity(MAX_HISTORY),
             input: String::with_capacity(255),
+            sent: Vec::new(),
+            sent_index: None,
         }
     }
 
-    pub fn get_history(&self) -> &Vec<(PlayServerChatMessageSpec, i64)> {
+    pub fn get_history(&self) -> &Vec<(ChatEvent, i64)> {
         &self.history
     }
 
-    pub fn add_message(&mut self, chat: PlayServerChatMessageSpec, time: i64) {
+    /// Adds a message to the scrollback, dropping the oldest once it's full
+    ///
+    /// # Arguments
+    ///
+    /// * `chat` - The message
+    /// * `time` - World age when the message arrived, used to fade it out
+    pub fn add_message(&mut self, chat: ChatEvent, time: i64) {
         self.history.push((chat, time));
+        if self.history.len() > MAX_HISTORY {
+            let excess = self.history.len() - MAX_HISTORY;
+            self.history.drain(..excess);
+        }
     }
 
     pub fn get_current_message(&self) -> &String {
@@ -40,4 +134,491 @@ impl Chat {
     pub fn set_current_message(&mut self, text: String) {
         self.input = text;
     }
+
+    /// Clears the input and remembers it as a sent message
+    ///
+    /// # Returns
+    ///
+    /// * The trimmed message to send, None if it was empty
+    pub fn submit_current_message(&mut self) -> Option<String> {
+        let text = self.get_current_message_and_clear().trim().to_string();
+        self.sent_index = None;
+        if text.is_empty() {
+            return None;
+        }
+
+        if self.sent.last() != Some(&text) {
+            self.sent.push(text.clone());
+            if self.sent.len() > MAX_SENT {
+                self.sent.remove(0);
+            }
+        }
+        Some(text)
+    }
+
+    /// Replaces the input with the sent message before the one currently shown
+    pub fn previous_sent(&mut self) {
+        let index = match self.sent_index {
+            Some(i) => i.saturating_sub(1),
+            None if self.sent.is_empty() => return,
+            None => self.sent.len() - 1,
+        };
+        self.sent_index = Some(index);
+        self.input = self.sent[index].clone();
+    }
+
+    /// Replaces the input with the sent message after the one currently shown, going past the
+    /// newest clears the input
+    pub fn next_sent(&mut self) {
+        match self.sent_index {
+            Some(i) if i + 1 < self.sent.len() => {
+                self.sent_index = Some(i + 1);
+                self.input = self.sent[i + 1].clone();
+            }
+            Some(_) => {
+                self.sent_index = None;
+                self.input.clear();
+            }
+            None => {}
+        }
+    }
+}
+
+/// Formatting of a chat component, unset fields are inherited from the parent component
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Style {
+    pub color: Option<[u8; 3]>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub underlined: Option<bool>,
+    pub strikethrough: Option<bool>,
+    pub obfuscated: Option<bool>,
+}
+
+impl Style {
+    fn parse(json: &Value) -> Style {
+        let flag = |key: &str| json.get(key).and_then(Value::as_bool);
+
+        Style {
+            color: json
+                .get("color")
+                .and_then(Value::as_str)
+                .and_then(parse_color),
+            bold: flag("bold"),
+            italic: flag("italic"),
+            underlined: flag("underlined"),
+            strikethrough: flag("strikethrough"),
+            obfuscated: flag("obfuscated"),
+        }
+    }
+
+    /// Fills in unset fields from the parent style
+    fn inherit(&self, parent: &Style) -> Style {
+        Style {
+            color: self.color.or(parent.color),
+            bold: self.bold.or(parent.bold),
+            italic: self.italic.or(parent.italic),
+            underlined: self.underlined.or(parent.underlined),
+            strikethrough: self.strikethrough.or(parent.strikethrough),
+            obfuscated: self.obfuscated.or(parent.obfuscated),
+        }
+    }
+}
+
+/// A run of text with a single style
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TextSpan {
+    pub text: String,
+    pub color: [u8; 3],
+    pub bold: bool,
+    pub italic: bool,
+    pub underlined: bool,
+    pub strikethrough: bool,
+    pub obfuscated: bool,
+}
+
+impl TextSpan {
+    fn new(text: String, style: &Style) -> TextSpan {
+        TextSpan {
+            text,
+            color: style.color.unwrap_or(WHITE),
+            bold: style.bold.unwrap_or(false),
+            italic: style.italic.unwrap_or(false),
+            underlined: style.underlined.unwrap_or(false),
+            strikethrough: style.strikethrough.unwrap_or(false),
+            obfuscated: style.obfuscated.unwrap_or(false),
+        }
+    }
+}
+
+/// A chat component in Minecraft's json text format, used for chat, disconnect reasons and MOTDs
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Component {
+    pub text: String,
+    /// Translation key, replaces the text if present
+    pub translate: Option<String>,
+    /// Arguments substituted into the translation
+    pub with: Vec<Component>,
+    pub extra: Vec<Component>,
+    pub style: Style,
+}
+
+impl Component {
+    pub fn parse(json: &Value) -> Component {
+        match json {
+            Value::String(text) => Component {
+                text: text.clone(),
+                ..Default::default()
+            },
+            // Arrays are treated as the first component with the rest as its extras
+            Value::Array(components) => {
+                let mut components = components.iter().map(Component::parse);
+                let mut base = components.next().unwrap_or_default();
+                base.extra.extend(components);
+                base
+            }
+            Value::Object(_) => {
+                let list = |key: &str| {
+                    json.get(key)
+                        .and_then(Value::as_array)
+                        .map(|l| l.iter().map(Component::parse).collect())
+                        .unwrap_or_default()
+                };
+
+                Component {
+                    text: match json.get("text") {
+                        Some(Value::String(text)) => text.clone(),
+                        Some(Value::Null) | None => String::new(),
+                        Some(other) => other.to_string(),
+                    },
+                    translate: json
+                        .get("translate")
+                        .and_then(Value::as_str)
+                        .map(String::from),
+                    with: list("with"),
+                    extra: list("extra"),
+                    style: Style::parse(json),
+                }
+            }
+            Value::Null => Component::default(),
+            other => Component {
+                text: other.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Flattens this component into styled spans, translating with the loaded language
+    pub fn to_spans(&self) -> Vec<TextSpan> {
+        self.to_spans_with_lang(&LANG)
+    }
+
+    /// Flattens this component into styled spans, translating with the provided language map
+    pub fn to_spans_with_lang(&self, lang: &HashMap<String, String>) -> Vec<TextSpan> {
+        let mut spans = Vec::new();
+        self.push_spans(&Style::default(), lang, &mut spans);
+        spans
+    }
+
+    /// Flattens this component into plain text without any formatting
+    pub fn to_plain_text(&self) -> String {
+        self.to_spans().into_iter().map(|s| s.text).collect()
+    }
+
+    fn push_spans(
+        &self,
+        parent: &Style,
+        lang: &HashMap<String, String>,
+        spans: &mut Vec<TextSpan>,
+    ) {
+        let style = self.style.inherit(parent);
+
+        match &self.translate {
+            Some(key) => {
+                let format = lang.get(key).map(String::as_str).unwrap_or(key);
+                self.push_translation(format, &style, lang, spans);
+            }
+            None => push_text(&self.text, &style, spans),
+        }
+
+        for extra in &self.extra {
+            extra.push_spans(&style, lang, spans);
+        }
+    }
+
+    /// Substitutes the `with` arguments into a translation in place of `%s` and `%1$s`
+    fn push_translation(
+        &self,
+        format: &str,
+        style: &Style,
+        lang: &HashMap<String, String>,
+        spans: &mut Vec<TextSpan>,
+    ) {
+        let mut next_arg = 0;
+        let mut rest = format;
+
+        while let Some(i) = rest.find('%') {
+            push_text(&rest[..i], style, spans);
+            let after = &rest[i + 1..];
+
+            let arg = if let Some(after) = after.strip_prefix('%') {
+                push_text("%", style, spans);
+                rest = after;
+                continue;
+            } else if let Some(after) = after.strip_prefix('s') {
+                rest = after;
+                next_arg += 1;
+                next_arg - 1
+            } else {
+                // Positional argument, counting from 1
+                match after
+                    .split_once("$s")
+                    .and_then(|(n, after)| Some((n.parse::<usize>().ok()?, after)))
+                {
+                    Some((n, after)) if n > 0 => {
+                        rest = after;
+                        n - 1
+                    }
+                    _ => {
+                        push_text("%", style, spans);
+                        rest = after;
+                        continue;
+                    }
+                }
+            };
+
+            if let Some(arg) = self.with.get(arg) {
+                arg.push_spans(style, lang, spans);
+            }
+        }
+
+        push_text(rest, style, spans);
+    }
+}
+
+/// Adds text to the spans, applying any legacy § formatting codes within it
+fn push_text(text: &str, style: &Style, spans: &mut Vec<TextSpan>) {
+    let mut parts = text.split('§');
+
+    if let Some(first) = parts.next() {
+        if !first.is_empty() {
+            spans.push(TextSpan::new(first.to_string(), style));
+        }
+    }
+
+    let mut current = style.clone();
+    for part in parts {
+        let mut chars = part.chars();
+        let code = match chars.next() {
+            Some(c) => c.to_ascii_lowercase(),
+            None => continue,
+        };
+
+        match code {
+            'k' => current.obfuscated = Some(true),
+            'l' => current.bold = Some(true),
+            'm' => current.strikethrough = Some(true),
+            'n' => current.underlined = Some(true),
+            'o' => current.italic = Some(true),
+            'r' => current = style.clone(),
+            // Colour codes also reset formatting
+            c => {
+                if let Some(i) = c.to_digit(16) {
+                    current = Style {
+                        color: Some(COLORS[i as usize].1),
+                        ..Default::default()
+                    };
+                }
+            }
+        }
+
+        let text = chars.as_str();
+        if !text.is_empty() {
+            spans.push(TextSpan::new(text.to_string(), &current));
+        }
+    }
+}
+
+const WHITE: [u8; 3] = [0xff, 0xff, 0xff];
+
+// Named colours in the order of their legacy formatting codes
+const COLORS: [(&str, [u8; 3]); 16] = [
+    ("black", [0x00, 0x00, 0x00]),
+    ("dark_blue", [0x00, 0x00, 0xaa]),
+    ("dark_green", [0x00, 0xaa, 0x00]),
+    ("dark_aqua", [0x00, 0xaa, 0xaa]),
+    ("dark_red", [0xaa, 0x00, 0x00]),
+    ("dark_purple", [0xaa, 0x00, 0xaa]),
+    ("gold", [0xff, 0xaa, 0x00]),
+    ("gray", [0xaa, 0xaa, 0xaa]),
+    ("dark_gray", [0x55, 0x55, 0x55]),
+    ("blue", [0x55, 0x55, 0xff]),
+    ("green", [0x55, 0xff, 0x55]),
+    ("aqua", [0x55, 0xff, 0xff]),
+    ("red", [0xff, 0x55, 0x55]),
+    ("light_purple", [0xff, 0x55, 0xff]),
+    ("yellow", [0xff, 0xff, 0x55]),
+    ("white", [0xff, 0xff, 0xff]),
+];
+
+/// Parses a named colour or a `#rrggbb` hex colour
+fn parse_color(color: &str) -> Option<[u8; 3]> {
+    if let Some(hex) = color.strip_prefix('#') {
+        let val = u32::from_str_radix(hex, 16).ok()?;
+        return Some([(val >> 16) as u8, (val >> 8) as u8, val as u8]);
+    }
+
+    COLORS
+        .iter()
+        .find(|(name, _)| *name == color)
+        .map(|(_, rgb)| *rgb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disconnect_reason_to_plain_text() {
+        let reason: mcproto_rs::types::Chat = serde_json::from_str(
+            r#"{"text": "Kicked: ", "extra": [{"text": "Server closed", "color": "red"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(component_text(&reason), "Kicked: Server closed");
+
+        // Plugins often still send legacy formatting codes
+        let json = serde_json::json!({"text": "§cYou are banned §lforever"});
+        assert_eq!(
+            Component::parse(&json).to_plain_text(),
+            "You are banned forever"
+        );
+    }
+
+    #[test]
+    fn sent_messages_history() {
+        let mut chat = Chat::new();
+        for text in ["first", "second", "second", "/help"] {
+            chat.set_current_message(String::from(text));
+            chat.submit_current_message();
+        }
+
+        // Repeats of the last message aren't remembered twice
+        chat.previous_sent();
+        assert_eq!(chat.get_current_message(), "/help");
+        chat.previous_sent();
+        assert_eq!(chat.get_current_message(), "second");
+        chat.previous_sent();
+        chat.previous_sent();
+        assert_eq!(chat.get_current_message(), "first");
+
+        chat.next_sent();
+        assert_eq!(chat.get_current_message(), "second");
+        chat.next_sent();
+        chat.next_sent();
+        assert_eq!(chat.get_current_message(), "");
+    }
+
+    #[test]
+    fn scrollback_is_capped() {
+        let mut chat = Chat::new();
+        for i in 0..MAX_HISTORY + 5 {
+            chat.add_message(ChatEvent::local(i.to_string()), i as i64);
+        }
+        assert_eq!(chat.get_history().len(), MAX_HISTORY);
+        assert_eq!(chat.get_history()[0].0.content, "5");
+    }
+
+    #[test]
+    fn chat_event_from_packet() {
+        let message = serde_json::from_str(
+            r#"{"text": "<", "extra": [{"text": "Notch", "color": "gold"}, "> hi"]}"#,
+        )
+        .unwrap();
+        let event = ChatEvent::from_packet(PlayServerChatMessageSpec {
+            message,
+            position: ChatPosition::SystemMessage,
+            sender: UUID4::from(7),
+        });
+
+        assert_eq!(event.content, "<Notch> hi");
+        assert_eq!(event.sender, UUID4::from(7));
+        assert_eq!(event.spans[1].color, [0xff, 0xaa, 0x00]);
+        assert_eq!(
+            event.content,
+            event
+                .spans
+                .iter()
+                .map(|s| s.text.as_str())
+                .collect::<String>()
+        );
+    }
+
+    fn lang(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn nested_extras_inherit_style() {
+        let json = serde_json::json!({
+            "text": "a",
+            "color": "red",
+            "bold": true,
+            "extra": [
+                {"text": "b", "italic": true, "extra": [{"text": "c", "color": "#123456"}]},
+                {"text": "d", "bold": false}
+            ]
+        });
+        let spans = Component::parse(&json).to_spans_with_lang(&HashMap::new());
+
+        let red = [0xff, 0x55, 0x55];
+        let summary: Vec<(&str, [u8; 3], bool, bool)> = spans
+            .iter()
+            .map(|s| (s.text.as_str(), s.color, s.bold, s.italic))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("a", red, true, false),
+                ("b", red, true, true),
+                ("c", [0x12, 0x34, 0x56], true, true),
+                ("d", red, false, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn translate_with_arguments() {
+        let lang = lang(&[
+            ("chat.type.text", "<%s> %s"),
+            ("swapped", "%2$s then %1$s, 100%%"),
+        ]);
+
+        let json = serde_json::json!({
+            "translate": "chat.type.text",
+            "with": [{"text": "Notch", "color": "gold"}, "hello"]
+        });
+        let spans = Component::parse(&json).to_spans_with_lang(&lang);
+        let text: String = spans.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(text, "<Notch> hello");
+        assert_eq!(
+            spans.iter().find(|s| s.text == "Notch").unwrap().color,
+            [0xff, 0xaa, 0x00]
+        );
+
+        let json = serde_json::json!({"translate": "swapped", "with": ["a", "b"]});
+        let spans = Component::parse(&json).to_spans_with_lang(&lang);
+        let text: String = spans.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(text, "b then a, 100%");
+    }
+
+    #[test]
+    fn unknown_translation_falls_back_to_key() {
+        let json = serde_json::json!({"translate": "some.missing.key", "with": ["x"]});
+        let spans = Component::parse(&json).to_spans_with_lang(&HashMap::new());
+        let text: String = spans.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(text, "some.missing.key");
+    }
 }
@@ -1,12 +1,63 @@
 use crate::{
     renderer::Vertex,
-    resources::{self, ENTITIES},
+    resources::{self, ENTITIES, PLAYER_INDEX},
+    world::aabb::Aabb,
 };
 
 pub mod components;
 use components::*;
 use glam::Vec3;
-use mcproto_rs::uuid::UUID4;
+use mcproto_rs::{
+    uuid::UUID4,
+    v1_16_3::{PlaySpawnEntitySpec, PlaySpawnLivingEntitySpec, PlaySpawnPlayerSpec},
+};
+
+/// Broad groups of entity types, used to tell entities apart before they have models
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntityKind {
+    Player,
+    Mob,
+    Item,
+    Projectile,
+    Vehicle,
+}
+
+impl EntityKind {
+    /// Returns the kind of an entity type from its name
+    pub fn from_name(name: &str) -> EntityKind {
+        let name = name.to_lowercase();
+
+        let items = ["item", "experience orb", "falling block", "tnt"];
+        let projectiles = [
+            "arrow",
+            "trident",
+            "fireball",
+            "skull",
+            "snowball",
+            "egg",
+            "ender pearl",
+            "potion",
+            "experience bottle",
+            "spit",
+            "bullet",
+            "firework",
+            "fishing bobber",
+        ];
+        let vehicles = ["boat", "minecart"];
+
+        if name == "player" {
+            EntityKind::Player
+        } else if items.iter().any(|i| name == *i) {
+            EntityKind::Item
+        } else if projectiles.iter().any(|p| name.contains(p)) {
+            EntityKind::Projectile
+        } else if vehicles.iter().any(|v| name.contains(v)) {
+            EntityKind::Vehicle
+        } else {
+            EntityKind::Mob
+        }
+    }
+}
 
 pub struct Entity {
     pub id: i32,
@@ -16,8 +67,11 @@ pub struct Entity {
 
     pub data: i32,
 
+    // Latest position from the server, and the position being interpolated from towards it
     pub pos: Vec3,
-    pub last_pos: Vec3,
+    pub prev_pos: Vec3,
+    // Fraction of a tick since the position was last updated
+    tick_progress: f32,
     pub vel: Vec3,
     pub ori: Orientation,
     pub ori_head: Orientation,
@@ -33,11 +87,12 @@ impl Entity {
 
             entity_type: ENTITIES
                 .get(&entity_type)
-                .expect(&format!("No entity with id {}", &entity_type)),
+                .unwrap_or_else(|| panic!("No entity with id {}", &entity_type)),
             data: 0,
 
             pos: Vec3::new(0.0, 0.0, 0.0),
-            last_pos: Vec3::new(0.0, 0.0, 0.0),
+            prev_pos: Vec3::new(0.0, 0.0, 0.0),
+            tick_progress: 1.0,
             vel: Vec3::new(0.0, 0.0, 0.0),
             ori: Orientation::new(),
             ori_head: Orientation::new(),
@@ -46,6 +101,12 @@ impl Entity {
         }
     }
 
+    /// Creates an entity from the values in a spawn packet
+    ///
+    /// # Returns
+    ///
+    /// * None if the entity type isn't known
+    #[allow(clippy::too_many_arguments)]
     pub fn new_with_values(
         id: i32,
         uuid: UUID4,
@@ -60,21 +121,80 @@ impl Entity {
         vx: f32,
         vy: f32,
         vz: f32,
-    ) -> Entity {
-        Entity {
+    ) -> Option<Entity> {
+        Some(Entity {
             id,
             uuid,
-            entity_type: ENTITIES
-                .get(&entity_type)
-                .expect(&format!("Failed to get entity from ID: {}", entity_type)),
+            entity_type: ENTITIES.get(&entity_type)?,
             data,
             pos: Vec3::new(px, py, pz),
-            last_pos: Vec3::new(px, py, pz),
+            prev_pos: Vec3::new(px, py, pz),
+            tick_progress: 1.0,
             vel: Vec3::new(vx, vy, vz),
             ori: Orientation::new_with_values(yaw, pitch, 0.0, 0.0),
             ori_head: Orientation::new_with_values(0.0, head_pitch, -90.0, 90.0),
             on_ground: true,
-        }
+        })
+    }
+
+    /// Creates a player from a Spawn Player packet, returns None if the player entity isn't known
+    pub fn from_spawn_player(pack: &PlaySpawnPlayerSpec) -> Option<Entity> {
+        let rot = &pack.location.rotation;
+        Entity::new_with_values(
+            pack.entity_id.0,
+            pack.uuid,
+            PLAYER_INDEX as u32,
+            0,
+            pack.location.position.x as f32,
+            pack.location.position.y as f32,
+            pack.location.position.z as f32,
+            angle_to_degrees(rot.yaw.value),
+            angle_to_degrees(rot.pitch.value),
+            angle_to_degrees(rot.pitch.value),
+            0.0,
+            0.0,
+            0.0,
+        )
+    }
+
+    /// Creates a mob from a Spawn Living Entity packet, returns None if the type isn't known
+    pub fn from_spawn_living(pack: &PlaySpawnLivingEntitySpec) -> Option<Entity> {
+        let rot = &pack.location.rotation;
+        Entity::new_with_values(
+            pack.entity_id.0,
+            pack.entity_uuid,
+            pack.entity_type.0 as u32,
+            0,
+            pack.location.position.x as f32,
+            pack.location.position.y as f32,
+            pack.location.position.z as f32,
+            angle_to_degrees(rot.yaw.value),
+            angle_to_degrees(rot.pitch.value),
+            angle_to_degrees(pack.head_pitch.value),
+            velocity_to_blocks(pack.velocity.x),
+            velocity_to_blocks(pack.velocity.y),
+            velocity_to_blocks(pack.velocity.z),
+        )
+    }
+
+    /// Creates an object such as an item or arrow from a Spawn Entity packet, returns None if the
+    /// type isn't known
+    pub fn from_spawn_entity(pack: &PlaySpawnEntitySpec) -> Option<Entity> {
+        Entity::new_with_values(
+            pack.entity_id.0,
+            pack.object_uuid,
+            pack.entity_type.0 as u32,
+            pack.data,
+            pack.position.x as f32,
+            pack.position.y as f32,
+            pack.position.z as f32,
+            angle_to_degrees(pack.yaw.value),
+            angle_to_degrees(pack.pitch.value),
+            0.0,
+            velocity_to_blocks(pack.velocity.x),
+            velocity_to_blocks(pack.velocity.y),
+            velocity_to_blocks(pack.velocity.z),
+        )
     }
 
     pub fn get_id(&self) -> i32 {
@@ -89,16 +209,75 @@ impl Entity {
         self.entity_type
     }
 
-    pub fn update(&mut self, delta: f32) {
-        let mut vel = self.vel;
-        if self.on_ground {
-            vel.y = 0.0;
-        } else {
-            vel.y -= 13.0 * delta;
-        }
+    pub fn get_kind(&self) -> EntityKind {
+        EntityKind::from_name(&self.entity_type.name)
+    }
+
+    /// Returns the box the entity takes up where it is currently drawn, centred on its position
+    /// horizontally with its position at the bottom
+    pub fn get_bounding_box(&self) -> Aabb {
+        let pos = self.interpolated_position(self.get_tick_fraction());
+        let half = Vec3::new(
+            self.entity_type.width / 2.0,
+            0.0,
+            self.entity_type.width / 2.0,
+        );
+        Aabb::new(
+            pos - half,
+            pos + half + Vec3::new(0.0, self.entity_type.height, 0.0),
+        )
+    }
+
+    /// Moves the entity by a relative move from the server, which is in 1/4096ths of a block from
+    /// the last position the server sent
+    pub fn move_relative(&mut self, dx: i16, dy: i16, dz: i16) {
+        let target = self.pos + Vec3::new(dx as f32, dy as f32, dz as f32) / 4096.0;
+        self.set_target(target);
+    }
+
+    /// Moves the entity to an absolute position from the server
+    pub fn teleport(&mut self, pos: Vec3) {
+        self.set_target(pos);
+    }
+
+    /// Starts interpolating from where the entity is currently drawn to a new position from the
+    /// server, which should be reached by the next tick
+    fn set_target(&mut self, target: Vec3) {
+        self.prev_pos = self.interpolated_position(self.get_tick_fraction());
+        self.pos = target;
+        self.tick_progress = 0.0;
+    }
 
-        self.pos += vel * delta;
+    /// Returns the position between the previous and latest position from the server
+    ///
+    /// # Arguments
+    ///
+    /// * `alpha` - How far through the tick to interpolate, 0 being the previous position and 1
+    /// the latest
+    pub fn interpolated_position(&self, alpha: f32) -> Vec3 {
+        self.prev_pos.lerp(self.pos, alpha.clamp(0.0, 1.0))
     }
+
+    /// Returns how far through the tick since the last position update the entity is, which stays
+    /// at 1 once the latest position has been reached
+    pub fn get_tick_fraction(&self) -> f32 {
+        self.tick_progress.min(1.0)
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        // Positions are sent every tick, 20 times a second
+        self.tick_progress = (self.tick_progress + delta * 20.0).min(1.0);
+    }
+}
+
+/// Converts an angle from the protocol, in 256ths of a full turn, to degrees
+pub fn angle_to_degrees(angle: u8) -> f32 {
+    angle as f32 * 360.0 / 256.0
+}
+
+/// Converts a velocity from the protocol, in 1/8000ths of a block per tick, to blocks per second
+pub fn velocity_to_blocks(v: i16) -> f32 {
+    v as f32 / 400.0
 }
 
 pub fn hitbox_model() -> Vec<Vertex> {
@@ -177,3 +356,111 @@ pub fn hitbox_model() -> Vec<Vertex> {
         },
     ]
 }
+
+#[cfg(test)]
+mod tests {
+    use mcproto_rs::{
+        protocol::{HasPacketBody, HasPacketId, RawPacket},
+        types::{self, Angle, BytesSerializer, EntityLocation, EntityRotation, VarInt},
+    };
+
+    use crate::network::{PacketType, RawPacketType};
+
+    use super::*;
+
+    /// Serializes a packet and reads it back the same way the network thread does
+    fn round_trip(packet: PacketType) -> PacketType {
+        let id = packet.id();
+        let mut serializer = BytesSerializer::default();
+        packet.mc_serialize_body(&mut serializer).unwrap();
+        let body = serializer.into_bytes();
+        RawPacketType::create(id, &body)
+            .unwrap()
+            .deserialize()
+            .unwrap()
+    }
+
+    fn zombie_id() -> u32 {
+        ENTITIES.values().find(|e| e.name == "Zombie").unwrap().id
+    }
+
+    fn spawn_zombie() -> PlaySpawnLivingEntitySpec {
+        PlaySpawnLivingEntitySpec {
+            entity_id: VarInt(57),
+            entity_uuid: UUID4::random(),
+            entity_type: VarInt(zombie_id() as i32),
+            location: EntityLocation {
+                position: types::Vec3 {
+                    x: 10.5,
+                    y: 64.0,
+                    z: -3.25,
+                },
+                rotation: EntityRotation {
+                    yaw: Angle { value: 64 },
+                    pitch: Angle { value: 0 },
+                },
+            },
+            head_pitch: Angle { value: 32 },
+            velocity: types::Vec3 {
+                x: 800,
+                y: 0,
+                z: -400,
+            },
+        }
+    }
+
+    #[test]
+    fn decode_spawn_living_entity() {
+        let spec = spawn_zombie();
+        let uuid = spec.entity_uuid;
+        let pack = match round_trip(PacketType::PlaySpawnLivingEntity(spec)) {
+            PacketType::PlaySpawnLivingEntity(pack) => pack,
+            other => panic!("Decoded the wrong packet: {:?}", other),
+        };
+
+        let ent = Entity::from_spawn_living(&pack).unwrap();
+        assert_eq!(ent.get_id(), 57);
+        assert_eq!(ent.get_uuid(), uuid);
+        assert_eq!(ent.get_type().id, zombie_id());
+        assert_eq!(ent.get_kind(), EntityKind::Mob);
+        assert_eq!(ent.pos, Vec3::new(10.5, 64.0, -3.25));
+        assert_eq!(ent.vel, Vec3::new(2.0, 0.0, -1.0));
+        assert_eq!(ent.ori.get_yaw(), 90.0);
+        assert_eq!(ent.ori_head.get_pitch(), 45.0);
+
+        // Types that aren't known can't be spawned
+        let mut unknown = spawn_zombie();
+        unknown.entity_type = VarInt(-1);
+        assert!(Entity::from_spawn_living(&unknown).is_none());
+    }
+
+    #[test]
+    fn relative_move_is_in_4096ths() {
+        let mut ent = Entity::from_spawn_living(&spawn_zombie()).unwrap();
+
+        ent.move_relative(4096, -2048, 512);
+        assert_eq!(ent.pos, Vec3::new(11.5, 63.5, -3.125));
+
+        // Deltas add up from the latest position rather than where the entity is drawn
+        ent.move_relative(-8192, 0, 0);
+        assert_eq!(ent.pos, Vec3::new(9.5, 63.5, -3.125));
+    }
+
+    #[test]
+    fn interpolate_halfway_between_positions() {
+        let mut ent = Entity::from_spawn_living(&spawn_zombie()).unwrap();
+        ent.teleport(Vec3::new(20.5, 66.0, -3.25));
+
+        assert_eq!(ent.get_tick_fraction(), 0.0);
+        assert_eq!(ent.interpolated_position(0.0), Vec3::new(10.5, 64.0, -3.25));
+        assert_eq!(ent.interpolated_position(0.5), Vec3::new(15.5, 65.0, -3.25));
+        assert_eq!(ent.interpolated_position(1.0), Vec3::new(20.5, 66.0, -3.25));
+
+        // Half a tick later the entity is drawn at the midpoint, and a new target starts from there
+        ent.update(0.025);
+        assert_eq!(ent.get_tick_fraction(), 0.5);
+        ent.move_relative(0, 0, 4096);
+        assert_eq!(ent.prev_pos, Vec3::new(15.5, 65.0, -3.25));
+        assert_eq!(ent.pos, Vec3::new(20.5, 66.0, -2.25));
+    }
+}
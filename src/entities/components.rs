@@ -9,6 +9,12 @@ pub struct Orientation {
     pitch_max: f32,
 }
 
+impl Default for Orientation {
+    fn default() -> Self {
+        Orientation::new()
+    }
+}
+
 impl Orientation {
     pub fn new() -> Orientation {
         Orientation {
@@ -69,12 +75,12 @@ impl Orientation {
         self.pitch
     }
 
+    /// Turns by an amount in degrees, the yaw wraps around to stay in 0 to 360 and the pitch is
+    /// clamped to the limits if there are any
     pub fn rotate(&mut self, yaw: f32, pitch: f32) {
-        self.yaw += yaw;
-        self.yaw %= 360.0;
+        self.yaw = (self.yaw + yaw).rem_euclid(360.0);
 
         self.pitch += pitch;
-        self.pitch %= 360.0;
 
         if self.pitch_min != 0.0 && self.pitch < self.pitch_min {
             self.pitch = self.pitch_min;
@@ -110,3 +116,30 @@ impl Orientation {
         Vec3::new(self.yaw, self.pitch, 0.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pitch_clamps_at_limits() {
+        let mut ori = Orientation::new_with_values(0.0, 0.0, -90.0, 90.0);
+        ori.rotate(0.0, 120.0);
+        assert_eq!(ori.get_pitch(), 90.0);
+        ori.rotate(0.0, -200.0);
+        assert_eq!(ori.get_pitch(), -90.0);
+        ori.rotate(0.0, 45.0);
+        assert_eq!(ori.get_pitch(), -45.0);
+    }
+
+    #[test]
+    fn yaw_wraps_modulo_360() {
+        let mut ori = Orientation::new_with_values(350.0, 0.0, -90.0, 90.0);
+        ori.rotate(20.0, 0.0);
+        assert_eq!(ori.get_yaw(), 10.0);
+        ori.rotate(-30.0, 0.0);
+        assert_eq!(ori.get_yaw(), 340.0);
+        ori.rotate(720.0, 0.0);
+        assert_eq!(ori.get_yaw(), 340.0);
+    }
+}
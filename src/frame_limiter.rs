@@ -0,0 +1,121 @@
+use std::time::{Duration, Instant};
+
+use glium::Display;
+
+// Sleeps can overshoot by a millisecond or so, so the end of each frame is waited out by spinning
+const SPIN_MARGIN: Duration = Duration::from_millis(1);
+// Refresh rate assumed for vsync when the monitor doesn't report one
+const DEFAULT_REFRESH_RATE: u32 = 60;
+
+/// Holds frames back so they are drawn no faster than a frame rate, sleeping for most of the wait
+/// so a core isn't kept busy
+pub struct FrameLimiter {
+    last_frame: Instant,
+}
+
+impl FrameLimiter {
+    pub fn new() -> FrameLimiter {
+        FrameLimiter {
+            last_frame: Instant::now(),
+        }
+    }
+
+    /// Waits until enough time has passed since the last frame to stay at or under a frame rate,
+    /// 0 doesn't wait at all
+    pub fn wait(&mut self, max_fps: u32) {
+        if max_fps > 0 {
+            let target = Duration::from_secs_f64(1.0 / max_fps as f64);
+            std::thread::sleep(sleep_time(target, self.last_frame.elapsed()));
+            while self.last_frame.elapsed() < target {
+                std::hint::spin_loop();
+            }
+        }
+        self.last_frame = Instant::now();
+    }
+}
+
+impl Default for FrameLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns how long to sleep for the rest of a frame, leaving a little to spin through
+///
+/// # Arguments
+///
+/// * `target` - How long each frame should take
+/// * `elapsed` - How long this frame has taken so far
+pub fn sleep_time(target: Duration, elapsed: Duration) -> Duration {
+    target.saturating_sub(elapsed).saturating_sub(SPIN_MARGIN)
+}
+
+/// Returns the frame rate to cap at from the max fps setting and the vsync toggle, which limits
+/// it to the refresh rate of the monitor. 0 is unlimited
+pub fn frame_cap(dis: &Display, max_fps: u32, vsync: bool) -> u32 {
+    let refresh = if vsync { refresh_rate(dis) } else { 0 };
+    lowest_cap(max_fps, refresh)
+}
+
+/// Returns the lower of two frame rate caps, where 0 is unlimited
+fn lowest_cap(a: u32, b: u32) -> u32 {
+    match (a, b) {
+        (0, b) => b,
+        (a, 0) => a,
+        (a, b) => a.min(b),
+    }
+}
+
+/// Returns the refresh rate of the monitor the window is on, from its fastest mode at the current
+/// resolution
+fn refresh_rate(dis: &Display) -> u32 {
+    let gl_window = dis.gl_window();
+    gl_window
+        .window()
+        .current_monitor()
+        .and_then(|monitor| {
+            let size = monitor.size();
+            monitor
+                .video_modes()
+                .filter(|mode| mode.size() == size)
+                .map(|mode| mode.refresh_rate() as u32)
+                .max()
+        })
+        .unwrap_or(DEFAULT_REFRESH_RATE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sleeps_for_rest_of_frame() {
+        let target = Duration::from_micros(16_667);
+
+        // Leaves a millisecond to spin through at the end
+        assert_eq!(
+            sleep_time(target, Duration::from_millis(5)),
+            Duration::from_micros(10_667)
+        );
+        assert_eq!(sleep_time(target, Duration::ZERO), target - SPIN_MARGIN);
+
+        // Nothing left to sleep for when the frame is nearly over or already too long
+        assert_eq!(
+            sleep_time(target, Duration::from_micros(16_000)),
+            Duration::ZERO
+        );
+        assert_eq!(
+            sleep_time(target, Duration::from_millis(40)),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn lowest_of_fps_and_refresh_rate() {
+        assert_eq!(lowest_cap(0, 0), 0);
+        assert_eq!(lowest_cap(120, 0), 120);
+        assert_eq!(lowest_cap(0, 60), 60);
+        assert_eq!(lowest_cap(30, 60), 30);
+        assert_eq!(lowest_cap(144, 60), 60);
+    }
+}
@@ -1,14 +1,17 @@
 #![allow(dead_code)]
 
-use egui::{Align2, Context, Id, Vec2};
+use egui::{text::LayoutJob, Align2, Color32, Context, Id, Stroke, TextFormat, Vec2};
 use glium_app::{utils::persistent_window::PersistentWindow, Timer};
 
-use crate::{server::InputState, state::State, Client};
+use crate::{chat::TextSpan, server::InputState, state::State, Client};
 
-use self::other_windows::fps_counter;
+use self::other_windows::{debug_overlay, fps_counter};
 
 pub mod chat_windows;
+pub mod death_screen;
 pub mod info_windows;
+pub mod loading_screen;
+pub mod minimap;
 pub mod other_windows;
 pub mod pause_windows;
 
@@ -17,24 +20,29 @@ pub mod main_menu;
 pub fn render(gui_ctx: &Context, cli: &mut Client, t: &Timer) {
     match &mut cli.state.server {
         Some(s) => {
-            if cli.state.settings.show_fps {
-                fps_counter::render(gui_ctx, t.fps(), t.delta());
+            if s.is_debug_shown() {
+                debug_overlay::render(gui_ctx, s, t.fps());
+            } else if cli.state.settings.show_fps {
+                fps_counter::render(gui_ctx, t.fps(), t.delta(), cli.state.rend.get_draw_stats());
             }
 
-            s.render(gui_ctx, &mut cli.window_manager);
+            s.render(gui_ctx, &mut cli.window_manager, &mut cli.state.settings);
         }
-        None => match main_menu::render(gui_ctx, cli) {
-            Some(mut s) => {
+        None => {
+            if let Some(mut s) = main_menu::render(gui_ctx, cli) {
                 s.set_input_state(InputState::Playing);
                 cli.state.server = Some(s);
             }
-            None => {}
-        },
+        }
     }
 }
 
-pub fn disconnect_window(reason: Option<String>) -> PersistentWindow<State> {
-    PersistentWindow::new(Box::new(move |id, _, gui_ctx, _| {
+/// Shows why the server was disconnected, with a button to reconnect to the address if provided
+pub fn disconnect_window(
+    reason: Option<String>,
+    reconnect: Option<String>,
+) -> PersistentWindow<State> {
+    PersistentWindow::new(Box::new(move |id, _, gui_ctx, state| {
         let mut open = true;
 
         egui::Window::new("Disconnected")
@@ -57,6 +65,21 @@ pub fn disconnect_window(reason: Option<String>) -> PersistentWindow<State> {
                     let size = label.unwrap().rect.width() / 2.0;
                     ui.add_space(size);
                     open = !ui.button("Ok").clicked();
+                    if let Some(address) = &reconnect {
+                        if ui.button("Reconnect").clicked() {
+                            match main_menu::connect(address, &state.settings, None) {
+                                Ok(mut s) => {
+                                    s.set_input_state(InputState::Playing);
+                                    state.server = Some(s);
+                                }
+                                Err(e) => {
+                                    log::error!("Couldn't reconnect to server: {}", e);
+                                    state.connect_error = Some(e);
+                                }
+                            }
+                            open = false;
+                        }
+                    }
                     ui.add_space(size);
                 });
             });
@@ -64,3 +87,32 @@ pub fn disconnect_window(reason: Option<String>) -> PersistentWindow<State> {
         open
     }))
 }
+
+/// Lays out styled chat text so it can be shown in a label
+pub fn spans_layout_job(spans: &[TextSpan], background: Color32) -> LayoutJob {
+    let mut job = LayoutJob::default();
+    for span in spans {
+        let color = Color32::from_rgb(span.color[0], span.color[1], span.color[2]);
+        let line = |enabled| {
+            if enabled {
+                Stroke::new(1.0, color)
+            } else {
+                Stroke::none()
+            }
+        };
+
+        job.append(
+            &span.text,
+            0.0,
+            TextFormat {
+                color,
+                background,
+                italics: span.italic,
+                underline: line(span.underlined),
+                strikethrough: line(span.strikethrough),
+                ..Default::default()
+            },
+        );
+    }
+    job
+}
@@ -1,12 +1,13 @@
 const CHAT_TIME: i64 = 300;
+// Ticks at the end of CHAT_TIME over which messages fade out
+const FADE_TIME: i64 = 100;
+const CHAT_BACKGROUND: Color32 = Color32::from_rgba_premultiplied(0, 0, 0, 175);
 
-use egui::{Align, Align2, Color32, Context, Frame, Layout, RichText, Vec2};
-use mcproto_rs::v1_16_3::PlayServerChatMessageSpec;
-
-use crate::server::Server;
+use crate::{chat::ChatEvent, gui::spans_layout_job, server::Server};
+use egui::{text::LayoutJob, Align, Align2, Color32, Context, Frame, Layout, Vec2};
 
 pub fn render_inactive(server: &Server, gui_ctx: &Context) {
-    let messages: Vec<&(PlayServerChatMessageSpec, i64)> = server
+    let messages: Vec<&(ChatEvent, i64)> = server
         .get_chat()
         .get_history()
         .iter()
@@ -24,13 +25,12 @@ pub fn render_inactive(server: &Server, gui_ctx: &Context) {
                 ui.with_layout(Layout::bottom_up(Align::LEFT), |ui| {
                     ui.add_space(ui.text_style_height(&egui::TextStyle::Body) + 9.0);
                     for message in messages {
-                        if let Some(text) = message.0.message.to_traditional() {
-                            ui.label(
-                                RichText::new(text).color(Color32::WHITE).background_color(
-                                    Color32::from_rgba_unmultiplied(0, 0, 0, 175),
-                                ),
-                            );
-                        }
+                        let age = server.get_world_time() - message.1;
+                        let alpha = ((CHAT_TIME - age) as f32 / FADE_TIME as f32).clamp(0.0, 1.0);
+                        ui.label(faded(
+                            spans_layout_job(&message.0.spans, CHAT_BACKGROUND),
+                            alpha,
+                        ));
                     }
                 });
             });
@@ -52,13 +52,7 @@ pub fn render_active(server: &mut Server, gui_ctx: &Context) {
 
                 egui::ScrollArea::vertical().show(ui, |ui| {
                     for message in server.get_chat().get_history().iter().rev() {
-                        if let Some(text) = message.0.message.to_traditional() {
-                            ui.label(
-                                RichText::new(text).color(Color32::WHITE).background_color(
-                                    Color32::from_rgba_unmultiplied(0, 0, 0, 175),
-                                ),
-                            );
-                        }
+                        ui.label(spans_layout_job(&message.0.spans, CHAT_BACKGROUND));
                     }
                 });
 
@@ -78,3 +72,12 @@ pub fn render_active(server: &mut Server, gui_ctx: &Context) {
             });
         });
 }
+
+/// Multiplies the opacity of the text and background of a layout
+fn faded(mut job: LayoutJob, alpha: f32) -> LayoutJob {
+    for section in job.sections.iter_mut() {
+        section.format.color = section.format.color.linear_multiply(alpha);
+        section.format.background = section.format.background.linear_multiply(alpha);
+    }
+    job
+}
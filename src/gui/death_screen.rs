@@ -0,0 +1,43 @@
+use egui::{Align2, Color32, Context, RichText, Vec2};
+
+pub enum DeathAction {
+    Nothing,
+    Respawn,
+    Disconnect,
+}
+
+/// Shows the death screen, returns whether the player has chosen to respawn or leave the server
+pub fn render(gui_ctx: &Context, hardcore: bool) -> DeathAction {
+    let mut out = DeathAction::Nothing;
+
+    egui::Window::new("Death")
+        .title_bar(false)
+        .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+        .resizable(false)
+        .collapsible(false)
+        .show(gui_ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.label(
+                    RichText::new(if hardcore { "Game over!" } else { "You died!" })
+                        .heading()
+                        .color(Color32::RED),
+                );
+                ui.add_space(10.0);
+
+                // Hardcore players come back as spectators
+                let respawn = if hardcore {
+                    "Spectate world"
+                } else {
+                    "Respawn"
+                };
+                if ui.button(respawn).clicked() {
+                    out = DeathAction::Respawn;
+                }
+                if ui.button("Disconnect").clicked() {
+                    out = DeathAction::Disconnect;
+                }
+            });
+        });
+
+    out
+}
@@ -9,7 +9,7 @@ pub fn render(gui_ctx: &Context, server: &Server) {
         .id(Id::new("Entities"))
         .show(gui_ctx, |ui| {
             let mut ents: HashMap<u32, Vec<&Entity>> = HashMap::new();
-            for (id, e) in server.get_entities() {
+            for e in server.get_entities().values() {
                 match ents.get_mut(&e.entity_type.id) {
                     Some(vec) => {
                         vec.push(e);
@@ -1,15 +1,34 @@
 use egui::Context;
+use mcproto_rs::v1_16_3::GameMode;
 
 use crate::server::Server;
 
 pub fn render(gui_ctx: &Context, server: &Server) {
-    egui::Window::new("Players").show(gui_ctx, |ui| {
-        egui::Grid::new("Players").striped(true).show(ui, |ui| {
-            for player in server.get_players().values() {
-                ui.label(&player.name);
-                ui.label(&format!("{}ms", &player.ping));
-                ui.end_row();
-            }
+    let players = server.get_players();
+    let title = match server.get_join_info() {
+        Some(info) => format!("Players ({}/{})", players.len(), info.max_players),
+        None => format!("Players ({})", players.len()),
+    };
+
+    egui::Window::new(title)
+        .id(egui::Id::new("Players"))
+        .show(gui_ctx, |ui| {
+            egui::Grid::new("Players").striped(true).show(ui, |ui| {
+                for player in players.get_sorted() {
+                    ui.label(player.get_shown_name());
+                    ui.label(gamemode_name(&player.gamemode));
+                    ui.label(&format!("{}ms", &player.ping));
+                    ui.end_row();
+                }
+            });
         });
-    });
+}
+
+fn gamemode_name(gamemode: &GameMode) -> &'static str {
+    match gamemode {
+        GameMode::Survival => "Survival",
+        GameMode::Creative => "Creative",
+        GameMode::Adventure => "Adventure",
+        GameMode::Spectator => "Spectator",
+    }
 }
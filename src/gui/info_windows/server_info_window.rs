@@ -18,12 +18,11 @@ pub fn render(gui_ctx: &Context, server: &Server) {
             );
         });
 
-        let difficulty_locked: &str;
-        if server.is_difficulty_locked() {
-            difficulty_locked = "(Locked)";
+        let difficulty_locked = if server.is_difficulty_locked() {
+            "(Locked)"
         } else {
-            difficulty_locked = "";
-        }
+            ""
+        };
         ui.horizontal(|ui| {
             ui.label(RichText::new("Difficulty: "));
             ui.label(
@@ -121,7 +120,7 @@ pub fn render(gui_ctx: &Context, server: &Server) {
 
         ui.separator();
 
-        let pos = block_coords(&server.get_player().get_position());
+        let pos = block_coords(server.get_player().get_position());
         let chunk = ChunkSection::section_containing(&pos);
         let chunk_coords = Chunk::map_from_world_coords(&pos);
 
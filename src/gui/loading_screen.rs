@@ -0,0 +1,34 @@
+use egui::{Align2, Color32, Context, ProgressBar, RichText, Vec2};
+
+use crate::resources::loading::ResourceLoader;
+
+/// Shows how far through loading the resources are, or why loading failed
+pub fn render(gui_ctx: &Context, loader: &ResourceLoader) {
+    egui::Window::new("Loading")
+        .title_bar(false)
+        .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+        .resizable(false)
+        .collapsible(false)
+        .show(gui_ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.heading("Minceraft!");
+                ui.add_space(10.0);
+
+                if let Some(e) = loader.get_error() {
+                    ui.label(RichText::new(e).color(Color32::RED));
+                    ui.label("Check the assets have been downloaded with get_assets.sh");
+                    return;
+                }
+
+                let stage = match loader.get_stage() {
+                    Some(stage) => format!("Loading {}...", stage.name().to_lowercase()),
+                    None => String::from("Done"),
+                };
+                ui.add(
+                    ProgressBar::new(loader.get_progress())
+                        .desired_width(300.0)
+                        .text(stage),
+                );
+            });
+        });
+}
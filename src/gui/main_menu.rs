@@ -1,12 +1,20 @@
-use egui::{Align2, Context, Id, ScrollArea, Vec2};
+use std::collections::HashMap;
+
+use egui::{Align2, Color32, Context, Id, ScrollArea, Vec2};
 use egui_extras::RetainedImage;
 use glium_app::utils::persistent_window::PersistentWindow;
-use log::{debug, error};
+use log::{debug, error, info};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    network::{NetworkCommand, NetworkManager, PROTOCOL},
+    auth::LoginFlow,
+    gui::{other_windows::options_window, spans_layout_job},
+    network::{
+        address::ServerAddress, version::ProtocolVersion, ConnectError, NetworkCommand,
+        NetworkManager, ServerStatus,
+    },
     server::Server,
+    settings::{Settings, SETTINGS_FILE},
     state::State,
     Client,
 };
@@ -31,15 +39,46 @@ pub fn render(gui_ctx: &Context, cli: &mut Client) -> Option<Server> {
             ui.separator();
 
             if cli.state.settings.online_play {
-                ui.label("Online play is not yet implemented");
+                render_account(ui, &mut cli.state);
             } else {
                 ui.horizontal(|ui| {
                     ui.label("Player Name: ");
                     ui.text_edit_singleline(&mut cli.state.settings.name);
                 });
+
+                if let Err(e) = validate_username(&cli.state.settings.name) {
+                    ui.colored_label(Color32::RED, e);
+                }
+            }
+
+            ui.separator();
+            ui.heading("Version");
+
+            // Used for servers that haven't been pinged
+            let version = &mut cli.state.settings.protocol_version;
+            egui::ComboBox::from_label("Protocol")
+                .selected_text(version.name())
+                .show_ui(ui, |ui| {
+                    for v in ProtocolVersion::ALL {
+                        ui.selectable_value(version, v, v.name());
+                    }
+                });
+            // Settings saved while 1.17 and 1.18 could still be picked may hold them
+            if !version.is_supported() {
+                ui.colored_label(Color32::RED, "This version isn't supported yet");
+            }
+
+            ui.separator();
+            if ui.button("Settings").clicked() {
+                cli.window_manager
+                    .push(options_window::new_options_window());
             }
         });
 
+    // Offline mode players need a valid name to join with
+    let can_connect =
+        cli.state.settings.online_play || validate_username(&cli.state.settings.name).is_ok();
+
     egui::CentralPanel::default().show(gui_ctx, |ui| {
         ui.heading("Servers");
         ui.add_space(15.0);
@@ -48,14 +87,26 @@ pub fn render(gui_ctx: &Context, cli: &mut Client) -> Option<Server> {
         ui.text_edit_singleline(&mut cli.state.settings.direct_connection);
 
         ui.horizontal(|ui| {
-            if ui.button("Direct Connect").clicked() {
-                match connect(
+            if ui
+                .add_enabled(can_connect, egui::Button::new("Direct Connect"))
+                .clicked()
+            {
+                let result = connect(
                     &cli.state.settings.direct_connection,
-                    cli.state.settings.name.clone(),
-                ) {
-                    Ok(s) => serv = Some(s),
-                    Err(e) => error!("Failed to connect to server: {:?}", e),
-                }
+                    &cli.state.settings,
+                    cli.state
+                        .server_pings
+                        .get(&cli.state.settings.direct_connection),
+                );
+                serv = joined(result, &mut cli.state.connect_error);
+            }
+
+            if ui.button("Ping").clicked() {
+                ping(
+                    &cli.state.settings.direct_connection,
+                    &cli.state.settings,
+                    &mut cli.state.outstanding_server_pings,
+                );
             }
 
             if ui.button("Save Server").clicked() {
@@ -68,8 +119,25 @@ pub fn render(gui_ctx: &Context, cli: &mut Client) -> Option<Server> {
                     .settings
                     .saved_servers
                     .push(SavedServer { ip, name });
+                save_settings(&cli.state.settings);
             }
         });
+
+        if let Some(e) = &cli.state.connect_error {
+            ui.colored_label(Color32::RED, e.to_string());
+        }
+
+        let State {
+            settings,
+            server_pings,
+            icon_handles,
+            ..
+        } = &mut cli.state;
+        if let Some(status) = server_pings.get(&settings.direct_connection) {
+            ui.horizontal(|ui| {
+                render_status(ui, &settings.direct_connection, status, icon_handles);
+            });
+        }
         ui.separator();
 
         ScrollArea::vertical().show(ui, |ui| {
@@ -78,11 +146,14 @@ pub fn render(gui_ctx: &Context, cli: &mut Client) -> Option<Server> {
                 server_pings,
                 outstanding_server_pings,
                 icon_handles,
+                connect_error,
                 ..
             } = &mut cli.state;
             let wm = &mut cli.window_manager;
 
             let mut remove: Option<usize> = None;
+            let mut select: Option<String> = None;
+            let mut join: Option<String> = None;
             for (i, s) in settings.saved_servers.iter().enumerate() {
                 ui.add_space(15.0);
 
@@ -91,29 +162,27 @@ pub fn render(gui_ctx: &Context, cli: &mut Client) -> Option<Server> {
 
                     // Info and controls
                     ui.vertical(|ui| {
-                        // Name and IP
-                        ui.label(&s.name);
+                        // Name and IP, clicking selects the server and double clicking joins it
+                        let entry =
+                            ui.selectable_label(settings.direct_connection == s.ip, &s.name);
+                        if entry.double_clicked() && can_connect {
+                            join = Some(s.ip.clone());
+                        } else if entry.clicked() {
+                            select = Some(s.ip.clone());
+                        }
                         ui.label(&s.ip);
 
                         // Buttons
                         ui.horizontal(|ui| {
-                            if ui.button("Connect").clicked() {
-                                match connect(&s.ip, settings.name.clone()) {
-                                    Ok(s) => serv = Some(s),
-                                    Err(e) => error!("Failed to connect to server: {:?}", e),
-                                }
+                            if ui
+                                .add_enabled(can_connect, egui::Button::new("Connect"))
+                                .clicked()
+                            {
+                                let result = connect(&s.ip, settings, server_pings.get(&s.ip));
+                                serv = joined(result, connect_error);
                             }
                             if ui.button("Refresh").clicked() {
-                                log::info!("Attempting to connect");
-                                match NetworkManager::connect(&s.ip) {
-                                    Ok(server) => {
-                                        server.send_command(NetworkCommand::RequestStatus).unwrap();
-                                        outstanding_server_pings.insert(s.ip.clone(), server);
-                                    }
-                                    Err(e) => {
-                                        error!("Couldn't get status from server: {:?}", e);
-                                    }
-                                };
+                                ping(&s.ip, settings, outstanding_server_pings);
                             }
                             if ui.button("Edit").clicked() {
                                 let len = settings.saved_servers.len();
@@ -150,6 +219,7 @@ pub fn render(gui_ctx: &Context, cli: &mut Client) -> Option<Server> {
                                                     if ui.button("Confirm").clicked() {
                                                         state.settings.saved_servers[index] =
                                                             new.clone();
+                                                        save_settings(&state.settings);
 
                                                         open = false;
                                                     }
@@ -171,54 +241,8 @@ pub fn render(gui_ctx: &Context, cli: &mut Client) -> Option<Server> {
 
                     // Status info
                     ui.separator();
-                    match server_pings.get(&s.ip) {
-                        Some(status) => {
-                            // Favicon
-                            if let Some(favicon) = &status.favicon {
-                                if icon_handles.get(&s.ip).is_none() {
-                                    // Load image
-                                    icon_handles.insert(
-                                        s.ip.clone(),
-                                        RetainedImage::from_image_bytes(
-                                            s.ip.clone(),
-                                            &favicon.data,
-                                        )
-                                        .unwrap(),
-                                    );
-                                }
-
-                                if let Some(icon) = icon_handles.get(&s.ip) {
-                                    // ui.image(tex_handle, Vec2::new(50.0, 50.0));
-
-                                    icon.show_size(ui, Vec2::new(50.0, 50.0));
-                                }
-                            }
-
-                            // Version, Players, Ping
-                            ui.vertical(|ui| {
-                                if let Some(version) = &status.version {
-                                    ui.label(&version.name);
-                                }
-
-                                let players = ui.label(&format!(
-                                    "Players: {} / {}",
-                                    status.players.online, status.players.max
-                                ));
-                                if status.players.online > 0 {
-                                    players.on_hover_ui(|ui| {
-                                        for p in &status.players.sample {
-                                            ui.label(&p.name);
-                                        }
-                                    });
-                                }
-                                // ui.label(&format!("Ping: {}ms", status.ping));
-                            });
-
-                            if let Some(desc) = status.description.to_traditional() {
-                                ui.label(&desc);
-                            }
-                        }
-                        None => {}
+                    if let Some(status) = server_pings.get(&s.ip) {
+                        render_status(ui, &s.ip, status, icon_handles);
                     }
                 });
 
@@ -227,7 +251,16 @@ pub fn render(gui_ctx: &Context, cli: &mut Client) -> Option<Server> {
             }
 
             if let Some(i) = remove {
-                cli.state.settings.saved_servers.remove(i);
+                settings.saved_servers.remove(i);
+                save_settings(settings);
+            }
+            if let Some(ip) = select {
+                settings.direct_connection = ip;
+            }
+            if let Some(ip) = join {
+                let result = connect(&ip, settings, server_pings.get(&ip));
+                serv = joined(result, connect_error);
+                settings.direct_connection = ip;
             }
         });
     });
@@ -235,16 +268,270 @@ pub fn render(gui_ctx: &Context, cli: &mut Client) -> Option<Server> {
     serv
 }
 
-fn connect(ip: &str, name: String) -> Result<Server, std::io::Error> {
-    match NetworkManager::connect(ip) {
+/// Saves settings after the saved servers have changed
+fn save_settings(settings: &Settings) {
+    if let Err(e) = settings.save(SETTINGS_FILE) {
+        error!("Failed to save settings: {:?}", e);
+    }
+}
+
+/// Starts a server list ping to the address, the result is put in the server pings once the
+/// server responds
+fn ping(
+    address: &str,
+    settings: &Settings,
+    outstanding_server_pings: &mut HashMap<String, Server>,
+) {
+    // Only the format is checked here, the address is looked up on the network thread
+    if let Err(e) = ServerAddress::parse(address) {
+        error!("Invalid server address {}: {}", address, e);
+        return;
+    }
+
+    log::info!("Attempting to connect");
+    match NetworkManager::connect(address, None, settings.connect_timeout()) {
         Ok(server) => {
+            server.send_command(NetworkCommand::RequestStatus).unwrap();
+            outstanding_server_pings.insert(address.to_string(), server);
+        }
+        Err(e) => {
+            error!("Couldn't get status from server: {:?}", e);
+        }
+    };
+}
+
+/// Renders the result of a server list ping
+fn render_status(
+    ui: &mut egui::Ui,
+    address: &str,
+    status: &Result<ServerStatus, String>,
+    icon_handles: &mut HashMap<String, RetainedImage>,
+) {
+    let status = match status {
+        Ok(status) => status,
+        Err(e) => {
+            ui.colored_label(Color32::RED, format!("Can't reach server: {}", e));
+            return;
+        }
+    };
+
+    // Favicon
+    if let Some(favicon) = &status.icon {
+        if icon_handles.get(address).is_none() {
+            // Load image
+            match RetainedImage::from_image_bytes(address, favicon) {
+                Ok(icon) => {
+                    icon_handles.insert(address.to_string(), icon);
+                }
+                Err(e) => error!("Couldn't load server icon: {}", e),
+            }
+        }
+
+        if let Some(icon) = icon_handles.get(address) {
+            icon.show_size(ui, Vec2::new(50.0, 50.0));
+        }
+    }
+
+    // Version, Players, Ping
+    ui.vertical(|ui| {
+        if status.is_compatible() {
+            ui.label(&status.version);
+        } else {
+            ui.colored_label(
+                Color32::RED,
+                format!("{} (protocol {})", status.version, status.protocol),
+            );
+        }
+
+        let players = ui.label(&format!(
+            "Players: {} / {}",
+            status.num_players, status.max_players
+        ));
+        if status.num_players > 0 {
+            players.on_hover_ui(|ui| {
+                for p in &status.online_players {
+                    ui.label(p);
+                }
+            });
+        }
+        ui.label(&format!("Ping: {}ms", status.ping));
+    });
+
+    ui.label(spans_layout_job(&status.motd_spans, Color32::TRANSPARENT));
+}
+
+/// Renders the Microsoft account login controls for online play
+fn render_account(ui: &mut egui::Ui, state: &mut State) {
+    // Check on any login in progress
+    if let Some(login) = &mut state.login {
+        match login.poll() {
+            Some(Ok(profile)) => {
+                info!("Logged in as {}", profile.name);
+                state.settings.profile = Some(profile);
+                state.login = None;
+                save_settings(&state.settings);
+            }
+            Some(Err(e)) => {
+                error!("Failed to log in: {}", e);
+                state.login_error = Some(e);
+                state.login = None;
+            }
+            None => {}
+        }
+    }
+
+    if let Some(profile) = &state.settings.profile {
+        ui.label(format!("Logged in as {}", profile.name));
+        if ui.button("Log out").clicked() {
+            state.settings.profile = None;
+            // Stop a refresh from logging back in
+            state.login = None;
+            save_settings(&state.settings);
+        }
+        return;
+    }
+
+    match &state.login {
+        Some(login) => match login.get_device_code() {
+            Some(code) => {
+                ui.label("To log in, go to:");
+                ui.hyperlink(&code.verification_uri);
+                ui.label("and enter the code:");
+                ui.heading(&code.user_code);
+            }
+            None => {
+                ui.label("Contacting Microsoft...");
+            }
+        },
+        None => {
+            ui.horizontal(|ui| {
+                ui.label("Client ID: ");
+                ui.text_edit_singleline(&mut state.settings.ms_client_id);
+            });
+
+            if ui.button("Log in with Microsoft").clicked() {
+                state.login_error = None;
+                state.login = Some(LoginFlow::start(state.settings.ms_client_id.clone()));
+            }
+
+            if let Some(e) = &state.login_error {
+                ui.colored_label(Color32::RED, e);
+            }
+        }
+    }
+}
+
+/// Connects and logs in to a server, using the protocol version from the server's status if it
+/// has been pinged or the version from the settings otherwise
+pub fn connect(
+    ip: &str,
+    settings: &Settings,
+    status: Option<&Result<ServerStatus, String>>,
+) -> Result<Server, ConnectError> {
+    let version = match status {
+        Some(Ok(status)) => ProtocolVersion::check(status.protocol, &status.version)?,
+        _ => ProtocolVersion::check(
+            settings.protocol_version.protocol(),
+            settings.protocol_version.name(),
+        )?,
+    };
+
+    // Online mode uses the name of the logged in profile
+    let (name, profile) = if settings.online_play {
+        match &settings.profile {
+            Some(profile) => (profile.name.clone(), Some(profile.clone())),
+            None => {
+                return Err(ConnectError::Other(String::from(
+                    "Not logged in to a Microsoft account",
+                )))
+            }
+        }
+    } else {
+        validate_username(&settings.name).map_err(ConnectError::Other)?;
+        (settings.name.clone(), None)
+    };
+
+    // Only the format is checked here, the address is looked up on the network thread
+    ServerAddress::parse(ip).map_err(|e| ConnectError::InvalidAddress(e.to_string()))?;
+
+    match NetworkManager::connect(
+        ip,
+        Some(settings.retry_policy()),
+        settings.connect_timeout(),
+    ) {
+        Ok(mut server) => {
             debug!("Connected to server.");
+            server.set_protocol_version(version);
             server
-                .send_command(NetworkCommand::Login(PROTOCOL, 25565, name))
+                .send_command(NetworkCommand::Login(version.protocol(), name, profile))
                 .expect("Failed to login");
 
             Ok(server)
         }
-        Err(e) => Err(e),
+        Err(e) => Err(ConnectError::Other(e.to_string())),
+    }
+}
+
+/// Returns the server if joining started, otherwise keeps the error to show in the menu
+fn joined(
+    result: Result<Server, ConnectError>,
+    connect_error: &mut Option<ConnectError>,
+) -> Option<Server> {
+    match result {
+        Ok(server) => {
+            *connect_error = None;
+            Some(server)
+        }
+        Err(e) => {
+            error!("Failed to connect to server: {}", e);
+            *connect_error = Some(e);
+            None
+        }
+    }
+}
+
+/// Checks a username follows Minecraft's rules of 3 to 16 letters, numbers or underscores
+pub fn validate_username(name: &str) -> Result<(), String> {
+    if name.len() < 3 {
+        return Err(String::from("Name must be at least 3 characters"));
+    }
+    if name.len() > 16 {
+        return Err(String::from("Name must be at most 16 characters"));
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(String::from(
+            "Name can only contain letters, numbers and underscores",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_usernames() {
+        for name in ["Notch", "jeb_", "abc", "A_Sixteen_Chars_", "12345"] {
+            assert_eq!(validate_username(name), Ok(()), "{}", name);
+        }
+    }
+
+    #[test]
+    fn username_too_short() {
+        assert!(validate_username("").is_err());
+        assert!(validate_username("ab").is_err());
+    }
+
+    #[test]
+    fn username_too_long() {
+        assert!(validate_username("Seventeen_Letters").is_err());
+    }
+
+    #[test]
+    fn username_invalid_characters() {
+        for name in ["Har ry", "Harry!", "Ha-rry", "Härry", "名前です"] {
+            assert!(validate_username(name).is_err(), "{}", name);
+        }
     }
 }
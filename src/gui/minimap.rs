@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+
+use egui::{
+    Align2, Color32, ColorImage, Context, Frame, Mesh, Pos2, Rect, Sense, Shape, Stroke,
+    TextureHandle, Vec2,
+};
+use glam::Vec3;
+
+use crate::{
+    settings::Settings,
+    world::{
+        block_coords,
+        chunks::Chunk,
+        map_colours::{SurfaceColours, EMPTY},
+        ChunkLocation, World,
+    },
+};
+
+// Chunks on each side of the player's chunk that the texture covers
+const CHUNK_RADIUS: i32 = 8;
+// Blocks along each side of the texture, one pixel per block
+const TEXTURE_SIZE: usize = (CHUNK_RADIUS as usize * 2 + 1) * 16;
+// Width and height of the map in points
+const PANEL_SIZE: f32 = 128.0;
+// Points per block the map can be zoomed between, the texture always covers the panel when zoomed
+// out all the way
+pub const MIN_ZOOM: f32 = 1.0;
+pub const MAX_ZOOM: f32 = 8.0;
+// Length of the line showing which way the player is facing
+const FACING_LENGTH: f32 = 8.0;
+
+/// A top down view of the loaded chunks around the player with north at the top. The colours are
+/// kept in a texture that only has the chunks whose surface changed drawn again each frame
+pub struct Minimap {
+    texture: Option<TextureHandle>,
+    image: ColorImage,
+    // Chunk at the top left corner of the texture
+    origin: ChunkLocation,
+    // Colours of the chunks that are drawn into the image
+    drawn: HashMap<ChunkLocation, SurfaceColours>,
+}
+
+impl Minimap {
+    pub fn new() -> Minimap {
+        Minimap {
+            texture: None,
+            image: ColorImage::new([TEXTURE_SIZE, TEXTURE_SIZE], Color32::TRANSPARENT),
+            origin: ChunkLocation::new(0, 0),
+            drawn: HashMap::new(),
+        }
+    }
+
+    /// Draws chunks that were loaded, unloaded or changed into the image since the last update.
+    /// The whole image is drawn again once the player moves into another chunk
+    ///
+    /// # Returns
+    ///
+    /// * True if the image changed and has to be uploaded again
+    fn update(&mut self, world: &World, centre: ChunkLocation) -> bool {
+        let mut changed = false;
+
+        let origin = centre - ChunkLocation::splat(CHUNK_RADIUS);
+        if origin != self.origin {
+            self.origin = origin;
+            self.image = ColorImage::new([TEXTURE_SIZE, TEXTURE_SIZE], Color32::TRANSPARENT);
+            self.drawn.clear();
+            changed = true;
+        }
+
+        // Blank out chunks that have been unloaded
+        let unloaded: Vec<ChunkLocation> = self
+            .drawn
+            .keys()
+            .filter(|loc| !world.is_chunk_loaded(loc))
+            .copied()
+            .collect();
+        for loc in unloaded {
+            self.drawn.remove(&loc);
+            self.draw_chunk(loc, &[[EMPTY; 16]; 16]);
+            changed = true;
+        }
+
+        for (loc, chunk) in world.loaded_chunks() {
+            let offset = *loc - self.origin;
+            if offset.min_element() < 0 || offset.max_element() > CHUNK_RADIUS * 2 {
+                continue;
+            }
+
+            let colours = chunk.top_colours();
+            if self.drawn.get(loc) != Some(&colours) {
+                self.draw_chunk(*loc, &colours);
+                self.drawn.insert(*loc, colours);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Copies the colours of a chunk into its square of the image
+    fn draw_chunk(&mut self, loc: ChunkLocation, colours: &SurfaceColours) {
+        let corner = (loc - self.origin) * 16;
+        for (z, row) in colours.iter().enumerate() {
+            let start = (corner.y as usize + z) * TEXTURE_SIZE + corner.x as usize;
+            for (x, [r, g, b, a]) in row.iter().enumerate() {
+                self.image.pixels[start + x] = Color32::from_rgba_unmultiplied(*r, *g, *b, *a);
+            }
+        }
+    }
+
+    /// Shows the map in the top right of the screen with a marker for the player, and buttons to
+    /// zoom in and out
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - Position of the player, which the map is centred on
+    /// * `look` - Direction the player is looking, shown by a line from the marker
+    pub fn render(
+        &mut self,
+        gui_ctx: &Context,
+        world: &World,
+        position: Vec3,
+        look: Vec3,
+        settings: &mut Settings,
+    ) {
+        if !settings.show_minimap {
+            return;
+        }
+
+        let centre = Chunk::chunk_containing(&block_coords(&position));
+        if self.update(world, centre) || self.texture.is_none() {
+            match &mut self.texture {
+                Some(texture) => texture.set(self.image.clone()),
+                None => self.texture = Some(gui_ctx.load_texture("minimap", self.image.clone())),
+            }
+        }
+        let texture = match &self.texture {
+            Some(texture) => texture,
+            None => return,
+        };
+
+        let zoom = &mut settings.minimap_zoom;
+        *zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+
+        egui::Window::new("Minimap")
+            .title_bar(false)
+            .resizable(false)
+            .anchor(Align2::RIGHT_TOP, Vec2::new(-5.0, 5.0))
+            .frame(Frame::none().fill(Color32::from_rgba_unmultiplied(0, 0, 0, 100)))
+            .show(gui_ctx, |ui| {
+                let (rect, _) = ui.allocate_exact_size(Vec2::splat(PANEL_SIZE), Sense::hover());
+                let painter = ui.painter_at(rect);
+
+                // Only the part of the texture around the player fits in the panel
+                let player = texture_position(self.origin, position);
+                let half = PANEL_SIZE / *zoom / 2.0;
+                let uv = Rect::from_min_max(
+                    ((player - Vec2::splat(half)) / TEXTURE_SIZE as f32).to_pos2(),
+                    ((player + Vec2::splat(half)) / TEXTURE_SIZE as f32).to_pos2(),
+                );
+                let mut mesh = Mesh::with_texture(texture.id());
+                mesh.add_rect_with_uv(rect, uv, Color32::WHITE);
+                painter.add(Shape::mesh(mesh));
+
+                let marker = panel_position(rect, player, *zoom, player);
+                let facing = Vec2::new(look.x, look.z).normalized();
+                if facing.x.is_finite() && facing.y.is_finite() {
+                    painter.line_segment(
+                        [marker, marker + facing * FACING_LENGTH],
+                        Stroke::new(2.0, Color32::WHITE),
+                    );
+                }
+                painter.circle_filled(marker, 3.0, Color32::RED);
+
+                ui.horizontal(|ui| {
+                    if ui.small_button("-").clicked() {
+                        *zoom = (*zoom / 2.0).max(MIN_ZOOM);
+                    }
+                    if ui.small_button("+").clicked() {
+                        *zoom = (*zoom * 2.0).min(MAX_ZOOM);
+                    }
+                    ui.label(format!("{}x", zoom));
+                });
+            });
+    }
+}
+
+impl Default for Minimap {
+    fn default() -> Self {
+        Minimap::new()
+    }
+}
+
+/// Converts the x/z position of something in the world to its position in pixels on a texture
+/// whose top left corner is at the corner of a chunk
+pub fn texture_position(origin: ChunkLocation, position: Vec3) -> Vec2 {
+    Vec2::new(
+        position.x - (origin.x * 16) as f32,
+        position.z - (origin.y * 16) as f32,
+    )
+}
+
+/// Converts a position on the texture to where it's shown in the panel
+///
+/// # Arguments
+///
+/// * `rect` - Where the panel is on the screen
+/// * `centre` - Position on the texture shown in the middle of the panel
+/// * `zoom` - Points per block
+/// * `point` - Position on the texture to convert
+pub fn panel_position(rect: Rect, centre: Vec2, zoom: f32, point: Vec2) -> Pos2 {
+    rect.center() + (point - centre) * zoom
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use crate::{
+        resources,
+        world::chunks::{BlockIndex, MIN_SECTION, SECTIONS_PER_CHUNK},
+    };
+
+    use super::*;
+
+    #[test]
+    fn player_marker_position() {
+        let position = Vec3::new(-20.25, 64.0, 37.5);
+        let centre = Chunk::chunk_containing(&block_coords(&position));
+        assert_eq!(centre, ChunkLocation::new(-2, 2));
+
+        // The player's chunk is in the middle of the texture
+        let origin = centre - ChunkLocation::splat(CHUNK_RADIUS);
+        let player = texture_position(origin, position);
+        assert_eq!(player, Vec2::new(139.75, 133.5));
+        let middle = (CHUNK_RADIUS * 16) as f32;
+        assert!(player.x >= middle && player.x < middle + 16.0);
+        assert!(player.y >= middle && player.y < middle + 16.0);
+
+        // Which is drawn in the middle of the panel, with north up and east to the right
+        let rect = Rect::from_min_size(Pos2::new(10.0, 10.0), Vec2::splat(PANEL_SIZE));
+        assert_eq!(panel_position(rect, player, 2.0, player), rect.center());
+        assert_eq!(
+            panel_position(rect, player, 2.0, player + Vec2::new(1.0, -3.0)),
+            rect.center() + Vec2::new(2.0, -6.0)
+        );
+    }
+
+    #[test]
+    fn only_changed_chunks_are_drawn() {
+        let centre = ChunkLocation::new(-2, 2);
+        let mut world = World::new();
+        world.insert_chunk(Chunk::empty(centre, MIN_SECTION, SECTIONS_PER_CHUNK));
+        let mut minimap = Minimap::new();
+
+        assert!(minimap.update(&world, centre));
+        assert!(!minimap.update(&world, centre));
+
+        // Block (3, 5) of the centre chunk
+        world.set_block(
+            &IVec3::new(-29, 10, 37),
+            resources::state_id("Dirt", &[]) as BlockIndex,
+        );
+        assert!(minimap.update(&world, centre));
+        let pixel = (8 * 16 + 5) * TEXTURE_SIZE + 8 * 16 + 3;
+        assert_eq!(
+            minimap.image.pixels[pixel],
+            Color32::from_rgba_unmultiplied(151, 109, 77, 255)
+        );
+
+        // Unloading blanks the chunk out again
+        world.unload_chunk(&centre);
+        assert!(minimap.update(&world, centre));
+        assert_eq!(minimap.image.pixels[pixel], Color32::TRANSPARENT);
+    }
+}
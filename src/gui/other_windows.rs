@@ -1,2 +1,6 @@
+pub mod boss_bar_overlay;
+pub mod debug_overlay;
 pub mod fps_counter;
+pub mod hotbar;
 pub mod options_window;
+pub mod scoreboard_sidebar;
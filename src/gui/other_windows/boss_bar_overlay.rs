@@ -0,0 +1,51 @@
+use egui::{Align2, Color32, Context, Frame, Rect, RichText, Sense, Stroke, Vec2};
+
+use crate::server::boss_bars::BossBars;
+
+const BAR_SIZE: Vec2 = Vec2::new(364.0, 10.0);
+
+/// Shows the boss bars at the top of the screen with their titles above them
+pub fn render(gui_ctx: &Context, bars: &BossBars) {
+    if bars.get_bars().next().is_none() {
+        return;
+    }
+
+    egui::Window::new("Boss bars")
+        .title_bar(false)
+        .resizable(false)
+        .anchor(Align2::CENTER_TOP, Vec2::new(0.0, 5.0))
+        .frame(Frame::none())
+        .show(gui_ctx, |ui| {
+            for bar in bars.get_bars() {
+                ui.vertical_centered(|ui| {
+                    ui.label(RichText::new(&bar.title).color(Color32::WHITE));
+
+                    let (rect, _) = ui.allocate_exact_size(BAR_SIZE, Sense::hover());
+                    let [r, g, b] = bar.get_rgb();
+                    let colour = Color32::from_rgb(r, g, b);
+                    let painter = ui.painter();
+
+                    painter.rect_filled(rect, 0.0, colour.linear_multiply(0.3));
+                    let filled = Rect::from_min_size(
+                        rect.min,
+                        Vec2::new(rect.width() * bar.health, rect.height()),
+                    );
+                    painter.rect_filled(filled, 0.0, colour);
+
+                    // Notches splitting the bar into equal segments
+                    let segments = bar.get_segments();
+                    for i in 1..segments {
+                        let x = rect.left() + rect.width() * i as f32 / segments as f32;
+                        painter.line_segment(
+                            [
+                                egui::pos2(x, rect.top()),
+                                egui::pos2(x, rect.top() + rect.height() * 0.6),
+                            ],
+                            Stroke::new(1.0, Color32::BLACK),
+                        );
+                    }
+                });
+                ui.add_space(4.0);
+            }
+        });
+}
@@ -0,0 +1,123 @@
+use egui::{Align2, Color32, Context, Frame, RichText, Ui, Vec2};
+
+use crate::{
+    server::{CameraMode, Server},
+    world::{block_coords, chunks::Chunk},
+};
+
+/// Shows the F3 debug overlay in the top left with where the player is and what they're looking at
+pub fn render(gui_ctx: &Context, server: &Server, fps: u32) {
+    let player = server.get_player();
+    let world = server.get_world();
+
+    let pos = *player.get_position();
+    let block = block_coords(&pos);
+    let chunk = Chunk::chunk_containing(&block);
+    let in_chunk = Chunk::map_from_world_coords(&block);
+    let yaw = player.get_orientation().get_yaw();
+    let pitch = player.get_orientation().get_pitch();
+
+    let biome = world
+        .get_chunk(&chunk)
+        .and_then(|c| c.biome_at(&in_chunk))
+        .map(|id| {
+            server
+                .get_join_info()
+                .and_then(|info| info.biomes.get(&(id as i32)))
+                .cloned()
+                .unwrap_or_else(|| format!("Unknown ({})", id))
+        })
+        .unwrap_or_else(|| String::from("Unknown"));
+
+    egui::Window::new("Debug")
+        .title_bar(false)
+        .resizable(false)
+        .anchor(Align2::LEFT_TOP, Vec2::new(5.0, 5.0))
+        .frame(Frame::none().fill(Color32::from_rgba_unmultiplied(0, 0, 0, 150)))
+        .show(gui_ctx, |ui| {
+            line(ui, format!("{} fps", fps));
+            if let Some(brand) = server.get_server_brand() {
+                line(ui, format!("Server: {}", brand));
+            }
+            ui.add_space(6.0);
+
+            line(
+                ui,
+                format!("XYZ: {:.3} / {:.3} / {:.3}", pos.x, pos.y, pos.z),
+            );
+            line(ui, format!("Block: {} {} {}", block.x, block.y, block.z));
+            if server.get_camera_mode() == CameraMode::FreeFly {
+                let cam = server.get_camera_position();
+                line(
+                    ui,
+                    format!("Free camera: {:.3} / {:.3} / {:.3}", cam.x, cam.y, cam.z),
+                );
+            }
+            line(
+                ui,
+                format!(
+                    "Chunk: {} {} {} in {} {}",
+                    in_chunk.x,
+                    in_chunk.y.rem_euclid(16),
+                    in_chunk.z,
+                    chunk.x,
+                    chunk.y
+                ),
+            );
+            line(
+                ui,
+                format!("Facing: {} ({:.1} / {:.1})", facing(yaw), yaw, pitch),
+            );
+            line(ui, format!("Biome: {}", biome));
+            ui.add_space(6.0);
+
+            line(ui, format!("Chunks: {}", world.get_chunks().len()));
+            line(ui, format!("Entities: {}", server.get_entities().len()));
+            ui.add_space(6.0);
+
+            match server.get_targeted_block() {
+                Some(hit) => {
+                    let target = hit.block_pos;
+                    line(
+                        ui,
+                        format!("Targeted Block: {} {} {}", target.x, target.y, target.z),
+                    );
+                    if let Some(state) = world.block_at(&target) {
+                        line(ui, format!("{} ({})", state.name, state.id));
+                    }
+                    line(ui, format!("Face: {:?}", hit.face));
+                }
+                None => line(ui, String::from("Targeted Block: None")),
+            }
+
+            let sounds = server.get_recent_sounds();
+            if !sounds.is_empty() {
+                ui.add_space(6.0);
+                line(ui, String::from("Sounds:"));
+                for sound in sounds.iter().rev() {
+                    let pos = sound.position;
+                    line(
+                        ui,
+                        format!(
+                            "{} at {:.1} / {:.1} / {:.1}",
+                            sound.name, pos.x, pos.y, pos.z
+                        ),
+                    );
+                }
+            }
+        });
+}
+
+fn line(ui: &mut Ui, text: String) {
+    ui.label(RichText::new(text).color(Color32::WHITE).monospace());
+}
+
+/// Returns the direction a yaw faces and the axis it points along
+fn facing(yaw: f32) -> &'static str {
+    match ((yaw.rem_euclid(360.0) + 45.0) / 90.0) as i32 % 4 {
+        0 => "south (Towards positive Z)",
+        1 => "west (Towards negative X)",
+        2 => "north (Towards negative Z)",
+        _ => "east (Towards positive X)",
+    }
+}
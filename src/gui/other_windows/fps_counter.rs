@@ -1,13 +1,13 @@
 use egui::{Align2, Color32, Context, Frame, RichText, Vec2};
 
-pub fn render(gui_ctx: &Context, fps: u32, delta: f32) {
-    let col: Color32;
+use crate::renderer::DrawStats;
 
-    if fps < 60 {
-        col = Color32::RED;
+pub fn render(gui_ctx: &Context, fps: u32, delta: f32, draws: &DrawStats) {
+    let col = if fps < 60 {
+        Color32::RED
     } else {
-        col = Color32::GREEN;
-    }
+        Color32::GREEN
+    };
 
     egui::Window::new("FPS Counter")
         .title_bar(false)
@@ -29,5 +29,14 @@ pub fn render(gui_ctx: &Context, fps: u32, delta: f32) {
                     .strong()
                     .heading(),
             );
+            ui.label(
+                RichText::new(format!(
+                    "DRAWS: {} ({} culled, {} occluded)",
+                    draws.drawn, draws.culled, draws.occluded
+                ))
+                .color(Color32::WHITE)
+                .background_color(Color32::from_rgba_unmultiplied(0, 0, 0, 175))
+                .strong(),
+            );
         });
 }
@@ -0,0 +1,47 @@
+use egui::{Align2, Color32, Context, Frame, RichText, Stroke, Vec2};
+
+use crate::inventory::Inventory;
+
+const SLOT_SIZE: f32 = 40.0;
+
+/// Shows the hotbar along the bottom of the screen with the selected slot outlined
+pub fn render(gui_ctx: &Context, inventory: &Inventory) {
+    egui::Window::new("Hotbar")
+        .title_bar(false)
+        .resizable(false)
+        .anchor(Align2::CENTER_BOTTOM, Vec2::new(0.0, -5.0))
+        .frame(Frame::none())
+        .show(gui_ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.spacing_mut().item_spacing = Vec2::new(2.0, 0.0);
+
+                for (i, slot) in inventory.get_hotbar().iter().enumerate() {
+                    let stroke = if i == inventory.get_selected() {
+                        Stroke::new(2.0, Color32::WHITE)
+                    } else {
+                        Stroke::new(1.0, Color32::DARK_GRAY)
+                    };
+
+                    Frame::none()
+                        .fill(Color32::from_rgba_unmultiplied(0, 0, 0, 150))
+                        .stroke(stroke)
+                        .show(ui, |ui| {
+                            ui.set_min_size(Vec2::splat(SLOT_SIZE));
+                            ui.set_max_size(Vec2::splat(SLOT_SIZE));
+                            if let Some(item) = slot {
+                                ui.vertical_centered(|ui| {
+                                    ui.label(RichText::new(item.get_name()).small())
+                                        .on_hover_text(item.get_name());
+                                    if item.count > 1 {
+                                        ui.label(
+                                            RichText::new(item.count.to_string())
+                                                .color(Color32::WHITE),
+                                        );
+                                    }
+                                });
+                            }
+                        });
+                }
+            });
+        });
+}
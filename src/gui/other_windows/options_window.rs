@@ -1,13 +1,52 @@
 use std::ops::RangeInclusive;
 
-use egui::{Id, ScrollArea};
+use egui::{Color32, Id, RichText, ScrollArea};
 use glium_app::utils::persistent_window::PersistentWindow;
 
-use crate::WindowManagerType;
+use crate::{
+    gui::main_menu::validate_username,
+    settings::{
+        key_bindings::{from_egui_key, from_egui_modifiers, key_name, Action},
+        GuiScale, MAX_RENDER_DISTANCE, MIN_RENDER_DISTANCE, SETTINGS_FILE,
+    },
+    WindowManagerType,
+};
 
+// Highest frame rate the max fps slider goes up to
+const MAX_FPS_SLIDER: u32 = 360;
+
+/// Window for changing settings, which are saved to the settings file whenever one is changed
 pub fn new_options_window() -> PersistentWindow<WindowManagerType> {
+    // Action waiting for a key to be pressed to bind it to
+    let mut rebinding: Option<Action> = None;
+
     PersistentWindow::new(Box::new(move |id, _, gui_ctx, state| {
         let mut open = true;
+        let mut changed = false;
+
+        if let Some(action) = rebinding {
+            let input = gui_ctx.input();
+            let pressed = input.events.iter().find_map(|e| match e {
+                egui::Event::Key {
+                    key, pressed: true, ..
+                } => Some(*key),
+                _ => None,
+            });
+            // Escape cancels rather than being bound since it always opens the pause menu
+            let key = match pressed {
+                Some(egui::Key::Escape) => {
+                    rebinding = None;
+                    None
+                }
+                Some(key) => from_egui_key(key),
+                None => from_egui_modifiers(&input.modifiers),
+            };
+            if let Some(key) = key {
+                state.settings.key_bindings.set(action, key);
+                rebinding = None;
+                changed = true;
+            }
+        }
 
         egui::Window::new("Settings")
             .id(Id::new(id))
@@ -15,45 +54,161 @@ pub fn new_options_window() -> PersistentWindow<WindowManagerType> {
             .show(gui_ctx, |ui| {
                 ScrollArea::vertical().show(ui, |ui| {
                     ui.collapsing("Window", |ui| {
-                        ui.label("No settings here yet");
+                        changed |= ui
+                            .checkbox(&mut state.settings.show_fps, "Show FPS")
+                            .changed();
+                        ui.horizontal(|ui| {
+                            ui.label("Max FPS");
+                            // 0 leaves the frame rate uncapped
+                            let unlimited = if state.settings.max_fps == 0 {
+                                "Unlimited"
+                            } else {
+                                ""
+                            };
+                            changed |= ui
+                                .add(
+                                    egui::Slider::new(
+                                        &mut state.settings.max_fps,
+                                        RangeInclusive::new(0, MAX_FPS_SLIDER),
+                                    )
+                                    .text(unlimited),
+                                )
+                                .changed();
+                        });
+                        changed |= ui.checkbox(&mut state.settings.vsync, "VSync").changed();
+
+                        let scale = &mut state.settings.gui_scale;
+                        egui::ComboBox::from_label("GUI scale")
+                            .selected_text(scale.name())
+                            .show_ui(ui, |ui| {
+                                for s in GuiScale::ALL {
+                                    changed |= ui.selectable_value(scale, s, s.name()).changed();
+                                }
+                            });
+                    });
+
+                    ui.collapsing("Sound", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Master volume");
+                            let percent = format!("{:.0}%", state.settings.master_volume * 100.0);
+                            changed |= ui
+                                .add(
+                                    egui::Slider::new(
+                                        &mut state.settings.master_volume,
+                                        RangeInclusive::new(0.0, 1.0),
+                                    )
+                                    .show_value(false)
+                                    .text(percent),
+                                )
+                                .changed();
+                        });
                     });
 
                     ui.collapsing("Camera", |ui| {
                         ui.horizontal(|ui| {
                             ui.label("FOV");
-                            let mut fov = state.rend.cam.get_fov();
                             if ui
                                 .add(egui::Slider::new(
-                                    &mut fov,
+                                    &mut state.settings.fov,
                                     RangeInclusive::new(60.0, 120.0),
                                 ))
                                 .changed()
                             {
-                                state.rend.cam.set_fov(fov);
+                                state.rend.cam.set_fov(state.settings.fov);
+                                changed = true;
                             }
                         });
                         ui.horizontal(|ui| {
-                            ui.label("Fog near");
-                            ui.add(egui::DragValue::new(&mut state.settings.fog_near));
-                        });
-                        ui.horizontal(|ui| {
-                            ui.label("Fog far");
-                            ui.add(egui::DragValue::new(&mut state.settings.fog_far));
+                            ui.label("Render distance");
+                            changed |= ui
+                                .add(egui::Slider::new(
+                                    &mut state.settings.render_distance,
+                                    RangeInclusive::new(MIN_RENDER_DISTANCE, MAX_RENDER_DISTANCE),
+                                ))
+                                .changed();
                         });
+                        changed |= ui
+                            .checkbox(&mut state.settings.occlusion_culling, "Occlusion culling")
+                            .changed();
+                        changed |= ui
+                            .checkbox(&mut state.settings.wireframe, "Wireframe (F7)")
+                            .changed();
+                        changed |= ui
+                            .checkbox(&mut state.settings.show_chunk_borders, "Chunk borders (F6)")
+                            .changed();
+                        changed |= ui
+                            .checkbox(&mut state.settings.show_minimap, "Minimap (M)")
+                            .changed();
                     });
 
                     ui.collapsing("Input", |ui| {
                         ui.horizontal(|ui| {
                             ui.label("Mouse sensitivity");
-                            ui.add(egui::Slider::new(
-                                &mut state.settings.mouse_sensitivity,
-                                RangeInclusive::new(0.1, 10.0),
-                            ));
+                            changed |= ui
+                                .add(egui::Slider::new(
+                                    &mut state.settings.mouse_sensitivity,
+                                    RangeInclusive::new(0.1, 10.0),
+                                ))
+                                .changed();
+                        });
+                        ui.separator();
+
+                        let keys = &mut state.settings.key_bindings;
+                        egui::Grid::new("key_bindings")
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for action in Action::ALL {
+                                    ui.label(action.get_name());
+
+                                    let text = if rebinding == Some(action) {
+                                        RichText::new("Press a key...")
+                                    } else if keys.is_conflicting(action) {
+                                        RichText::new(key_name(keys.get(action)))
+                                            .color(Color32::RED)
+                                    } else {
+                                        RichText::new(key_name(keys.get(action)))
+                                    };
+                                    if ui.button(text).clicked() {
+                                        rebinding = Some(action);
+                                    }
+                                    if ui.small_button("Reset").clicked() {
+                                        keys.set(action, action.default_key());
+                                        changed = true;
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+
+                        for (a, b) in keys.get_conflicts() {
+                            ui.colored_label(
+                                Color32::RED,
+                                format!(
+                                    "{} and {} are bound to the same key",
+                                    a.get_name(),
+                                    b.get_name()
+                                ),
+                            );
+                        }
+                    });
+
+                    ui.collapsing("Account", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Offline name");
+                            changed |= ui.text_edit_singleline(&mut state.settings.name).changed();
                         });
+                        if let Err(e) = validate_username(&state.settings.name) {
+                            ui.colored_label(Color32::RED, e);
+                        }
                     });
                 });
             });
 
+        if changed {
+            if let Err(e) = state.settings.save(SETTINGS_FILE) {
+                log::error!("Failed to save settings: {:?}", e);
+            }
+        }
+
         open
     }))
 }
@@ -0,0 +1,32 @@
+use egui::{Align2, Color32, Context, Frame, RichText, Vec2};
+
+use crate::server::scoreboard::Scoreboard;
+
+// Most scores the sidebar shows, the same as the vanilla client
+const MAX_SCORES: usize = 15;
+
+/// Shows the sidebar objective on the right of the screen with its scores, highest first
+pub fn render(gui_ctx: &Context, scoreboard: &Scoreboard) {
+    let objective = match scoreboard.get_sidebar() {
+        Some(objective) => objective,
+        None => return,
+    };
+
+    egui::Window::new("Scoreboard")
+        .title_bar(false)
+        .resizable(false)
+        .anchor(Align2::RIGHT_CENTER, Vec2::new(-5.0, 0.0))
+        .frame(Frame::none().fill(Color32::from_rgba_unmultiplied(0, 0, 0, 100)))
+        .show(gui_ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.label(RichText::new(&objective.title).color(Color32::WHITE));
+            });
+            egui::Grid::new("scoreboard_sidebar").show(ui, |ui| {
+                for (name, score) in objective.get_sorted_scores().into_iter().take(MAX_SCORES) {
+                    ui.label(RichText::new(name).color(Color32::WHITE));
+                    ui.label(RichText::new(score.to_string()).color(Color32::RED));
+                    ui.end_row();
+                }
+            });
+        });
+}
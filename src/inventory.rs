@@ -0,0 +1,236 @@
+use mcproto_rs::{nbt::NamedTag, types::Slot};
+
+use crate::{
+    resources::{Item, ITEMS},
+    world::chunks::BlockIndex,
+};
+
+// Slots in the player's inventory window, which are the crafting output, the 2x2 crafting grid,
+// armour, the main inventory, the hotbar and the offhand in that order
+pub const PLAYER_SLOTS: usize = 46;
+pub const HOTBAR_START: usize = 36;
+pub const HOTBAR_SIZE: usize = 9;
+pub const OFFHAND_SLOT: usize = 45;
+// Window id of the player's own inventory
+pub const PLAYER_WINDOW: i8 = 0;
+
+/// A stack of items in a slot
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemStack {
+    pub id: i32,
+    pub count: u8,
+    // Extra data like enchantments, damage and custom names
+    pub nbt: Option<NamedTag>,
+}
+
+impl ItemStack {
+    /// Reads the item in a slot from an inventory packet, None if the slot isn't present or has
+    /// no items in it
+    pub fn from_slot(slot: &Slot) -> Option<ItemStack> {
+        let item = slot.as_ref()?;
+        if item.item_count <= 0 {
+            return None;
+        }
+
+        Some(ItemStack {
+            id: item.item_id.0,
+            count: item.item_count as u8,
+            nbt: item.nbt.clone(),
+        })
+    }
+
+    /// Returns the item in the registry, None if the id isn't known
+    pub fn get_item(&self) -> Option<&'static Item> {
+        ITEMS.get(&(self.id as u32))
+    }
+
+    /// Returns the name of the item, or its id if it isn't in the registry
+    pub fn get_name(&self) -> String {
+        match self.get_item() {
+            Some(item) => item.name.clone(),
+            None => format!("#{}", self.id),
+        }
+    }
+
+    /// Returns the block state placed by this item, None if it isn't a block
+    pub fn get_block(&self) -> Option<BlockIndex> {
+        self.get_item()?.block.map(|b| b as BlockIndex)
+    }
+}
+
+/// The slots of the player's inventory and which hotbar slot is selected
+#[derive(Debug, Clone)]
+pub struct Inventory {
+    slots: Vec<Option<ItemStack>>,
+    // Index into the hotbar, 0 to 8
+    selected: usize,
+}
+
+impl Inventory {
+    pub fn new() -> Inventory {
+        Inventory {
+            slots: vec![None; PLAYER_SLOTS],
+            selected: 0,
+        }
+    }
+
+    /// Replaces the contents of every slot, from a window items packet
+    pub fn set_slots(&mut self, slots: Vec<Option<ItemStack>>) {
+        if slots.len() != PLAYER_SLOTS {
+            log::warn!(
+                "Got {} inventory slots, expected {}",
+                slots.len(),
+                PLAYER_SLOTS
+            );
+        }
+        self.slots = slots;
+        self.slots.resize(PLAYER_SLOTS, None);
+    }
+
+    /// Sets the contents of a single slot, returns false if there is no slot with that index
+    pub fn set_slot(&mut self, index: usize, item: Option<ItemStack>) -> bool {
+        match self.slots.get_mut(index) {
+            Some(slot) => {
+                *slot = item;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get_slot(&self, index: usize) -> Option<&ItemStack> {
+        self.slots.get(index)?.as_ref()
+    }
+
+    pub fn get_hotbar(&self) -> &[Option<ItemStack>] {
+        &self.slots[HOTBAR_START..HOTBAR_START + HOTBAR_SIZE]
+    }
+
+    pub fn get_selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Selects a hotbar slot, returns false if it isn't in 0 to 8
+    pub fn select(&mut self, slot: usize) -> bool {
+        if slot >= HOTBAR_SIZE {
+            return false;
+        }
+        self.selected = slot;
+        true
+    }
+
+    /// Returns the item in the selected hotbar slot
+    pub fn held_item(&self) -> Option<&ItemStack> {
+        self.get_slot(HOTBAR_START + self.selected)
+    }
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mcproto_rs::{
+        nbt::Tag,
+        protocol::{HasPacketId, RawPacket},
+        v1_16_3::PlaySetSlotSpec,
+    };
+
+    use crate::network::{PacketType, RawPacketType};
+
+    use super::*;
+
+    fn stack(id: i32, count: u8) -> ItemStack {
+        ItemStack {
+            id,
+            count,
+            nbt: None,
+        }
+    }
+
+    #[test]
+    fn set_slot_updates_hotbar() {
+        let mut inventory = Inventory::new();
+        assert!(inventory.set_slot(HOTBAR_START + 2, Some(stack(1, 64))));
+        assert!(!inventory.set_slot(PLAYER_SLOTS, Some(stack(1, 1))));
+
+        assert_eq!(inventory.get_hotbar()[2], Some(stack(1, 64)));
+        assert_eq!(inventory.held_item(), None);
+
+        // Emptying the slot again
+        inventory.set_slot(HOTBAR_START + 2, None);
+        assert_eq!(inventory.get_slot(HOTBAR_START + 2), None);
+    }
+
+    #[test]
+    fn switching_held_slot() {
+        let mut inventory = Inventory::new();
+        inventory.set_slot(HOTBAR_START, Some(stack(1, 1)));
+        inventory.set_slot(HOTBAR_START + 8, Some(stack(2, 32)));
+        assert_eq!(inventory.held_item(), Some(&stack(1, 1)));
+
+        assert!(inventory.select(8));
+        assert_eq!(inventory.get_selected(), 8);
+        assert_eq!(inventory.held_item(), Some(&stack(2, 32)));
+
+        // Out of range slots leave the selection alone
+        assert!(!inventory.select(HOTBAR_SIZE));
+        assert_eq!(inventory.get_selected(), 8);
+    }
+
+    /// Decodes the slot in the body of a set slot packet for hotbar slot 0
+    fn decode_slot(slot: &[u8]) -> Option<ItemStack> {
+        let id = PacketType::PlaySetSlot(PlaySetSlotSpec {
+            window_id: PLAYER_WINDOW as u8,
+            slow: 0,
+            slot_data: None,
+        })
+        .id();
+        let mut body = vec![PLAYER_WINDOW as u8, 0x00, HOTBAR_START as u8];
+        body.extend_from_slice(slot);
+
+        match RawPacketType::create(id, &body)
+            .unwrap()
+            .deserialize()
+            .unwrap()
+        {
+            PacketType::PlaySetSlot(pack) => ItemStack::from_slot(&pack.slot_data),
+            other => panic!("Decoded the wrong packet: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decode_present_and_empty_slots() {
+        #[rustfmt::skip]
+        let present = [
+            // Present, item 1 and 12 of them
+            0x01, 0x01, 0x0c,
+            // Unnamed compound holding the int "Damage" = 5
+            0x0a, 0x00, 0x00,
+            0x03, 0x00, 0x06, b'D', b'a', b'm', b'a', b'g', b'e', 0x00, 0x00, 0x00, 0x05,
+            0x00,
+        ];
+        let item = decode_slot(&present).unwrap();
+        assert_eq!(item.id, 1);
+        assert_eq!(item.count, 12);
+        match item.nbt.map(|nbt| nbt.payload) {
+            Some(Tag::Compound(tags)) => {
+                assert_eq!(tags.len(), 1);
+                assert_eq!(tags[0].name, "Damage");
+                assert!(matches!(tags[0].payload, Tag::Int(5)));
+            }
+            other => panic!("Expected a compound, got {:?}", other),
+        }
+
+        // Present without NBT
+        let item = decode_slot(&[0x01, 0x01, 0x40, 0x00]).unwrap();
+        assert_eq!((item.count, item.nbt), (64, None));
+
+        // Absent, and present with nothing in it
+        assert_eq!(decode_slot(&[0x00]), None);
+        assert_eq!(decode_slot(&[0x01, 0x01, 0x00, 0x00]), None);
+    }
+}
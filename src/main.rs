@@ -8,36 +8,39 @@ extern crate lazy_static;
 extern crate log;
 extern crate quartz_nbt;
 
-use std::{sync::mpsc::TryRecvError, time::Instant};
-
+use crate::frame_limiter::{frame_cap, FrameLimiter};
 use crate::network::*;
-use crate::resources::BLOCK_MODELS_PARSED;
+use crate::resources::loading::ResourceLoader;
+use crate::settings::{key_bindings::Action, SETTINGS_FILE};
 
 mod network;
 
 use egui::{FontData, FontDefinitions, FontFamily};
 use egui_winit::winit::{event::Event, window::WindowBuilder};
-use glam::Vec3;
-use glium::glutin;
+use glium::{glutin, Surface};
 use log::{debug, error, info};
 
 use glium_app::*;
 use glium_app::{context::Context, utils::persistent_window::PersistentWindowManager};
-use mcproto_rs::{
-    types::{self, EntityLocation},
-    v1_16_3::PlayClientPlayerPositionAndRotationSpec,
-};
-use server::InputState;
+use server::{InputState, ServerEvent};
 use state::State;
 
+#[cfg(feature = "audio")]
+pub mod audio;
+pub mod auth;
 pub mod chat;
 pub mod entities;
+pub mod frame_limiter;
 pub mod gui;
+pub mod inventory;
+pub mod particles;
 pub mod player;
 pub mod renderer;
 pub mod resources;
+pub mod screenshot;
 pub mod server;
 pub mod settings;
+pub mod sound;
 pub mod state;
 pub mod world;
 
@@ -66,9 +69,10 @@ pub type WindowManager = PersistentWindowManager<WindowManagerType>;
 pub struct Client {
     pub state: State,
     pub window_manager: WindowManager,
+    // Loads the resources when the client starts, None once they've loaded
+    loader: Option<ResourceLoader>,
 
-    period: f32,
-    last_mod: f32,
+    frame_limiter: FrameLimiter,
 }
 
 impl Application for Client {
@@ -98,69 +102,64 @@ impl Application for Client {
         let aspect = dims.0 as f32 / dims.1 as f32;
         self.state.rend.cam.set_aspect_ratio(aspect);
 
-        std::thread::spawn(|| {
-            let start = Instant::now();
-            log::info!("Loaded {} blocks.", resources::BLOCKS.len());
-            log::info!("Loaded {} entities.", resources::ENTITIES.len());
-            log::info!("Loaded {} models' data.", resources::BLOCK_MODELS_RAW.len());
-            log::info!("Loaded {} models.", resources::BLOCK_MODELS_PARSED.len());
-            log::info!("Loaded {} block textures.", resources::BLOCK_TEXTURES.len());
-            let dur = Instant::now() - start;
-            info!("Loading assets took {}ms", dur.as_millis());
-        });
-
-        self.state.rend.load_textures(&ctx.dis);
+        self.loader = Some(ResourceLoader::start());
     }
 
     fn update(&mut self, t: &glium_app::timer::Timer, ctx: &mut glium_app::context::Context) {
         let delta = t.delta();
-        let time = t.absolute_time();
-
-        // Runs some code only once every self.period seconds
-        let modulus = time % self.period;
-        if modulus < self.last_mod {
-            match &self.state.server {
-                Some(serv) => {
-                    // Send player position update packets
-                    if serv.get_player().id != 0 {
-                        serv.send_packet(encode(PacketType::PlayClientPlayerPositionAndRotation(
-                            PlayClientPlayerPositionAndRotationSpec {
-                                feet_location: EntityLocation {
-                                    position: types::Vec3 {
-                                        x: serv.get_player().get_position().x as f64,
-                                        y: serv.get_player().get_position().y as f64,
-                                        z: serv.get_player().get_position().z as f64,
-                                    },
-                                    rotation: types::EntityRotation {
-                                        yaw: serv.get_player().get_orientation().get_yaw(),
-                                        pitch: serv.get_player().get_orientation().get_pitch(),
-                                    },
-                                },
-                                on_ground: true,
-                            },
-                        )));
-                    }
-                }
-                None => {}
+
+        // Nothing else can run until the resources have loaded
+        if let Some(loader) = &mut self.loader {
+            loader.poll();
+            if loader.is_done() {
+                self.state.rend.load_textures(&ctx.dis);
+                self.loader = None;
+            } else {
+                let mut target = ctx.dis.draw();
+                target.clear_color(0.0, 0.0, 0.0, 1.0);
+                let _repaint = ctx.gui.run(&ctx.dis, |gui_ctx| {
+                    gui::loading_screen::render(gui_ctx, loader);
+                });
+                ctx.gui.paint(&ctx.dis, &mut target);
+                target.finish().unwrap();
+
+                let settings = &self.state.settings;
+                self.frame_limiter
+                    .wait(frame_cap(&ctx.dis, settings.max_fps, settings.vsync));
+                return;
+            }
+        }
+
+        let screenshot = self.state.settings.key_bindings.get(Action::Screenshot);
+        if ctx.keyboard.pressed_this_frame(&screenshot) {
+            match screenshot::take_screenshot(&ctx.dis, &self.state.settings.screenshot_dir) {
+                Ok(path) => info!("Saved screenshot to {}", path.display()),
+                Err(e) => error!("Failed to save screenshot: {:?}", e),
             }
         }
-        self.last_mod = modulus;
 
         // Runs some code while the server is valid
         match &mut self.state.server {
             Some(serv) => {
                 // Update camera
-                self.state
-                    .rend
-                    .cam
-                    .set_pos(*serv.get_player().get_position());
-                self.state.rend.cam.translate(Vec3::new(0.0, 1.7, 0.0));
+                self.state.rend.cam.set_pos(serv.get_camera_position());
                 self.state
                     .rend
                     .cam
                     .set_rot(serv.get_player().get_orientation().get_rotations() * -1.0);
 
                 serv.update(ctx, delta, &mut self.state.settings);
+
+                #[cfg(feature = "audio")]
+                if let Some(audio) = &self.state.audio {
+                    for sound in serv.get_sound_events() {
+                        audio.play(
+                            sound,
+                            serv.get_camera_position(),
+                            self.state.settings.master_volume,
+                        );
+                    }
+                }
             }
             None => {
                 let State {
@@ -169,15 +168,25 @@ impl Application for Client {
                     ..
                 } = &mut self.state;
                 outstanding_server_pings.retain(|k, v| {
-                    match v.network.recv.try_recv() {
-                        Ok(NetworkCommand::ReceiveStatus(status)) => {
-                            server_pings.insert(k.clone(), status);
-                            return false;
-                        }
-                        Err(TryRecvError::Disconnected) => {
-                            return false;
+                    for event in v.poll_events() {
+                        match event {
+                            ServerEvent::Status(status) => {
+                                server_pings.insert(k.clone(), Ok(status));
+                                return false;
+                            }
+                            ServerEvent::ConnectionLost(e) => {
+                                server_pings.insert(k.clone(), Err(e.to_string()));
+                                return false;
+                            }
+                            ServerEvent::ConnectFailed(e) => {
+                                server_pings.insert(k.clone(), Err(e.to_string()));
+                                return false;
+                            }
+                            ServerEvent::Closed => return false,
+                            ServerEvent::Packet(_)
+                            | ServerEvent::PluginMessage { .. }
+                            | ServerEvent::ConnectionState(_) => {}
                         }
-                        _ => {}
                     }
 
                     true
@@ -201,10 +210,19 @@ impl Application for Client {
         if let Some(s) = &self.state.server {
             self.state
                 .rend
-                .render_server(&mut target, s, &self.state.settings);
+                .render_server(dis, &mut target, s, &self.state.settings);
         }
 
-        // GUI
+        // GUI, the scale is applied before running it so input and drawing both use it
+        let (width, height) = dis.get_framebuffer_dimensions();
+        let scale = self
+            .state
+            .settings
+            .gui_scale
+            .pixels_per_point(width, height);
+        if gui.egui_ctx.pixels_per_point() != scale {
+            gui.egui_ctx.set_pixels_per_point(scale);
+        }
         let _repaint = gui.run(dis, |gui_ctx| {
             gui::render(gui_ctx, self, t);
 
@@ -236,11 +254,21 @@ impl Application for Client {
 
         target.finish().unwrap();
 
+        let settings = &self.state.settings;
+        self.frame_limiter
+            .wait(frame_cap(&ctx.dis, settings.max_fps, settings.vsync));
+
         // Check for server disconnect
         if let Some(serv) = &mut self.state.server {
             if serv.server_disconnect {
-                self.window_manager
-                    .push(gui::disconnect_window(serv.disconnect_reason.clone()));
+                // Only offer to reconnect to servers that were successfully joined
+                let reconnect = serv
+                    .is_logged_in()
+                    .then(|| serv.get_network_destination().to_string());
+                self.window_manager.push(gui::disconnect_window(
+                    serv.disconnect_reason.clone(),
+                    reconnect,
+                ));
                 self.state.server = None;
             } else if serv.client_disconnect {
                 self.state.server = None;
@@ -248,8 +276,8 @@ impl Application for Client {
         }
     }
 
-    fn close(&mut self, ctx: &Context) {
-        match self.state.settings.save("settings.json") {
+    fn close(&mut self, _ctx: &Context) {
+        match self.state.settings.save(SETTINGS_FILE) {
             Ok(_) => {
                 info!("Saved settings!");
             }
@@ -269,7 +297,7 @@ impl Application for Client {
         debug!("Closing App");
     }
 
-    fn handle_event(&mut self, ctx: &mut Context, event: &Event<()>) {
+    fn handle_event(&mut self, _ctx: &mut Context, event: &Event<()>) {
         match event {
             Event::WindowEvent {
                 window_id: _,
@@ -299,9 +327,9 @@ impl Client {
             state: State::new(&ctx.dis),
 
             window_manager: PersistentWindowManager::new(),
+            loader: None,
 
-            period: 0.05,
-            last_mod: 0.0,
+            frame_limiter: FrameLimiter::new(),
         }
     }
 }
@@ -1,24 +1,45 @@
-use log::debug;
-use log::{error, info, warn};
+use log::{debug, error, info, warn};
 use mcproto_rs::protocol::{HasPacketBody, HasPacketId, Id, PacketErr, RawPacket};
 use mcproto_rs::types::{self, BaseComponent, BytesSerializer, TextComponent, VarInt};
 use mcproto_rs::{protocol, v1_16_3};
 use mcproto_rs::{status, v1_16_3::*, Serializer};
-use miniz_oxide::{deflate::compress_to_vec_zlib, inflate::decompress_to_vec_zlib};
+use miniz_oxide::{deflate::compress_to_vec_zlib, inflate::decompress_to_vec_zlib_with_limit};
 
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt::Debug;
 use std::io::{self, Cursor, ErrorKind};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use std::{
     io::{Error, Read, Write},
-    net::TcpStream,
+    net::{TcpStream, ToSocketAddrs},
     sync::mpsc::{self, Receiver, Sender},
     thread,
 };
 
+use crate::auth::{self, Profile};
+use crate::chat::{component_spans, TextSpan};
+
+use self::{address::ServerAddress, version::ProtocolVersion};
 use crate::server::*;
 
+pub mod address;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+pub mod version;
+
 pub const PROTOCOL: i32 = 753;
+// How long to wait for a server to respond to a status request
+const STATUS_TIMEOUT: Duration = Duration::from_secs(5);
+// How long to wait for a keep alive before warning that the connection may have been lost
+const KEEP_ALIVE_WARNING: Duration = Duration::from_secs(20);
+const MAX_CHAT_LENGTH: usize = 256;
+/// Channel that the client and server tell each other their brand on
+pub const BRAND_CHANNEL: &str = "minecraft:brand";
+/// Brand the client tells servers it is
+pub const CLIENT_BRAND: &str = "mince-raft";
+// How long the network thread sleeps when there are no packets or messages to handle
+const IDLE_SLEEP: Duration = Duration::from_millis(1);
 pub type PacketType = v1_16_3::Packet753;
 pub type RawPacketType<'a> = v1_16_3::RawPacket753<'a>;
 
@@ -30,76 +51,295 @@ pub struct NetworkManager {
     compress: bool,
     threshold: usize,
 
+    #[cfg(feature = "encryption")]
+    cipher: Option<encryption::Cipher>,
+    profile: Option<Profile>,
+    // Address of the server, sent in the handshake
+    address: ServerAddress,
+    // How long to wait for the server to answer while connecting and logging in
+    timeout: Duration,
+    // When the last keep alive was received, None until the first one arrives
+    last_keep_alive: Option<Instant>,
+    keep_alive_warned: bool,
+
     state: protocol::State,
+    // Handlers for login plugin requests by channel
+    login_plugins: HashMap<String, LoginPluginHandler>,
     pub count: u32,
 }
 
+/// Handles the data of a login plugin request on a channel, returning the data to respond with or
+/// None if the request wasn't understood
+pub type LoginPluginHandler = Box<dyn Fn(&[u8]) -> Option<Vec<u8>> + Send>;
+
+/// Reads from the TcpStream of a NetworkManager, decrypting the incoming data once encryption
+/// has been enabled
+struct StreamReader<'a> {
+    stream: &'a mut TcpStream,
+    #[cfg(feature = "encryption")]
+    cipher: Option<&'a mut encryption::Cipher>,
+}
+
+impl Read for StreamReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.stream.read(buf)?;
+        #[cfg(feature = "encryption")]
+        if let Some(cipher) = &mut self.cipher {
+            cipher.decrypt(&mut buf[..len]);
+        }
+        Ok(len)
+    }
+}
+
+/// Information about a server from a server list ping
 #[derive(Debug)]
 pub struct ServerStatus {
+    /// PNG encoded server icon
     pub icon: Option<Vec<u8>>,
     pub motd: String,
+    pub motd_spans: Vec<TextSpan>,
     pub version: String,
+    pub protocol: i32,
     pub num_players: u32,
     pub max_players: u32,
     pub online_players: Vec<String>,
+    /// Time in milliseconds taken for the server to respond
     pub ping: u32,
 }
 
+impl ServerStatus {
+    fn from_spec(spec: status::StatusSpec, ping: u32) -> ServerStatus {
+        let motd_spans = component_spans(&spec.description);
+        ServerStatus {
+            icon: spec.favicon.map(|f| f.data),
+            motd: motd_spans.iter().map(|s| s.text.as_str()).collect(),
+            motd_spans,
+            version: spec
+                .version
+                .as_ref()
+                .map(|v| v.name.clone())
+                .unwrap_or_default(),
+            protocol: spec.version.as_ref().map(|v| v.protocol).unwrap_or(-1),
+            num_players: spec.players.online.max(0) as u32,
+            max_players: spec.players.max.max(0) as u32,
+            online_players: spec.players.sample.into_iter().map(|p| p.name).collect(),
+            ping,
+        }
+    }
+
+    /// Returns true if the server speaks the same protocol version as this client
+    pub fn is_compatible(&self) -> bool {
+        ProtocolVersion::check(self.protocol, &self.version).is_ok()
+    }
+}
+
+/// How to retry connecting to a server, waiting twice as long after each failed attempt
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Returns how long to wait after the provided number of failed attempts
+    pub fn delay(&self, failed_attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(failed_attempts.saturating_sub(1));
+        self.base_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+}
+
+/// Progress of connecting to a server
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionState {
+    /// Trying to connect, counting attempts from 1
+    Connecting(u32),
+    /// Waiting to try again after a failed attempt
+    Retrying(Duration),
+    Connected,
+    /// Every attempt failed, followed by the error of the last attempt
+    Failed,
+}
+
+/// Why a connection to a server couldn't be made
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectError {
+    /// The server's address couldn't be looked up
+    DnsFailure(String),
+    /// The address entered isn't a valid address, such as a port that isn't a number
+    InvalidAddress(String),
+    /// The server didn't answer within the connect timeout
+    Timeout,
+    /// Nothing is listening on the server's port
+    Refused,
+    /// The server is on a version the client can't talk to
+    ProtocolMismatch {
+        server: String,
+        client: String,
+    },
+    Other(String),
+}
+
+impl ConnectError {
+    /// Sorts an error from opening a connection into the reasons the GUI shows differently
+    pub fn from_io(e: &Error) -> ConnectError {
+        match e.kind() {
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => ConnectError::Timeout,
+            ErrorKind::ConnectionRefused => ConnectError::Refused,
+            _ => ConnectError::Other(e.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectError::DnsFailure(e) => write!(f, "Couldn't find the server: {}", e),
+            ConnectError::InvalidAddress(e) => write!(f, "Invalid server address: {}", e),
+            ConnectError::Timeout => write!(f, "Connection timed out"),
+            ConnectError::Refused => write!(f, "Connection refused, is the server running?"),
+            ConnectError::ProtocolMismatch { server, client } => write!(
+                f,
+                "Server is on version {}, client supports {}",
+                server, client
+            ),
+            ConnectError::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConnectError {}
+
 // Types of Messages that can be sent
 #[derive(Debug)]
 pub enum NetworkCommand {
     Ok,
     Error(Error),
+    // Opening the connection failed, sent instead of an error
+    ConnectFailed(ConnectError),
     Disconnect,
-    // Login(protocol, port, name)
-    Login(i32, u16, String),
+    // Login(protocol, name, profile), the profile is required to join online mode servers
+    Login(i32, String, Option<Profile>),
 
     SendPacket(Vec<u8>),
     ReceivePacket(PacketType),
+    // Progress of connecting to the server, failures are sent as an Error
+    ConnectionState(ConnectionState),
+    // Responds to a keep alive from the server with the same id
+    KeepAlive(i64),
+    // Asks the server to respawn the player or send statistics
+    ClientStatus(ClientStatusAction),
+    // Sends a chat message or command
+    SendChat(String),
+    // Sends where the player is and where they are looking
+    PlayerPositionAndLook {
+        x: f64,
+        y: f64,
+        z: f64,
+        yaw: f32,
+        pitch: f32,
+        on_ground: bool,
+    },
+    // Confirms a teleport from the server with the same id
+    TeleportConfirm(i32),
+    // Tells the server the client's settings, which it needs before it fully spawns the player
+    ClientSettings {
+        locale: String,
+        view_distance: i8,
+        chat_mode: ClientChatMode,
+        chat_colors: bool,
+        displayed_skin_parts: ClientDisplayedSkinParts,
+        main_hand: ClientMainHand,
+    },
+    // Tells the server which hotbar slot is selected, from 0 to 8
+    HeldItemChange(i16),
+    // Tells the server the player started, stopped or finished breaking a block
+    PlayerDigging {
+        status: PlayerDiggingStatus,
+        position: types::IntPosition,
+        face: DiggingFace,
+    },
+    // Uses the held item on a face of a block, which places it if it is a block. The cursor is
+    // where on the block the face was clicked, from 0 to 1 on each axis
+    UseItemOn {
+        hand: Hand,
+        position: types::IntPosition,
+        face: DiggingFace,
+        cursor_x: f32,
+        cursor_y: f32,
+        cursor_z: f32,
+        inside_block: bool,
+    },
+    // Sends data on a plugin channel, channels must be namespaced like `minecraft:brand`
+    PluginMessage {
+        channel: String,
+        data: Vec<u8>,
+    },
 
     RequestStatus,
-    ReceiveStatus(status::StatusSpec),
+    ReceiveStatus(ServerStatus),
 
     Spawn,
 }
 
 impl NetworkManager {
-    /// Attempts to connect to a server, returning a NetworkChannel to communicate with the NetworkManager and receive packets from
+    /// Opens a connection to a server on a new thread, which reports its progress and any failure
+    /// over the returned server's channel
     ///
     /// # Arguments
     ///
-    /// * `destination` - The target server to connect to
+    /// * `address` - Address of the server as the player entered it, which is resolved on the
+    ///     network thread as looking up SRV records can take a while
+    /// * `retry` - How to retry failed attempts, None to only try once
+    /// * `timeout` - How long each attempt waits for the server to answer
     ///
     /// # Returns
     ///
-    /// * `Result<Server, Error>` - Ok holding a Server which can communicate with the new network thread
-    ///     Or errors if the TcpStream could not be established.
-    ///
-    pub fn connect(destination: &str) -> Result<Server, Error> {
+    /// * `Result<Server, Error>` - Ok holding a Server whose channel receives the connection state,
+    ///     or errors only if the network thread could not be spawned
+    pub fn connect(
+        address: &str,
+        retry: Option<RetryPolicy>,
+        timeout: Duration,
+    ) -> Result<Server, Error> {
         let (tx, ri) = mpsc::channel::<NetworkCommand>();
         let (ti, rx) = mpsc::channel::<NetworkCommand>();
 
-        let mut dest: String = destination.to_string();
-
-        // Check for port included in address
-        if !dest.contains(':') {
-            debug!("Server address didn't contain port, appending :25565");
-            dest.push_str(":25565");
-        }
+        let destination = address.to_string();
 
         //Start new thread to be the network manager
         thread::Builder::new()
             .name("NetworkManager".to_string())
             .spawn(move || {
-                match TcpStream::connect(dest) {
-                    Ok(stream) => {
+                let connected = ServerAddress::resolve(&destination)
+                    .map_err(|e| ConnectError::InvalidAddress(e.to_string()))
+                    .and_then(|address| {
+                        connect_with_retry(&address.to_string(), retry, timeout, &ti)
+                            .map(|stream| (address, stream))
+                    });
+                match connected {
+                    Ok((address, stream)) => {
+                        ti.send(NetworkCommand::ConnectionState(ConnectionState::Connected))
+                            .ok();
+
                         let mut nm = Box::new(NetworkManager {
                             stream,
                             compress: false,
                             threshold: 0,
+                            #[cfg(feature = "encryption")]
+                            cipher: None,
+                            profile: None,
+                            address,
+                            timeout,
+                            last_keep_alive: None,
+                            keep_alive_warned: false,
                             close: false,
                             channel: NetworkChannel { send: ti, recv: ri },
-                            state: protocol::State::Status,
+                            state: protocol::State::Handshaking,
+                            login_plugins: HashMap::new(),
                             count: 0,
                         });
 
@@ -109,63 +349,100 @@ impl NetworkManager {
 
                         // Loop until stopped
                         while !nm.close {
-                            nm.update();
+                            // Avoid spinning while there's nothing to do
+                            if !nm.update() {
+                                thread::sleep(IDLE_SLEEP);
+                            }
                         }
                         info!("Closing network connection.");
 
-                        nm.stream
-                            .shutdown(std::net::Shutdown::Both)
-                            .expect("Couldn't shutdown TCPStream");
+                        // The server may have already closed the connection
+                        if let Err(e) = nm.stream.shutdown(std::net::Shutdown::Both) {
+                            debug!("Couldn't shutdown TcpStream: {}", e);
+                        }
                     }
                     Err(e) => {
-                        error!("Could not connect to server.");
-                        ti.send(NetworkCommand::Error(e))
-                            .expect("NetworkChannel Receiver cannot be reached");
+                        error!("Could not connect to server: {}", e);
+                        // The receiver is gone if the connection was cancelled
+                        ti.send(NetworkCommand::ConnectionState(ConnectionState::Failed))
+                            .ok();
+                        ti.send(NetworkCommand::ConnectFailed(e)).ok();
                     }
                 }
             })?;
 
         Ok(Server::new(
-            destination.to_string(),
+            address.to_string(),
             NetworkChannel { send: tx, recv: rx },
         ))
     }
 
     /// Manages any incoming packets or messages from other threads
-    fn update(&mut self) {
+    fn update(&mut self) -> bool {
+        let mut busy = false;
+
         // Handles all queued messages from other threads
         loop {
             match self.channel.recv.try_recv() {
-                Ok(msg) => self.handle_message(msg),
+                Ok(msg) => {
+                    busy = true;
+                    self.handle_message(msg);
+                }
                 Err(e) => match e {
                     mpsc::TryRecvError::Empty => break,
                     mpsc::TryRecvError::Disconnected => {
                         log::info!("Network channel disconnected, stopping network manager.");
                         self.close = true;
-                        return;
+                        return true;
                     }
                 },
             }
         }
 
+        // Servers send keep alives every 15 seconds and disconnect clients after 30 seconds
+        if let Some(last) = self.last_keep_alive {
+            if !self.keep_alive_warned && last.elapsed() > KEEP_ALIVE_WARNING {
+                warn!(
+                    "Haven't received a keep alive from the server in {} seconds",
+                    last.elapsed().as_secs()
+                );
+                self.keep_alive_warned = true;
+            }
+        }
+
+        // Nothing is sent by the server until it knows which state the handshake is switching to,
+        // and the status and login states read their own packets until they're done
+        if self.state != protocol::State::Play {
+            return busy;
+        }
+
         // Handles incoming packets
         while !self.close {
             match self.next_packet() {
-                Ok(packet_result) => match packet_result {
-                    Ok(packet) => self.handle_packet(packet),
-                    Err(e) => {
-                        log::error!("Couldn't deserialize packet: {}", e);
-                    }
-                },
-                Err(e) => {
-                    if e.kind() == ErrorKind::WouldBlock {
-                        return;
-                    } else {
-                        panic!("Error handling packet: {:?}", e);
+                Ok(packet_result) => {
+                    busy = true;
+                    match packet_result {
+                        Ok(packet) => self.handle_packet(packet),
+                        Err(e) => {
+                            log::error!("Couldn't deserialize packet: {}", e);
+                        }
                     }
                 }
+                Err(e) => match e.kind() {
+                    ErrorKind::WouldBlock => return busy,
+                    // The whole packet has already been read so the stream is still in sync
+                    ErrorKind::InvalidData => {
+                        log::error!("Dropping invalid packet: {}", e);
+                    }
+                    _ => {
+                        error!("Lost connection to server: {:?}", e);
+                        self.send_message(NetworkCommand::Error(e));
+                        self.close = true;
+                    }
+                },
             }
         }
+        busy
     }
 
     /// Attempts to get the next packet in the TcpStream
@@ -178,7 +455,10 @@ impl NetworkManager {
         let mut check = [0u8];
         match self.stream.peek(&mut check) {
             Ok(0) => {
-                panic!("TcpStream ded???");
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "Server closed the connection",
+                ));
             }
             Err(e) => {
                 return Err(e);
@@ -189,10 +469,17 @@ impl NetworkManager {
         self.stream
             .set_nonblocking(false)
             .expect("Failed to set TcpStream to blocking mode");
-        let len = read_varint(&mut self.stream)?;
+        // A bad length means the stream can't be read any further
+        let len = read_varint(&mut self.reader()).map_err(|e| match e {
+            VarIntError::Io(e) => e,
+            VarIntError::TooLong => Error::new(ErrorKind::Other, "Packet length is too long"),
+        })?;
+        if len < 0 {
+            return Err(Error::new(ErrorKind::Other, "Packet length is negative"));
+        }
 
         let mut buf = vec![0u8; len as usize];
-        self.stream.read_exact(&mut buf)?;
+        self.reader().read_exact(&mut buf)?;
         self.stream
             .set_nonblocking(true)
             .expect("Failed to set TcpStream to nonblocking mode");
@@ -201,39 +488,16 @@ impl NetworkManager {
             let mut cur = Cursor::new(&buf);
             let data_len = read_varint(&mut cur)?;
 
+            // Packets below the compression threshold are sent uncompressed with a data length of 0
             if data_len == 0 {
                 let id = read_varint(&mut cur)?;
-                let id = Id {
-                    id,
-                    state: self.state,
-                    direction: protocol::PacketDirection::ClientBound,
-                };
-                return Ok(
-                    match RawPacketType::create(id, &buf[cur.position() as usize..]) {
-                        Ok(raw_packet) => raw_packet.deserialize(),
-                        Err(e) => Err(e),
-                    },
-                );
+                return Ok(self.decode_packet(id, cur.remaining_slice()));
             }
 
-            match decompress_to_vec_zlib(&buf[cur.position() as usize..]) {
-                Ok(mut uncompressed) => {
-                    let mut cur = Cursor::new(&mut uncompressed);
-                    let id = read_varint(&mut cur)?;
-                    let id = Id {
-                        id,
-                        state: self.state,
-                        direction: protocol::PacketDirection::ClientBound,
-                    };
-                    return Ok(match RawPacketType::create(id, cur.remaining_slice()) {
-                        Ok(raw_packet) => raw_packet.deserialize(),
-                        Err(e) => Err(e),
-                    });
-                }
-                Err(e) => {
-                    todo!("Properly decompression error handling");
-                }
-            }
+            let uncompressed = decompress_packet(cur.remaining_slice(), data_len as usize)?;
+            let mut cur = Cursor::new(&uncompressed);
+            let id = read_varint(&mut cur)?;
+            return Ok(self.decode_packet(id, cur.remaining_slice()));
         }
 
         let mut cur = Cursor::new(&mut buf);
@@ -241,15 +505,42 @@ impl NetworkManager {
         let contents = cur.remaining_slice();
         log::debug!("Got packet with ID: {:#x}", id);
         log::debug!("Packet length: {}", contents.len());
+        Ok(self.decode_packet(id, contents))
+    }
+
+    /// Returns a reader over the incoming data from the server
+    fn reader(&mut self) -> StreamReader {
+        StreamReader {
+            stream: &mut self.stream,
+            #[cfg(feature = "encryption")]
+            cipher: self.cipher.as_mut(),
+        }
+    }
+
+    /// Moves the connection to another state, which changes how the ids of incoming packets are
+    /// read
+    fn set_state(&mut self, next: protocol::State) {
+        if !can_transition(self.state, next) {
+            warn!(
+                "Unexpected connection state change from {:?} to {:?}",
+                self.state, next
+            );
+        }
+        debug!("Connection state changed to {:?}", next);
+        self.state = next;
+    }
+
+    /// Deserializes the contents of an uncompressed packet with the provided ID
+    fn decode_packet(&self, id: i32, contents: &[u8]) -> Result<PacketType, PacketErr> {
         let id = Id {
             id,
             state: self.state,
             direction: protocol::PacketDirection::ClientBound,
         };
-        return Ok(match RawPacketType::create(id, contents) {
+        match RawPacketType::create(id, contents) {
             Ok(raw_packet) => raw_packet.deserialize(),
             Err(e) => Err(e),
-        });
+        }
     }
 
     /// Attempts to login to the server
@@ -257,161 +548,210 @@ impl NetworkManager {
     /// # Returns
     ///
     /// * `Some(())` if it successfully logs in, `None` if it fails
-    fn login(&mut self, protocol: i32, port: u16, name: String) -> Option<()> {
-        use std::net::SocketAddr;
-
-        // Extracts local address from TcpStream
-        let local_addr = match self.stream.local_addr() {
-            Err(e) => {
-                panic!("Failed to get local adress from TcpStream: {}", e);
-            }
-            Ok(addr) => match addr {
-                SocketAddr::V4(local) => local.ip().to_string(),
-                SocketAddr::V6(local) => local.ip().to_string(),
-            },
-        };
-
+    fn login(&mut self, protocol: i32, name: String, profile: Option<Profile>) -> Option<()> {
         // Construct and send handshake and login packets
         let handshake = HandshakeSpec {
             version: VarInt(protocol),
-            server_address: local_addr,
-            server_port: port,
+            server_address: self.address.host.clone(),
+            server_port: self.address.port,
             next_state: HandshakeNextState::Login,
         };
 
         let login = LoginStartSpec { name };
+        self.profile = profile;
 
-        self.send_packet(&encode(PacketType::Handshake(handshake)))
-            .expect("Failed to send handshake");
-        self.state = protocol::State::Login;
-        self.send_packet(&encode(PacketType::LoginStart(login)))
-            .expect("Failed to send login request");
+        let sent = self
+            .send_packet(&encode(PacketType::Handshake(handshake)))
+            .and_then(|_| {
+                self.set_state(protocol::State::Login);
+                self.send_packet(&encode(PacketType::LoginStart(login)))
+            });
+        if let Err(e) = sent {
+            self.lose_connection(format!("Failed to send login request: {}", e));
+            return None;
+        }
 
-        // Handle all incoming packets until success or failure
+        // Handle all incoming packets until success or failure, giving up if the server stops
+        // answering
+        let mut last_packet = Instant::now();
         loop {
             match self.next_packet() {
-                Ok(packet) => {
-                    match packet {
-                        Ok(packet) => {
-                            match packet {
-                                // Please no
-                                PacketType::LoginEncryptionRequest(_) => {
-                                    panic!("I ain't implemented this shit yet");
-                                }
-                                PacketType::LoginSetCompression(pack) => {
-                                    if pack.threshold.0 <= 0 {
-                                        self.compress = false;
-                                        info!("Disabled Compression");
-                                    } else {
-                                        self.compress = true;
-                                        self.threshold = pack.threshold.0 as usize;
-                                        info!("Set compression: {}", pack.threshold.0);
-                                    }
-                                }
-                                PacketType::LoginDisconnect(_) => {
-                                    self.send_message(NetworkCommand::ReceivePacket(packet));
-                                    self.close = true;
+                Ok(packet) => match packet {
+                    Ok(packet) => {
+                        last_packet = Instant::now();
+                        match packet {
+                            PacketType::LoginEncryptionRequest(pack) => {
+                                if let Err(e) = self.enable_encryption(pack) {
+                                    self.lose_connection(format!(
+                                        "Failed to enable encryption: {}",
+                                        e
+                                    ));
                                     return None;
                                 }
-                                PacketType::LoginPluginRequest(_) => {
-                                    panic!("I don't want to think about LoginPlugin");
+                            }
+                            PacketType::LoginSetCompression(pack) => {
+                                if pack.threshold.0 <= 0 {
+                                    self.compress = false;
+                                    info!("Disabled Compression");
+                                } else {
+                                    self.compress = true;
+                                    self.threshold = pack.threshold.0 as usize;
+                                    info!("Set compression: {}", pack.threshold.0);
                                 }
-                                PacketType::LoginSuccess(_) => {
+                            }
+                            PacketType::LoginDisconnect(_) => {
+                                self.send_message(NetworkCommand::ReceivePacket(packet));
+                                self.close = true;
+                                return None;
+                            }
+                            PacketType::LoginPluginRequest(pack) => {
+                                self.respond_to_login_plugin(pack);
+                            }
+                            PacketType::LoginSuccess(_) => {
+                                if self.profile.is_none() {
                                     warn!("Connecting to server with no authentication!");
+                                }
 
-                                    self.state = protocol::State::Play;
-                                    self.send_message(NetworkCommand::ReceivePacket(packet));
+                                self.set_state(protocol::State::Play);
+                                self.send_message(NetworkCommand::ReceivePacket(packet));
 
-                                    return Some(());
-                                }
-                                _ => {
-                                    warn!("Got unexpected packet during login: {:?}", packet);
-                                }
-                            };
-                        }
-                        Err(e) => {
-                            panic!("Error decoding packet: {}", e);
-                        }
+                                return Some(());
+                            }
+                            _ => {
+                                warn!("Got unexpected packet during login: {:?}", packet);
+                            }
+                        };
                     }
-                }
+                    Err(e) => {
+                        last_packet = Instant::now();
+                        error!("Error decoding packet during login: {}", e);
+                    }
+                },
                 Err(e) => {
                     if e.kind() == ErrorKind::WouldBlock {
+                        if last_packet.elapsed() > self.timeout {
+                            self.lose_connection(String::from("Timed out while logging in"));
+                            return None;
+                        }
+                        thread::sleep(IDLE_SLEEP);
                         continue;
                     } else {
-                        panic!("Error reading packet: {:?}", e);
+                        error!("Error reading packet during login: {:?}", e);
+                        self.send_message(NetworkCommand::Error(e));
+                        self.close = true;
+                        return None;
                     }
                 }
             }
         }
     }
 
-    fn status(&mut self) -> Option<status::StatusSpec> {
-        use std::net::SocketAddr;
+    /// Registers a handler for login plugin requests on a channel, such as `velocity:player_info`
+    #[allow(dead_code)]
+    pub fn register_login_plugin(&mut self, channel: &str, handler: LoginPluginHandler) {
+        self.login_plugins.insert(channel.to_string(), handler);
+    }
 
-        // Extracts local address from TcpStream
-        let local_addr = match self.stream.local_addr() {
-            Err(e) => {
-                panic!("Failed to get local adress from TcpStream: {}", e);
+    /// Responds to a login plugin request with the handler for its channel. Requests on channels
+    /// without a handler are answered as not understood, which the server waits for before
+    /// continuing the login
+    fn respond_to_login_plugin(&mut self, request: LoginPluginRequestSpec) {
+        let data = match self.login_plugins.get(&request.channel) {
+            Some(handler) => handler(&request.data.data),
+            None => {
+                debug!("Ignoring login plugin request on {}", request.channel);
+                None
             }
-            Ok(addr) => match addr {
-                SocketAddr::V4(local) => local.ip().to_string(),
-                SocketAddr::V6(local) => local.ip().to_string(),
-            },
         };
 
-        // Construct and send handshake and login packets
+        let response = login_plugin_response(request.message_id.0, data);
+        if let Err(e) = self.send_packet(&encode(PacketType::LoginPluginResponse(response))) {
+            error!("Failed to respond to login plugin request: {}", e);
+        }
+    }
+
+    /// Responds to an encryption request from the server and encrypts the connection from then on
+    #[cfg(feature = "encryption")]
+    fn enable_encryption(
+        &mut self,
+        request: LoginEncryptionRequestSpec,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let shared_secret = encryption::generate_shared_secret();
+
+        // Online mode servers check with the session server that we have joined
+        match &self.profile {
+            Some(profile) => {
+                let hash =
+                    auth::server_hash(&request.server_id, &shared_secret, &request.public_key);
+                auth::join_server(&auth::UreqClient, profile, &hash)?;
+            }
+            None => warn!("Server requested encryption but we aren't logged in"),
+        }
+
+        let response = LoginEncryptionResponseSpec {
+            shared_secret: encryption::rsa_encrypt(&request.public_key, &shared_secret)?.into(),
+            verify_token: encryption::rsa_encrypt(&request.public_key, &request.verify_token)?
+                .into(),
+        };
+        self.send_packet(&encode(PacketType::LoginEncryptionResponse(response)))?;
+
+        // Everything after the encryption response is encrypted
+        self.cipher = Some(encryption::Cipher::new(&shared_secret));
+        info!("Enabled encryption");
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    fn enable_encryption(
+        &mut self,
+        _request: LoginEncryptionRequestSpec,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        Err(
+            "Server requested encryption but this client was built without the encryption feature"
+                .into(),
+        )
+    }
+
+    /// Performs a server list ping, requesting the status of the server
+    fn status(&mut self) -> Result<ServerStatus, Box<dyn std::error::Error>> {
+        // Construct and send handshake and status packets
         let handshake = HandshakeSpec {
             version: VarInt(PROTOCOL),
-            server_address: local_addr,
-            server_port: 0,
+            server_address: self.address.host.clone(),
+            server_port: self.address.port,
             next_state: HandshakeNextState::Status,
         };
 
         let now = Instant::now();
-        self.send_packet(&encode(PacketType::Handshake(handshake)))
-            .expect("Failed to send handshake");
-        self.send_packet(&encode(PacketType::StatusRequest(StatusRequestSpec {})))
-            .expect("Failed to send status request");
+        self.send_packet(&encode(PacketType::Handshake(handshake)))?;
+        self.set_state(protocol::State::Status);
+        self.send_packet(&encode(PacketType::StatusRequest(StatusRequestSpec {})))?;
         self.send_packet(&encode(PacketType::StatusPing(StatusPingSpec {
             payload: 0,
-        })))
-        .expect("Failed to send Status Ping");
-
-        let ping;
-        let mut status: status::StatusSpec;
+        })))?;
 
         loop {
+            if now.elapsed() > STATUS_TIMEOUT {
+                return Err("Server didn't respond to status request".into());
+            }
+
             match self.next_packet() {
-                Ok(pack) => match pack {
-                    Ok(pack) => match pack {
-                        PacketType::StatusResponse(pack) => {
-                            ping = (Instant::now() - now).as_millis() as u32;
-                            status = pack.response;
-                            break;
-                        }
-                        _ => {
-                            warn!(
-                                "Got unexpected packet waiting for status response: {:?}",
-                                pack
-                            );
-                        }
-                    },
-                    Err(e) => {
-                        panic!("Error decoding packet: {}", e);
-                    }
-                },
-                Err(e) => {
-                    if e.kind() == ErrorKind::WouldBlock {
-                        continue;
-                    } else {
-                        error!("Couldn't get response from server status request: {:?}", e);
-                        return None;
-                    }
+                Ok(Ok(PacketType::StatusResponse(pack))) => {
+                    let ping = now.elapsed().as_millis() as u32;
+                    return Ok(ServerStatus::from_spec(pack.response, ping));
+                }
+                Ok(Ok(pack)) => {
+                    warn!(
+                        "Got unexpected packet waiting for status response: {:?}",
+                        pack
+                    );
                 }
+                Ok(Err(e)) => return Err(format!("Error decoding status response: {}", e).into()),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(IDLE_SLEEP),
+                Err(e) => return Err(e.into()),
             }
         }
-
-        Some(status)
     }
 
     /// Sends a packet to the server
@@ -423,9 +763,7 @@ impl NetworkManager {
     /// * `Ok(())` if the packet is successfully sent
     /// * `Err` if it is not
     fn send_packet(&mut self, packet: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
-        let s = &mut self.stream;
-        s.set_nonblocking(false)
-            .expect("Failed to set Stream to blocking mode");
+        let mut buf = Vec::new();
 
         // Handle zlib compression
         if self.compress {
@@ -433,81 +771,185 @@ impl NetworkManager {
                 let mut data_length = Vec::new();
                 write_varint(&mut data_length, packet.len() as i32)?;
                 let compressed = compress_to_vec_zlib(packet, 0);
-                let mut packet_length = Vec::new();
-                write_varint(
-                    &mut packet_length,
-                    (data_length.len() + compressed.len()) as i32,
-                )?;
-
-                s.write_all(&packet_length)?;
-                s.write_all(&data_length)?;
-                s.write_all(&compressed)?;
+                write_varint(&mut buf, (data_length.len() + compressed.len()) as i32)?;
+
+                buf.extend_from_slice(&data_length);
+                buf.extend_from_slice(&compressed);
             } else {
-                write_varint(s, (packet.len() + 1) as i32)?;
-                s.write_all(&[0u8])?;
-                s.write_all(packet)?;
+                write_varint(&mut buf, (packet.len() + 1) as i32)?;
+                buf.push(0u8);
+                buf.extend_from_slice(packet);
             }
         } else {
-            write_varint(s, packet.len() as i32)?;
-            s.write_all(packet)?;
+            write_varint(&mut buf, packet.len() as i32)?;
+            buf.extend_from_slice(packet);
         }
 
+        #[cfg(feature = "encryption")]
+        if let Some(cipher) = &mut self.cipher {
+            cipher.encrypt(&mut buf);
+        }
+
+        let s = &mut self.stream;
+        s.set_nonblocking(false)
+            .expect("Failed to set Stream to blocking mode");
+        s.write_all(&buf)?;
         s.set_nonblocking(true)
             .expect("Failed to set TcpStream nonblocking");
         Ok(())
     }
 
-    /// Handles a message (from another thread)
+    /// Handles a message (from another thread). The connection is closed if a packet can't be
+    /// sent, as the server would be left out of sync with the client
     fn handle_message(&mut self, msg: NetworkCommand) {
-        match msg {
-            NetworkCommand::Login(protocol, port, name) => {
+        let sent = match msg {
+            NetworkCommand::Login(protocol, name, profile) => {
                 info!("Attempting to login to server");
-                self.login(protocol, port, name);
+                self.login(protocol, name, profile);
+                Ok(())
             }
             NetworkCommand::Disconnect => {
-                self.send_packet(&encode(PacketType::PlayDisconnect(PlayDisconnectSpec {
-                    reason: types::Chat::Text(TextComponent {
-                        text: String::from("Player Disconnected"),
-                        base: BaseComponent::default(),
-                    }),
-                })))
-                .expect("Failed to send packet");
+                let sent =
+                    self.send_packet(&encode(PacketType::PlayDisconnect(PlayDisconnectSpec {
+                        reason: types::Chat::Text(TextComponent {
+                            text: String::from("Player Disconnected"),
+                            base: BaseComponent::default(),
+                        }),
+                    })));
                 self.close = true;
+                if let Err(e) = sent {
+                    debug!("Couldn't send disconnect packet: {}", e);
+                }
+                Ok(())
             }
-            NetworkCommand::SendPacket(dp) => {
-                self.send_packet(&dp).expect("Failed to send packet");
+            NetworkCommand::KeepAlive(id) => self.send_packet(&encode(
+                PacketType::PlayClientKeepAlive(PlayClientKeepAliveSpec { id }),
+            )),
+            NetworkCommand::ClientStatus(action) => self.send_packet(&encode(
+                PacketType::PlayClientStatus(PlayClientStatusSpec { action }),
+            )),
+            NetworkCommand::SendChat(message) => {
+                // Servers kick clients that send messages longer than this
+                let message: String = message.chars().take(MAX_CHAT_LENGTH).collect();
+                self.send_packet(&encode(PacketType::PlayClientChatMessage(
+                    PlayClientChatMessageSpec { message },
+                )))
             }
+            NetworkCommand::PlayerPositionAndLook {
+                x,
+                y,
+                z,
+                yaw,
+                pitch,
+                on_ground,
+            } => self.send_packet(&encode(PacketType::PlayClientPlayerPositionAndRotation(
+                PlayClientPlayerPositionAndRotationSpec {
+                    on_ground,
+                    feet_location: types::EntityLocation {
+                        position: types::Vec3 { x, y, z },
+                        rotation: types::EntityRotation { yaw, pitch },
+                    },
+                },
+            ))),
+            NetworkCommand::TeleportConfirm(id) => self.send_packet(&encode(
+                PacketType::PlayTeleportConfirm(PlayTeleportConfirmSpec {
+                    teleport_id: VarInt(id),
+                }),
+            )),
+            NetworkCommand::ClientSettings {
+                locale,
+                view_distance,
+                chat_mode,
+                chat_colors,
+                displayed_skin_parts,
+                main_hand,
+            } => self.send_packet(&encode(PacketType::PlayClientSettings(
+                PlayClientSettingsSpec {
+                    locale,
+                    view_distance,
+                    chat_mode,
+                    chat_colors,
+                    displayed_skin_parts,
+                    main_hand,
+                },
+            ))),
+            NetworkCommand::HeldItemChange(slot) => self.send_packet(&encode(
+                PacketType::PlayClientHeldItemChange(PlayClientHeldItemChangeSpec { slot }),
+            )),
+            NetworkCommand::PlayerDigging {
+                status,
+                position,
+                face,
+            } => self.send_packet(&encode(PacketType::PlayPlayerDigging(
+                PlayPlayerDiggingSpec {
+                    status,
+                    location: position,
+                    face,
+                },
+            ))),
+            NetworkCommand::UseItemOn {
+                hand,
+                position,
+                face,
+                cursor_x,
+                cursor_y,
+                cursor_z,
+                inside_block,
+            } => self.send_packet(&encode(PacketType::PlayBlockPlacement(
+                PlayBlockPlacementSpec {
+                    hand,
+                    location: position,
+                    face,
+                    cursor_position: types::Vec3 {
+                        x: cursor_x,
+                        y: cursor_y,
+                        z: cursor_z,
+                    },
+                    inside_block,
+                },
+            ))),
+            NetworkCommand::PluginMessage { channel, data } => {
+                if !valid_channel(&channel) {
+                    warn!("Not sending plugin message on invalid channel: {}", channel);
+                    return;
+                }
+                self.send_packet(&encode(plugin_message(channel, data)))
+            }
+            NetworkCommand::SendPacket(dp) => self.send_packet(&dp),
             NetworkCommand::RequestStatus => {
                 match self.status() {
-                    Some(status) => {
-                        self.send_message(NetworkCommand::ReceiveStatus(status));
+                    Ok(status) => self.send_message(NetworkCommand::ReceiveStatus(status)),
+                    Err(e) => {
+                        error!("Couldn't get response from server status request: {:?}", e);
+                        self.send_message(NetworkCommand::Error(Error::new(
+                            ErrorKind::Other,
+                            e.to_string(),
+                        )));
                     }
-                    None => {}
                 }
                 self.close = true;
+                Ok(())
             }
-            _ => {}
+            _ => Ok(()),
+        };
+
+        if let Err(e) = sent {
+            self.lose_connection(format!("Failed to send packet: {}", e));
         }
     }
 
-    /// Handles an incoming packet
+    /// Handles an incoming packet in the play state, login packets are handled while logging in
     fn handle_packet(&mut self, packet: PacketType) {
         match &packet {
             PacketType::PlayServerKeepAlive(pack) => {
-                self.send_packet(&encode(PacketType::PlayClientKeepAlive(
-                    PlayClientKeepAliveSpec { id: pack.id },
-                )))
-                .expect("Failed to send heartbeat.");
+                self.last_keep_alive = Some(Instant::now());
+                self.keep_alive_warned = false;
+                self.handle_message(NetworkCommand::KeepAlive(pack.id));
             }
-            PacketType::LoginSetCompression(pack) => {
-                if pack.threshold.0 <= 0 {
-                    self.compress = false;
-                    info!("Disabled packet compression.");
-                } else {
-                    info!("Set Packet Compression: {}", pack.threshold);
-                    self.compress = true;
-                    self.threshold = pack.threshold.0 as usize;
-                }
+            // The server closes the connection after sending the reason
+            PacketType::PlayDisconnect(_) => {
+                self.send_message(NetworkCommand::ReceivePacket(packet));
+                self.close = true;
             }
             _ => {
                 self.send_message(NetworkCommand::ReceivePacket(packet));
@@ -525,55 +967,273 @@ impl NetworkManager {
                     base: types::BaseComponent::default(),
                 }),
             })))
-            .expect("Failed to send Disconnect packet");
+            .ok();
+        }
+    }
+
+    /// Tells the main thread the connection was lost and closes it
+    fn lose_connection(&mut self, reason: String) {
+        error!("Lost connection to server: {}", reason);
+        self.send_message(NetworkCommand::Error(Error::new(ErrorKind::Other, reason)));
+        self.close = true;
+    }
+}
+
+/// Creates the response to a login plugin request, which is unsuccessful with no data if the
+/// request wasn't understood
+pub fn login_plugin_response(message_id: i32, data: Option<Vec<u8>>) -> LoginPluginResponseSpec {
+    LoginPluginResponseSpec {
+        message_id: VarInt(message_id),
+        successful: data.is_some(),
+        data: types::RemainingBytes {
+            data: data.unwrap_or_default(),
+        },
+    }
+}
+
+/// Creates a serverbound plugin message packet
+pub fn plugin_message(channel: String, data: Vec<u8>) -> PacketType {
+    PacketType::PlayClientPluginMessage(PlayClientPluginMessageSpec {
+        channel,
+        data: types::RemainingBytes { data },
+    })
+}
+
+/// Returns true if a plugin channel is a valid namespaced identifier, e.g. `minecraft:brand`.
+/// Namespaces may contain lowercase letters, digits, `.`, `_` and `-`, paths may also contain `/`
+pub fn valid_channel(channel: &str) -> bool {
+    let valid_char = |c: char| matches!(c, 'a'..='z' | '0'..='9' | '.' | '_' | '-');
+
+    match channel.split_once(':') {
+        Some((namespace, path)) => {
+            !namespace.is_empty()
+                && !path.is_empty()
+                && namespace.chars().all(valid_char)
+                && path.chars().all(|c| valid_char(c) || c == '/')
         }
+        None => false,
     }
 }
 
+/// Encodes a brand as the data of a `minecraft:brand` plugin message, which is a single string
+pub fn encode_brand(brand: &str) -> Vec<u8> {
+    let mut data = Vec::with_capacity(brand.len() + 1);
+    write_varint(&mut data, brand.len() as i32).unwrap();
+    data.extend_from_slice(brand.as_bytes());
+    data
+}
+
+/// Decodes the brand from the data of a `minecraft:brand` plugin message
+pub fn decode_brand(data: &[u8]) -> Option<String> {
+    let mut cursor = Cursor::new(data);
+    let len = read_varint(&mut cursor).ok()?;
+    let start = cursor.position() as usize;
+    let bytes = data.get(start..start.checked_add(usize::try_from(len).ok()?)?)?;
+    String::from_utf8(bytes.to_vec()).ok()
+}
+
+/// Returns true if the protocol moves from one state to the other. The handshake picks either the
+/// status or login state, and logging in successfully moves to the play state
+pub fn can_transition(from: protocol::State, to: protocol::State) -> bool {
+    use protocol::State::*;
+
+    matches!(
+        (from, to),
+        (Handshaking, Status) | (Handshaking, Login) | (Login, Play)
+    )
+}
+
 // Struct to hold communication channels between network manager and other threads
 pub struct NetworkChannel {
     pub send: Sender<NetworkCommand>,
     pub recv: Receiver<NetworkCommand>,
 }
 
-pub fn read_varint<R: Read>(r: &mut R) -> io::Result<i32> {
-    const PART: u32 = 0x7F;
+/// Opens a connection to the destination, retrying with the policy if provided and reporting each
+/// attempt over the channel
+fn connect_with_retry(
+    dest: &str,
+    retry: Option<RetryPolicy>,
+    timeout: Duration,
+    channel: &Sender<NetworkCommand>,
+) -> Result<TcpStream, ConnectError> {
+    let max_attempts = retry.map(|r| r.max_attempts.max(1)).unwrap_or(1);
+
+    let mut attempt = 1;
+    loop {
+        channel
+            .send(NetworkCommand::ConnectionState(
+                ConnectionState::Connecting(attempt),
+            ))
+            .ok();
+
+        match connect_timeout(dest, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e @ ConnectError::DnsFailure(_)) => return Err(e),
+            Err(e) if attempt >= max_attempts => return Err(e),
+            Err(e) => {
+                // Can unwrap since there is only one attempt without a policy
+                let delay = retry.unwrap().delay(attempt);
+                warn!(
+                    "Failed to connect to {} ({}), retrying in {:?}",
+                    dest, e, delay
+                );
+                channel
+                    .send(NetworkCommand::ConnectionState(ConnectionState::Retrying(
+                        delay,
+                    )))
+                    .ok();
+                thread::sleep(delay);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Opens a connection to the first address the destination resolves to that answers within the
+/// timeout
+fn connect_timeout(dest: &str, timeout: Duration) -> Result<TcpStream, ConnectError> {
+    let addrs: Vec<_> = dest
+        .to_socket_addrs()
+        .map_err(|e| ConnectError::DnsFailure(e.to_string()))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(ConnectError::DnsFailure(format!(
+            "No addresses for {}",
+            dest
+        )));
+    }
+
+    let mut error = ConnectError::Timeout;
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => error = ConnectError::from_io(&e),
+        }
+    }
+    Err(error)
+}
+
+/// Error reading a VarInt or VarLong
+#[derive(Debug)]
+pub enum VarIntError {
+    Io(io::Error),
+    /// The value had more continuation bytes than fit in its type
+    TooLong,
+}
+
+impl std::fmt::Display for VarIntError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VarIntError::Io(e) => write!(f, "{}", e),
+            VarIntError::TooLong => write!(f, "VarInt is too long"),
+        }
+    }
+}
+
+impl std::error::Error for VarIntError {}
+
+impl From<io::Error> for VarIntError {
+    fn from(e: io::Error) -> Self {
+        VarIntError::Io(e)
+    }
+}
+
+impl From<VarIntError> for io::Error {
+    fn from(e: VarIntError) -> Self {
+        match e {
+            VarIntError::Io(e) => e,
+            VarIntError::TooLong => io::Error::new(ErrorKind::InvalidData, e.to_string()),
+        }
+    }
+}
+
+/// Reads a VarInt of at most 5 bytes
+pub fn read_varint<R: Read>(r: &mut R) -> Result<i32, VarIntError> {
+    Ok(read_var(r, 5)? as i32)
+}
+
+/// Reads a VarLong of at most 10 bytes
+#[allow(dead_code)]
+pub fn read_varlong<R: Read>(r: &mut R) -> Result<i64, VarIntError> {
+    Ok(read_var(r, 10)? as i64)
+}
+
+fn read_var<R: Read>(r: &mut R, max_bytes: u32) -> Result<u64, VarIntError> {
+    const PART: u64 = 0x7F;
     let mut size = 0;
-    let mut val = 0u32;
+    let mut val = 0u64;
     let mut byte: [u8; 1] = [0];
 
-    r.read_exact(&mut byte)?;
     loop {
-        val |= (byte[0] as u32 & PART) << (size * 7);
+        if size >= max_bytes {
+            return Err(VarIntError::TooLong);
+        }
+        r.read_exact(&mut byte)?;
+        val |= (byte[0] as u64 & PART) << (size * 7);
         size += 1;
 
         if (byte[0] & 0x80) == 0 {
             break;
         }
-
-        r.read_exact(&mut byte)?;
     }
 
-    Ok(val as i32)
+    Ok(val)
 }
 
+/// Writes a VarInt, negative values always take the full 5 bytes
 pub fn write_varint<W: Write>(w: &mut W, val: i32) -> io::Result<()> {
+    write_var(w, val as u32 as u64)
+}
+
+/// Writes a VarLong, negative values always take the full 10 bytes
+#[allow(dead_code)]
+pub fn write_varlong<W: Write>(w: &mut W, val: i64) -> io::Result<()> {
+    write_var(w, val as u64)
+}
+
+fn write_var<W: Write>(w: &mut W, val: u64) -> io::Result<()> {
     let mut buf: Vec<u8> = Vec::new();
 
-    const PART: u32 = 0x7F;
-    let mut val = val as u32;
+    const PART: u64 = 0x7F;
+    let mut val = val;
     loop {
         if (val & !PART) == 0 {
             buf.push(val as u8);
             break;
         }
-        buf.push(val as u8 | !0x7F);
+        buf.push(val as u8 | 0x80);
         val >>= 7;
     }
     w.write_all(&buf)?;
     Ok(())
 }
 
+/// Inflates the zlib compressed body of a packet, checking it matches the uncompressed length
+/// sent with the packet. Inflating stops at that length so a packet can't expand past what it
+/// claims to be
+pub fn decompress_packet(data: &[u8], data_len: usize) -> io::Result<Vec<u8>> {
+    let uncompressed = decompress_to_vec_zlib_with_limit(data, data_len).map_err(|e| {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Failed to decompress packet: {:?}", e),
+        )
+    })?;
+
+    if uncompressed.len() != data_len {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Decompressed packet length {} didn't match expected length {}",
+                uncompressed.len(),
+                data_len
+            ),
+        ));
+    }
+
+    Ok(uncompressed)
+}
+
 pub fn encode(packet: PacketType) -> Vec<u8> {
     let mut id: Vec<u8> = Vec::new();
     write_varint(&mut id, packet.id().id).unwrap();
@@ -585,3 +1245,552 @@ pub fn encode(packet: PacketType) -> Vec<u8> {
         .expect("Failed to serialize packet");
     serializer.into_bytes()
 }
+
+#[cfg(test)]
+mod tests {
+    use mcproto_rs::uuid::UUID4;
+
+    use super::*;
+
+    /// Makes a manager connected to a local socket standing in for the server, returning the
+    /// server's end of the connection and the main thread's end of the manager's channel
+    fn connected_manager() -> (NetworkManager, TcpStream, NetworkChannel) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let local = listener.local_addr().unwrap();
+        let stream = TcpStream::connect(local).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        let (ti, rx) = mpsc::channel();
+        let (tx, ri) = mpsc::channel();
+        let manager = NetworkManager {
+            stream,
+            compress: false,
+            threshold: 0,
+            #[cfg(feature = "encryption")]
+            cipher: None,
+            profile: None,
+            address: ServerAddress {
+                host: local.ip().to_string(),
+                port: local.port(),
+            },
+            timeout: Duration::from_secs(1),
+            last_keep_alive: None,
+            keep_alive_warned: false,
+            close: false,
+            channel: NetworkChannel { send: ti, recv: ri },
+            state: protocol::State::Play,
+            login_plugins: HashMap::new(),
+            count: 0,
+        };
+        (manager, server, NetworkChannel { send: tx, recv: rx })
+    }
+
+    /// Reads an uncompressed packet sent to the server, without its length
+    fn read_sent_packet(server: &mut TcpStream) -> Vec<u8> {
+        let len = read_varint(server).unwrap();
+        let mut packet = vec![0; len as usize];
+        server.read_exact(&mut packet).unwrap();
+        packet
+    }
+
+    #[test]
+    fn keep_alive_echoes_id() {
+        let (mut manager, mut server, _channel) = connected_manager();
+
+        for id in [0, 1_234_567_890_123, -5] {
+            manager.handle_packet(PacketType::PlayServerKeepAlive(PlayServerKeepAliveSpec {
+                id,
+            }));
+
+            let packet = read_sent_packet(&mut server);
+            assert_eq!(
+                packet,
+                encode(PacketType::PlayClientKeepAlive(PlayClientKeepAliveSpec {
+                    id
+                }))
+            );
+            assert_eq!(packet[packet.len() - 8..], id.to_be_bytes());
+        }
+        assert!(manager.last_keep_alive.is_some());
+        assert!(!manager.close);
+    }
+
+    #[test]
+    fn send_chat_message() {
+        let (mut manager, mut server, _channel) = connected_manager();
+
+        manager.handle_message(NetworkCommand::SendChat(String::from("Hello")));
+        let packet = read_sent_packet(&mut server);
+        assert_eq!(
+            packet,
+            encode(PacketType::PlayClientChatMessage(
+                PlayClientChatMessageSpec {
+                    message: String::from("Hello")
+                }
+            ))
+        );
+        assert!(packet.ends_with(b"\x05Hello"));
+
+        // Long messages are cut down to what the server accepts
+        manager.handle_message(NetworkCommand::SendChat("a".repeat(300)));
+        let packet = read_sent_packet(&mut server);
+        let mut expected = vec![0x80, 0x02];
+        expected.extend_from_slice("a".repeat(MAX_CHAT_LENGTH).as_bytes());
+        assert!(packet.ends_with(&expected));
+    }
+
+    #[test]
+    fn client_settings_encoding() {
+        let (mut manager, mut server, _channel) = connected_manager();
+
+        manager.handle_message(NetworkCommand::ClientSettings {
+            locale: String::from("en_GB"),
+            view_distance: 12,
+            chat_mode: ClientChatMode::Enabled,
+            chat_colors: true,
+            displayed_skin_parts: ClientDisplayedSkinParts::default(),
+            main_hand: ClientMainHand::Right,
+        });
+        let packet = read_sent_packet(&mut server);
+
+        // After the packet id, the locale as a length prefixed string then the view distance as a
+        // single byte
+        let mut expected = vec![5];
+        expected.extend_from_slice(b"en_GB");
+        expected.push(12);
+        assert_eq!(packet[1..8], expected);
+    }
+
+    #[test]
+    fn client_status_encoding() {
+        let (mut manager, mut server, _channel) = connected_manager();
+
+        manager.handle_message(NetworkCommand::ClientStatus(
+            ClientStatusAction::PerformRespawn,
+        ));
+
+        // Client status is packet 0x04, and performing a respawn is action 0
+        assert_eq!(read_sent_packet(&mut server), vec![0x04, 0x00]);
+    }
+
+    #[test]
+    fn connecting_to_closed_port() {
+        // Nothing is listening on a port once its listener is dropped
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let dest = format!("127.0.0.1:{}", port);
+        let timeout = Duration::from_secs(2);
+
+        let (ti, states) = mpsc::channel();
+        let start = Instant::now();
+        let result = connect_with_retry(&dest, None, timeout, &ti);
+        assert!(matches!(
+            result,
+            Err(ConnectError::Refused) | Err(ConnectError::Timeout)
+        ));
+        assert!(start.elapsed() < timeout * 2);
+
+        // Only one attempt is made without a retry policy
+        assert!(matches!(
+            states.try_recv(),
+            Ok(NetworkCommand::ConnectionState(
+                ConnectionState::Connecting(1)
+            ))
+        ));
+        assert!(states.try_recv().is_err());
+    }
+
+    #[test]
+    fn connecting_without_port_fails_lookup() {
+        assert!(matches!(
+            connect_timeout("127.0.0.1", Duration::from_secs(1)),
+            Err(ConnectError::DnsFailure(_))
+        ));
+    }
+
+    #[test]
+    fn read_varint_boundaries() {
+        let read = |bytes: &[u8]| read_varint(&mut Cursor::new(bytes));
+
+        assert_eq!(read(&[0x00]).unwrap(), 0);
+        assert_eq!(read(&[0x7f]).unwrap(), 127);
+        assert_eq!(read(&[0x80, 0x01]).unwrap(), 128);
+        assert_eq!(read(&[0xff, 0xff, 0xff, 0xff, 0x07]).unwrap(), i32::MAX);
+        assert_eq!(read(&[0xff, 0xff, 0xff, 0xff, 0x0f]).unwrap(), -1);
+        assert_eq!(read(&[0x80, 0x80, 0x80, 0x80, 0x08]).unwrap(), i32::MIN);
+    }
+
+    #[test]
+    fn read_varint_overlong() {
+        let overlong = [0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert!(matches!(
+            read_varint(&mut Cursor::new(&overlong)),
+            Err(VarIntError::TooLong)
+        ));
+
+        // Ending part way through is an io error rather than too long
+        assert!(matches!(
+            read_varint(&mut Cursor::new(&[0x80, 0x80])),
+            Err(VarIntError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn read_varlong_boundaries() {
+        let read = |bytes: &[u8]| read_varlong(&mut Cursor::new(bytes));
+
+        let mut max = vec![0xff; 8];
+        max.push(0x7f);
+        assert_eq!(read(&max).unwrap(), i64::MAX);
+
+        let mut minus_one = vec![0xff; 9];
+        minus_one.push(0x01);
+        assert_eq!(read(&minus_one).unwrap(), -1);
+
+        let overlong = vec![0xff; 11];
+        assert!(matches!(read(&overlong), Err(VarIntError::TooLong)));
+    }
+
+    #[test]
+    fn varint_round_trip() {
+        for (val, len) in [
+            (0, 1),
+            (1, 1),
+            (127, 1),
+            (128, 2),
+            (16_383, 2),
+            (16_384, 3),
+            (2_097_151, 3),
+            (i32::MAX, 5),
+            (-1, 5),
+            (i32::MIN, 5),
+        ] {
+            let mut buf = Vec::new();
+            write_varint(&mut buf, val).unwrap();
+            assert_eq!(buf.len(), len, "{}", val);
+            assert_eq!(read_varint(&mut Cursor::new(&buf)).unwrap(), val);
+        }
+    }
+
+    #[test]
+    fn varlong_round_trip() {
+        for (val, len) in [
+            (0, 1),
+            (127, 1),
+            (128, 2),
+            (i32::MAX as i64, 5),
+            (i64::MAX, 9),
+            (-1, 10),
+            (i64::MIN, 10),
+        ] {
+            let mut buf = Vec::new();
+            write_varlong(&mut buf, val).unwrap();
+            assert_eq!(buf.len(), len, "{}", val);
+            assert_eq!(read_varlong(&mut Cursor::new(&buf)).unwrap(), val);
+        }
+    }
+
+    #[test]
+    fn commands_are_flushed_and_events_delivered() {
+        let (mut manager, mut server, channel) = connected_manager();
+        manager.stream.set_nonblocking(true).unwrap();
+        channel
+            .send
+            .send(NetworkCommand::SendChat(String::from("hi")))
+            .unwrap();
+        let mut client = Server::new(String::from("test"), channel);
+
+        // Nothing has arrived yet, so updating doesn't wait for the server
+        manager.update();
+        assert_eq!(
+            read_sent_packet(&mut server),
+            encode(PacketType::PlayClientChatMessage(
+                PlayClientChatMessageSpec {
+                    message: String::from("hi")
+                }
+            ))
+        );
+        assert!(client.poll_events().is_empty());
+
+        let packet = encode(PacketType::PlayTimeUpdate(PlayTimeUpdateSpec {
+            world_age: 100,
+            time_of_day: 6000,
+        }));
+        write_varint(&mut server, packet.len() as i32).unwrap();
+        server.write_all(&packet).unwrap();
+
+        let start = Instant::now();
+        let mut events = Vec::new();
+        while events.is_empty() && start.elapsed() < Duration::from_secs(5) {
+            manager.update();
+            events = client.poll_events();
+        }
+        assert!(matches!(
+            events.as_slice(),
+            [ServerEvent::Packet(PacketType::PlayTimeUpdate(pack))] if pack.time_of_day == 6000
+        ));
+
+        // Dropping the manager stops the network thread
+        drop(manager);
+        assert!(matches!(
+            client.poll_events().as_slice(),
+            [ServerEvent::Closed]
+        ));
+    }
+
+    #[test]
+    fn handshake_sends_protocol_of_version() {
+        for version in ProtocolVersion::ALL {
+            let (mut manager, mut server, _channel) = connected_manager();
+            manager.state = protocol::State::Handshaking;
+            manager.stream.set_nonblocking(true).unwrap();
+            // The fake server never answers, so logging in gives up straight away
+            manager.timeout = Duration::from_millis(10);
+
+            manager.handle_message(NetworkCommand::Login(
+                version.protocol(),
+                String::from("Notch"),
+                None,
+            ));
+
+            let handshake = read_sent_packet(&mut server);
+            let mut cur = Cursor::new(&handshake);
+            assert_eq!(read_varint(&mut cur).unwrap(), 0x00);
+            assert_eq!(
+                read_varint(&mut cur).unwrap(),
+                version.protocol(),
+                "{}",
+                version.name()
+            );
+            assert!(manager.close);
+        }
+    }
+
+    /// Adds the length to an encoded packet, in the compressed format with a data length of 0 if
+    /// compression is on
+    fn frame(packet: PacketType, compressed: bool) -> Vec<u8> {
+        let mut body = Vec::new();
+        if compressed {
+            write_varint(&mut body, 0).unwrap();
+        }
+        body.extend(encode(packet));
+
+        let mut framed = Vec::new();
+        write_varint(&mut framed, body.len() as i32).unwrap();
+        framed.extend(body);
+        framed
+    }
+
+    #[test]
+    fn login_state_machine() {
+        let (mut manager, mut server, channel) = connected_manager();
+        manager.state = protocol::State::Handshaking;
+
+        // Id 0x02 is Login Success while logging in, and something else entirely once playing
+        let uuid = UUID4::from(42);
+        let mut script = frame(
+            PacketType::LoginSetCompression(LoginSetCompressionSpec {
+                threshold: VarInt(256),
+            }),
+            false,
+        );
+        script.extend(frame(
+            PacketType::LoginSuccess(LoginSuccessSpec {
+                uuid,
+                username: String::from("Notch"),
+            }),
+            true,
+        ));
+        script.extend(frame(
+            PacketType::PlayServerKeepAlive(PlayServerKeepAliveSpec { id: 7 }),
+            true,
+        ));
+        server.write_all(&script).unwrap();
+
+        assert_eq!(
+            manager.login(PROTOCOL, String::from("Notch"), None),
+            Some(())
+        );
+        assert_eq!(manager.state, protocol::State::Play);
+        assert!(manager.compress);
+        assert_eq!(manager.threshold, 256);
+        assert!(matches!(
+            channel.recv.try_recv(),
+            Ok(NetworkCommand::ReceivePacket(PacketType::LoginSuccess(pack))) if pack.uuid == uuid
+        ));
+
+        // The next packet is read with the play state's ids
+        assert!(matches!(
+            manager.next_packet(),
+            Ok(Ok(PacketType::PlayServerKeepAlive(
+                PlayServerKeepAliveSpec { id: 7 }
+            )))
+        ));
+
+        // The handshake and login start were sent before any of it
+        assert_eq!(read_sent_packet(&mut server)[0], 0x00);
+        assert_eq!(read_sent_packet(&mut server)[0], 0x00);
+    }
+
+    fn plugin_request(message_id: i32, channel: &str) -> LoginPluginRequestSpec {
+        LoginPluginRequestSpec {
+            message_id: VarInt(message_id),
+            channel: channel.to_string(),
+            data: types::RemainingBytes { data: vec![1, 2] },
+        }
+    }
+
+    #[test]
+    fn unhandled_login_plugin_request() {
+        let (mut manager, mut server, _channel) = connected_manager();
+        manager.state = protocol::State::Login;
+
+        manager.respond_to_login_plugin(plugin_request(300, "velocity:player_info"));
+
+        // Id, the message id as a varint, and not successful with no data after it
+        assert_eq!(read_sent_packet(&mut server), vec![0x02, 0xac, 0x02, 0x00]);
+    }
+
+    #[test]
+    fn handled_login_plugin_request() {
+        let (mut manager, mut server, _channel) = connected_manager();
+        manager.state = protocol::State::Login;
+        manager.register_login_plugin(
+            "velocity:player_info",
+            Box::new(|data| Some(data.iter().rev().copied().collect())),
+        );
+
+        manager.respond_to_login_plugin(plugin_request(5, "velocity:player_info"));
+        assert_eq!(read_sent_packet(&mut server), vec![0x02, 0x05, 0x01, 2, 1]);
+    }
+
+    #[test]
+    fn brand_plugin_messages() {
+        let (mut manager, mut server, _channel) = connected_manager();
+
+        manager.handle_message(NetworkCommand::PluginMessage {
+            channel: String::from(BRAND_CHANNEL),
+            data: encode_brand(CLIENT_BRAND),
+        });
+        let packet = read_sent_packet(&mut server);
+        let mut expected = vec![15];
+        expected.extend_from_slice(b"minecraft:brand");
+        expected.push(10);
+        expected.extend_from_slice(b"mince-raft");
+        assert!(packet.ends_with(&expected));
+
+        // Invalid channels aren't sent, so the next packet read is the one after it
+        manager.handle_message(NetworkCommand::PluginMessage {
+            channel: String::from("Not A Channel"),
+            data: vec![],
+        });
+        manager.handle_message(NetworkCommand::SendChat(String::from("after")));
+        assert!(read_sent_packet(&mut server).ends_with(b"\x05after"));
+
+        // A brand from the server is decoded from the packet it arrives in
+        server
+            .write_all(&frame(
+                PacketType::PlayServerPluginMessage(PlayServerPluginMessageSpec {
+                    channel: String::from(BRAND_CHANNEL),
+                    data: types::RemainingBytes {
+                        data: encode_brand("Paper"),
+                    },
+                }),
+                false,
+            ))
+            .unwrap();
+        match manager.next_packet() {
+            Ok(Ok(PacketType::PlayServerPluginMessage(pack))) => {
+                assert_eq!(pack.channel, BRAND_CHANNEL);
+                assert_eq!(decode_brand(&pack.data.data), Some(String::from("Paper")));
+            }
+            other => panic!("Expected a plugin message, got {:?}", other),
+        }
+        assert_eq!(decode_brand(&[10, b'P']), None);
+    }
+
+    #[test]
+    fn plugin_channel_names() {
+        for channel in [
+            "minecraft:brand",
+            "velocity:player_info",
+            "bungeecord:main/sub",
+        ] {
+            assert!(valid_channel(channel), "{}", channel);
+        }
+        for channel in ["brand", ":brand", "minecraft:", "Minecraft:brand", "a b:c"] {
+            assert!(!valid_channel(channel), "{}", channel);
+        }
+    }
+
+    #[test]
+    fn retry_backoff_schedule() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        };
+        let delays: Vec<u64> = (1..=7).map(|n| policy.delay(n).as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 30, 30]);
+
+        // Very large attempt counts don't overflow
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(30));
+        assert_eq!(policy.delay(0), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn compressed_packet_round_trip() {
+        // Packet id followed by a body long enough to be over a normal compression threshold
+        let mut packet = vec![0x0e];
+        packet.extend((0..600).map(|i| (i % 13) as u8));
+
+        let compressed = compress_to_vec_zlib(&packet, 0);
+        assert_eq!(
+            decompress_packet(&compressed, packet.len()).unwrap(),
+            packet
+        );
+    }
+
+    #[test]
+    fn compressed_packet_length_mismatch() {
+        let packet = vec![7u8; 300];
+        let compressed = compress_to_vec_zlib(&packet, 0);
+
+        // Claiming less than the real length stops inflating early rather than expanding past it
+        assert!(decompress_packet(&compressed, 100).is_err());
+        assert!(decompress_packet(&compressed, 301).is_err());
+    }
+
+    #[test]
+    fn parse_status_response() {
+        let json = r#"{
+            "version": {"name": "1.16.3", "protocol": 753},
+            "players": {
+                "max": 20,
+                "online": 2,
+                "sample": [
+                    {"name": "Notch", "id": "069a79f4-44e9-4726-a5be-fca90e38aaf5"},
+                    {"name": "jeb_", "id": "853c80ef-3c37-49fd-aa49-938b674adae6"}
+                ]
+            },
+            "description": {"text": "A ", "extra": [{"text": "server", "bold": true}]},
+            "favicon": "data:image/png;base64,iVBORw0KGgo="
+        }"#;
+        let spec: status::StatusSpec = serde_json::from_str(json).unwrap();
+        let status = ServerStatus::from_spec(spec, 42);
+
+        assert_eq!(status.motd, "A server");
+        assert_eq!(status.motd_spans.len(), 2);
+        assert_eq!(status.version, "1.16.3");
+        assert_eq!(status.protocol, 753);
+        assert_eq!(status.num_players, 2);
+        assert_eq!(status.max_players, 20);
+        assert_eq!(status.online_players, vec!["Notch", "jeb_"]);
+        assert_eq!(status.icon.as_deref(), Some(&b"\x89PNG\r\n\x1a\n"[..]));
+        assert_eq!(status.ping, 42);
+        assert!(status.is_compatible());
+    }
+}
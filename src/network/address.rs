@@ -0,0 +1,181 @@
+use std::{error::Error, fmt, net::IpAddr};
+
+use log::debug;
+use simple_error::bail;
+use trust_dns_resolver::Resolver;
+
+pub const DEFAULT_PORT: u16 = 25565;
+
+/// The host and port of a server to connect to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerAddress {
+    pub host: String,
+    pub port: u16,
+}
+
+impl ServerAddress {
+    /// Parses an address in the form `host`, `host:port`, `[ipv6]:port` or a bare IPv6 literal,
+    /// using the default port if none is given
+    pub fn parse(address: &str) -> Result<ServerAddress, Box<dyn Error>> {
+        let (host, port) = split_host_port(address)?;
+        Ok(ServerAddress {
+            host,
+            port: port.unwrap_or(DEFAULT_PORT),
+        })
+    }
+
+    /// Parses an address, looking up the `_minecraft._tcp` SRV record of the host when no port is
+    /// given like the vanilla client does. The lookup blocks, so this is only called on the network
+    /// thread
+    pub fn resolve(address: &str) -> Result<ServerAddress, Box<dyn Error>> {
+        let (host, port) = split_host_port(address)?;
+        if let Some(port) = port {
+            return Ok(ServerAddress { host, port });
+        }
+
+        // IP addresses don't have SRV records
+        if host.parse::<IpAddr>().is_ok() {
+            return Ok(ServerAddress {
+                host,
+                port: DEFAULT_PORT,
+            });
+        }
+
+        match lookup_srv(&host) {
+            Ok(Some(address)) => {
+                debug!("Found SRV record for {}: {}", host, address);
+                Ok(address)
+            }
+            Ok(None) | Err(_) => Ok(ServerAddress {
+                host,
+                port: DEFAULT_PORT,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for ServerAddress {
+    /// Formats the address so it can be passed to TcpStream::connect
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/// Splits an address into its host and port, if the port is present
+fn split_host_port(address: &str) -> Result<(String, Option<u16>), Box<dyn Error>> {
+    let address = address.trim();
+    if address.is_empty() {
+        bail!("Server address is empty");
+    }
+
+    // Bracketed IPv6, optionally with a port
+    if let Some(rest) = address.strip_prefix('[') {
+        let (host, rest) = match rest.split_once(']') {
+            Some(split) => split,
+            None => bail!("Missing closing bracket in address: {}", address),
+        };
+        let port = match rest.strip_prefix(':') {
+            Some(port) => Some(port.parse()?),
+            None if rest.is_empty() => None,
+            None => bail!("Unexpected characters after address: {}", rest),
+        };
+        return Ok((host.to_string(), port));
+    }
+
+    match address.split_once(':') {
+        // More than one colon is a bare IPv6 literal which can't have a port
+        Some((_, port)) if port.contains(':') => Ok((address.to_string(), None)),
+        Some((host, port)) => Ok((host.to_string(), Some(port.parse()?))),
+        None => Ok((address.to_string(), None)),
+    }
+}
+
+/// Looks up the host and port from the `_minecraft._tcp` SRV record of a host
+fn lookup_srv(host: &str) -> Result<Option<ServerAddress>, Box<dyn Error>> {
+    let resolver = Resolver::from_system_conf()?;
+    let lookup = resolver.srv_lookup(format!("_minecraft._tcp.{}", host))?;
+
+    Ok(lookup.iter().next().map(|srv| ServerAddress {
+        host: srv.target().to_utf8().trim_end_matches('.').to_string(),
+        port: srv.port(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(host: &str, port: u16) -> ServerAddress {
+        ServerAddress {
+            host: host.to_string(),
+            port,
+        }
+    }
+
+    #[test]
+    fn host_without_port() {
+        assert_eq!(
+            ServerAddress::parse("example.com").unwrap(),
+            address("example.com", DEFAULT_PORT)
+        );
+        assert_eq!(
+            ServerAddress::parse("  example.com ").unwrap(),
+            address("example.com", DEFAULT_PORT)
+        );
+    }
+
+    #[test]
+    fn host_with_port() {
+        assert_eq!(
+            ServerAddress::parse("example.com:30000").unwrap(),
+            address("example.com", 30000)
+        );
+        assert!(ServerAddress::parse("example.com:").is_err());
+        assert!(ServerAddress::parse("example.com:99999").is_err());
+    }
+
+    #[test]
+    fn ipv6_literals() {
+        assert_eq!(
+            ServerAddress::parse("::1").unwrap(),
+            address("::1", DEFAULT_PORT)
+        );
+        assert_eq!(
+            ServerAddress::parse("[2001:db8::1]").unwrap(),
+            address("2001:db8::1", DEFAULT_PORT)
+        );
+        assert_eq!(
+            ServerAddress::parse("[2001:db8::1]:30000").unwrap(),
+            address("2001:db8::1", 30000)
+        );
+        assert!(ServerAddress::parse("[2001:db8::1").is_err());
+
+        // Brackets are put back so the port isn't read as part of the address
+        assert_eq!(
+            address("2001:db8::1", 30000).to_string(),
+            "[2001:db8::1]:30000"
+        );
+    }
+
+    #[test]
+    fn explicit_port_skips_srv_lookup() {
+        assert_eq!(
+            ServerAddress::resolve("example.com:30000").unwrap(),
+            address("example.com", 30000)
+        );
+        assert_eq!(
+            ServerAddress::resolve("127.0.0.1").unwrap(),
+            address("127.0.0.1", DEFAULT_PORT)
+        );
+    }
+
+    #[test]
+    fn empty_address() {
+        assert!(ServerAddress::parse("").is_err());
+        assert!(ServerAddress::parse("   ").is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use std::error::Error;
+
+use aes::Aes128;
+use cfb8::{
+    cipher::{AsyncStreamCipher, NewCipher},
+    Cfb8,
+};
+use rsa::{pkcs8::FromPublicKey, PaddingScheme, PublicKey, RsaPublicKey};
+
+type AesCfb8 = Cfb8<Aes128>;
+
+/// AES/CFB8 cipher used to encrypt the connection after logging in to an online mode server.
+/// The shared secret is used as both the key and IV, with separate cipher states for each
+/// direction of the stream
+pub struct Cipher {
+    encryptor: AesCfb8,
+    decryptor: AesCfb8,
+}
+
+impl Cipher {
+    pub fn new(shared_secret: &[u8; 16]) -> Cipher {
+        Cipher {
+            encryptor: AesCfb8::new_from_slices(shared_secret, shared_secret)
+                .expect("Invalid shared secret length"),
+            decryptor: AesCfb8::new_from_slices(shared_secret, shared_secret)
+                .expect("Invalid shared secret length"),
+        }
+    }
+
+    /// Encrypts data in place to be sent to the server
+    pub fn encrypt(&mut self, data: &mut [u8]) {
+        self.encryptor.encrypt(data);
+    }
+
+    /// Decrypts data received from the server in place
+    pub fn decrypt(&mut self, data: &mut [u8]) {
+        self.decryptor.decrypt(data);
+    }
+}
+
+/// Generates a random 16 byte shared secret for the connection
+pub fn generate_shared_secret() -> [u8; 16] {
+    rand::random()
+}
+
+/// Encrypts data with the server's public key, which is sent in DER format in the encryption
+/// request
+pub fn rsa_encrypt(public_key: &[u8], data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let key = RsaPublicKey::from_public_key_der(public_key)
+        .map_err(|e| format!("Invalid server public key: {:?}", e))?;
+    let encrypted = key.encrypt(
+        &mut rand::thread_rng(),
+        PaddingScheme::new_pkcs1v15_encrypt(),
+        data,
+    )?;
+    Ok(encrypted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // CFB8-AES128 vector from NIST SP 800-38A, section F.3.7
+    const KEY: [u8; 16] = [
+        0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f,
+        0x3c,
+    ];
+    const IV: [u8; 16] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f,
+    ];
+    const PLAINTEXT: [u8; 18] = [
+        0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93, 0x17,
+        0x2a, 0xae, 0x2d,
+    ];
+    const CIPHERTEXT: [u8; 18] = [
+        0x3b, 0x79, 0x42, 0x4c, 0x9c, 0x0d, 0xd4, 0x36, 0xba, 0xce, 0x9e, 0x0e, 0xd4, 0x58, 0x6a,
+        0x4f, 0x32, 0xb9,
+    ];
+
+    #[test]
+    fn cfb8_keystream_matches_nist_vector() {
+        let mut data = PLAINTEXT;
+        AesCfb8::new_from_slices(&KEY, &IV)
+            .unwrap()
+            .encrypt(&mut data);
+        assert_eq!(data, CIPHERTEXT);
+
+        AesCfb8::new_from_slices(&KEY, &IV)
+            .unwrap()
+            .decrypt(&mut data);
+        assert_eq!(data, PLAINTEXT);
+    }
+
+    #[test]
+    fn cipher_keeps_state_between_packets() {
+        // Packets are encrypted one after the other as a single stream
+        let mut whole = PLAINTEXT;
+        Cipher::new(&KEY).encrypt(&mut whole);
+
+        let mut cipher = Cipher::new(&KEY);
+        let mut split = PLAINTEXT;
+        let (first, second) = split.split_at_mut(5);
+        cipher.encrypt(first);
+        cipher.encrypt(second);
+        assert_eq!(split, whole);
+
+        let mut server = Cipher::new(&KEY);
+        server.decrypt(&mut split);
+        assert_eq!(split, PLAINTEXT);
+    }
+}
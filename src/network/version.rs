@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{network::ConnectError, world::chunks::PackingMode};
+
+/// Minecraft versions the client recognises in status pings, only those in `ALL` can be joined
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ProtocolVersion {
+    #[default]
+    V1_16,
+    V1_17,
+    V1_18,
+}
+
+impl ProtocolVersion {
+    /// Versions that can be picked in the settings. 1.17 and 1.18 are left out until there are
+    /// packet definitions to decode them with
+    pub const ALL: [ProtocolVersion; 1] = [ProtocolVersion::V1_16];
+
+    /// Returns the protocol number sent in the handshake, which is the latest release of each
+    /// version
+    pub fn protocol(&self) -> i32 {
+        match self {
+            ProtocolVersion::V1_16 => 753,
+            ProtocolVersion::V1_17 => 756,
+            ProtocolVersion::V1_18 => 757,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProtocolVersion::V1_16 => "1.16.3",
+            ProtocolVersion::V1_17 => "1.17.1",
+            ProtocolVersion::V1_18 => "1.18.1",
+        }
+    }
+
+    /// Finds the version a protocol number from a status ping belongs to. Only 1.16.3 counts as
+    /// 1.16, the other 1.16 releases have packets that don't match the 1.16.3 definitions
+    pub fn from_protocol(protocol: i32) -> Option<ProtocolVersion> {
+        match protocol {
+            753 => Some(ProtocolVersion::V1_16),
+            755..=756 => Some(ProtocolVersion::V1_17),
+            757..=758 => Some(ProtocolVersion::V1_18),
+            _ => None,
+        }
+    }
+
+    /// Finds the version for a server's protocol number, erroring with both versions if it isn't
+    /// one the client can join
+    ///
+    /// # Arguments
+    ///
+    /// * `protocol` - Protocol number the server reported
+    /// * `server_version` - Name of the server's version, shown in the error
+    pub fn check(protocol: i32, server_version: &str) -> Result<ProtocolVersion, ConnectError> {
+        match ProtocolVersion::from_protocol(protocol) {
+            Some(version) if version.is_supported() => Ok(version),
+            _ => Err(ConnectError::ProtocolMismatch {
+                server: server_version.to_string(),
+                client: supported_names(),
+            }),
+        }
+    }
+
+    /// Returns how chunk data is packed in this version
+    pub fn packing(&self) -> PackingMode {
+        PackingMode::for_protocol(self.protocol())
+    }
+
+    /// Returns true if packets of this version can be decoded. Packets are currently decoded with
+    /// the 1.16.3 definitions from mcproto-rs, so only 1.16 servers can be joined
+    pub fn is_supported(&self) -> bool {
+        matches!(self, ProtocolVersion::V1_16)
+    }
+}
+
+/// Returns the names of the versions the client can join, e.g. `1.16.3, 1.17.1`
+pub fn supported_names() -> String {
+    ProtocolVersion::ALL
+        .iter()
+        .filter(|v| v.is_supported())
+        .map(|v| v.name())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Finds the version a server asks for in an outdated client or server message, e.g. `1.18.2`
+/// from "Outdated client! Please use 1.18.2"
+pub fn version_in_message(message: &str) -> Option<String> {
+    message
+        .split(|c: char| c.is_whitespace() || c == '!' || c == ',')
+        .map(|w| w.trim_end_matches('.'))
+        .filter(|w| w.starts_with(|c: char| c.is_ascii_digit()) && w.contains('.'))
+        .last()
+        .map(String::from)
+}
+
+/// Returns true if a login disconnect message is the server refusing the client's version
+pub fn is_outdated_message(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("outdated") || message.contains("incompatible")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mismatched_protocol_is_an_error() {
+        assert_eq!(
+            ProtocolVersion::check(753, "1.16.3").unwrap(),
+            ProtocolVersion::V1_16
+        );
+
+        // 1.18.1 is known but packets from it can't be decoded yet, and 1.16.5 isn't known
+        for (protocol, name) in [(757, "1.18.1"), (754, "1.16.5"), (47, "1.8.9")] {
+            match ProtocolVersion::check(protocol, name) {
+                Err(e @ ConnectError::ProtocolMismatch { .. }) => assert_eq!(
+                    e.to_string(),
+                    format!("Server is on version {}, client supports 1.16.3", name)
+                ),
+                other => panic!("Expected a mismatch for {}, got {:?}", name, other),
+            }
+        }
+    }
+
+    #[test]
+    fn protocol_numbers_round_trip() {
+        let known = [
+            ProtocolVersion::V1_16,
+            ProtocolVersion::V1_17,
+            ProtocolVersion::V1_18,
+        ];
+        for version in known {
+            assert_eq!(
+                ProtocolVersion::from_protocol(version.protocol()),
+                Some(version)
+            );
+        }
+    }
+
+    #[test]
+    fn outdated_disconnect_messages() {
+        let message = "Outdated client! Please use 1.18.2";
+        assert!(is_outdated_message(message));
+        assert_eq!(version_in_message(message), Some(String::from("1.18.2")));
+        assert_eq!(
+            version_in_message("Outdated server! I'm still on 1.16.5."),
+            Some(String::from("1.16.5"))
+        );
+
+        assert!(!is_outdated_message("You have been banned"));
+        assert_eq!(version_in_message("You have been banned"), None);
+    }
+}
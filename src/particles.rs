@@ -0,0 +1,348 @@
+use std::{
+    collections::VecDeque,
+    io::{Cursor, Read},
+};
+
+use glam::Vec3;
+use mcproto_rs::{types::Slot, v1_16_3::PlayParticleSpec, Deserialize};
+use rand::Rng;
+
+use crate::{inventory::ItemStack, network::read_varint};
+
+// Seconds a particle stays visible for after being spawned
+const PARTICLE_LIFETIME: f32 = 1.0;
+// Most particles kept at once, the oldest are dropped to make room for new ones
+const MAX_PARTICLES: usize = 4096;
+
+// Ids of the kinds of particle that carry extra data
+const BLOCK_PARTICLE: i32 = 3;
+const DUST_PARTICLE: i32 = 14;
+const FALLING_DUST_PARTICLE: i32 = 23;
+const ITEM_PARTICLE: i32 = 34;
+
+// Names of each kind of particle by id in 1.16
+const PARTICLE_NAMES: [&str; 72] = [
+    "AmbientEntityEffect",
+    "AngryVillager",
+    "Barrier",
+    "Block",
+    "Bubble",
+    "Cloud",
+    "Crit",
+    "DamageIndicator",
+    "DragonBreath",
+    "DrippingLava",
+    "FallingLava",
+    "LandingLava",
+    "DrippingWater",
+    "FallingWater",
+    "Dust",
+    "Effect",
+    "ElderGuardian",
+    "EnchantedHit",
+    "Enchant",
+    "EndRod",
+    "EntityEffect",
+    "ExplosionEmitter",
+    "Explosion",
+    "FallingDust",
+    "Firework",
+    "Fishing",
+    "Flame",
+    "SoulFireFlame",
+    "Soul",
+    "Flash",
+    "HappyVillager",
+    "Composter",
+    "Heart",
+    "InstantEffect",
+    "Item",
+    "ItemSlime",
+    "ItemSnowball",
+    "LargeSmoke",
+    "Lava",
+    "Mycelium",
+    "Note",
+    "Poof",
+    "Portal",
+    "Rain",
+    "Smoke",
+    "Sneeze",
+    "Spit",
+    "SquidInk",
+    "SweepAttack",
+    "TotemOfUndying",
+    "Underwater",
+    "Splash",
+    "Witch",
+    "BubblePop",
+    "CurrentDown",
+    "BubbleColumnUp",
+    "Nautilus",
+    "Dolphin",
+    "CampfireCosySmoke",
+    "CampfireSignalSmoke",
+    "DrippingHoney",
+    "FallingHoney",
+    "LandingHoney",
+    "FallingNectar",
+    "Ash",
+    "CrimsonSpore",
+    "WarpedSpore",
+    "DrippingObsidianTear",
+    "FallingObsidianTear",
+    "LandingObsidianTear",
+    "ReversePortal",
+    "WhiteAsh",
+];
+
+/// Extra data some kinds of particle carry
+#[derive(Debug, Clone)]
+pub enum ParticleData {
+    None,
+    // Block state the particle takes its texture from, for block and falling dust particles
+    Block(u32),
+    // Colour of redstone dust from 0 to 1, and its size
+    Dust { colour: [f32; 3], scale: f32 },
+    // Item the particle takes its texture from
+    Item(Option<ItemStack>),
+}
+
+/// A particle effect the server asked to be spawned
+#[derive(Debug, Clone)]
+pub struct Particle {
+    // Name of the kind of particle, e.g. `Flame` or `Block`
+    pub kind: String,
+    pub position: Vec3,
+    // How far particles are spread from the position on each axis, or their velocity when only
+    // one is spawned
+    pub offset: Vec3,
+    pub count: i32,
+    pub data: ParticleData,
+}
+
+impl Particle {
+    /// Decodes a Particle packet. mcproto-rs leaves the particle count and the data for each kind
+    /// of particle in the remaining bytes of the packet, so they're read here
+    pub fn from_packet(pack: &PlayParticleSpec) -> Particle {
+        let mut extra = Cursor::new(&pack.data.data[..]);
+        let count = read_bytes(&mut extra).map(i32::from_be_bytes).unwrap_or(0);
+        let data = match pack.particle_id {
+            BLOCK_PARTICLE | FALLING_DUST_PARTICLE => match read_varint(&mut extra) {
+                Ok(state) => ParticleData::Block(state as u32),
+                Err(_) => ParticleData::None,
+            },
+            DUST_PARTICLE => read_dust(&mut extra).unwrap_or(ParticleData::None),
+            ITEM_PARTICLE => ParticleData::Item(
+                Slot::mc_deserialize(extra.remaining_slice())
+                    .ok()
+                    .and_then(|slot| ItemStack::from_slot(&slot.value)),
+            ),
+            _ => ParticleData::None,
+        };
+
+        Particle {
+            kind: particle_name(pack.particle_id),
+            position: Vec3::new(
+                pack.position.x as f32,
+                pack.position.y as f32,
+                pack.position.z as f32,
+            ),
+            offset: Vec3::new(pack.offset.x, pack.offset.y, pack.offset.z),
+            count,
+            data,
+        }
+    }
+
+    /// Returns the colour the particle is drawn with, dust has its own colour and everything
+    /// else is light grey
+    pub fn get_colour(&self) -> [f32; 3] {
+        match self.data {
+            ParticleData::Dust { colour, .. } => colour,
+            _ => [0.8, 0.8, 0.8],
+        }
+    }
+}
+
+/// Returns the name of a kind of particle from its id, or the id if it isn't known
+fn particle_name(id: i32) -> String {
+    match PARTICLE_NAMES.get(id as usize) {
+        Some(name) => name.to_string(),
+        None => format!("Unknown ({})", id),
+    }
+}
+
+/// Reads the colour and scale of a dust particle
+fn read_dust<R: Read>(r: &mut R) -> Option<ParticleData> {
+    let mut values = [0.0; 4];
+    for value in values.iter_mut() {
+        *value = f32::from_be_bytes(read_bytes(r)?);
+    }
+    Some(ParticleData::Dust {
+        colour: [values[0], values[1], values[2]],
+        scale: values[3],
+    })
+}
+
+/// Reads 4 bytes, None if the data ran out
+fn read_bytes<R: Read>(r: &mut R) -> Option<[u8; 4]> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes).ok()?;
+    Some(bytes)
+}
+
+/// A particle spawned in the world and how long ago it was spawned
+#[derive(Debug, Clone)]
+pub struct SpawnedParticle {
+    pub position: Vec3,
+    pub colour: [f32; 3],
+    age: f32,
+}
+
+/// Particles that are currently visible, which disappear after a second
+#[derive(Default)]
+pub struct Particles {
+    particles: VecDeque<SpawnedParticle>,
+}
+
+impl Particles {
+    pub fn new() -> Particles {
+        Particles {
+            particles: VecDeque::new(),
+        }
+    }
+
+    /// Spawns the particles from a Particle packet. A count of 0 spawns one at the position,
+    /// otherwise each is placed randomly within the offset of it
+    pub fn spawn(&mut self, particle: &Particle) {
+        let mut rng = rand::thread_rng();
+        let colour = particle.get_colour();
+
+        if particle.count <= 0 {
+            self.push(particle.position, colour);
+        } else {
+            for _ in 0..particle.count.min(MAX_PARTICLES as i32) {
+                let spread = Vec3::new(
+                    rng.gen_range(-1.0..=1.0),
+                    rng.gen_range(-1.0..=1.0),
+                    rng.gen_range(-1.0..=1.0),
+                );
+                self.push(particle.position + spread * particle.offset, colour);
+            }
+        }
+    }
+
+    fn push(&mut self, position: Vec3, colour: [f32; 3]) {
+        if self.particles.len() >= MAX_PARTICLES {
+            self.particles.pop_front();
+        }
+        self.particles.push_back(SpawnedParticle {
+            position,
+            colour,
+            age: 0.0,
+        });
+    }
+
+    /// Ages every particle, removing ones that have been visible for long enough
+    pub fn update(&mut self, delta: f32) {
+        for particle in self.particles.iter_mut() {
+            particle.age += delta;
+        }
+        self.particles.retain(|p| p.age < PARTICLE_LIFETIME);
+    }
+
+    pub fn get_particles(&self) -> &VecDeque<SpawnedParticle> {
+        &self.particles
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mcproto_rs::protocol::{Id, PacketDirection, RawPacket, State};
+
+    use crate::{
+        network::{PacketType, RawPacketType},
+        resources,
+    };
+
+    use super::*;
+
+    /// Decodes a particle packet at (1.5, 64, -2.5) spread 0.25 on each axis
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the kind of particle
+    /// * `data` - Extra data for that kind of particle, after the count
+    fn decode_particle(id: i32, data: &[u8]) -> Particle {
+        let mut body = id.to_be_bytes().to_vec();
+        // Not long distance
+        body.push(0x00);
+        for pos in [1.5f64, 64.0, -2.5] {
+            body.extend_from_slice(&pos.to_be_bytes());
+        }
+        for offset in [0.25f32, 0.25, 0.25, 0.0] {
+            body.extend_from_slice(&offset.to_be_bytes());
+        }
+        body.extend_from_slice(&20i32.to_be_bytes());
+        body.extend_from_slice(data);
+
+        let id = Id {
+            id: 0x22,
+            state: State::Play,
+            direction: PacketDirection::ClientBound,
+        };
+        match RawPacketType::create(id, &body)
+            .unwrap()
+            .deserialize()
+            .unwrap()
+        {
+            PacketType::PlayParticle(pack) => Particle::from_packet(&pack),
+            other => panic!("Decoded the wrong packet: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decode_block_dust_with_block_state() {
+        let stone = resources::state_id("Stone", &[]);
+        assert!(stone < 0x80, "Stone doesn't fit in a single VarInt byte");
+        // Block particles are id 3, followed by the block state as a VarInt
+        let particle = decode_particle(3, &[stone as u8]);
+
+        assert_eq!(particle.kind, "Block");
+        assert_eq!(particle.position, Vec3::new(1.5, 64.0, -2.5));
+        assert_eq!(particle.offset, Vec3::splat(0.25));
+        assert_eq!(particle.count, 20);
+        assert!(matches!(particle.data, ParticleData::Block(state) if state == stone));
+        assert_eq!(particle.get_colour(), [0.8, 0.8, 0.8]);
+    }
+
+    #[test]
+    fn decode_dust_with_colour() {
+        let mut data = Vec::new();
+        for value in [1.0f32, 0.0, 0.5, 2.0] {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        // Dust particles are id 14, followed by their colour and scale
+        let particle = decode_particle(14, &data);
+
+        assert_eq!(particle.kind, "Dust");
+        assert!(matches!(particle.data, ParticleData::Dust { scale, .. } if scale == 2.0));
+        assert_eq!(particle.get_colour(), [1.0, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn spawned_particles_fade_out() {
+        let mut particles = Particles::new();
+        particles.spawn(&decode_particle(3, &[1]));
+        assert_eq!(particles.get_particles().len(), 20);
+
+        particles.update(PARTICLE_LIFETIME / 2.0);
+        assert_eq!(particles.get_particles().len(), 20);
+        particles.update(PARTICLE_LIFETIME);
+        assert!(particles.get_particles().is_empty());
+    }
+}
@@ -1,18 +1,49 @@
 use glam::Vec3;
 use mcproto_rs::v1_16_3::{ClientChatMode, ClientDisplayedSkinParts, ClientMainHand};
 
-use super::entities::components::Orientation;
+use super::{
+    entities::components::Orientation,
+    inventory::Inventory,
+    world::{aabb::Aabb, World},
+};
+
+use self::physics::{GRAVITY, JUMP_VELOCITY, TERMINAL_VELOCITY};
+
+pub mod physics;
+
+// Furthest the player can look up or down in degrees, straight up or down
+pub const MAX_PITCH: f32 = 90.0;
+// Height of the camera above the player's feet
+pub const EYE_HEIGHT: f32 = 1.7;
+// Furthest a block can be from the eyes to be targeted
+pub const REACH: f32 = 4.5;
+// Size of the player's collision box, which is centred on their feet
+pub const WIDTH: f32 = 0.6;
+pub const HEIGHT: f32 = 1.8;
+
+// Bits of the flags in a position and look packet, a set bit means that value is relative to the
+// player's current one rather than absolute
+pub const RELATIVE_X: u8 = 0x01;
+pub const RELATIVE_Y: u8 = 0x02;
+pub const RELATIVE_Z: u8 = 0x04;
+pub const RELATIVE_YAW: u8 = 0x08;
+pub const RELATIVE_PITCH: u8 = 0x10;
 
 pub struct Player {
     pub id: i32,
 
     position: Vec3,
     orientation: Orientation,
+    // Blocks per second, only the vertical part carries over between frames
+    velocity: Vec3,
+    on_ground: bool,
 
     pub health: f32,
     pub food: i32,
     pub saturation: f32,
 
+    pub inventory: Inventory,
+
     // Client Settings
     pub locale: String,
     pub view_distance: i8,
@@ -22,18 +53,28 @@ pub struct Player {
     pub disable_text_filtering: bool,                   // idek what this does
 }
 
+impl Default for Player {
+    fn default() -> Self {
+        Player::new()
+    }
+}
+
 impl Player {
     pub fn new() -> Player {
         Player {
             id: 0,
 
             position: Vec3::new(0.0, 0.0, 0.0),
-            orientation: Orientation::new_with_values(0.0, 0.0, -89.0, 89.0),
+            orientation: Orientation::new_with_values(0.0, 0.0, -MAX_PITCH, MAX_PITCH),
+            velocity: Vec3::ZERO,
+            on_ground: false,
 
             health: 20.0,
             food: 20,
             saturation: 5.0,
 
+            inventory: Inventory::new(),
+
             locale: String::from("en_GB"),
             view_distance: 8,
             chat_mode: ClientChatMode::Enabled,
@@ -57,6 +98,81 @@ impl Player {
         &self.orientation
     }
 
+    /// Returns the position of the player's eyes, where the camera is and blocks are targeted from
+    pub fn get_eye_position(&self) -> Vec3 {
+        self.position + Vec3::new(0.0, EYE_HEIGHT, 0.0)
+    }
+
+    /// Moves the player to where a position and look packet from the server says, each value is
+    /// either absolute or added to the current one depending on the flags
+    ///
+    /// # Arguments
+    ///
+    /// * `pos` - Position of the player's feet, or the offset to move them by
+    /// * `yaw` - Yaw to look at, or to turn by
+    /// * `pitch` - Pitch to look at, or to turn by
+    /// * `flags` - Which values are relative, from the `RELATIVE_` bits
+    pub fn teleport(&mut self, pos: Vec3, yaw: f32, pitch: f32, flags: u8) {
+        self.position = apply_position_flags(self.position, pos, flags);
+        self.velocity = Vec3::ZERO;
+
+        let relative = |bit: u8, current: f32, new: f32| {
+            if flags & bit != 0 {
+                current + new
+            } else {
+                new
+            }
+        };
+        let yaw = relative(RELATIVE_YAW, self.orientation.get_yaw(), yaw);
+        let pitch = relative(RELATIVE_PITCH, self.orientation.get_pitch(), pitch);
+        self.orientation.set(yaw, pitch);
+    }
+
+    /// Returns the box the player collides with in world space
+    pub fn get_bounding_box(&self) -> Aabb {
+        let half = Vec3::new(WIDTH / 2.0, 0.0, WIDTH / 2.0);
+        Aabb::new(
+            self.position - half,
+            self.position + half + Vec3::new(0.0, HEIGHT, 0.0),
+        )
+    }
+
+    /// Moves the player for a frame, walking along the ground while gravity pulls them down and
+    /// stopping them at the blocks they run into
+    ///
+    /// # Arguments
+    ///
+    /// * `world` - The world to collide with
+    /// * `walk` - Horizontal velocity to walk at in blocks per second
+    /// * `jump` - Whether to jump, which only happens while standing on the ground
+    /// * `delta` - Seconds since the last frame
+    pub fn walk(&mut self, world: &World, walk: Vec3, jump: bool, delta: f32) {
+        self.velocity.x = walk.x;
+        self.velocity.z = walk.z;
+        if jump && self.on_ground {
+            self.velocity.y = JUMP_VELOCITY;
+        }
+        self.velocity.y = (self.velocity.y - GRAVITY * delta).max(-TERMINAL_VELOCITY);
+
+        let movement = self.velocity * delta;
+        let moved = physics::move_and_collide(world, &self.get_bounding_box(), movement);
+        self.position += moved;
+
+        // Landing on something or hitting a ceiling stops vertical movement
+        self.on_ground = movement.y < 0.0 && moved.y > movement.y;
+        if moved.y != movement.y {
+            self.velocity.y = 0.0;
+        }
+    }
+
+    pub fn is_on_ground(&self) -> bool {
+        self.on_ground
+    }
+
+    pub fn get_velocity(&self) -> &Vec3 {
+        &self.velocity
+    }
+
     pub fn get_position_mut(&mut self) -> &mut Vec3 {
         &mut self.position
     }
@@ -64,3 +180,58 @@ impl Player {
         &mut self.orientation
     }
 }
+
+/// Returns the position after a position and look packet, adding each axis with its relative bit
+/// set in the flags to the current position and replacing the others
+pub fn apply_position_flags(current: Vec3, pos: Vec3, flags: u8) -> Vec3 {
+    let axis = |bit: u8, current: f32, new: f32| {
+        if flags & bit != 0 {
+            current + new
+        } else {
+            new
+        }
+    };
+
+    Vec3::new(
+        axis(RELATIVE_X, current.x, pos.x),
+        axis(RELATIVE_Y, current.y, pos.y),
+        axis(RELATIVE_Z, current.z, pos.z),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_y_adds_and_absolute_x_replaces() {
+        let current = Vec3::new(10.0, 64.0, -5.0);
+        let pos = Vec3::new(2.5, 1.5, -0.5);
+
+        assert_eq!(
+            apply_position_flags(current, pos, RELATIVE_Y),
+            Vec3::new(2.5, 65.5, -0.5)
+        );
+        assert_eq!(apply_position_flags(current, pos, 0), pos);
+        assert_eq!(
+            apply_position_flags(current, pos, RELATIVE_X | RELATIVE_Y | RELATIVE_Z),
+            current + pos
+        );
+    }
+
+    #[test]
+    fn teleport_with_relative_look() {
+        let mut player = Player::new();
+        player.teleport(Vec3::new(8.0, 70.0, 8.0), 90.0, 10.0, 0);
+        player.teleport(
+            Vec3::new(0.0, -2.0, 3.0),
+            45.0,
+            0.0,
+            RELATIVE_Y | RELATIVE_YAW,
+        );
+
+        assert_eq!(*player.get_position(), Vec3::new(0.0, 68.0, 3.0));
+        assert_eq!(player.get_orientation().get_yaw(), 135.0);
+        assert_eq!(player.get_orientation().get_pitch(), 0.0);
+    }
+}
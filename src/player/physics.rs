@@ -0,0 +1,152 @@
+use glam::{IVec3, Vec3};
+
+use crate::world::{aabb::Aabb, World};
+
+// Acceleration downwards in blocks per second squared, 0.08 blocks per tick squared in vanilla
+pub const GRAVITY: f32 = 32.0;
+// Fastest the player can fall in blocks per second
+pub const TERMINAL_VELOCITY: f32 = 78.4;
+// Upwards speed of a jump in blocks per second, which clears just over a block
+pub const JUMP_VELOCITY: f32 = 8.4;
+// Gap kept between the player and blocks they run into so they aren't counted as overlapping
+const SKIN: f32 = 1e-4;
+
+/// Returns the collision boxes of the blocks overlapping a region in world space. Blocks in chunks
+/// that aren't loaded are treated as solid so the player can't walk off into them
+pub fn collision_boxes(world: &World, region: &Aabb) -> Vec<Aabb> {
+    // Fences and walls stick up into the block above them
+    let min = region.min.floor().as_ivec3() - IVec3::Y;
+    let max = region.max.floor().as_ivec3();
+
+    let mut boxes = Vec::new();
+    for x in min.x..=max.x {
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                let pos = IVec3::new(x, y, z);
+                let offset = pos.as_vec3();
+                match world.block_at(&pos) {
+                    Some(block) => boxes.extend(
+                        block
+                            .collision_shape()
+                            .iter()
+                            .map(|shape| shape.offset(offset)),
+                    ),
+                    None if world.get_chunk_containing(&pos).is_none() => {
+                        boxes.push(Aabb::FULL_CUBE.offset(offset))
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+    boxes
+}
+
+/// Moves a box through the world, stopping it at the blocks it runs into. Axes are resolved one at
+/// a time, Y then X then Z, so the box slides along walls and floors rather than stopping dead.
+/// The whole path is checked so fast movement can't pass through thin blocks. Returns how far the
+/// box actually moved
+pub fn move_and_collide(world: &World, bounds: &Aabb, movement: Vec3) -> Vec3 {
+    let region = Aabb::new(
+        bounds.min.min(bounds.min + movement),
+        bounds.max.max(bounds.max + movement),
+    );
+    let boxes = collision_boxes(world, &region);
+
+    let mut current = *bounds;
+    let mut moved = Vec3::ZERO;
+    for axis in [1, 0, 2] {
+        moved[axis] = sweep_axis(&current, &boxes, axis, movement[axis]);
+
+        let mut offset = Vec3::ZERO;
+        offset[axis] = moved[axis];
+        current = current.offset(offset);
+    }
+    moved
+}
+
+/// Returns how far a box can move along one axis before hitting any of the boxes, up to the
+/// distance asked for. Boxes it already overlaps are ignored so it can't get stuck inside them
+fn sweep_axis(mover: &Aabb, boxes: &[Aabb], axis: usize, dist: f32) -> f32 {
+    let mut dist = dist;
+    for b in boxes {
+        // Only boxes in the way on the other two axes can be hit
+        let in_path = (0..3)
+            .filter(|a| *a != axis)
+            .all(|a| mover.min[a] < b.max[a] && mover.max[a] > b.min[a]);
+        if !in_path {
+            continue;
+        }
+
+        if dist > 0.0 && b.min[axis] >= mover.max[axis] {
+            dist = dist.min((b.min[axis] - mover.max[axis] - SKIN).max(0.0));
+        } else if dist < 0.0 && b.max[axis] <= mover.min[axis] {
+            dist = dist.max((b.max[axis] - mover.min[axis] + SKIN).min(0.0));
+        }
+    }
+    dist
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec2;
+
+    use crate::{
+        player::{Player, WIDTH},
+        resources,
+        world::chunks::{BlockIndex, Chunk, MIN_SECTION, SECTIONS_PER_CHUNK},
+    };
+
+    use super::*;
+
+    /// A world with a single chunk holding stone at each of the positions
+    fn world_with_stone(blocks: &[IVec3]) -> World {
+        let mut world = World::new();
+        world.insert_chunk(Chunk::empty(IVec2::ZERO, MIN_SECTION, SECTIONS_PER_CHUNK));
+        for pos in blocks {
+            world.set_block(pos, resources::state_id("Stone", &[]) as BlockIndex);
+        }
+        world
+    }
+
+    /// Walks the player for a second in steps of 50ms
+    fn walk_for_a_second(player: &mut Player, world: &World, walk: Vec3) {
+        for _ in 0..20 {
+            player.walk(world, walk, false, 0.05);
+        }
+    }
+
+    #[test]
+    fn lands_on_floor_block() {
+        let world = world_with_stone(&[IVec3::new(8, 63, 8)]);
+        let mut player = Player::new();
+        player.set_position(Vec3::new(8.5, 66.0, 8.5));
+
+        walk_for_a_second(&mut player, &world, Vec3::ZERO);
+
+        assert!(player.is_on_ground());
+        assert_eq!(player.get_velocity().y, 0.0);
+        assert!((player.get_position().y - 64.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn blocked_by_wall() {
+        let world = world_with_stone(&[
+            IVec3::new(8, 63, 8),
+            IVec3::new(9, 63, 8),
+            IVec3::new(10, 64, 8),
+            IVec3::new(10, 65, 8),
+        ]);
+        let mut player = Player::new();
+        player.set_position(Vec3::new(8.5, 64.0, 8.5));
+
+        walk_for_a_second(&mut player, &world, Vec3::new(4.0, 0.0, 0.0));
+
+        // Stopped with the side of the box against the wall, still standing on the floor
+        let pos = *player.get_position();
+        assert!((pos.x - (10.0 - WIDTH / 2.0)).abs() < 1e-3);
+        assert!((pos.y - 64.0).abs() < 1e-3);
+        assert_eq!(pos.z, 8.5);
+        assert!(player.is_on_ground());
+    }
+}
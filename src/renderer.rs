@@ -1,25 +1,35 @@
-use std::collections::HashMap;
+use std::cmp::Ordering;
 use std::io::Cursor;
 
 use glam::{Mat4, Vec3};
-use glium::index::{NoIndices, PrimitiveType::TrianglesList};
 use glium::texture::{RawImage2d, SrgbTexture2dArray};
-use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, SamplerWrapFunction};
 use glium::*;
 use glium::{Display, Surface};
 
+use crate::renderer::{
+    camera::Camera,
+    chunk_borders::ChunkBorderRenderer,
+    entity_renderer::EntityRenderer,
+    occlusion::{near_box, OcclusionCuller},
+    particle_renderer::ParticleRenderer,
+    rain::RainRenderer,
+    sky::{fog_factor, fog_range, horizontal_distance, SkyRenderer},
+};
 use crate::resources::BLOCK_TEXTURES;
 use crate::settings::Settings;
-use crate::world::chunks::{MAX_SECTION, MIN_SECTION};
-use crate::{
-    entities::{self, Entity},
-    renderer::camera::Camera,
-};
+use crate::world::{aabb::Aabb, block_coords, chunks::Chunk, in_view_distance, SectionLocation};
 
 use super::server::Server;
 
 mod camera;
+pub mod chunk_borders;
+mod entity_renderer;
+pub mod occlusion;
+mod particle_renderer;
+pub mod rain;
 mod shader;
+pub mod sky;
 
 #[derive(Debug, Copy, Clone)]
 pub struct Vertex {
@@ -27,28 +37,36 @@ pub struct Vertex {
 }
 implement_vertex!(Vertex, position);
 
-#[derive(Debug, Copy, Clone)]
-pub struct BlockVertex {
-    pub position: [f32; 3],
-    pub tex_coords: [f32; 3],
-}
-implement_vertex!(BlockVertex, position, tex_coords);
+pub use crate::world::mesh::BlockVertex;
+implement_vertex!(BlockVertex, position, tex_coords, brightness, light);
 
 pub struct Renderer {
     pub cam: Camera,
 
     chunk_prog: Program,
 
-    hitbox_prog: Program,
-    hitbox_model: VertexBuffer<Vertex>,
+    entity_renderer: EntityRenderer,
+    chunk_borders: ChunkBorderRenderer,
+    particles: ParticleRenderer,
+    rain: RainRenderer,
+    occlusion: OcclusionCuller,
+    sky: SkyRenderer,
 
     block_textures: SrgbTexture2dArray,
+
+    draw_stats: DrawStats,
+}
+
+/// Number of chunk sections drawn and skipped by frustum and occlusion culling in the last frame
+#[derive(Debug, Default, Copy, Clone)]
+pub struct DrawStats {
+    pub drawn: u32,
+    pub culled: u32,
+    pub occluded: u32,
 }
 
 impl Renderer {
     pub fn new(dis: &Display) -> Renderer {
-        let hitbox_model = glium::VertexBuffer::new(dis, &entities::hitbox_model()).unwrap();
-
         let prog = shader::compile_shaders(
             dis,
             include_bytes!("../shaders/test/v.glsl"),
@@ -56,13 +74,6 @@ impl Renderer {
         )
         .expect("Failed to compile shaders");
 
-        let hitbox_prog = shader::compile_shaders(
-            dis,
-            include_bytes!("../shaders/hitboxes/v.glsl"),
-            include_bytes!("../shaders/hitboxes/f.glsl"),
-        )
-        .expect("Failed to compile shaders");
-
         log::debug!("Setup renderer!");
 
         Renderer {
@@ -73,14 +84,24 @@ impl Renderer {
                 90.0,
             ),
 
-            hitbox_model,
             chunk_prog: prog,
-            hitbox_prog,
+            entity_renderer: EntityRenderer::new(dis),
+            chunk_borders: ChunkBorderRenderer::new(dis),
+            particles: ParticleRenderer::new(dis),
+            rain: RainRenderer::new(dis),
+            occlusion: OcclusionCuller::new(dis),
+            sky: SkyRenderer::new(dis),
 
             block_textures: SrgbTexture2dArray::empty(dis, 0, 0, 1).unwrap(),
+
+            draw_stats: DrawStats::default(),
         }
     }
 
+    pub fn get_draw_stats(&self) -> &DrawStats {
+        &self.draw_stats
+    }
+
     pub fn load_textures(&mut self, dis: &Display) {
         let mut textures1 = vec![RawImage2d::from_raw_rgba_reversed(
             image::load(
@@ -105,50 +126,20 @@ impl Renderer {
         self.block_textures = SrgbTexture2dArray::new(dis, textures1).unwrap();
     }
 
-    pub fn render_hitboxes(&mut self, target: &mut Frame, ents: &HashMap<i32, Entity>) {
-        let params = DrawParameters {
-            depth: Depth {
-                test: draw_parameters::DepthTest::IfLess,
-                write: true,
-                ..Default::default()
-            },
-            backface_culling: BackfaceCullingMode::CullingDisabled,
-            polygon_mode: PolygonMode::Line,
-            line_width: Some(2.0),
-            ..Default::default()
-        };
-
-        let inds = NoIndices(glium::index::PrimitiveType::LinesList);
-        let pvmat = self.cam.get_pvmat().to_cols_array_2d();
-
-        for ent in ents.values() {
-            let e = ent.get_type();
-
-            let mut tmat = Mat4::IDENTITY;
-            tmat *= Mat4::from_translation(ent.pos);
-            tmat *= Mat4::from_scale(Vec3::new(e.width, e.height, e.width));
-
-            let uniforms = uniform! {
-                pvmat: pvmat,
-                tmat: tmat.to_cols_array_2d(),
-                textures: &self.block_textures,
-            };
-
-            target
-                .draw(
-                    &self.hitbox_model,
-                    inds,
-                    &self.hitbox_prog,
-                    &uniforms,
-                    &params,
-                )
-                .expect("Error rendering hitbox");
-        }
-    }
-
-    pub fn render_server(&mut self, target: &mut Frame, serv: &Server, settings: &Settings) {
-        let col = serv.get_sky_colour(&settings.day_colour);
+    pub fn render_server(
+        &mut self,
+        dis: &Display,
+        target: &mut Frame,
+        serv: &Server,
+        settings: &Settings,
+    ) {
+        let sky = serv.get_sky_colour(&settings.day_colour);
+        let col = serv.get_fog_colour(&settings.day_colour);
+        let (fog_near, fog_far) = fog_range(settings.render_distance);
+        // Moonlight keeps the sky from going completely dark at night
+        let daylight = 0.2 + 0.8 * serv.get_world().sky_brightness();
         target.clear_color_and_depth((col.x, col.y, col.z, 0.0), 1.0);
+        self.sky.render(target, &self.cam, sky, col);
 
         let params = DrawParameters {
             depth: Depth {
@@ -157,92 +148,205 @@ impl Renderer {
                 ..Default::default()
             },
             backface_culling: BackfaceCullingMode::CullClockwise,
+            polygon_mode: if settings.wireframe {
+                PolygonMode::Line
+            } else {
+                PolygonMode::Fill
+            },
             ..Default::default()
         };
 
         let behaviour = glium::uniforms::SamplerBehavior {
             minify_filter: MinifySamplerFilter::Nearest,
             magnify_filter: MagnifySamplerFilter::Nearest,
+            // Greedy meshed faces repeat their texture across each block
+            wrap_function: (
+                SamplerWrapFunction::Repeat,
+                SamplerWrapFunction::Repeat,
+                SamplerWrapFunction::Repeat,
+            ),
             ..Default::default()
         };
 
-        let inds = NoIndices(TrianglesList);
-
         let vf = self.cam.generate_view_frustum();
         let pvmat = self.cam.get_pvmat().to_cols_array_2d();
-        let mut points = vec![Vec3::new(0.0, 0.0, 0.0); 8];
+        self.draw_stats = DrawStats::default();
 
-        for (pos, chunk) in serv.get_world().get_chunks() {
-            // Try to frustum cull this whole chunk column
-            let cx = (pos.x * 16) as f32;
-            let cz = (pos.y * 16) as f32;
-
-            points[0] = Vec3::new(cx, 0.0, cz);
-            points[1] = Vec3::new(cx + 16.0, 0.0, cz);
-            points[2] = Vec3::new(cx + 16.0, 0.0, cz + 16.0);
-            points[3] = Vec3::new(cx, 0.0, cz + 16.0);
-            points[4] = Vec3::new(cx, 256.0, cz);
-            points[5] = Vec3::new(cx + 16.0, 256.0, cz);
-            points[6] = Vec3::new(cx + 16.0, 256.0, cz + 16.0);
-            points[7] = Vec3::new(cx, 256.0, cz + 16.0);
-
-            if !vf.accept_points(&points) {
+        if settings.occlusion_culling {
+            self.occlusion.poll();
+        } else {
+            self.occlusion.clear();
+        }
+        let cam_pos = *self.cam.get_pos();
+
+        // Sections that survive frustum culling with their bounds, and the ones skipped by
+        // occlusion culling which still need to be queried
+        let mut visible = Vec::new();
+        let mut in_frustum = Vec::new();
+        let center = Chunk::chunk_containing(&block_coords(serv.get_player().get_position()));
+        let render_distance = settings.render_distance as i32;
+
+        for chunk in serv.get_world().get_chunks().values() {
+            if !in_view_distance(center, *chunk.get_coords(), render_distance) {
                 continue;
             }
 
-            for y in MIN_SECTION..=MAX_SECTION {
-                if let Some(vbo) = chunk.get_section_vbo(y) {
-                    let cy = (y * 16) as f32;
-
-                    // Get points for corners of chunk section
-                    points[0].x = cx;
-                    points[0].y = cy;
-                    points[0].z = cz;
-                    points[1].x = cx + 16.0;
-                    points[1].y = cy;
-                    points[1].z = cz;
-                    points[2].x = cx;
-                    points[2].y = cy + 16.0;
-                    points[2].z = cz;
-                    points[3].x = cx;
-                    points[3].y = cy;
-                    points[3].z = cz + 16.0;
-                    points[4].x = cx + 16.0;
-                    points[4].y = cy + 16.0;
-                    points[4].z = cz;
-                    points[5].x = cx + 16.0;
-                    points[5].y = cy;
-                    points[5].z = cz + 16.0;
-                    points[6].x = cx;
-                    points[6].y = cy + 16.0;
-                    points[6].z = cz + 16.0;
-                    points[7].x = cx + 16.0;
-                    points[7].y = cy + 16.0;
-                    points[7].z = cz + 16.0;
-
-                    // Frustum cull this chunk section
-                    if !vf.accept_points(&points) {
-                        continue;
-                    }
-
-                    let tmat: Mat4 = Mat4::from_translation(Vec3::new(cx, cy, cz));
-
-                    let uniforms = uniform! {
-                        pvmat: pvmat,
-                        tmat: tmat.to_cols_array_2d(),
-                        textures: glium::uniforms::Sampler(&self.block_textures, behaviour),
-                        fogCol: [col.x, col.y, col.z, 1.0],
-                        fogNear: settings.fog_near,
-                        fogFar: settings.fog_far,
-                    };
-
-                    target
-                        .draw(vbo, inds, &self.chunk_prog, &uniforms, &params)
-                        .unwrap();
+            // Try to frustum cull this whole chunk column
+            let column = Aabb::new(
+                chunk.section_aabb(chunk.get_min_section()).min,
+                chunk.section_aabb(chunk.get_max_section()).max,
+            );
+            let column_visible = vf.accept_aabb(&column);
+
+            for y in chunk.get_min_section()..=chunk.get_max_section() {
+                if chunk.get_section_opaque(y).is_none()
+                    && chunk.get_section_translucent(y).is_none()
+                {
+                    continue;
                 }
+
+                // Frustum cull this chunk section
+                let aabb = chunk.section_aabb(y);
+                // Sections completely hidden by fog are skipped too
+                if !column_visible
+                    || !vf.accept_aabb(&aabb)
+                    || fog_factor(horizontal_distance(&aabb, cam_pos), fog_near, fog_far) >= 1.0
+                {
+                    self.draw_stats.culled += 1;
+                    continue;
+                }
+
+                let loc = SectionLocation::new(chunk.get_coords().x, y, chunk.get_coords().y);
+                in_frustum.push((loc, aabb));
+                if settings.occlusion_culling
+                    && !self.occlusion.is_visible(&loc)
+                    && !near_box(&aabb, cam_pos, 1.0)
+                {
+                    self.draw_stats.occluded += 1;
+                    continue;
+                }
+                self.draw_stats.drawn += 1;
+
+                visible.push((chunk, y, aabb));
+            }
+        }
+
+        // Opaque sections are drawn nearest first so the depth test skips hidden fragments, then
+        // translucent faces furthest first so they blend over whatever is behind them
+        visible.sort_by(|(_, _, a), (_, _, b)| {
+            compare_distance(cam_pos, (a.min + a.max) / 2.0, (b.min + b.max) / 2.0)
+        });
+
+        let translucent_params = DrawParameters {
+            depth: Depth {
+                write: false,
+                ..params.depth
+            },
+            blend: Blend::alpha_blending(),
+            ..params.clone()
+        };
+
+        let passes = [(false, &params, 0.5), (true, &translucent_params, 0.01)];
+        for (translucent, params, alpha_cutoff) in passes {
+            let order: Box<dyn Iterator<Item = _>> = if translucent {
+                Box::new(visible.iter().rev())
+            } else {
+                Box::new(visible.iter())
+            };
+
+            for (chunk, y, aabb) in order {
+                let mesh = if translucent {
+                    chunk.get_section_translucent(*y)
+                } else {
+                    chunk.get_section_opaque(*y)
+                };
+                let mesh = match mesh {
+                    Some(mesh) => mesh,
+                    None => continue,
+                };
+
+                let tmat: Mat4 = Mat4::from_translation(aabb.min);
+
+                let uniforms = uniform! {
+                    pvmat: pvmat,
+                    tmat: tmat.to_cols_array_2d(),
+                    textures: glium::uniforms::Sampler(&self.block_textures, behaviour),
+                    fogCol: [col.x, col.y, col.z, 1.0],
+                    daylight: daylight,
+                    fogNear: fog_near,
+                    fogFar: fog_far,
+                    camPos: cam_pos.to_array(),
+                    alphaCutoff: alpha_cutoff,
+                };
+
+                target
+                    .draw(
+                        &mesh.vertices,
+                        &mesh.indices,
+                        &self.chunk_prog,
+                        &uniforms,
+                        params,
+                    )
+                    .unwrap();
             }
         }
 
-        self.render_hitboxes(target, serv.get_entities());
+        // Translucent faces don't write depth, so the queries only test against opaque faces
+        if settings.occlusion_culling {
+            self.occlusion
+                .issue_queries(dis, target, self.cam.get_pvmat(), &in_frustum);
+        }
+
+        self.entity_renderer
+            .render(target, self.cam.get_pvmat(), serv.get_entities());
+        self.particles.render(
+            dis,
+            target,
+            self.cam.get_pvmat(),
+            serv.get_particles().get_particles(),
+        );
+        self.rain.render(
+            dis,
+            target,
+            self.cam.get_pvmat(),
+            serv.get_world(),
+            serv.get_camera_position(),
+        );
+
+        if settings.show_chunk_borders {
+            if let Some(chunk) = serv.get_world().get_chunk(&center) {
+                self.chunk_borders
+                    .render(dis, target, self.cam.get_pvmat(), chunk);
+            }
+        }
+    }
+}
+
+/// Orders two points by their distance from a position, nearest first
+pub fn compare_distance(from: Vec3, a: Vec3, b: Vec3) -> Ordering {
+    from.distance_squared(a)
+        .partial_cmp(&from.distance_squared(b))
+        .unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_ordered_by_distance_from_camera() {
+        let cam_pos = Vec3::new(8.0, 70.0, 8.0);
+        let near = Vec3::new(24.0, 72.0, 8.0);
+        let far = Vec3::new(-40.0, 8.0, 56.0);
+
+        assert_eq!(compare_distance(cam_pos, near, far), Ordering::Less);
+        assert_eq!(compare_distance(cam_pos, far, near), Ordering::Greater);
+        assert_eq!(compare_distance(cam_pos, near, near), Ordering::Equal);
+
+        // Opaque sections are drawn in this order and translucent ones in reverse
+        let mut centres = vec![far, near];
+        centres.sort_by(|a, b| compare_distance(cam_pos, *a, *b));
+        assert_eq!(centres, vec![near, far]);
+        assert_eq!(centres.iter().next_back(), Some(&far));
     }
 }
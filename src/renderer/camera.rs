@@ -1,5 +1,7 @@
 use glam::{Mat4, Vec3, Vec4, Vec4Swizzles};
 
+use crate::world::aabb::Aabb;
+
 const NEAR_PLANE: f32 = 0.05;
 const FAR_PLANE: f32 = 1000.0;
 
@@ -15,6 +17,12 @@ pub struct Camera {
     pvmat: Mat4,
 }
 
+impl Default for Camera {
+    fn default() -> Self {
+        Camera::new()
+    }
+}
+
 impl Camera {
     /// Creates a new camera with default values and a
     ///
@@ -186,8 +194,8 @@ impl Camera {
 
     fn update_pvmat(&mut self) {
         self.pvmat = Mat4::IDENTITY;
-        self.pvmat = self.pvmat * self.pmat;
-        self.pvmat = self.pvmat * self.vmat;
+        self.pvmat *= self.pmat;
+        self.pvmat *= self.vmat;
     }
 
     fn update(&mut self) {
@@ -197,7 +205,7 @@ impl Camera {
     }
 
     pub fn get_look_vector(&self) -> Vec3 {
-        let mut dir: Vec4 = Vec4::new(0.0, 0.0, -1.0, 1.0);
+        let dir: Vec4 = Vec4::new(0.0, 0.0, -1.0, 1.0);
 
         let mut vmat: Mat4 = Mat4::IDENTITY;
 
@@ -219,9 +227,9 @@ impl Camera {
 
         let mut vmat: Mat4 = Mat4::IDENTITY;
 
-        vmat = vmat * Mat4::from_rotation_x((-self.rot.y).to_radians());
-        vmat = vmat * Mat4::from_rotation_y((-self.rot.x + 180.0).to_radians());
-        vmat = vmat * Mat4::from_rotation_z((-self.rot.z).to_radians());
+        vmat *= Mat4::from_rotation_x((-self.rot.y).to_radians());
+        vmat *= Mat4::from_rotation_y((-self.rot.x + 180.0).to_radians());
+        vmat *= Mat4::from_rotation_z((-self.rot.z).to_radians());
 
         let inv_vmat = vmat.inverse();
 
@@ -361,8 +369,83 @@ impl ViewFrustum {
         true
     }
 
+    /// Returns false only if the box is entirely behind one of the planes of the frustum. Boxes
+    /// near the corners of the frustum can be accepted without being visible, which is fine for
+    /// culling
+    pub fn accept_aabb(&self, aabb: &Aabb) -> bool {
+        let planes = [
+            (&self.near_pos, &self.d_near),
+            (&self.near_pos, &self.d_left),
+            (&self.near_pos, &self.d_right),
+            (&self.near_pos, &self.d_bottom),
+            (&self.near_pos, &self.d_top),
+            (&self.far_pos, &self.d_far),
+        ];
+
+        planes.iter().all(|(pos, norm)| {
+            // The corner furthest along the normal is the last to leave the plane
+            let corner = Vec3::select(norm.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+            ViewFrustum::check_plane(pos, norm, &corner)
+        })
+    }
+
     fn check_plane(plane_pos: &Vec3, plane_norm: &Vec3, point: &Vec3) -> bool {
         let v = *point - *plane_pos;
         plane_norm.dot(v) > 0.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A box with sides of 2 centred on a point
+    fn cube_at(centre: Vec3) -> Aabb {
+        Aabb::new(centre - Vec3::ONE, centre + Vec3::ONE)
+    }
+
+    fn frustum() -> (ViewFrustum, Vec3) {
+        let camera =
+            Camera::new_with_values((800, 600), Vec3::new(0.0, 64.0, 0.0), Vec3::ZERO, 90.0);
+        (camera.generate_view_frustum(), camera.get_look_vector())
+    }
+
+    #[test]
+    fn box_inside_frustum() {
+        let (frustum, look) = frustum();
+        assert!(frustum.accept_aabb(&cube_at(Vec3::new(0.0, 64.0, 0.0) + look * 20.0)));
+    }
+
+    #[test]
+    fn box_outside_frustum() {
+        let (frustum, look) = frustum();
+        let camera = Vec3::new(0.0, 64.0, 0.0);
+        let side = look.cross(Vec3::Y);
+
+        // Behind the camera, far off to the side and past the far plane
+        assert!(!frustum.accept_aabb(&cube_at(camera - look * 20.0)));
+        assert!(!frustum.accept_aabb(&cube_at(camera + look * 5.0 + side * 100.0)));
+        assert!(!frustum.accept_aabb(&cube_at(camera + look * (FAR_PLANE + 10.0))));
+    }
+
+    #[test]
+    fn box_straddling_a_plane() {
+        let (frustum, look) = frustum();
+        let camera = Vec3::new(0.0, 64.0, 0.0);
+        let side = look.cross(Vec3::Y);
+
+        // Centred outside the side plane but reaching back inside it
+        let straddling = Aabb::new(
+            camera + look * 10.0 + side * 5.0,
+            camera + look * 12.0 + side * 40.0 + Vec3::Y,
+        );
+        assert!(!frustum.accept_point(&(camera + look * 11.0 + side * 30.0)));
+        assert!(frustum.accept_aabb(&straddling));
+
+        // The section the camera is in straddles the near plane
+        assert!(frustum.accept_aabb(&Aabb::new(
+            Vec3::new(0.0, 64.0, 0.0),
+            Vec3::new(16.0, 80.0, 16.0)
+        )));
+    }
+}
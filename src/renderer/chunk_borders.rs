@@ -0,0 +1,138 @@
+use glam::{IVec3, Mat4, Vec3};
+use glium::{
+    draw_parameters,
+    index::{NoIndices, PrimitiveType::LinesList},
+    uniform, BackfaceCullingMode, Depth, Display, DrawParameters, Frame, Program, Surface,
+    VertexBuffer,
+};
+
+use crate::world::chunks::Chunk;
+
+use super::{shader, Vertex};
+
+/// Draws the edges of a chunk column and the boundaries between its sections, like F3+G in the
+/// vanilla client
+pub struct ChunkBorderRenderer {
+    prog: Program,
+}
+
+impl ChunkBorderRenderer {
+    pub fn new(dis: &Display) -> ChunkBorderRenderer {
+        let prog = shader::compile_shaders(
+            dis,
+            include_bytes!("../../shaders/hitboxes/v.glsl"),
+            include_bytes!("../../shaders/hitboxes/f.glsl"),
+        )
+        .expect("Failed to compile shaders");
+
+        ChunkBorderRenderer { prog }
+    }
+
+    pub fn render(&self, dis: &Display, target: &mut Frame, pvmat: &Mat4, chunk: &Chunk) {
+        let lines = match VertexBuffer::new(dis, &chunk_border_lines(chunk)) {
+            Ok(lines) => lines,
+            Err(e) => {
+                log::warn!("Couldn't create chunk border buffer: {:?}", e);
+                return;
+            }
+        };
+
+        let params = DrawParameters {
+            depth: Depth {
+                test: draw_parameters::DepthTest::IfLess,
+                write: false,
+                ..Default::default()
+            },
+            backface_culling: BackfaceCullingMode::CullingDisabled,
+            line_width: Some(2.0),
+            ..Default::default()
+        };
+        let uniforms = uniform! {
+            pvmat: pvmat.to_cols_array_2d(),
+            tmat: Mat4::IDENTITY.to_cols_array_2d(),
+            colour: [1.0f32, 1.0, 0.0],
+        };
+
+        target
+            .draw(&lines, NoIndices(LinesList), &self.prog, &uniforms, &params)
+            .expect("Error drawing chunk borders");
+    }
+}
+
+/// Returns pairs of points in world space making up lines up the 4 corners of a chunk column from
+/// its lowest to highest section, and around its edges at the bottom of every section and the
+/// top of the highest
+pub fn chunk_border_lines(chunk: &Chunk) -> Vec<Vertex> {
+    let bottom = chunk.get_min_section() * 16;
+    let top = (chunk.get_max_section() + 1) * 16;
+
+    // The far corner is the far side of the last block in the column
+    let min = chunk.map_to_world_coords(&IVec3::new(0, 0, 0)).as_vec3();
+    let max =
+        chunk.map_to_world_coords(&IVec3::new(15, 0, 15)).as_vec3() + Vec3::new(1.0, 0.0, 1.0);
+    let corners = [
+        (min.x, min.z),
+        (max.x, min.z),
+        (max.x, max.z),
+        (min.x, max.z),
+    ];
+
+    let point = |(x, z): (f32, f32), y: i32| Vertex {
+        position: [x, y as f32, z],
+    };
+
+    let mut lines = Vec::new();
+    for corner in corners {
+        lines.push(point(corner, bottom));
+        lines.push(point(corner, top));
+    }
+    for y in (bottom..=top).step_by(16) {
+        for i in 0..corners.len() {
+            lines.push(point(corners[i], y));
+            lines.push(point(corners[(i + 1) % corners.len()], y));
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec2;
+
+    use crate::world::chunks::{MIN_SECTION, SECTIONS_PER_CHUNK};
+
+    use super::*;
+
+    #[test]
+    fn lines_follow_column_corners() {
+        let chunk = Chunk::empty(IVec2::new(-2, 3), MIN_SECTION, SECTIONS_PER_CHUNK);
+        let lines = chunk_border_lines(&chunk);
+        let bottom = (MIN_SECTION * 16) as f32;
+        let top = ((MIN_SECTION + SECTIONS_PER_CHUNK as i32) * 16) as f32;
+
+        // A vertical line up each corner of the 16x16 column
+        let vertical: Vec<[f32; 3]> = lines[..8].iter().map(|v| v.position).collect();
+        assert_eq!(
+            vertical,
+            vec![
+                [-32.0, bottom, 48.0],
+                [-32.0, top, 48.0],
+                [-16.0, bottom, 48.0],
+                [-16.0, top, 48.0],
+                [-16.0, bottom, 64.0],
+                [-16.0, top, 64.0],
+                [-32.0, bottom, 64.0],
+                [-32.0, top, 64.0],
+            ]
+        );
+
+        // Then a loop of 4 lines at every section boundary
+        assert_eq!(lines.len(), 8 + (SECTIONS_PER_CHUNK + 1) * 8);
+        for v in &lines[8..] {
+            let [x, y, z] = v.position;
+            assert!(x == -32.0 || x == -16.0);
+            assert!(z == 48.0 || z == 64.0);
+            assert_eq!(y.rem_euclid(16.0), 0.0);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use glam::{Mat4, Vec3};
+use glium::{
+    draw_parameters,
+    index::{NoIndices, PrimitiveType::LinesList},
+    uniform, BackfaceCullingMode, Depth, Display, DrawParameters, Frame, PolygonMode, Program,
+    Surface, VertexBuffer,
+};
+
+use crate::entities::{self, Entity, EntityKind};
+
+use super::{shader, Vertex};
+
+/// Draws entities as outlines of their bounding boxes, coloured by what kind of entity they are
+pub struct EntityRenderer {
+    prog: Program,
+    // Outline of a box 1 block in size, centred on x and z with its bottom at 0
+    model: VertexBuffer<Vertex>,
+}
+
+impl EntityRenderer {
+    pub fn new(dis: &Display) -> EntityRenderer {
+        let prog = shader::compile_shaders(
+            dis,
+            include_bytes!("../../shaders/hitboxes/v.glsl"),
+            include_bytes!("../../shaders/hitboxes/f.glsl"),
+        )
+        .expect("Failed to compile shaders");
+
+        EntityRenderer {
+            prog,
+            model: VertexBuffer::new(dis, &entities::hitbox_model()).unwrap(),
+        }
+    }
+
+    pub fn render(&self, target: &mut Frame, pvmat: &Mat4, ents: &HashMap<i32, Entity>) {
+        let params = DrawParameters {
+            depth: Depth {
+                test: draw_parameters::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            backface_culling: BackfaceCullingMode::CullingDisabled,
+            polygon_mode: PolygonMode::Line,
+            line_width: Some(2.0),
+            ..Default::default()
+        };
+
+        let inds = NoIndices(LinesList);
+        let pvmat = pvmat.to_cols_array_2d();
+
+        for ent in ents.values() {
+            let uniforms = uniform! {
+                pvmat: pvmat,
+                tmat: entity_transform(ent).to_cols_array_2d(),
+                colour: kind_colour(ent.get_kind()),
+            };
+
+            target
+                .draw(&self.model, inds, &self.prog, &uniforms, &params)
+                .expect("Error rendering hitbox");
+        }
+    }
+}
+
+/// Returns the transform that fits the box model to an entity's bounding box
+pub fn entity_transform(ent: &Entity) -> Mat4 {
+    let aabb = ent.get_bounding_box();
+    let size = aabb.size();
+    let base = aabb.min + Vec3::new(size.x / 2.0, 0.0, size.z / 2.0);
+
+    Mat4::from_translation(base) * Mat4::from_scale(size)
+}
+
+/// Returns the colour entities of a kind are outlined in
+fn kind_colour(kind: EntityKind) -> [f32; 3] {
+    match kind {
+        EntityKind::Player => [0.2, 0.6, 1.0],
+        EntityKind::Mob => [1.0, 0.3, 0.3],
+        EntityKind::Item => [1.0, 0.9, 0.2],
+        EntityKind::Projectile => [1.0, 0.5, 0.0],
+        EntityKind::Vehicle => [0.6, 0.4, 0.2],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::BVec3;
+
+    use crate::resources::PLAYER_INDEX;
+
+    use super::*;
+
+    #[test]
+    fn box_vertices_are_bounding_box_corners() {
+        let mut ent = Entity::new(PLAYER_INDEX as u32);
+        ent.pos = Vec3::new(4.5, 70.0, -12.25);
+        ent.prev_pos = ent.pos;
+
+        let aabb = ent.get_bounding_box();
+        assert!(aabb.size().abs_diff_eq(Vec3::new(0.6, 1.8, 0.6), 1e-4));
+
+        let corners: Vec<Vec3> = (0..8)
+            .map(|i| {
+                Vec3::select(
+                    BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0),
+                    aabb.max,
+                    aabb.min,
+                )
+            })
+            .collect();
+
+        // Every vertex of the outline is on a corner, and every corner has an edge to it
+        let tmat = entity_transform(&ent);
+        let vertices: Vec<Vec3> = entities::hitbox_model()
+            .iter()
+            .map(|v| tmat.transform_point3(Vec3::from(v.position)))
+            .collect();
+        for v in &vertices {
+            assert!(
+                corners.iter().any(|c| c.abs_diff_eq(*v, 1e-4)),
+                "{} isn't a corner",
+                v
+            );
+        }
+        for c in &corners {
+            assert!(vertices.iter().any(|v| c.abs_diff_eq(*v, 1e-4)));
+        }
+    }
+
+    #[test]
+    fn kinds_have_different_colours() {
+        let kinds = [
+            EntityKind::Player,
+            EntityKind::Mob,
+            EntityKind::Item,
+            EntityKind::Projectile,
+            EntityKind::Vehicle,
+        ];
+        for (i, a) in kinds.iter().enumerate() {
+            for b in &kinds[i + 1..] {
+                assert_ne!(kind_colour(*a), kind_colour(*b));
+            }
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use glam::{Mat4, Vec3};
+use glium::{
+    draw_parameters::{self, AnySamplesPassedQuery},
+    index::{NoIndices, PrimitiveType::TrianglesList},
+    uniform, BackfaceCullingMode, Depth, Display, DrawParameters, Frame, Program, Surface,
+    VertexBuffer,
+};
+
+use crate::world::{aabb::Aabb, SectionLocation};
+
+use super::{shader, Vertex};
+
+/// What is known about whether a section is hidden behind other sections
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryState {
+    /// No query has been issued yet so the section is drawn
+    Unknown,
+    /// A query is waiting for the GPU, the section is drawn or skipped by the last result until
+    /// it finishes
+    Pending { visible: bool },
+    /// The last query has finished, and a new one can be issued
+    Resolved { visible: bool },
+}
+
+impl QueryState {
+    /// Returns true if the section should be drawn
+    pub fn is_visible(&self) -> bool {
+        match *self {
+            QueryState::Unknown => true,
+            QueryState::Pending { visible } | QueryState::Resolved { visible } => visible,
+        }
+    }
+
+    /// Returns true if a new query should be issued for the section
+    pub fn needs_query(&self) -> bool {
+        !matches!(self, QueryState::Pending { .. })
+    }
+
+    /// Returns the state after a query has been issued
+    pub fn issued(self) -> QueryState {
+        QueryState::Pending {
+            visible: self.is_visible(),
+        }
+    }
+
+    /// Returns the state after a query has finished
+    pub fn resolved(self, visible: bool) -> QueryState {
+        QueryState::Resolved { visible }
+    }
+}
+
+/// Skips sections hidden behind other sections by drawing their bounding boxes against the depth
+/// of the opaque pass. Results are read a frame or more after the query was issued so the CPU
+/// never waits for the GPU, sections coming into view can show up a frame late
+pub struct OcclusionCuller {
+    prog: Program,
+    // Triangles of a box 1 block in size from 0 to 1 on every axis
+    cube: VertexBuffer<Vertex>,
+    sections: HashMap<SectionLocation, (QueryState, Option<AnySamplesPassedQuery>)>,
+}
+
+impl OcclusionCuller {
+    pub fn new(dis: &Display) -> OcclusionCuller {
+        let prog = shader::compile_shaders(
+            dis,
+            include_bytes!("../../shaders/hitboxes/v.glsl"),
+            include_bytes!("../../shaders/hitboxes/f.glsl"),
+        )
+        .expect("Failed to compile shaders");
+
+        OcclusionCuller {
+            prog,
+            cube: VertexBuffer::new(dis, &cube_model()).unwrap(),
+            sections: HashMap::new(),
+        }
+    }
+
+    /// Reads the results of the queries the GPU has finished
+    pub fn poll(&mut self) {
+        for (state, query) in self.sections.values_mut() {
+            if query.as_ref().map(|q| q.is_ready()).unwrap_or(false) {
+                // Reading the result consumes the query
+                if let Some(q) = query.take() {
+                    *state = state.resolved(q.get());
+                }
+            }
+        }
+    }
+
+    /// Returns true if a section should be drawn, sections without a query result are drawn
+    pub fn is_visible(&self, loc: &SectionLocation) -> bool {
+        self.sections
+            .get(loc)
+            .map(|(state, _)| state.is_visible())
+            .unwrap_or(true)
+    }
+
+    /// Issues queries for the bounding boxes of sections, which must be drawn after the opaque
+    /// pass. Sections that aren't in the list are forgotten and are drawn until a new result
+    /// comes back
+    ///
+    /// # Arguments
+    ///
+    /// * `sections` - Every section inside the view frustum with its bounds
+    pub fn issue_queries(
+        &mut self,
+        dis: &Display,
+        target: &mut Frame,
+        pvmat: &Mat4,
+        sections: &[(SectionLocation, Aabb)],
+    ) {
+        self.sections
+            .retain(|loc, _| sections.iter().any(|(l, _)| l == loc));
+
+        let pvmat = pvmat.to_cols_array_2d();
+        for (loc, aabb) in sections {
+            let (state, query) = self
+                .sections
+                .entry(*loc)
+                .or_insert((QueryState::Unknown, None));
+            if !state.needs_query() {
+                continue;
+            }
+
+            let q = match AnySamplesPassedQuery::new(dis, true) {
+                Ok(q) => q,
+                Err(e) => {
+                    log::warn!("Couldn't create occlusion query: {:?}", e);
+                    return;
+                }
+            };
+
+            let params = DrawParameters {
+                depth: Depth {
+                    test: draw_parameters::DepthTest::IfLessOrEqual,
+                    write: false,
+                    ..Default::default()
+                },
+                color_mask: (false, false, false, false),
+                backface_culling: BackfaceCullingMode::CullingDisabled,
+                samples_passed_query: Some((&q).into()),
+                ..Default::default()
+            };
+            let tmat = Mat4::from_translation(aabb.min) * Mat4::from_scale(aabb.size());
+            let uniforms = uniform! {
+                pvmat: pvmat,
+                tmat: tmat.to_cols_array_2d(),
+                colour: [0.0f32; 3],
+            };
+            target
+                .draw(
+                    &self.cube,
+                    NoIndices(TrianglesList),
+                    &self.prog,
+                    &uniforms,
+                    &params,
+                )
+                .expect("Error drawing occlusion query");
+
+            *state = state.issued();
+            *query = Some(q);
+        }
+    }
+
+    /// Forgets every result, for when occlusion culling is turned off
+    pub fn clear(&mut self) {
+        self.sections.clear();
+    }
+}
+
+/// Returns the 12 triangles of a box from 0 to 1 on every axis
+fn cube_model() -> Vec<Vertex> {
+    let corner = |i: usize| Vertex {
+        position: [(i & 1) as f32, (i >> 1 & 1) as f32, (i >> 2 & 1) as f32],
+    };
+    // Corners of each face, indexed by their x, y and z bits
+    let faces = [
+        [0, 2, 6, 4],
+        [1, 3, 7, 5],
+        [0, 1, 5, 4],
+        [2, 3, 7, 6],
+        [0, 1, 3, 2],
+        [4, 5, 7, 6],
+    ];
+
+    faces
+        .iter()
+        .flat_map(|f| [f[0], f[1], f[2], f[0], f[2], f[3]])
+        .map(corner)
+        .collect()
+}
+
+/// Returns true if a point is inside a box grown by a margin, sections this close to the camera
+/// are always drawn since the near plane can clip away their query box
+pub fn near_box(aabb: &Aabb, point: Vec3, margin: f32) -> bool {
+    Aabb::new(
+        aabb.min - Vec3::splat(margin),
+        aabb.max + Vec3::splat(margin),
+    )
+    .contains(point)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_state_transitions() {
+        // Drawn until the first query says otherwise
+        let state = QueryState::Unknown;
+        assert!(state.is_visible());
+        assert!(state.needs_query());
+
+        // Keeps the last answer while the query is pending, and no second query is issued
+        let state = state.issued();
+        assert_eq!(state, QueryState::Pending { visible: true });
+        assert!(state.is_visible());
+        assert!(!state.needs_query());
+
+        let state = state.resolved(false);
+        assert_eq!(state, QueryState::Resolved { visible: false });
+        assert!(!state.is_visible());
+        assert!(state.needs_query());
+
+        // A hidden section stays hidden while it's queried again, until the result shows it
+        let state = state.issued();
+        assert!(!state.is_visible());
+        let state = state.resolved(true);
+        assert!(state.is_visible());
+    }
+
+    #[test]
+    fn camera_near_box() {
+        let aabb = Aabb::new(Vec3::ZERO, Vec3::splat(16.0));
+        assert!(near_box(&aabb, Vec3::splat(8.0), 0.5));
+        assert!(near_box(&aabb, Vec3::new(-0.25, 8.0, 8.0), 0.5));
+        assert!(!near_box(&aabb, Vec3::new(-1.0, 8.0, 8.0), 0.5));
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::VecDeque;
+
+use glam::Mat4;
+use glium::{
+    draw_parameters, implement_vertex,
+    index::{NoIndices, PrimitiveType::Points},
+    uniform, Depth, Display, DrawParameters, Frame, Program, Surface, VertexBuffer,
+};
+
+use crate::particles::SpawnedParticle;
+
+use super::shader;
+
+// Size in pixels of a particle one block from the camera
+const PARTICLE_SIZE: f32 = 48.0;
+
+#[derive(Debug, Copy, Clone)]
+struct ParticleVertex {
+    position: [f32; 3],
+    colour: [f32; 3],
+}
+implement_vertex!(ParticleVertex, position, colour);
+
+/// Draws particles as coloured points, standing in for textured billboards
+pub struct ParticleRenderer {
+    prog: Program,
+}
+
+impl ParticleRenderer {
+    pub fn new(dis: &Display) -> ParticleRenderer {
+        let prog = shader::compile_shaders(
+            dis,
+            include_bytes!("../../shaders/particles/v.glsl"),
+            include_bytes!("../../shaders/particles/f.glsl"),
+        )
+        .expect("Failed to compile shaders");
+
+        ParticleRenderer { prog }
+    }
+
+    pub fn render(
+        &self,
+        dis: &Display,
+        target: &mut Frame,
+        pvmat: &Mat4,
+        particles: &VecDeque<SpawnedParticle>,
+    ) {
+        if particles.is_empty() {
+            return;
+        }
+
+        let vertices: Vec<ParticleVertex> = particles
+            .iter()
+            .map(|p| ParticleVertex {
+                position: p.position.to_array(),
+                colour: p.colour,
+            })
+            .collect();
+        let points = match VertexBuffer::new(dis, &vertices) {
+            Ok(points) => points,
+            Err(e) => {
+                log::warn!("Couldn't create particle buffer: {:?}", e);
+                return;
+            }
+        };
+
+        let params = DrawParameters {
+            depth: Depth {
+                test: draw_parameters::DepthTest::IfLess,
+                write: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let uniforms = uniform! {
+            pvmat: pvmat.to_cols_array_2d(),
+            size: PARTICLE_SIZE,
+        };
+
+        target
+            .draw(&points, NoIndices(Points), &self.prog, &uniforms, &params)
+            .expect("Error drawing particles");
+    }
+}
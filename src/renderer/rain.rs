@@ -0,0 +1,159 @@
+use std::time::Instant;
+
+use glam::{IVec2, Mat4, Vec3};
+use glium::{
+    draw_parameters,
+    index::{NoIndices, PrimitiveType::LinesList},
+    uniform, Depth, Display, DrawParameters, Frame, Program, Surface, VertexBuffer,
+};
+
+use crate::world::World;
+
+use super::{shader, Vertex};
+
+// Blocks around the camera on each axis that rain falls in
+const RAIN_RADIUS: i32 = 10;
+// Blocks per second drops fall at, and the length of each drop and the gap between them
+const FALL_SPEED: f32 = 12.0;
+const DROP_LENGTH: f32 = 1.0;
+const DROP_SPACING: f32 = 4.0;
+// Half the width of the cross drawn where a drop lands, and how far the drop would have fallen
+// past the ground while it's shown
+const SPLASH_SIZE: f32 = 0.15;
+const SPLASH_DISTANCE: f32 = 2.0;
+
+/// Draws rain falling around the camera as streaks, stopping at the highest block in each column
+/// so it doesn't fall through roofs
+pub struct RainRenderer {
+    prog: Program,
+    start: Instant,
+}
+
+impl RainRenderer {
+    pub fn new(dis: &Display) -> RainRenderer {
+        let prog = shader::compile_shaders(
+            dis,
+            include_bytes!("../../shaders/hitboxes/v.glsl"),
+            include_bytes!("../../shaders/hitboxes/f.glsl"),
+        )
+        .expect("Failed to compile shaders");
+
+        RainRenderer {
+            prog,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn render(
+        &self,
+        dis: &Display,
+        target: &mut Frame,
+        pvmat: &Mat4,
+        world: &World,
+        pos: Vec3,
+    ) {
+        let level = world.get_weather().get_rain_level();
+        if level <= 0.0 {
+            return;
+        }
+
+        let vertices = rain_lines(world, pos, level, self.start.elapsed().as_secs_f32());
+        if vertices.is_empty() {
+            return;
+        }
+        let lines = match VertexBuffer::new(dis, &vertices) {
+            Ok(lines) => lines,
+            Err(e) => {
+                log::warn!("Couldn't create rain buffer: {:?}", e);
+                return;
+            }
+        };
+
+        let params = DrawParameters {
+            depth: Depth {
+                test: draw_parameters::DepthTest::IfLess,
+                write: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let uniforms = uniform! {
+            pvmat: pvmat.to_cols_array_2d(),
+            tmat: Mat4::IDENTITY.to_cols_array_2d(),
+            colour: [0.45f32, 0.55, 0.8],
+        };
+
+        target
+            .draw(&lines, NoIndices(LinesList), &self.prog, &uniforms, &params)
+            .expect("Error drawing rain");
+    }
+}
+
+/// Returns pairs of points making up drops of rain falling in columns around a position, with a
+/// splash where they land on the highest block. Fewer columns have rain the lighter it is
+///
+/// # Arguments
+///
+/// * `world` - World to find the surface of each column in, columns in unloaded chunks are skipped
+/// * `centre` - Position rain falls around, normally the camera
+/// * `level` - How heavily it's raining from 0 to 1
+/// * `time` - Seconds used to animate the drops falling
+pub fn rain_lines(world: &World, centre: Vec3, level: f32, time: f32) -> Vec<Vertex> {
+    let point = |x: f32, y: f32, z: f32| Vertex {
+        position: [x, y, z],
+    };
+
+    let mut lines = Vec::new();
+    let top = centre.y + RAIN_RADIUS as f32;
+    for dx in -RAIN_RADIUS..=RAIN_RADIUS {
+        for dz in -RAIN_RADIUS..=RAIN_RADIUS {
+            if dx * dx + dz * dz > RAIN_RADIUS * RAIN_RADIUS {
+                continue;
+            }
+            let column = IVec2::new(centre.x.floor() as i32 + dx, centre.z.floor() as i32 + dz);
+            let random = column_random(column);
+            if random >= level {
+                continue;
+            }
+
+            let ground = match world.get_highest_block(&column) {
+                Some(y) => (y + 1) as f32,
+                None => continue,
+            };
+            if ground >= top {
+                continue;
+            }
+            let bottom = ground.max(centre.y - RAIN_RADIUS as f32);
+
+            // Each drop is somewhere across the column so they don't line up with the blocks
+            let x = column.x as f32 + 0.2 + random / level * 0.6;
+            let z = column.y as f32 + 0.8 - random / level * 0.6;
+
+            let fallen = time * FALL_SPEED + random * DROP_SPACING * 10.0;
+            let mut y = top - fallen.rem_euclid(DROP_SPACING);
+            while y > bottom {
+                lines.push(point(x, y, z));
+                lines.push(point(x, (y - DROP_LENGTH).max(bottom), z));
+                y -= DROP_SPACING;
+            }
+
+            // Splash for a moment each time a drop reaches the ground
+            let since_landing = DROP_SPACING - (top - ground - fallen).rem_euclid(DROP_SPACING);
+            if bottom == ground && since_landing < SPLASH_DISTANCE {
+                let y = ground + 0.05;
+                lines.push(point(x - SPLASH_SIZE, y, z));
+                lines.push(point(x + SPLASH_SIZE, y, z));
+                lines.push(point(x, y, z - SPLASH_SIZE));
+                lines.push(point(x, y, z + SPLASH_SIZE));
+            }
+        }
+    }
+    lines
+}
+
+/// Returns a number from 0 to 1 that's always the same for a column
+fn column_random(column: IVec2) -> f32 {
+    let hash =
+        (column.x as u32).wrapping_mul(73_856_093) ^ (column.y as u32).wrapping_mul(19_349_663);
+    (hash % 1000) as f32 / 1000.0
+}
@@ -0,0 +1,135 @@
+use glam::{Vec2, Vec3, Vec4};
+use glium::{
+    index::{NoIndices, PrimitiveType::TrianglesList},
+    uniform, Display, DrawParameters, Frame, Program, Surface, VertexBuffer,
+};
+
+use crate::world::aabb::Aabb;
+
+use super::{camera::Camera, shader, Vertex};
+
+// Fraction of the fog distance where fog starts to fade in
+const FOG_START: f32 = 0.6;
+
+/// Draws a gradient behind the world from the fog colour at the horizon to the sky colour above
+pub struct SkyRenderer {
+    prog: Program,
+    // Triangle covering the whole screen
+    screen: VertexBuffer<Vertex>,
+}
+
+impl SkyRenderer {
+    pub fn new(dis: &Display) -> SkyRenderer {
+        let prog = shader::compile_shaders(
+            dis,
+            include_bytes!("../../shaders/sky/v.glsl"),
+            include_bytes!("../../shaders/sky/f.glsl"),
+        )
+        .expect("Failed to compile shaders");
+
+        let corner = |x: f32, y: f32| Vertex {
+            position: [x, y, 0.0],
+        };
+        let screen = vec![corner(-1.0, -1.0), corner(3.0, -1.0), corner(-1.0, 3.0)];
+
+        SkyRenderer {
+            prog,
+            screen: VertexBuffer::new(dis, &screen).unwrap(),
+        }
+    }
+
+    /// Fills the screen with the sky, which must be drawn before anything else since it doesn't
+    /// test or write depth
+    ///
+    /// # Arguments
+    ///
+    /// * `sky` - Colour straight up
+    /// * `horizon` - Colour at and below the horizon, which should match the fog
+    pub fn render(&self, target: &mut Frame, cam: &Camera, sky: Vec3, horizon: Vec3) {
+        // Only the direction of each pixel matters so the camera's translation is left out
+        let mut vmat = *cam.get_vmat();
+        vmat.w_axis = Vec4::W;
+        let inv_pvmat = (*cam.get_pmat() * vmat).inverse();
+
+        let uniforms = uniform! {
+            invPvmat: inv_pvmat.to_cols_array_2d(),
+            skyCol: sky.to_array(),
+            horizonCol: horizon.to_array(),
+        };
+        target
+            .draw(
+                &self.screen,
+                NoIndices(TrianglesList),
+                &self.prog,
+                &uniforms,
+                &DrawParameters::default(),
+            )
+            .expect("Error drawing sky");
+    }
+}
+
+/// Returns the horizontal distances from the camera where fog starts and where it completely
+/// hides the world, which is the edge of the render distance so chunks fade in rather than
+/// popping in as they load
+pub fn fog_range(render_distance: u8) -> (f32, f32) {
+    let far = render_distance as f32 * 16.0;
+    (far * FOG_START, far)
+}
+
+/// Returns how much of a point's colour is replaced by fog, from 0 before the fog starts to 1 at
+/// the far end. Matches the fog in the chunk shader
+pub fn fog_factor(distance: f32, near: f32, far: f32) -> f32 {
+    if far <= near {
+        return if distance < far { 0.0 } else { 1.0 };
+    }
+    let t = ((distance - near) / (far - near)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Returns the horizontal distance from a point to the nearest part of a box, which is how far
+/// fog is measured
+pub fn horizontal_distance(aabb: &Aabb, point: Vec3) -> f32 {
+    let p = Vec2::new(point.x, point.z);
+    let nearest = p.clamp(
+        Vec2::new(aabb.min.x, aabb.min.z),
+        Vec2::new(aabb.max.x, aabb.max.z),
+    );
+    p.distance(nearest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fog_factor_from_camera_to_far_plane() {
+        let (near, far) = fog_range(8);
+        assert_eq!((near, far), (128.0 * FOG_START, 128.0));
+
+        assert_eq!(fog_factor(0.0, near, far), 0.0);
+        assert_eq!(fog_factor(near, near, far), 0.0);
+        assert!((fog_factor((near + far) / 2.0, near, far) - 0.5).abs() < 1e-5);
+        assert_eq!(fog_factor(far, near, far), 1.0);
+        assert_eq!(fog_factor(far * 2.0, near, far), 1.0);
+
+        // Thickens the further away it is
+        let mut last = 0.0;
+        for d in 0..=128 {
+            let f = fog_factor(d as f32, near, far);
+            assert!(f >= last);
+            last = f;
+        }
+
+        // A render distance of 0 puts the fog straight in front of the camera
+        let (near, far) = fog_range(0);
+        assert_eq!(fog_factor(0.0, near, far), 1.0);
+    }
+
+    #[test]
+    fn fog_measured_to_nearest_side_of_box() {
+        let aabb = Aabb::new(Vec3::new(16.0, 0.0, 0.0), Vec3::new(32.0, 256.0, 16.0));
+        assert_eq!(horizontal_distance(&aabb, Vec3::new(20.0, 500.0, 8.0)), 0.0);
+        assert_eq!(horizontal_distance(&aabb, Vec3::new(10.0, 64.0, 8.0)), 6.0);
+        assert_eq!(horizontal_distance(&aabb, Vec3::new(35.0, 64.0, 20.0)), 5.0);
+    }
+}
@@ -1,12 +1,23 @@
-use std::{collections::HashMap, io::Cursor};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    io::Cursor,
+    sync::{Mutex, RwLock},
+};
 
+use glam::Vec3;
 use inflector::Inflector;
 use lazy_static::lazy_static;
 use serde_json::{self, Value};
+use simple_error::{bail, require_with};
+
+use crate::{network::version::ProtocolVersion, world::aabb::Aabb};
 
-use self::block_models::BlockModel;
+use self::{block_models::BlockModel, loading::Resource};
 
+pub mod atlas;
 pub mod block_models;
+pub mod loading;
 
 pub struct Entity {
     pub name: String,
@@ -16,12 +27,141 @@ pub struct Entity {
     pub height: f32,
 }
 
+pub struct Item {
+    pub name: String,
+    pub id: u32,
+    pub max_stack_size: u8,
+    // Block state placed when using the item on a block, None for items that aren't blocks
+    pub block: Option<u32>,
+}
+
 #[derive(Debug)]
 pub struct BlockState {
     pub name: String,
     pub id: u32,
     pub models: Option<Vec<String>>,
     pub collision_shape: Option<u64>,
+    // Properties of this state of the block, e.g. facing=north or waterlogged=false
+    pub properties: HashMap<String, String>,
+    // How long the block takes to break, negative for blocks that can't be broken
+    pub hardness: f32,
+    // Whether the block only drops when broken with the right tool, which also slows breaking
+    pub requires_tool: bool,
+}
+
+impl BlockState {
+    /// Returns true if this is any of the air blocks, cave air and void air are just as empty as
+    /// air even though they have different ids
+    pub fn is_air(&self) -> bool {
+        matches!(self.name.as_str(), "Air" | "Cave Air" | "Void Air")
+    }
+
+    /// Returns the value of a property of this state, e.g. `facing` or `waterlogged`. None if the
+    /// block doesn't have the property
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(|v| v.as_str())
+    }
+
+    /// Returns the boxes making up the collision shape of this block in block space. Blocks
+    /// without a shape in the data, like air and plants, have no boxes
+    pub fn collision_shape(&self) -> &'static [Aabb] {
+        static FULL_CUBE: [Aabb; 1] = [Aabb::FULL_CUBE];
+
+        match self.collision_shape {
+            _ if self.is_air() => &[],
+            Some(shape) => match SHAPES.get(shape as usize) {
+                Some(boxes) => boxes,
+                // Shouldn't happen but solid is a safer guess for player collision
+                None => &FULL_CUBE,
+            },
+            None => &[],
+        }
+    }
+
+    /// Returns true if entities can't move through this block
+    pub fn has_collision(&self) -> bool {
+        !self.collision_shape().is_empty()
+    }
+
+    /// Returns the model used to render this block, which is the missing model if the block has a
+    /// model that couldn't be loaded. None for blocks that aren't rendered with a model
+    pub fn get_model(&self) -> Option<&'static BlockModel> {
+        let key = self.models.as_ref()?.get(0)?;
+        match BLOCK_MODELS_PARSED.get(key) {
+            Some(model) => Some(model),
+            None => {
+                log::debug!("Couldn't find model {}", key);
+                Some(&MISSING_MODEL)
+            }
+        }
+    }
+
+    /// Returns true if this block completely hides the faces of the blocks next to it
+    pub fn is_opaque(&self) -> bool {
+        match &self.models {
+            Some(models) => {
+                let model = models.get(0).map(|s| s.as_ref()).unwrap_or("");
+
+                let exceptions: Vec<&str> = vec!["glass", "leaves", "water", "spawner"];
+                for exception in exceptions {
+                    if model.contains(exception) {
+                        return false;
+                    }
+                }
+
+                BLOCK_MODELS_PARSED
+                    .get(model)
+                    .map(|m| m.get_cull_against())
+                    .unwrap_or(false)
+            }
+            None => false,
+        }
+    }
+
+    /// Returns true if this block is rendered as a single cube filling the whole block
+    pub fn is_full_cube(&self) -> bool {
+        !self.is_air() && self.get_model().map_or(false, |m| m.is_full_cube())
+    }
+
+    /// Returns how the faces of this block are drawn, depending on whether its textures are see
+    /// through
+    pub fn render_layer(&self) -> RenderLayer {
+        let model = match self.models.as_ref().and_then(|m| m.get(0)) {
+            Some(model) => model,
+            None => return RenderLayer::Solid,
+        };
+
+        let translucent = [
+            "water",
+            "stained_glass",
+            "ice",
+            "slime_block",
+            "honey_block",
+        ];
+        if translucent.iter().any(|t| model.contains(t))
+            && !model.contains("packed_ice")
+            && !model.contains("blue_ice")
+        {
+            return RenderLayer::Translucent;
+        }
+
+        if self.is_full_cube() && self.is_opaque() {
+            RenderLayer::Solid
+        } else {
+            RenderLayer::Cutout
+        }
+    }
+}
+
+/// How the faces of a block are blended with what is behind them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderLayer {
+    /// Every pixel is drawn
+    Solid,
+    /// Pixels are either drawn or discarded, like leaves and glass
+    Cutout,
+    /// Pixels are blended with what is behind them, like water and stained glass
+    Translucent,
 }
 
 #[derive(Debug)]
@@ -43,201 +183,462 @@ pub const MISSING_TEXTURE: BlockTexture = BlockTexture {
 
 lazy_static! {
 
-    pub static ref ENTITIES: HashMap<u32, Entity> = {
-        let mut entities = HashMap::new();
-
-        let json: HashMap<String, Value> =
-            serde_json::from_slice(include_bytes!("../assets/entities.min.json"))
-                .expect("Failed to interpret entities.json");
-        for (name, val) in json.iter() {
-            if let Some(id) = val.get("id") {
-                entities.insert(
-                    id.as_u64().unwrap() as u32,
-                    Entity {
-                        name: format_name(name),
-                        id: id.as_u64().unwrap() as u32,
-                        translation_key: val
-                            .get("loot_table")
-                            .unwrap()
-                            .as_str()
-                            .unwrap()
-                            .to_string(),
-                        width: val.get("width").unwrap().as_f64().unwrap() as f32,
-                        height: val.get("height").unwrap().as_f64().unwrap() as f32,
-                    },
-                );
-            }
+    /// Translations for the English language, from translation keys to format strings
+    pub static ref LANG: HashMap<String, String> =
+        serde_json::from_slice(include_bytes!("../assets/en_us.json"))
+            .expect("Failed to interpret en_us.json");
+
+    // Block states of other versions, loaded when first joining a server on that version
+    static ref VERSION_BLOCKS: Mutex<HashMap<ProtocolVersion, &'static HashMap<u32, BlockState>>> =
+        Mutex::new(HashMap::new());
+    // Block states that ids are looked up in by block_state, which are the ones of the server's
+    // version
+    static ref ACTIVE_BLOCKS: RwLock<&'static HashMap<u32, BlockState>> = RwLock::new(&*BLOCKS);
+    pub static ref MISSING_MODEL: BlockModel = BlockModel::block_missing();
+    /// Stands in for block state ids that aren't in the block data, such as blocks from newer
+    /// versions. Its model doesn't exist so it's drawn as a cube with the missing texture
+    pub static ref UNKNOWN_BLOCK: BlockState = BlockState {
+        name: String::from("Unknown Block"),
+        id: u32::MAX,
+        models: Some(vec![String::from("unknown")]),
+        collision_shape: None,
+        properties: HashMap::new(),
+        hardness: -1.0,
+        requires_tool: false,
+    };
+    // Unknown ids that have already been logged
+    static ref UNKNOWN_IDS: Mutex<HashSet<u32>> = Mutex::new(HashSet::new());
+}
+
+pub static ENTITIES: Resource<HashMap<u32, Entity>> = Resource::new(load_entities);
+/// Block states of the version whose data is built into the client, by state id
+pub static BLOCKS: Resource<HashMap<u32, BlockState>> = Resource::new(load_builtin_blocks);
+/// Items by id, with the default state of the block each block item places
+pub static ITEMS: Resource<HashMap<u32, Item>> = Resource::new(load_items);
+/// Names of sound events by registry id, e.g. `minecraft:block.stone.break`
+pub static SOUNDS: Resource<HashMap<u32, String>> = Resource::new(load_sounds);
+/// Collision shapes referenced by block states, made up of boxes in block space
+pub static SHAPES: Resource<Vec<Vec<Aabb>>> = Resource::new(load_shapes);
+pub static BLOCK_MODELS_RAW: Resource<HashMap<String, Value>> = Resource::new(load_raw_models);
+pub static BLOCK_MODELS_PARSED: Resource<HashMap<String, BlockModel>> =
+    Resource::new(load_parsed_models);
+pub static BLOCK_TEXTURES: Resource<HashMap<String, BlockTexture>> =
+    Resource::new(load_block_textures);
+
+fn load_entities() -> Result<HashMap<u32, Entity>, Box<dyn Error>> {
+    let mut entities = HashMap::new();
+
+    let json: HashMap<String, Value> =
+        serde_json::from_slice(include_bytes!("../assets/entities.min.json"))?;
+    for (name, val) in json.iter() {
+        if let Some(id) = val.get("id") {
+            let id = require_with!(id.as_u64(), "Entity {} has an invalid id", name) as u32;
+            let loot_table = val.get("loot_table").and_then(Value::as_str);
+            let width = val.get("width").and_then(Value::as_f64);
+            let height = val.get("height").and_then(Value::as_f64);
+            entities.insert(
+                id,
+                Entity {
+                    name: format_name(name),
+                    id,
+                    translation_key: require_with!(loot_table, "Entity {} has no loot table", name)
+                        .to_string(),
+                    width: require_with!(width, "Entity {} has no width", name) as f32,
+                    height: require_with!(height, "Entity {} has no height", name) as f32,
+                },
+            );
         }
+    }
 
-        entities
-    };
-    pub static ref BLOCKS: HashMap<u32, BlockState> = {
-        let mut blocks = HashMap::new();
-
-        let json: HashMap<String, Value> =
-            serde_json::from_slice(include_bytes!("../assets/blocks.min.json"))
-                .expect("Failed to interpret blocks.json");
-        for (name, val) in json.iter() {
-            let name = format_name(name);
-            for (id, state) in val.get("states").unwrap().as_object().unwrap().iter() {
-                let id = id.parse().unwrap();
-                blocks.insert(
+    Ok(entities)
+}
+
+fn load_builtin_blocks() -> Result<HashMap<u32, BlockState>, Box<dyn Error>> {
+    let json: HashMap<String, Value> =
+        serde_json::from_slice(include_bytes!("../assets/blocks.min.json"))?;
+    parse_blocks(&json)
+}
+
+fn load_items() -> Result<HashMap<u32, Item>, Box<dyn Error>> {
+    let blocks: HashMap<String, Value> =
+        serde_json::from_slice(include_bytes!("../assets/blocks.min.json"))?;
+    // Blocks are referred to by items with their registry id, which isn't a state id
+    let default_states: HashMap<u64, u32> = blocks
+        .values()
+        .filter_map(|block| Some((block.get("id")?.as_u64()?, default_state(block)?)))
+        .collect();
+
+    let json: HashMap<String, Value> =
+        serde_json::from_slice(include_bytes!("../assets/items.min.json"))?;
+    Ok(json
+        .iter()
+        .filter_map(|(name, val)| {
+            let id = val.get("id")?.as_u64()? as u32;
+            let block = match val.get("block") {
+                Some(Value::Number(block)) => {
+                    block.as_u64().and_then(|b| default_states.get(&b).copied())
+                }
+                Some(Value::String(block)) => blocks.get(block).and_then(default_state),
+                _ => None,
+            };
+            Some((
+                id,
+                Item {
+                    name: format_name(name),
                     id,
-                    BlockState {
-                        name: name.clone(),
-                        id,
-                        models: {
-                            match state.get("render") {
-                                // Has a single model
-                                Some(serde_json::Value::Object(render)) => {
-                                    if let Some(serde_json::Value::String(model)) = render.get("model") {
-                                        Some(vec![model.clone()])
-                                    } else {
-                                        log::error!("Couldn't find models in block state: {:?}", state);
-                                        None
-                                    }
-                                },
-                                // Has an array of models
-                                Some(serde_json::Value::Array(render)) => {
-                                    let mut vec = Vec::new();
-
-                                    for render in render {
-                                        if let Some(serde_json::Value::String(model)) = render.get("model") {
-                                            vec.push(model.clone());
-                                        } else {
-                                            if let serde_json::Value::Array(render) = render {
-                                                for render in render {
-                                                    if let Some(serde_json::Value::String(model)) = render.get("model") {
-                                                        vec.push(model.clone());
-                                                    } else {
-                                                        log::error!("Couldn't find model in render section 2");
-                                                    }
-                                                }
-                                            } else {
-                                                println!("{:?}", render);
-                                                panic!();
-                                            }
-                                        }
-                                    }
-
-                                    if vec.len() == 0 {
-                                        println!("{:?}", render);
-                                        panic!("No models in render section");
-                                    }
-
-                                    Some(vec)
-                                },
-                                _ => None,
-                            }
-                        },
-                        collision_shape: {
-                            match state.get("collision_shape") {
-                                Some(collision_shape) => collision_shape.as_u64(),
-                                None => None,
-                            }
-                        },
-                    },
-                );
-            }
+                    max_stack_size: val
+                        .get("max_stack_size")
+                        .and_then(Value::as_u64)
+                        .unwrap_or(64) as u8,
+                    block,
+                },
+            ))
+        })
+        .collect())
+}
+
+fn load_sounds() -> Result<HashMap<u32, String>, Box<dyn Error>> {
+    let json: Value = serde_json::from_slice(include_bytes!("../assets/registries.min.json"))?;
+    Ok(json
+        .get("sound_event")
+        .and_then(|r| r.get("entries"))
+        .and_then(Value::as_object)
+        .map(|entries| {
+            entries
+                .iter()
+                .filter_map(|(name, val)| Some((val.get("id")?.as_u64()? as u32, name.clone())))
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+fn load_shapes() -> Result<Vec<Vec<Aabb>>, Box<dyn Error>> {
+    let json: Value = serde_json::from_slice(include_bytes!("../assets/shapes.min.json"))?;
+
+    let aabbs: Vec<Aabb> = require_with!(
+        json.get("aabbs").and_then(Value::as_array),
+        "Missing aabbs in shapes.json"
+    )
+    .iter()
+    .map(|aabb| {
+        Aabb::new(
+            parse_shape_point(aabb.get("from")),
+            parse_shape_point(aabb.get("to")),
+        )
+    })
+    .collect();
+
+    let shapes = require_with!(
+        json.get("shapes").and_then(Value::as_array),
+        "Missing shapes in shapes.json"
+    );
+    Ok(shapes
+        .iter()
+        .map(|shape| {
+            // Shapes are either the index of a single box or an array of indices
+            let indices = match shape {
+                Value::Array(indices) => indices.iter().filter_map(Value::as_u64).collect(),
+                Value::Number(index) => index.as_u64().into_iter().collect(),
+                _ => Vec::new(),
+            };
+            indices
+                .into_iter()
+                .filter_map(|i| aabbs.get(i as usize).copied())
+                .collect()
+        })
+        .collect())
+}
+
+fn load_raw_models() -> Result<HashMap<String, Value>, Box<dyn Error>> {
+    Ok(serde_json::from_slice(include_bytes!(
+        "../assets/models.min.json"
+    ))?)
+}
+
+fn load_parsed_models() -> Result<HashMap<String, BlockModel>, Box<dyn Error>> {
+    let mut models = HashMap::new();
+
+    for (key, data) in BLOCK_MODELS_RAW.try_load()?.iter() {
+        if models.contains_key(key) {
+            continue;
         }
 
-        blocks
-    };
-    pub static ref BLOCK_MODELS_RAW: HashMap<String, Value> =
-        serde_json::from_slice(include_bytes!("../assets/models.min.json"))
-            .expect("Failed to interpret models.json");
-    pub static ref BLOCK_MODELS_PARSED: HashMap<String, BlockModel> = {
-        let mut models = HashMap::new();
-
-        for (key, data) in BLOCK_MODELS_RAW.iter() {
-            if models.contains_key(key) { continue; }
-
-            match BlockModel::parse(data, Some(&mut models)) {
-                Ok(model) => { models.insert(key.clone(), model); },
-                Err(e) => { log::debug!("Couldn't parse block model: {:?}", e); },
+        match BlockModel::parse(data, Some(&mut models)) {
+            Ok(model) => {
+                models.insert(key.clone(), model);
+            }
+            Err(e) => {
+                log::debug!("Couldn't parse block model: {:?}", e);
             }
         }
+    }
 
-        models
-    };
-    pub static ref BLOCK_TEXTURES: HashMap<String, BlockTexture> = {
-        // Get list of texture and metadata files available
-        let mut textures: Vec<_> = std::fs::read_dir("assets/textures/block/")
-            .expect("Couldn't find textures directory")
-            .filter_map(|f| f.ok())
-            .filter(|f| {
-                let name = f.file_name();
-                let name = name.to_string_lossy();
-                name.ends_with(".png") || name.ends_with(".mcmeta")
-            }).collect();
-        let metadata: Vec<_> = textures.drain_filter(|f| {
-            f.file_name().to_string_lossy().ends_with(".mcmeta")
-        }).collect();
-
-        let mut out = HashMap::new();
-
-        // Load textures
-        image::load(Cursor::new(&include_bytes!("../assets/missing_texture.png")), image::ImageFormat::Png).unwrap().to_rgba8();
-        out.insert(String::new(), MISSING_TEXTURE);
-
-        let mut index: usize = 1; // Reserve index 0 for missing texture
-        for tex in textures {
-            let full_name = tex.file_name();
-            let full_name = full_name.to_string_lossy();
-            let name = full_name.split(".").nth(0).unwrap();
-
-            let data = std::fs::read(tex.path());
-            if data.is_err() {continue;}
-            let data = data.unwrap();
-            let img = image::load(Cursor::new(&data), image::ImageFormat::Png).unwrap().to_rgba8();
-
-            let mut frames = Vec::new();
-            if img.height() == 16 {
-                // Load single texture
-                frames.push(img);
-            } else {
-                // Load as multiple textures
-                let num_frames = img.height() / 16;
-                for i in 0..num_frames {
-                    frames.push(image::SubImage::new(&img, 0, i * 16, 16, 16).to_image());
-                }
+    Ok(models)
+}
+
+fn load_block_textures() -> Result<HashMap<String, BlockTexture>, Box<dyn Error>> {
+    // Get list of texture and metadata files available
+    let mut textures: Vec<_> = std::fs::read_dir("assets/textures/block/")
+        .map_err(|e| format!("Couldn't find textures directory: {}", e))?
+        .filter_map(|f| f.ok())
+        .filter(|f| {
+            let name = f.file_name();
+            let name = name.to_string_lossy();
+            name.ends_with(".png") || name.ends_with(".mcmeta")
+        })
+        .collect();
+    let metadata: Vec<_> = textures
+        .drain_filter(|f| f.file_name().to_string_lossy().ends_with(".mcmeta"))
+        .collect();
+
+    let mut out = HashMap::new();
+
+    // Load textures
+    image::load(
+        Cursor::new(&include_bytes!("../assets/missing_texture.png")),
+        image::ImageFormat::Png,
+    )?;
+    out.insert(String::new(), MISSING_TEXTURE);
+
+    let mut index: usize = 1; // Reserve index 0 for missing texture
+    for tex in textures {
+        let full_name = tex.file_name();
+        let full_name = full_name.to_string_lossy();
+        let name = full_name.split('.').next().unwrap_or_default();
+
+        let data = match std::fs::read(tex.path()) {
+            Ok(data) => data,
+            Err(_) => continue,
+        };
+        let img = image::load(Cursor::new(&data), image::ImageFormat::Png)
+            .map_err(|e| format!("Couldn't read texture {}: {}", full_name, e))?
+            .to_rgba8();
+
+        let mut frames = Vec::new();
+        if img.height() == 16 {
+            // Load single texture
+            frames.push(img);
+        } else {
+            // Load as multiple textures
+            let num_frames = img.height() / 16;
+            for i in 0..num_frames {
+                frames.push(image::SubImage::new(&img, 0, i * 16, 16, 16).to_image());
             }
-            let inc = frames.len();
+        }
+        let inc = frames.len();
 
-            out.insert(format!("minecraft:block/{}", name), BlockTexture {
+        out.insert(
+            format!("minecraft:block/{}", name),
+            BlockTexture {
                 index,
                 interpolation: false,
                 frames,
                 frametime: 0,
-            });
+            },
+        );
 
-            index += inc;
-        }
+        index += inc;
+    }
 
-        // Add any metadata
-        for metadata in metadata {
-            let full_name = metadata.file_name();
-            let full_name = full_name.to_string_lossy();
-            let name = full_name.split(".").nth(0).unwrap();
+    // Add any metadata
+    for metadata in metadata {
+        let full_name = metadata.file_name();
+        let full_name = full_name.to_string_lossy();
+        let name = full_name.split('.').next().unwrap_or_default();
 
-            if !out.contains_key(name){continue;}
-            let tex = out.get_mut(name).unwrap();
+        let tex = match out.get_mut(name) {
+            Some(tex) => tex,
+            None => continue,
+        };
 
-            let contents = std::fs::read_to_string(metadata.path()).unwrap();
-            let meta = serde_json::from_str::<serde_json::Value>(&contents).unwrap();
+        let contents = std::fs::read_to_string(metadata.path())?;
+        let meta = serde_json::from_str::<serde_json::Value>(&contents)?;
 
-            if let Some(anim) = meta.get("animation") {
-                if let Some(interp) = anim.get("interpolate") {
-                    tex.interpolation = interp.as_bool().unwrap();
-                }
-                if let Some(frametime) = anim.get("frametime") {
-                    tex.frametime = frametime.as_u64().unwrap() as usize;
-                }
+        if let Some(anim) = meta.get("animation") {
+            if let Some(interp) = anim.get("interpolate") {
+                tex.interpolation = interp.as_bool().unwrap_or(false);
+            }
+            if let Some(frametime) = anim.get("frametime") {
+                tex.frametime = frametime.as_u64().unwrap_or(1) as usize;
             }
         }
+    }
 
-        out
+    Ok(out)
+}
+
+/// Reads the block states by id from blocks.json
+///
+/// # Returns
+///
+/// The block states, or an error naming the block with missing or malformed data
+fn parse_blocks(json: &HashMap<String, Value>) -> Result<HashMap<u32, BlockState>, Box<dyn Error>> {
+    let mut blocks = HashMap::new();
+
+    for (name, val) in json.iter() {
+        let states = require_with!(
+            val.get("states").and_then(Value::as_object),
+            "Block {} has no states",
+            name
+        );
+        let name = format_name(name);
+        for (id, state) in states.iter() {
+            let id = id
+                .parse()
+                .map_err(|e| format!("Invalid state id {} of {}: {}", id, name, e))?;
+            blocks.insert(
+                id,
+                BlockState {
+                    name: name.clone(),
+                    id,
+                    models: parse_models(state.get("render"))
+                        .map_err(|e| format!("Block state {} of {}: {}", id, name, e))?,
+                    collision_shape: state.get("collision_shape").and_then(Value::as_u64),
+                    properties: parse_properties(state.get("properties")),
+                    hardness: state.get("hardness").and_then(Value::as_f64).unwrap_or(0.0) as f32,
+                    requires_tool: state
+                        .get("requires_correct_tool_for_drops")
+                        .and_then(Value::as_bool)
+                        .unwrap_or(false),
+                },
+            );
+        }
+    }
+
+    Ok(blocks)
+}
+
+/// Reads the names of the models of a block state from its render section, which is either a
+/// single model, or an array of models and arrays of models
+///
+/// # Returns
+///
+/// The model names, None if the block state isn't rendered, or an error if a model has no name
+fn parse_models(render: Option<&Value>) -> Result<Option<Vec<String>>, Box<dyn Error>> {
+    let render = match render {
+        // Has a single model
+        Some(Value::Object(render)) => {
+            let model = render.get("model").and_then(Value::as_str);
+            return Ok(Some(vec![require_with!(
+                model,
+                "No model in render section"
+            )
+            .to_string()]));
+        }
+        // Has an array of models
+        Some(Value::Array(render)) => render,
+        _ => return Ok(None),
     };
+
+    let mut models = Vec::new();
+    for render in render {
+        // Entries are either a model or an array of models
+        let entries = match render {
+            Value::Array(entries) => entries.iter().collect(),
+            entry => vec![entry],
+        };
+        for entry in entries {
+            let model = entry.get("model").and_then(Value::as_str);
+            models.push(require_with!(model, "No model in render section: {}", entry).to_string());
+        }
+    }
+
+    if models.is_empty() {
+        bail!("No models in render section");
+    }
+    Ok(Some(models))
+}
+
+/// Returns the block state with an id, or the unknown block if the id isn't in the block data.
+/// Each unknown id is only logged the first time it's looked up
+pub fn block_state(id: u32) -> &'static BlockState {
+    let blocks: &'static HashMap<u32, BlockState> = *ACTIVE_BLOCKS.read().unwrap();
+    match blocks.get(&id) {
+        Some(block) => block,
+        None => {
+            if UNKNOWN_IDS.lock().unwrap().insert(id) {
+                log::warn!("Unknown block state id {}, showing a placeholder", id);
+            }
+            &UNKNOWN_BLOCK
+        }
+    }
+}
+
+/// Returns the lowest id of a block's states that has all the given properties, for tests to look
+/// blocks up by name rather than by ids that change between versions
+#[cfg(test)]
+pub fn state_id(name: &str, properties: &[(&str, &str)]) -> u32 {
+    BLOCKS
+        .values()
+        .filter(|b| b.name == name && properties.iter().all(|(k, v)| b.property(k) == Some(*v)))
+        .map(|b| b.id)
+        .min()
+        .unwrap_or_else(|| panic!("No state of {} with {:?}", name, properties))
+}
+
+/// Returns the block states of a version by id. The version built into the client uses BLOCKS,
+/// other versions are read from `assets/<version>/blocks.min.json` the first time they're asked
+/// for and kept from then on
+pub fn load_blocks(
+    version: ProtocolVersion,
+) -> Result<&'static HashMap<u32, BlockState>, Box<dyn Error>> {
+    if version == ProtocolVersion::default() {
+        return BLOCKS.try_load();
+    }
+
+    let mut tables = VERSION_BLOCKS.lock().unwrap();
+    if let Some(blocks) = tables.get(&version) {
+        return Ok(blocks);
+    }
+
+    let path = format!("assets/{}/blocks.min.json", version.name());
+    let json: HashMap<String, Value> = serde_json::from_slice(&std::fs::read(path)?)?;
+    // Tables are never unloaded so block states can be borrowed from them like from BLOCKS
+    let blocks: &'static HashMap<u32, BlockState> = Box::leak(Box::new(parse_blocks(&json)?));
+    log::info!("Loaded {} blocks for {}.", blocks.len(), version.name());
+    tables.insert(version, blocks);
+    Ok(blocks)
+}
+
+/// Looks up block state ids in the block states of a version from now on, for when joining a
+/// server on that version
+pub fn use_blocks(version: ProtocolVersion) -> Result<(), Box<dyn Error>> {
+    *ACTIVE_BLOCKS.write().unwrap() = load_blocks(version)?;
+    Ok(())
+}
+
+/// Reads the properties of a block state, values that are booleans or numbers in the data are
+/// stored as the same text the game uses for them
+fn parse_properties(properties: Option<&Value>) -> HashMap<String, String> {
+    properties
+        .and_then(Value::as_object)
+        .map(|properties| {
+            properties
+                .iter()
+                .map(|(key, val)| {
+                    let val = match val {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    (key.clone(), val)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the default state of a block from blocks.json, or its first state if it doesn't list
+/// a default
+fn default_state(block: &Value) -> Option<u32> {
+    if let Some(state) = block.get("default_state").and_then(Value::as_u64) {
+        return Some(state as u32);
+    }
+    block
+        .get("states")?
+        .as_object()?
+        .keys()
+        .filter_map(|id| id.parse().ok())
+        .min()
 }
 
 pub fn format_name(name: &str) -> String {
@@ -245,3 +646,183 @@ pub fn format_name(name: &str) -> String {
         .replace('_', " ")
         .to_title_case()
 }
+
+/// Reads a corner of a box from shapes.json, which is either an array of the x, y and z or a
+/// single number used for every axis
+fn parse_shape_point(point: Option<&Value>) -> Vec3 {
+    match point {
+        Some(Value::Array(axes)) => Vec3::new(
+            axes.get(0).and_then(Value::as_f64).unwrap_or(0.0) as f32,
+            axes.get(1).and_then(Value::as_f64).unwrap_or(0.0) as f32,
+            axes.get(2).and_then(Value::as_f64).unwrap_or(0.0) as f32,
+        ),
+        Some(Value::Number(n)) => Vec3::splat(n.as_f64().unwrap_or(0.0) as f32),
+        _ => Vec3::ZERO,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(name: &str, properties: &[(&str, &str)]) -> &'static BlockState {
+        block_state(state_id(name, properties))
+    }
+
+    #[test]
+    fn full_block_collision() {
+        assert_eq!(block("Stone", &[]).collision_shape(), &[Aabb::FULL_CUBE]);
+        assert!(block("Stone", &[]).has_collision());
+    }
+
+    #[test]
+    fn slab_collision() {
+        let bottom = block("Stone Slab", &[("type", "bottom")]).collision_shape();
+        assert_eq!(bottom, &[Aabb::new(Vec3::ZERO, Vec3::new(1.0, 0.5, 1.0))]);
+
+        let top = block("Stone Slab", &[("type", "top")]).collision_shape();
+        assert_eq!(top, &[Aabb::new(Vec3::new(0.0, 0.5, 0.0), Vec3::ONE)]);
+
+        let double = block("Stone Slab", &[("type", "double")]).collision_shape();
+        assert_eq!(double, &[Aabb::FULL_CUBE]);
+    }
+
+    #[test]
+    fn no_collision() {
+        for name in ["Air", "Cave Air", "Grass", "Water"] {
+            assert!(block(name, &[]).collision_shape().is_empty(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn air_variants() {
+        for name in ["Air", "Cave Air", "Void Air"] {
+            assert!(block(name, &[]).is_air(), "{}", name);
+            assert!(!block(name, &[]).is_opaque(), "{}", name);
+        }
+        assert!(!block("Stone", &[]).is_air());
+    }
+
+    #[test]
+    fn block_properties() {
+        let stairs = block("Oak Stairs", &[("facing", "north"), ("half", "bottom")]);
+        assert_eq!(stairs.property("facing"), Some("north"));
+        assert_eq!(stairs.property("half"), Some("bottom"));
+        assert!(stairs.property("waterlogged").is_some());
+        assert_eq!(stairs.property("level"), None);
+        assert!(!stairs.is_full_cube());
+
+        assert_eq!(block("Stone", &[]).property("facing"), None);
+    }
+
+    #[test]
+    fn opacity_and_render_layers() {
+        let stone = block("Stone", &[]);
+        assert!(stone.is_opaque());
+        assert!(stone.is_full_cube());
+        assert_eq!(stone.render_layer(), RenderLayer::Solid);
+
+        // Glass is a full cube but the blocks behind it can be seen
+        let glass = block("Glass", &[]);
+        assert!(!glass.is_opaque());
+        assert!(glass.is_full_cube());
+        assert_eq!(glass.render_layer(), RenderLayer::Cutout);
+
+        let stained = block("Red Stained Glass", &[]);
+        assert!(!stained.is_opaque());
+        assert_eq!(stained.render_layer(), RenderLayer::Translucent);
+    }
+
+    #[test]
+    fn unknown_ids_are_placeholders() {
+        let past_end = BLOCKS.keys().max().unwrap() + 1;
+        for id in [past_end, u16::MAX as u32, u32::MAX - 1] {
+            let block = block_state(id);
+            assert!(std::ptr::eq(block, &*UNKNOWN_BLOCK), "{}", id);
+            assert_eq!(block.name, "Unknown Block");
+            assert!(!block.is_air());
+            assert!(std::ptr::eq(block.get_model().unwrap(), &*MISSING_MODEL));
+        }
+
+        // Looking the same id up again still gives the placeholder
+        assert!(std::ptr::eq(block_state(past_end), &*UNKNOWN_BLOCK));
+        assert_eq!(block_state(state_id("Stone", &[])).name, "Stone");
+    }
+
+    /// Reads a block table from the same layout as blocks.json
+    fn table(json: Value) -> HashMap<u32, BlockState> {
+        parse_blocks(&serde_json::from_value(json).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn same_id_differs_between_versions() {
+        let older = table(serde_json::json!({
+            "minecraft:granite": {"states": {"2": {"render": {"model": "block/granite"}}}},
+            "minecraft:grass_block": {"states": {
+                "8": {"properties": {"snowy": true}},
+                "9": {"properties": {"snowy": false}}
+            }}
+        }));
+        // A block added before granite moves every id after it along
+        let newer = table(serde_json::json!({
+            "minecraft:deepslate": {"states": {"2": {"render": {"model": "block/deepslate"}}}},
+            "minecraft:granite": {"states": {"3": {"render": {"model": "block/granite"}}}},
+            "minecraft:grass_block": {"states": {
+                "9": {"properties": {"snowy": true}},
+                "10": {"properties": {"snowy": false}}
+            }}
+        }));
+
+        assert_eq!(older[&2].name, "Granite");
+        assert_eq!(newer[&2].name, "Deepslate");
+        assert_eq!(newer[&3].name, "Granite");
+        assert_eq!(older[&2].models, Some(vec![String::from("block/granite")]));
+
+        assert_eq!(older[&9].property("snowy"), Some("false"));
+        assert_eq!(newer[&9].property("snowy"), Some("true"));
+        assert!(!older.contains_key(&10));
+    }
+
+    #[test]
+    fn malformed_blocks_are_errors() {
+        let parse = |json: Value| parse_blocks(&serde_json::from_value(json).unwrap());
+        assert!(parse(serde_json::json!({"minecraft:stone": {}})).is_err());
+        assert!(parse(serde_json::json!({"minecraft:stone": {"states": {"a": {}}}})).is_err());
+        assert!(parse(serde_json::json!({
+            "minecraft:stone": {"states": {"1": {"render": [{"x": 90}]}}}
+        }))
+        .is_err());
+        assert!(parse(serde_json::json!({
+            "minecraft:stone": {"states": {"1": {"render": []}}}
+        }))
+        .is_err());
+
+        // Nested arrays of models are flattened
+        let blocks = parse(
+            serde_json::json!({"minecraft:stone": {"states": {"1": {"render": [
+                [{"model": "block/stone"}, {"model": "block/stone_mirrored"}]
+            ]}}}}),
+        )
+        .unwrap();
+        assert_eq!(blocks[&1].models.as_ref().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn built_in_version_uses_built_in_blocks() {
+        let blocks = load_blocks(ProtocolVersion::default()).unwrap();
+        assert!(std::ptr::eq(blocks, &*BLOCKS));
+    }
+
+    #[test]
+    fn shape_points() {
+        assert_eq!(
+            parse_shape_point(Some(&serde_json::json!([0.0, 0.5, 1.0]))),
+            Vec3::new(0.0, 0.5, 1.0)
+        );
+        assert_eq!(
+            parse_shape_point(Some(&serde_json::json!(0.25))),
+            Vec3::splat(0.25)
+        );
+        assert_eq!(parse_shape_point(None), Vec3::ZERO);
+    }
+}
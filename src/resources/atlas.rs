@@ -0,0 +1,236 @@
+use std::{collections::HashMap, error::Error, path::Path};
+
+use glam::Vec2;
+use image::{imageops, RgbaImage};
+
+// Where block textures are found in a resource pack, or the extracted vanilla jar
+const BLOCK_TEXTURE_DIR: &str = "assets/minecraft/textures/block";
+
+/// Area of a texture within the atlas, in normalised texture coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UvRect {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+/// Block textures packed into a single power of two image so they can be bound once per draw
+pub struct TextureAtlas {
+    image: RgbaImage,
+    rects: HashMap<String, UvRect>,
+}
+
+impl TextureAtlas {
+    /// Loads the block textures of a resource pack and packs them into an atlas
+    ///
+    /// # Arguments
+    ///
+    /// * `pack` - Root directory of the resource pack, or of the extracted vanilla jar
+    /// * `padding` - Pixels around each texture filled with its edge to stop mipmaps and filtering
+    /// bleeding neighbouring textures into each other
+    pub fn load_resource_pack<P: AsRef<Path>>(
+        pack: P,
+        padding: u32,
+    ) -> Result<TextureAtlas, Box<dyn Error>> {
+        Self::load_dir(pack.as_ref().join(BLOCK_TEXTURE_DIR), padding)
+    }
+
+    /// Loads every png in a directory and packs them into an atlas, textures are named the same
+    /// as in block models, e.g. `minecraft:block/stone`
+    pub fn load_dir<P: AsRef<Path>>(dir: P, padding: u32) -> Result<TextureAtlas, Box<dyn Error>> {
+        let mut textures = Vec::new();
+
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().map_or(true, |e| e != "png") {
+                continue;
+            }
+            let name = match path.file_stem() {
+                Some(name) => name.to_string_lossy().to_string(),
+                None => continue,
+            };
+
+            let mut img = image::open(&path)?.to_rgba8();
+            // Animated textures have their frames stacked vertically, only the first is used
+            if img.height() > img.width() {
+                img = imageops::crop_imm(&img, 0, 0, img.width(), img.width()).to_image();
+            }
+
+            textures.push((format!("minecraft:block/{}", name), img));
+        }
+
+        Ok(Self::pack(textures, padding))
+    }
+
+    /// Packs textures into rows of the smallest power of two square that fits them all
+    pub fn pack(mut textures: Vec<(String, RgbaImage)>, padding: u32) -> TextureAtlas {
+        // Packing the tallest first keeps rows from wasting space
+        textures.sort_by(|(_, a), (_, b)| b.height().cmp(&a.height()));
+
+        let padded = |img: &RgbaImage| (img.width() + padding * 2, img.height() + padding * 2);
+
+        let area: u32 = textures
+            .iter()
+            .map(|(_, img)| {
+                let (w, h) = padded(img);
+                w * h
+            })
+            .sum();
+        let widest = textures
+            .iter()
+            .map(|(_, img)| padded(img).0)
+            .max()
+            .unwrap_or(1);
+
+        let mut size = ((area as f64).sqrt().ceil() as u32)
+            .max(widest)
+            .next_power_of_two();
+        let positions = loop {
+            match layout_rows(&textures, padding, size) {
+                Some(positions) => break positions,
+                None => size *= 2,
+            }
+        };
+
+        let mut image = RgbaImage::new(size, size);
+        let mut rects = HashMap::new();
+        for ((name, img), (x, y)) in textures.iter().zip(positions) {
+            blit_padded(&mut image, img, x, y, padding);
+
+            let min = Vec2::new((x + padding) as f32, (y + padding) as f32);
+            let max = min + Vec2::new(img.width() as f32, img.height() as f32);
+            rects.insert(
+                name.clone(),
+                UvRect {
+                    min: min / size as f32,
+                    max: max / size as f32,
+                },
+            );
+        }
+
+        TextureAtlas { image, rects }
+    }
+
+    pub fn get_image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    /// Returns the area of the named texture in the atlas
+    pub fn get_rect(&self, name: &str) -> Option<&UvRect> {
+        self.rects.get(name)
+    }
+
+    pub fn get_rects(&self) -> &HashMap<String, UvRect> {
+        &self.rects
+    }
+}
+
+/// Places textures left to right in rows, returns the top left corner of each padded texture or
+/// None if they don't fit in the size
+fn layout_rows(
+    textures: &[(String, RgbaImage)],
+    padding: u32,
+    size: u32,
+) -> Option<Vec<(u32, u32)>> {
+    let mut positions = Vec::with_capacity(textures.len());
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+
+    for (_, img) in textures {
+        let (w, h) = (img.width() + padding * 2, img.height() + padding * 2);
+        if x + w > size {
+            x = 0;
+            y += row_height;
+            row_height = 0;
+        }
+        if y + h > size {
+            return None;
+        }
+
+        positions.push((x, y));
+        x += w;
+        row_height = row_height.max(h);
+    }
+
+    Some(positions)
+}
+
+/// Copies a texture into the atlas with its edge pixels extended out into the padding
+fn blit_padded(atlas: &mut RgbaImage, img: &RgbaImage, x: u32, y: u32, padding: u32) {
+    let (w, h) = (img.width(), img.height());
+    if w == 0 || h == 0 {
+        return;
+    }
+
+    for py in 0..h + padding * 2 {
+        for px in 0..w + padding * 2 {
+            let sx = px.saturating_sub(padding).min(w - 1);
+            let sy = py.saturating_sub(padding).min(h - 1);
+            atlas.put_pixel(x + px, y + py, *img.get_pixel(sx, sy));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    fn tiles(count: u32, size: u32) -> Vec<(String, RgbaImage)> {
+        (0..count)
+            .map(|i| {
+                let colour = Rgba([i as u8, (i >> 8) as u8, 0xff, 0xff]);
+                (
+                    format!("tile_{}", i),
+                    RgbaImage::from_pixel(size, size, colour),
+                )
+            })
+            .collect()
+    }
+
+    fn overlaps(a: &UvRect, b: &UvRect) -> bool {
+        a.min.cmplt(b.max).all() && b.min.cmplt(a.max).all()
+    }
+
+    #[test]
+    fn packed_tiles_dont_overlap() {
+        for (count, padding) in [(1, 0), (7, 0), (64, 1), (300, 2)] {
+            let atlas = TextureAtlas::pack(tiles(count, 16), padding);
+            let size = atlas.get_image().width();
+            assert!(size.is_power_of_two());
+            assert_eq!(atlas.get_image().height(), size);
+
+            let rects: Vec<&UvRect> = atlas.get_rects().values().collect();
+            assert_eq!(rects.len(), count as usize);
+            for (i, a) in rects.iter().enumerate() {
+                assert!(a.min.cmpge(Vec2::ZERO).all() && a.max.cmple(Vec2::ONE).all());
+                assert_eq!((a.max - a.min) * size as f32, Vec2::splat(16.0));
+                for b in &rects[i + 1..] {
+                    assert!(!overlaps(a, b), "{:?} overlaps {:?}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn textures_are_copied_with_padding() {
+        let atlas = TextureAtlas::pack(tiles(3, 16), 2);
+        let size = atlas.get_image().width() as f32;
+
+        for (name, rect) in atlas.get_rects() {
+            let i: u32 = name.trim_start_matches("tile_").parse().unwrap();
+            let expected = Rgba([i as u8, 0, 0xff, 0xff]);
+            let min = rect.min * size;
+            let max = rect.max * size;
+
+            // The corners inside the texture and the padding beyond them
+            for (x, y) in [
+                (min.x, min.y),
+                (max.x - 1.0, max.y - 1.0),
+                (min.x - 2.0, min.y - 2.0),
+                (max.x + 1.0, max.y + 1.0),
+            ] {
+                assert_eq!(*atlas.get_image().get_pixel(x as u32, y as u32), expected);
+            }
+        }
+    }
+}
@@ -3,9 +3,12 @@ use std::{collections::HashMap, error::Error};
 use glam::{Vec2, Vec3};
 use simple_error::{bail, require_with};
 
-use crate::renderer::BlockVertex;
+use crate::world::mesh::BlockVertex;
 
-use super::{BLOCKS, BLOCK_MODELS_PARSED, BLOCK_MODELS_RAW, BLOCK_TEXTURES, MISSING_TEXTURE};
+use super::{block_state, BLOCK_MODELS_RAW, BLOCK_TEXTURES, MISSING_TEXTURE};
+
+/// Names of the faces of a block
+pub const FACES: [&str; 6] = ["up", "down", "north", "east", "south", "west"];
 
 #[derive(Clone, Debug)]
 pub struct BlockModel {
@@ -153,6 +156,16 @@ impl BlockModel {
         }
     }
 
+    /// Cube with the missing texture on every face, used for blocks whose model couldn't be found
+    pub fn block_missing() -> BlockModel {
+        let mut base = Self::block_cube();
+        for face in FACES {
+            // The empty texture name maps to the missing texture
+            base.textures.insert(face.to_string(), String::new());
+        }
+        base
+    }
+
     pub fn block_cube_column() -> BlockModel {
         let mut base = Self::block_cube();
         base.cull_against = false;
@@ -171,6 +184,45 @@ impl BlockModel {
         base
     }
 
+    /// Returns true if this model is a single unrotated cube filling the whole block with each face
+    /// using an entire unrotated texture, so its faces can be merged with neighbouring faces
+    pub fn is_full_cube(&self) -> bool {
+        if self.elements.len() != 1 {
+            return false;
+        }
+        let element = &self.elements[0];
+
+        element.from == Vec3::ZERO
+            && element.to == Vec3::ONE
+            && element.rot.is_none()
+            && FACES.iter().all(|f| {
+                element
+                    .faces
+                    .get(*f)
+                    .map(|face| face.uv == (Vec2::ZERO, Vec2::ONE) && face.rotation == 0.0)
+                    .unwrap_or(false)
+            })
+    }
+
+    /// Returns the texture index of a face of the first element of this model
+    pub fn face_texture(&self, face: &str) -> Option<f32> {
+        self.elements
+            .get(0)?
+            .faces
+            .get(face)
+            .map(|f| get_texture_index(&self.textures, &f.texture))
+    }
+
+    /// Returns true if the faces of this model should be darkened by neighbouring blocks
+    pub fn has_ambient_occlusion(&self) -> bool {
+        self.ambient_occlusion
+    }
+
+    /// Returns true if this model hides the faces of neighbouring blocks
+    pub fn get_cull_against(&self) -> bool {
+        self.cull_against
+    }
+
     pub fn generate_mesh(
         &self,
         above: u16,
@@ -197,28 +249,7 @@ impl BlockModel {
                 return false;
             }
 
-            if let Some(block) = BLOCKS.get(&target.into()) {
-                return match &block.models {
-                    Some(models) => {
-                        let model = models.get(0).map(|s| s.as_ref()).unwrap_or("");
-
-                        let exceptions: Vec<&str> = vec!["glass", "leaves", "water", "spawner"];
-                        for exception in exceptions {
-                            if model.contains(exception) {
-                                return false;
-                            }
-                        }
-
-                        BLOCK_MODELS_PARSED
-                            .get(model)
-                            .map(|m| m.cull_against)
-                            .unwrap_or(false)
-                    }
-                    None => false,
-                };
-            }
-
-            false
+            block_state(target.into()).is_opaque()
         };
 
         // Generate mesh for each element
@@ -230,165 +261,7 @@ impl BlockModel {
 
                 let texture = get_texture_index(&self.textures, &face.texture);
 
-                match key.as_ref() {
-                    "up" => {
-                        verts.push(BlockVertex {
-                            position: [element.to.x, element.to.y, element.to.z],
-                            tex_coords: [face.uv.1.x, face.uv.1.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.to.x, element.to.y, element.from.z],
-                            tex_coords: [face.uv.1.x, face.uv.0.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.from.x, element.to.y, element.from.z],
-                            tex_coords: [face.uv.0.x, face.uv.0.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.to.x, element.to.y, element.to.z],
-                            tex_coords: [face.uv.1.x, face.uv.1.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.from.x, element.to.y, element.from.z],
-                            tex_coords: [face.uv.0.x, face.uv.0.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.from.x, element.to.y, element.to.z],
-                            tex_coords: [face.uv.0.x, face.uv.1.y, texture],
-                        });
-                    }
-                    "down" => {
-                        verts.push(BlockVertex {
-                            position: [element.to.x, element.from.y, element.to.z],
-                            tex_coords: [face.uv.1.x, face.uv.1.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.from.x, element.from.y, element.to.z],
-                            tex_coords: [face.uv.0.x, face.uv.1.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.from.x, element.from.y, element.from.z],
-                            tex_coords: [face.uv.0.x, face.uv.0.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.to.x, element.from.y, element.to.z],
-                            tex_coords: [face.uv.1.x, face.uv.1.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.from.x, element.from.y, element.from.z],
-                            tex_coords: [face.uv.0.x, face.uv.0.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.to.x, element.from.y, element.from.z],
-                            tex_coords: [face.uv.1.x, face.uv.0.y, texture],
-                        });
-                    }
-                    "north" => {
-                        verts.push(BlockVertex {
-                            position: [element.to.x, element.to.y, element.from.z],
-                            tex_coords: [face.uv.1.x, face.uv.1.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.from.x, element.from.y, element.from.z],
-                            tex_coords: [face.uv.0.x, face.uv.0.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.from.x, element.to.y, element.from.z],
-                            tex_coords: [face.uv.0.x, face.uv.1.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.to.x, element.to.y, element.from.z],
-                            tex_coords: [face.uv.1.x, face.uv.1.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.to.x, element.from.y, element.from.z],
-                            tex_coords: [face.uv.1.x, face.uv.0.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.from.x, element.from.y, element.from.z],
-                            tex_coords: [face.uv.0.x, face.uv.0.y, texture],
-                        });
-                    }
-                    "east" => {
-                        verts.push(BlockVertex {
-                            position: [element.to.x, element.to.y, element.to.z],
-                            tex_coords: [face.uv.1.x, face.uv.1.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.to.x, element.from.y, element.from.z],
-                            tex_coords: [face.uv.0.x, face.uv.0.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.to.x, element.to.y, element.from.z],
-                            tex_coords: [face.uv.0.x, face.uv.1.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.to.x, element.to.y, element.to.z],
-                            tex_coords: [face.uv.1.x, face.uv.1.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.to.x, element.from.y, element.to.z],
-                            tex_coords: [face.uv.1.x, face.uv.0.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.to.x, element.from.y, element.from.z],
-                            tex_coords: [face.uv.0.x, face.uv.0.y, texture],
-                        });
-                    }
-                    "south" => {
-                        verts.push(BlockVertex {
-                            position: [element.to.x, element.to.y, element.to.z],
-                            tex_coords: [face.uv.1.x, face.uv.1.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.from.x, element.to.y, element.to.z],
-                            tex_coords: [face.uv.0.x, face.uv.1.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.from.x, element.from.y, element.to.z],
-                            tex_coords: [face.uv.0.x, face.uv.0.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.to.x, element.to.y, element.to.z],
-                            tex_coords: [face.uv.1.x, face.uv.1.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.from.x, element.from.y, element.to.z],
-                            tex_coords: [face.uv.0.x, face.uv.0.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.to.x, element.from.y, element.to.z],
-                            tex_coords: [face.uv.1.x, face.uv.0.y, texture],
-                        });
-                    }
-                    "west" => {
-                        verts.push(BlockVertex {
-                            position: [element.from.x, element.to.y, element.to.z],
-                            tex_coords: [face.uv.1.x, face.uv.1.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.from.x, element.to.y, element.from.z],
-                            tex_coords: [face.uv.0.x, face.uv.1.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.from.x, element.from.y, element.from.z],
-                            tex_coords: [face.uv.0.x, face.uv.0.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.from.x, element.to.y, element.to.z],
-                            tex_coords: [face.uv.1.x, face.uv.1.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.from.x, element.from.y, element.from.z],
-                            tex_coords: [face.uv.0.x, face.uv.0.y, texture],
-                        });
-                        verts.push(BlockVertex {
-                            position: [element.from.x, element.from.y, element.to.z],
-                            tex_coords: [face.uv.1.x, face.uv.0.y, texture],
-                        });
-                    }
-                    _ => {}
-                }
+                push_face(&mut verts, key, element.from, element.to, face.uv, texture);
             }
         }
 
@@ -446,7 +319,7 @@ impl BlockModel {
                 }
                 let texture = tex.as_str().unwrap().to_string();
                 for val in base.textures.values_mut() {
-                    if val.starts_with("#") && &val[1..] == key {
+                    if val.starts_with('#') && &val[1..] == key {
                         *val = texture.clone();
                     }
                 }
@@ -731,6 +604,255 @@ impl Face {
     }
 }
 
+/// Pushes the two triangles making up a face of the box between `from` and `to`, `uv` holds the
+/// texture coordinates of the corners of the face
+pub fn push_face(
+    verts: &mut Vec<BlockVertex>,
+    face: &str,
+    from: Vec3,
+    to: Vec3,
+    uv: (Vec2, Vec2),
+    texture: f32,
+) {
+    match face {
+        "up" => {
+            verts.push(BlockVertex {
+                position: [to.x, to.y, to.z],
+                tex_coords: [uv.1.x, uv.1.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [to.x, to.y, from.z],
+                tex_coords: [uv.1.x, uv.0.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [from.x, to.y, from.z],
+                tex_coords: [uv.0.x, uv.0.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [to.x, to.y, to.z],
+                tex_coords: [uv.1.x, uv.1.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [from.x, to.y, from.z],
+                tex_coords: [uv.0.x, uv.0.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [from.x, to.y, to.z],
+                tex_coords: [uv.0.x, uv.1.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+        }
+        "down" => {
+            verts.push(BlockVertex {
+                position: [to.x, from.y, to.z],
+                tex_coords: [uv.1.x, uv.1.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [from.x, from.y, to.z],
+                tex_coords: [uv.0.x, uv.1.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [from.x, from.y, from.z],
+                tex_coords: [uv.0.x, uv.0.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [to.x, from.y, to.z],
+                tex_coords: [uv.1.x, uv.1.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [from.x, from.y, from.z],
+                tex_coords: [uv.0.x, uv.0.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [to.x, from.y, from.z],
+                tex_coords: [uv.1.x, uv.0.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+        }
+        "north" => {
+            verts.push(BlockVertex {
+                position: [to.x, to.y, from.z],
+                tex_coords: [uv.1.x, uv.1.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [from.x, from.y, from.z],
+                tex_coords: [uv.0.x, uv.0.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [from.x, to.y, from.z],
+                tex_coords: [uv.0.x, uv.1.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [to.x, to.y, from.z],
+                tex_coords: [uv.1.x, uv.1.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [to.x, from.y, from.z],
+                tex_coords: [uv.1.x, uv.0.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [from.x, from.y, from.z],
+                tex_coords: [uv.0.x, uv.0.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+        }
+        "east" => {
+            verts.push(BlockVertex {
+                position: [to.x, to.y, to.z],
+                tex_coords: [uv.1.x, uv.1.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [to.x, from.y, from.z],
+                tex_coords: [uv.0.x, uv.0.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [to.x, to.y, from.z],
+                tex_coords: [uv.0.x, uv.1.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [to.x, to.y, to.z],
+                tex_coords: [uv.1.x, uv.1.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [to.x, from.y, to.z],
+                tex_coords: [uv.1.x, uv.0.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [to.x, from.y, from.z],
+                tex_coords: [uv.0.x, uv.0.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+        }
+        "south" => {
+            verts.push(BlockVertex {
+                position: [to.x, to.y, to.z],
+                tex_coords: [uv.1.x, uv.1.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [from.x, to.y, to.z],
+                tex_coords: [uv.0.x, uv.1.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [from.x, from.y, to.z],
+                tex_coords: [uv.0.x, uv.0.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [to.x, to.y, to.z],
+                tex_coords: [uv.1.x, uv.1.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [from.x, from.y, to.z],
+                tex_coords: [uv.0.x, uv.0.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [to.x, from.y, to.z],
+                tex_coords: [uv.1.x, uv.0.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+        }
+        "west" => {
+            verts.push(BlockVertex {
+                position: [from.x, to.y, to.z],
+                tex_coords: [uv.1.x, uv.1.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [from.x, to.y, from.z],
+                tex_coords: [uv.0.x, uv.1.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [from.x, from.y, from.z],
+                tex_coords: [uv.0.x, uv.0.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [from.x, to.y, to.z],
+                tex_coords: [uv.1.x, uv.1.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [from.x, from.y, from.z],
+                tex_coords: [uv.0.x, uv.0.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+            verts.push(BlockVertex {
+                position: [from.x, from.y, to.z],
+                tex_coords: [uv.1.x, uv.0.y, texture],
+                brightness: 1.0,
+                light: [1.0, 0.0],
+            });
+        }
+        _ => {}
+    }
+}
+
+/// Returns the index in the texture array of a texture by its full name, e.g.
+/// `minecraft:block/water_still`. Textures that aren't loaded use the missing texture
+pub fn texture_index(name: &str) -> f32 {
+    BLOCK_TEXTURES.get(name).unwrap_or(&MISSING_TEXTURE).index as f32
+}
+
 fn get_texture_index(texture_map: &HashMap<String, String>, texture: &str) -> f32 {
     let texture_key: &str = texture_map
         .get(&texture[1..])
@@ -745,9 +867,59 @@ fn get_texture_index(texture_map: &HashMap<String, String>, texture: &str) -> f3
         )
         .index;
 
-    if index == 0 {
+    if index == 0 && !texture_key.is_empty() {
         log::error!("Missing texture: {}", texture_key);
     }
 
     index as f32
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::resources::state_id;
+
+    use super::*;
+
+    fn model(name: &str, properties: &[(&str, &str)]) -> &'static BlockModel {
+        block_state(state_id(name, properties))
+            .get_model()
+            .unwrap_or_else(|| panic!("{} has no model", name))
+    }
+
+    #[test]
+    fn grass_block_top_and_side_differ() {
+        let grass = model("Grass Block", &[("snowy", "false")]);
+        let top = grass.face_texture("up").unwrap();
+        let side = grass.face_texture("north").unwrap();
+        let bottom = grass.face_texture("down").unwrap();
+
+        assert_eq!(top, texture_index("minecraft:block/grass_block_top"));
+        assert_eq!(side, texture_index("minecraft:block/grass_block_side"));
+        assert_eq!(bottom, texture_index("minecraft:block/dirt"));
+        assert_ne!(top, side);
+        assert_ne!(side, MISSING_TEXTURE.index as f32);
+    }
+
+    #[test]
+    fn log_ends_and_sides_differ() {
+        let log = model("Oak Log", &[("axis", "y")]);
+        assert_eq!(log.face_texture("up"), log.face_texture("down"));
+        assert_ne!(log.face_texture("up"), log.face_texture("east"));
+        assert_eq!(log.face_texture("east"), log.face_texture("west"));
+    }
+
+    #[test]
+    fn missing_model_uses_missing_texture() {
+        let missing = BlockModel::block_missing();
+        for face in FACES {
+            assert_eq!(
+                missing.face_texture(face),
+                Some(MISSING_TEXTURE.index as f32)
+            );
+        }
+        assert_eq!(
+            texture_index("minecraft:block/not_a_texture"),
+            MISSING_TEXTURE.index as f32
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use std::{
+    error::Error,
+    ops::Deref,
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        OnceLock,
+    },
+    thread,
+    time::Instant,
+};
+
+use log::{error, info};
+
+use super::{
+    BLOCKS, BLOCK_MODELS_PARSED, BLOCK_MODELS_RAW, BLOCK_TEXTURES, ENTITIES, ITEMS, SHAPES, SOUNDS,
+};
+
+/// A resource that is loaded the first time it's used. The loading thread loads each of them
+/// ahead of time so a failure can be shown on the loading screen
+pub struct Resource<T> {
+    value: OnceLock<T>,
+    load: fn() -> Result<T, Box<dyn Error>>,
+}
+
+impl<T> Resource<T> {
+    pub const fn new(load: fn() -> Result<T, Box<dyn Error>>) -> Resource<T> {
+        Resource {
+            value: OnceLock::new(),
+            load,
+        }
+    }
+
+    /// Loads the resource if it hasn't been loaded yet
+    ///
+    /// # Returns
+    ///
+    /// The resource, or why it couldn't be loaded
+    pub fn try_load(&self) -> Result<&T, Box<dyn Error>> {
+        if let Some(value) = self.value.get() {
+            return Ok(value);
+        }
+        let value = (self.load)()?;
+        // If another thread loaded it in the meantime, the value it loaded is kept
+        Ok(self.value.get_or_init(|| value))
+    }
+}
+
+impl<T> Deref for Resource<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self.try_load() {
+            Ok(value) => value,
+            Err(e) => panic!("Resource used after it failed to load: {}", e),
+        }
+    }
+}
+
+/// Groups of resources loaded one after the other before the main menu is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadStage {
+    Blocks,
+    Models,
+    Textures,
+}
+
+impl LoadStage {
+    pub const ALL: [LoadStage; 3] = [LoadStage::Blocks, LoadStage::Models, LoadStage::Textures];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LoadStage::Blocks => "Blocks",
+            LoadStage::Models => "Models",
+            LoadStage::Textures => "Textures",
+        }
+    }
+
+    /// Loads the resources of this stage
+    ///
+    /// # Returns
+    ///
+    /// How many things were loaded, or why the stage couldn't be loaded
+    fn load(&self) -> Result<usize, Box<dyn Error>> {
+        match self {
+            LoadStage::Blocks => {
+                SHAPES.try_load()?;
+                ENTITIES.try_load()?;
+                ITEMS.try_load()?;
+                SOUNDS.try_load()?;
+                Ok(BLOCKS.try_load()?.len())
+            }
+            LoadStage::Models => {
+                BLOCK_MODELS_RAW.try_load()?;
+                Ok(BLOCK_MODELS_PARSED.try_load()?.len())
+            }
+            LoadStage::Textures => Ok(BLOCK_TEXTURES.try_load()?.len()),
+        }
+    }
+}
+
+/// Sent from the loading thread as each stage finishes
+enum LoadEvent {
+    Loaded(LoadStage),
+    Failed(LoadStage, String),
+}
+
+/// Loads the resources on a background thread so the window can show a loading screen, the
+/// resources can be used once it's done
+pub struct ResourceLoader {
+    rx: Receiver<LoadEvent>,
+    completed: usize,
+    // Why loading stopped, the resources that failed can't be used
+    error: Option<String>,
+}
+
+impl ResourceLoader {
+    /// Starts loading every stage in order
+    pub fn start() -> ResourceLoader {
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let start = Instant::now();
+            for stage in LoadStage::ALL {
+                let event = match stage.load() {
+                    Ok(count) => {
+                        info!("Loaded {} {}.", count, stage.name().to_lowercase());
+                        LoadEvent::Loaded(stage)
+                    }
+                    Err(e) => LoadEvent::Failed(stage, e.to_string()),
+                };
+                let failed = matches!(event, LoadEvent::Failed(..));
+                if tx.send(event).is_err() || failed {
+                    return;
+                }
+            }
+            info!("Loading assets took {}ms", start.elapsed().as_millis());
+        });
+
+        ResourceLoader {
+            rx,
+            completed: 0,
+            error: None,
+        }
+    }
+
+    /// Reads the stages that have finished since the last poll
+    pub fn poll(&mut self) {
+        loop {
+            match self.rx.try_recv() {
+                Ok(LoadEvent::Loaded(_)) => self.completed += 1,
+                Ok(LoadEvent::Failed(stage, e)) => {
+                    error!("Failed to load {}: {}", stage.name().to_lowercase(), e);
+                    self.error = Some(format!("Failed to load {}: {}", stage.name(), e));
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !self.is_done() && self.error.is_none() {
+                        self.error = Some(String::from("Loading stopped unexpectedly"));
+                    }
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Returns how much has been loaded from 0 to 1
+    pub fn get_progress(&self) -> f32 {
+        progress(self.completed, LoadStage::ALL.len())
+    }
+
+    /// Returns the stage being loaded, None once everything has loaded
+    pub fn get_stage(&self) -> Option<LoadStage> {
+        LoadStage::ALL.get(self.completed).copied()
+    }
+
+    pub fn get_error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.completed >= LoadStage::ALL.len()
+    }
+}
+
+/// Returns the fraction of stages that have been completed, each stage counts the same
+pub fn progress(completed: usize, stages: usize) -> f32 {
+    if stages == 0 {
+        return 1.0;
+    }
+    completed.min(stages) as f32 / stages as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::Sender;
+
+    use super::*;
+
+    /// A loader whose events come from the returned sender rather than a loading thread
+    fn test_loader() -> (ResourceLoader, Sender<LoadEvent>) {
+        let (tx, rx) = mpsc::channel();
+        let loader = ResourceLoader {
+            rx,
+            completed: 0,
+            error: None,
+        };
+        (loader, tx)
+    }
+
+    #[test]
+    fn progress_across_stages() {
+        assert_eq!(progress(0, 3), 0.0);
+        assert_eq!(progress(1, 4), 0.25);
+        assert_eq!(progress(3, 3), 1.0);
+        assert_eq!(progress(5, 3), 1.0);
+        assert_eq!(progress(0, 0), 1.0);
+    }
+
+    #[test]
+    fn stages_load_in_order() {
+        let (mut loader, tx) = test_loader();
+        assert_eq!(loader.get_stage(), Some(LoadStage::Blocks));
+
+        tx.send(LoadEvent::Loaded(LoadStage::Blocks)).unwrap();
+        loader.poll();
+        assert_eq!(loader.get_stage(), Some(LoadStage::Models));
+        assert_eq!(loader.get_progress(), 1.0 / 3.0);
+
+        tx.send(LoadEvent::Loaded(LoadStage::Models)).unwrap();
+        tx.send(LoadEvent::Loaded(LoadStage::Textures)).unwrap();
+        drop(tx);
+        loader.poll();
+        assert!(loader.is_done());
+        assert_eq!(loader.get_stage(), None);
+        assert_eq!(loader.get_progress(), 1.0);
+        assert!(loader.get_error().is_none());
+    }
+
+    #[test]
+    fn failures_are_shown() {
+        let (mut loader, tx) = test_loader();
+        tx.send(LoadEvent::Loaded(LoadStage::Blocks)).unwrap();
+        tx.send(LoadEvent::Failed(
+            LoadStage::Models,
+            String::from("No such file"),
+        ))
+        .unwrap();
+        drop(tx);
+        loader.poll();
+
+        assert!(!loader.is_done());
+        assert_eq!(
+            loader.get_error(),
+            Some("Failed to load Models: No such file")
+        );
+
+        // The thread ending early without saying why is an error too
+        let (mut loader, tx) = test_loader();
+        drop(tx);
+        loader.poll();
+        assert_eq!(loader.get_error(), Some("Loading stopped unexpectedly"));
+    }
+
+    #[test]
+    fn failed_resources_give_their_error() {
+        fn read_missing() -> Result<Vec<u8>, Box<dyn Error>> {
+            Ok(std::fs::read("assets/no_such_file.json")?)
+        }
+        static MISSING: Resource<Vec<u8>> = Resource::new(read_missing);
+        assert!(MISSING.try_load().is_err());
+        // Failures aren't kept, so a later attempt tries again
+        assert!(MISSING.try_load().is_err());
+
+        fn count() -> Result<usize, Box<dyn Error>> {
+            Ok(5)
+        }
+        static COUNT: Resource<usize> = Resource::new(count);
+        assert_eq!(*COUNT.try_load().unwrap(), 5);
+        assert_eq!(*COUNT, 5);
+    }
+}
@@ -0,0 +1,81 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use glium::{texture::RawImage2d, Display};
+use image::RgbaImage;
+use simple_error::require_with;
+
+/// Saves what was last shown on screen to a PNG named after the current time in a directory, which
+/// is created if it doesn't exist. Returns the path of the new file
+pub fn take_screenshot<P: AsRef<Path>>(dis: &Display, dir: P) -> Result<PathBuf, Box<dyn Error>> {
+    let raw: RawImage2d<u8> = dis.read_front_buffer()?;
+    let img = require_with!(
+        to_image(&raw.data, raw.width, raw.height),
+        "Framebuffer is the wrong size for its dimensions"
+    );
+
+    std::fs::create_dir_all(&dir)?;
+    let path = screenshot_path(dir.as_ref());
+    img.save_with_format(&path, image::ImageFormat::Png)?;
+    Ok(path)
+}
+
+/// Converts pixels read from OpenGL, which are RGBA with the bottom row first, to an image with
+/// the top row first. None if there aren't exactly enough pixels for the size
+pub fn to_image(data: &[u8], width: u32, height: u32) -> Option<RgbaImage> {
+    let row = width as usize * 4;
+    if data.len() != row * height as usize {
+        return None;
+    }
+
+    let flipped = if row == 0 {
+        Vec::new()
+    } else {
+        data.chunks_exact(row).rev().flatten().copied().collect()
+    };
+    RgbaImage::from_raw(width, height, flipped)
+}
+
+/// Returns a path in a directory named after the current time like the vanilla client does, with a
+/// number added if more than one screenshot is taken in a second
+fn screenshot_path(dir: &Path) -> PathBuf {
+    let name = chrono::Local::now().format("%Y-%m-%d_%H.%M.%S").to_string();
+
+    let mut path = dir.join(format!("{}.png", name));
+    let mut i = 1;
+    while path.exists() {
+        path = dir.join(format!("{}_{}.png", name, i));
+        i += 1;
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_flipped() {
+        // 2x3 pixels, each RGBA pixel filled with its row number from the bottom and column
+        let data: Vec<u8> = (0..3u8)
+            .flat_map(|y| (0..2u8).flat_map(move |x| [y, x, 10 * y + x, 255]))
+            .collect();
+        let img = to_image(&data, 2, 3).unwrap();
+
+        assert_eq!(img.dimensions(), (2, 3));
+        // The top of the image is the last row OpenGL gave
+        assert_eq!(img.get_pixel(0, 0).0, [2, 0, 20, 255]);
+        assert_eq!(img.get_pixel(1, 0).0, [2, 1, 21, 255]);
+        assert_eq!(img.get_pixel(1, 1).0, [1, 1, 11, 255]);
+        assert_eq!(img.get_pixel(0, 2).0, [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn wrong_sized_buffers_are_rejected() {
+        assert!(to_image(&[0; 4 * 5], 2, 3).is_none());
+        assert!(to_image(&[0; 4 * 7], 2, 3).is_none());
+        assert_eq!(to_image(&[], 0, 0).unwrap().dimensions(), (0, 0));
+    }
+}
@@ -1,34 +1,102 @@
-use std::{collections::HashMap, f32::consts::PI, ops::AddAssign};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::mpsc::TryRecvError,
+    time::Instant,
+};
 
 use egui_winit::winit::event::VirtualKeyCode;
-use glam::{IVec2, Vec3};
+use glam::{IVec2, IVec3, Vec3};
 use glium_app::context::Context;
 use lazy_static::__Deref;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use mcproto_rs::{
-    types::{self, EntityLocation, VarInt},
-    uuid::UUID4,
+    types::IntPosition,
     v1_16_3::{
-        ClientStatusAction, Difficulty, GameMode, PlayClientChatMessageSpec,
-        PlayClientPlayerPositionAndRotationSpec, PlayClientSettingsSpec, PlayClientStatusSpec,
-        PlayTeleportConfirmSpec, PlayerInfoAction,
+        ClientStatusAction, Difficulty, GameChangeReason, GameMode, Hand, PlayRespawnSpec,
+        PlayServerPlayerPositionAndLookSpec, PlayerDiggingStatus,
     },
 };
 
 use crate::{
-    gui::{chat_windows, info_windows, pause_windows},
-    network::{encode, NetworkChannel, NetworkCommand, PacketType},
-    resources::PLAYER_INDEX,
-    settings::Settings,
-    world::chunks::Chunk,
+    gui::{
+        chat_windows, death_screen, info_windows,
+        minimap::Minimap,
+        other_windows::{boss_bar_overlay, hotbar, scoreboard_sidebar},
+        pause_windows,
+    },
+    inventory::{ItemStack, PLAYER_WINDOW},
+    network::{
+        self,
+        version::{self, ProtocolVersion},
+        ConnectError, ConnectionState, NetworkChannel, NetworkCommand, PacketType, ServerStatus,
+    },
+    resources,
+    settings::{
+        key_bindings::{Action, KeyBindings},
+        Settings,
+    },
     WindowManager,
 };
 
-use self::remote_player::RemotePlayer;
+use self::{
+    boss_bars::BossBars,
+    digging::{break_time, digging_face, Digging},
+    join_game::JoinGameInfo,
+    remote_player::PlayerList,
+    scoreboard::Scoreboard,
+};
 
-use super::{chat::Chat, entities::Entity, player::Player, world::World};
+use super::{
+    chat::{component_text, Chat, ChatEvent},
+    entities::{angle_to_degrees, velocity_to_blocks, Entity},
+    particles::{Particle, Particles},
+    player::{Player, REACH},
+    sound::{self, SoundEvent},
+    world::{
+        aabb::Aabb,
+        block_coords,
+        chunks::{BlockIndex, Chunk, AIR},
+        raycast::{BlockFace, RaycastHit},
+        World, WorldCoords,
+    },
+};
 
+pub mod boss_bars;
+pub mod digging;
+pub mod join_game;
 pub mod remote_player;
+pub mod scoreboard;
+
+// Degrees turned per pixel the mouse moves at a sensitivity of 1
+const MOUSE_DEGREES_PER_PIXEL: f32 = 0.05;
+// Seconds between sending movement to the server, once a tick like the vanilla client
+const MOVEMENT_INTERVAL: f32 = 0.05;
+// The position is sent at least this often in seconds even when the player hasn't moved
+const MOVEMENT_KEEPALIVE: f32 = 1.0;
+// How much faster the free camera flies while control is held
+const FAST_FLY_MULTIPLIER: f32 = 4.0;
+// How many of the latest sounds are kept to show in the debug overlay
+const RECENT_SOUNDS: usize = 5;
+
+/// Events received from the network thread
+#[derive(Debug)]
+pub enum ServerEvent {
+    Packet(PacketType),
+    Status(ServerStatus),
+    /// Data received on a plugin channel
+    PluginMessage {
+        channel: String,
+        data: Vec<u8>,
+    },
+    /// Progress of opening the connection
+    ConnectionState(ConnectionState),
+    /// The connection was lost
+    ConnectionLost(std::io::Error),
+    /// The connection couldn't be made
+    ConnectFailed(ConnectError),
+    /// The network thread has stopped
+    Closed,
+}
 
 pub struct Server {
     network_destination: String,
@@ -36,20 +104,53 @@ pub struct Server {
 
     input_state: InputState,
 
-    world_time: i64,
-    day_time: i64,
-
     player: Player,
     chat: Chat,
 
     world: World,
 
     entities: HashMap<i32, Entity>,
-    players: HashMap<UUID4, RemotePlayer>,
+    players: PlayerList,
+    particles: Particles,
+    boss_bars: BossBars,
+    scoreboard: Scoreboard,
+    minimap: Minimap,
 
     difficulty: Difficulty,
     difficulty_locked: bool,
 
+    protocol_version: ProtocolVersion,
+    join_info: Option<JoinGameInfo>,
+    // Chunk the player was in when distant chunks were last unloaded
+    center_chunk: Option<IVec2>,
+
+    // Latest progress of the connection and when it was received
+    connection_state: (ConnectionState, Instant),
+    logged_in: bool,
+    // Id of the last teleport from the server, which has been confirmed
+    last_teleport_id: Option<i32>,
+    // Where the compass points and the player respawns without a bed
+    spawn_position: Option<IVec3>,
+    // Position, yaw and pitch last sent to the server and the seconds since then
+    last_sent_position: Option<(Vec3, f32, f32)>,
+    movement_timer: f32,
+
+    // Block the player is breaking
+    digging: Option<Digging>,
+
+    camera_mode: CameraMode,
+    // Where the camera is while flying freely
+    free_camera: Vec3,
+
+    // Sounds the server sent in the last update, and the latest few for the debug overlay
+    sound_events: Vec<SoundEvent>,
+    recent_sounds: VecDeque<SoundEvent>,
+
+    // Whether the F3 debug overlay is shown
+    show_debug: bool,
+    // Brand the server sent on minecraft:brand, e.g. vanilla or paper
+    server_brand: Option<String>,
+
     pub client_disconnect: bool,
     pub server_disconnect: bool,
     pub disconnect_reason: Option<String>,
@@ -63,6 +164,7 @@ pub struct Server {
 /// `InteractingInfo` - Debug and other useful info is visible, mouse is visible and can interact
 /// with the info windows
 /// `ChatOpen` - Chat is visible and interactable, mouse is visible and can scroll through the chat
+/// `Dead` - The death screen is visible and the player can't move until they respawn
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum InputState {
     Playing,
@@ -70,6 +172,17 @@ pub enum InputState {
     ShowingInfo,
     InteractingInfo,
     ChatOpen,
+    Dead,
+}
+
+/// How the camera moves.
+/// `Walking` - The camera is at the player's eyes and they walk with gravity and collision
+/// `FreeFly` - The camera flies through blocks on its own, leaving the player standing where they
+/// were so the server never sees it move. Only for looking around and debugging the renderer
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum CameraMode {
+    Walking,
+    FreeFly,
 }
 
 impl Server {
@@ -80,20 +193,43 @@ impl Server {
 
             input_state: InputState::Playing,
 
-            world_time: 0,
-            day_time: 0,
-
             player: Player::new(),
             chat: Chat::new(),
 
             world: World::new(),
 
             entities: HashMap::new(),
-            players: HashMap::new(),
+            players: PlayerList::new(),
+            particles: Particles::new(),
+            boss_bars: BossBars::new(),
+            scoreboard: Scoreboard::new(),
+            minimap: Minimap::new(),
 
             difficulty: Difficulty::Easy,
             difficulty_locked: false,
 
+            protocol_version: ProtocolVersion::default(),
+            join_info: None,
+            center_chunk: None,
+
+            connection_state: (ConnectionState::Connecting(1), Instant::now()),
+            logged_in: false,
+            last_teleport_id: None,
+            spawn_position: None,
+            last_sent_position: None,
+            movement_timer: 0.0,
+
+            digging: None,
+
+            camera_mode: CameraMode::Walking,
+            free_camera: Vec3::ZERO,
+
+            sound_events: Vec::new(),
+            recent_sounds: VecDeque::new(),
+
+            show_debug: false,
+            server_brand: None,
+
             client_disconnect: false,
             server_disconnect: false,
             disconnect_reason: None,
@@ -104,22 +240,78 @@ impl Server {
         &self.network_destination
     }
 
+    pub fn get_connection_state(&self) -> ConnectionState {
+        self.connection_state.0
+    }
+
+    pub fn get_last_teleport_id(&self) -> Option<i32> {
+        self.last_teleport_id
+    }
+
+    /// Returns true once the server has accepted the login
+    pub fn is_logged_in(&self) -> bool {
+        self.logged_in
+    }
+
     pub fn get_input_state(&self) -> InputState {
         self.input_state
     }
 
     pub fn get_world_time(&self) -> i64 {
-        self.world_time
+        self.world.get_world_age()
     }
 
     pub fn get_day_time(&self) -> i64 {
-        self.day_time
+        self.world.get_time_of_day()
     }
 
     pub fn get_player(&self) -> &Player {
         &self.player
     }
 
+    pub fn get_camera_mode(&self) -> CameraMode {
+        self.camera_mode
+    }
+
+    /// Switches between walking and flying freely, the free camera starts at the player's eyes
+    pub fn toggle_camera_mode(&mut self) {
+        self.camera_mode = match self.camera_mode {
+            CameraMode::Walking => {
+                self.free_camera = self.player.get_eye_position();
+                CameraMode::FreeFly
+            }
+            CameraMode::FreeFly => CameraMode::Walking,
+        };
+    }
+
+    /// Returns where the world is drawn from, the player's eyes or the free camera
+    pub fn get_camera_position(&self) -> Vec3 {
+        match self.camera_mode {
+            CameraMode::Walking => self.player.get_eye_position(),
+            CameraMode::FreeFly => self.free_camera,
+        }
+    }
+
+    pub fn get_protocol_version(&self) -> ProtocolVersion {
+        self.protocol_version
+    }
+
+    /// Sets the version of the protocol the server is using, which decides how chunks are decoded
+    pub fn set_protocol_version(&mut self, version: ProtocolVersion) {
+        self.protocol_version = version;
+        // Palettes in chunk data refer to the block state ids of the server's version
+        if let Err(e) = resources::use_blocks(version) {
+            warn!(
+                "Couldn't load blocks for {}, using {} instead: {}",
+                version.name(),
+                ProtocolVersion::default().name(),
+                e
+            );
+            resources::use_blocks(ProtocolVersion::default()).ok();
+        }
+        self.world.set_packing(version.packing());
+    }
+
     pub fn get_chat(&self) -> &Chat {
         &self.chat
     }
@@ -132,10 +324,64 @@ impl Server {
         &self.world
     }
 
+    pub fn is_debug_shown(&self) -> bool {
+        self.show_debug
+    }
+
+    /// Returns the block the player is looking at within reach
+    pub fn get_targeted_block(&self) -> Option<RaycastHit> {
+        self.world.raycast(
+            self.player.get_eye_position(),
+            self.player.get_orientation().get_look_vector(),
+            REACH,
+        )
+    }
+
     pub fn get_entities(&self) -> &HashMap<i32, Entity> {
         &self.entities
     }
 
+    pub fn get_boss_bars(&self) -> &BossBars {
+        &self.boss_bars
+    }
+
+    pub fn get_scoreboard(&self) -> &Scoreboard {
+        &self.scoreboard
+    }
+
+    pub fn get_particles(&self) -> &Particles {
+        &self.particles
+    }
+
+    /// Returns the sounds the server asked to play during the last update
+    pub fn get_sound_events(&self) -> &[SoundEvent] {
+        &self.sound_events
+    }
+
+    /// Returns the last few sounds the server asked to play, oldest first
+    pub fn get_recent_sounds(&self) -> &VecDeque<SoundEvent> {
+        &self.recent_sounds
+    }
+
+    fn play_sound(&mut self, sound: SoundEvent) {
+        debug!("Sound {} at {}", sound.name, sound.position);
+        if self.recent_sounds.len() >= RECENT_SOUNDS {
+            self.recent_sounds.pop_front();
+        }
+        self.recent_sounds.push_back(sound.clone());
+        self.sound_events.push(sound);
+    }
+
+    /// Adds an entity from a spawn packet, replacing any existing entity with the same id
+    fn spawn_entity(&mut self, entity: Option<Entity>) {
+        match entity {
+            Some(entity) => {
+                self.entities.insert(entity.get_id(), entity);
+            }
+            None => warn!("Tried to spawn an entity of an unknown type"),
+        }
+    }
+
     pub fn get_difficulty(&self) -> Difficulty {
         self.difficulty.clone()
     }
@@ -148,26 +394,123 @@ impl Server {
         self.input_state == InputState::Paused
     }
 
+    /// Returns true once the server has said the player has no health left, until they respawn
+    pub fn is_dead(&self) -> bool {
+        self.logged_in && self.player.health <= 0.0
+    }
+
+    /// Asks the server to respawn the player after dying, the death screen closes once the server
+    /// sends their health back. If the network thread has gone the death screen stays up, and the
+    /// lost connection is reported by the network thread
+    pub fn respawn(&mut self) {
+        let command = NetworkCommand::ClientStatus(ClientStatusAction::PerformRespawn);
+        if let Err(e) = self.network.send.send(command) {
+            error!("Failed to ask the server to respawn: {}", e);
+        }
+    }
+
     pub fn set_input_state(&mut self, state: InputState) {
         self.input_state = state;
     }
 
-    pub fn join_game(&mut self, player_id: i32) {
-        self.player.id = player_id;
+    /// Sets up the player and world from the Join Game packet
+    pub fn join_game(&mut self, info: JoinGameInfo) {
+        self.player.id = info.entity_id;
+        self.world
+            .set_height(info.dimension.min_y, info.dimension.height);
+        self.join_info = Some(info);
+    }
+
+    /// Moves the player to the world from a Respawn packet. Chunks and entities from another
+    /// dimension are dropped, and the player's health is reset unless the server says to keep it
+    fn handle_respawn(&mut self, pack: &PlayRespawnSpec) {
+        let changed_dimension = match self.join_info.as_mut() {
+            Some(info) => info.respawn(pack),
+            None => true,
+        };
+
+        if changed_dimension {
+            self.world.clear();
+            self.entities.clear();
+            self.particles.clear();
+            self.boss_bars.clear();
+            if let Some(info) = &self.join_info {
+                self.world
+                    .set_height(info.dimension.min_y, info.dimension.height);
+            }
+            info!("Moved to dimension {}", pack.world_name);
+        }
+
+        if !pack.copy_metadata {
+            let fresh = Player::new();
+            self.player.health = fresh.health;
+            self.player.food = fresh.food;
+            self.player.saturation = fresh.saturation;
+        }
+
+        // Movement isn't sent until the server teleports the player to the spawn
+        self.digging = None;
+        self.last_teleport_id = None;
+        self.last_sent_position = None;
+    }
+
+    /// Moves the player to where the server teleported them, confirming the teleport with its id
+    fn handle_teleport(&mut self, pack: &PlayServerPlayerPositionAndLookSpec) {
+        debug!("Player position updated!");
+
+        self.player.teleport(
+            Vec3::new(
+                pack.location.position.x as f32,
+                pack.location.position.y as f32,
+                pack.location.position.z as f32,
+            ),
+            pack.location.rotation.yaw,
+            pack.location.rotation.pitch,
+            pack.flags.0,
+        );
+
+        // The server waits for the teleport to be confirmed before accepting movement from the
+        // client
+        self.last_teleport_id = Some(pack.teleport_id.0);
+        self.send_command(NetworkCommand::TeleportConfirm(pack.teleport_id.0));
+        self.send_position(true);
+    }
+
+    /// Returns what the server sent when joining, None if the game hasn't been joined yet
+    pub fn get_join_info(&self) -> Option<&JoinGameInfo> {
+        self.join_info.as_ref()
     }
 
-    pub fn get_players(&self) -> &HashMap<UUID4, RemotePlayer> {
+    pub fn get_players(&self) -> &PlayerList {
         &self.players
     }
 
-    /// Generates a sky colour based on a provided base colour and the current time of day on the
-    /// server
+    /// Generates a sky colour based on a provided base colour and the current time of day and
+    /// weather on the server
     pub fn get_sky_colour(&self, col: &[f32; 3]) -> Vec3 {
-        const LIGHTEST: i64 = 9_000;
-        let lerp = (((self.day_time - LIGHTEST) as f32 / 24_000.0) * PI * 2.0).cos() / 2.0 + 0.5;
         let dark = Vec3::new(0.001, 0.002, 0.005);
         let light = Vec3::from(*col);
-        dark.lerp(light, lerp)
+        let sky = dark.lerp(light, self.world.sky_brightness());
+
+        // Rain and storms turn the sky a darker grey like in vanilla
+        let weather = self.world.get_weather();
+        let grey = Vec3::splat(sky.dot(Vec3::new(0.3, 0.59, 0.11)));
+        let sky = sky.lerp(grey * 0.6, weather.get_rain_level() * 0.75);
+        let sky = sky.lerp(grey * 0.2, weather.get_thunder_level() * 0.75);
+
+        // Bosses like the wither darken the sky while they're fought
+        if self.boss_bars.darkens_sky() {
+            sky * 0.5
+        } else {
+            sky
+        }
+    }
+
+    /// Returns the colour of the fog and the sky at the horizon, which is paler than the sky
+    /// above during the day
+    pub fn get_fog_colour(&self, col: &[f32; 3]) -> Vec3 {
+        let brightness = self.world.sky_brightness();
+        self.get_sky_colour(col).lerp(Vec3::ONE, 0.4 * brightness)
     }
 
     /// Attempts to send a packet over the provided (possible) network channel
@@ -192,6 +535,94 @@ impl Server {
         }
     }
 
+    /// Sends the position and rotation of the player to the server
+    pub fn send_position(&mut self, on_ground: bool) -> Option<()> {
+        let pos = *self.player.get_position();
+        let ori = self.player.get_orientation();
+        let (yaw, pitch) = (ori.get_yaw(), ori.get_pitch());
+
+        self.last_sent_position = Some((pos, yaw, pitch));
+        self.movement_timer = 0.0;
+        self.send_command(NetworkCommand::PlayerPositionAndLook {
+            x: pos.x as f64,
+            y: pos.y as f64,
+            z: pos.z as f64,
+            yaw,
+            pitch,
+            on_ground,
+        })
+    }
+
+    /// Sends the position and rotation of the player once a tick if they have changed, and every
+    /// so often if they haven't. Nothing is sent until the first teleport is confirmed since the
+    /// server ignores movement before then
+    fn send_movement(&mut self, delta: f32) {
+        if self.last_teleport_id.is_none() {
+            return;
+        }
+
+        self.movement_timer += delta;
+        if self.movement_timer < MOVEMENT_INTERVAL {
+            return;
+        }
+
+        let ori = self.player.get_orientation();
+        let current = (*self.player.get_position(), ori.get_yaw(), ori.get_pitch());
+        if self.last_sent_position != Some(current) || self.movement_timer >= MOVEMENT_KEEPALIVE {
+            self.send_position(self.player.is_on_ground());
+        }
+    }
+
+    /// Sends data to the server on a plugin channel
+    pub fn send_plugin_message(&self, channel: &str, data: Vec<u8>) -> Option<()> {
+        self.send_command(NetworkCommand::PluginMessage {
+            channel: channel.to_string(),
+            data,
+        })
+    }
+
+    /// Handles data received on a plugin channel, the server's brand is answered with the client's
+    fn handle_plugin_message(&mut self, channel: &str, data: &[u8]) {
+        if !network::valid_channel(channel) {
+            warn!("Ignoring plugin message on invalid channel: {}", channel);
+            return;
+        }
+
+        match channel {
+            network::BRAND_CHANNEL => {
+                self.server_brand = network::decode_brand(data);
+                info!("Server brand: {:?}", self.server_brand);
+                self.send_plugin_message(
+                    network::BRAND_CHANNEL,
+                    network::encode_brand(network::CLIENT_BRAND),
+                );
+            }
+            _ => debug!("Unhandled plugin message on {}", channel),
+        }
+    }
+
+    /// Returns true once the chunk the player is standing in has loaded
+    pub fn is_player_chunk_loaded(&self) -> bool {
+        let block = block_coords(self.player.get_position());
+        self.world
+            .get_chunk(&Chunk::chunk_containing(&block))
+            .is_some()
+    }
+
+    pub fn get_spawn_position(&self) -> Option<&IVec3> {
+        self.spawn_position.as_ref()
+    }
+
+    /// Returns the brand the server sent, if it has sent one
+    pub fn get_server_brand(&self) -> Option<&String> {
+        self.server_brand.as_ref()
+    }
+
+    /// Sends a chat message or command to the server
+    pub fn send_chat(&self, message: String) -> Option<()> {
+        self.send_command(NetworkCommand::SendChat(message))
+    }
+
     pub fn should_grab_mouse(&self) -> bool {
         match self.input_state {
             InputState::Playing => true,
@@ -199,14 +630,37 @@ impl Server {
             InputState::ShowingInfo => true,
             InputState::InteractingInfo => false,
             InputState::ChatOpen => false,
+            InputState::Dead => false,
         }
     }
 
-    pub fn render(&mut self, gui_ctx: &egui::Context, windows: &mut WindowManager) {
+    pub fn render(
+        &mut self,
+        gui_ctx: &egui::Context,
+        windows: &mut WindowManager,
+        settings: &mut Settings,
+    ) {
         if self.input_state != InputState::ChatOpen {
             chat_windows::render_inactive(self, gui_ctx);
         }
 
+        if !self.logged_in {
+            self.render_connection_state(gui_ctx);
+        }
+
+        if self.logged_in {
+            hotbar::render(gui_ctx, &self.player.inventory);
+            boss_bar_overlay::render(gui_ctx, &self.boss_bars);
+            scoreboard_sidebar::render(gui_ctx, &self.scoreboard);
+            self.minimap.render(
+                gui_ctx,
+                &self.world,
+                *self.player.get_position(),
+                self.player.get_orientation().get_look_vector(),
+                settings,
+            );
+        }
+
         match self.input_state {
             InputState::Playing => {}
             InputState::Paused => match pause_windows::render(gui_ctx, windows) {
@@ -218,16 +672,139 @@ impl Server {
                 info_windows::render(gui_ctx, self)
             }
             InputState::ChatOpen => chat_windows::render_active(self, gui_ctx),
+            InputState::Dead => {
+                let hardcore = self.join_info.as_ref().map_or(false, |info| info.hardcore);
+                match death_screen::render(gui_ctx, hardcore) {
+                    death_screen::DeathAction::Respawn => {
+                        self.respawn();
+                    }
+                    death_screen::DeathAction::Disconnect => self.disconnect(),
+                    death_screen::DeathAction::Nothing => {}
+                }
+            }
+        }
+    }
+
+    /// Tells the server the player's locale, view distance and other client settings
+    fn send_client_settings(&self) -> Option<()> {
+        self.send_command(NetworkCommand::ClientSettings {
+            locale: self.player.locale.clone(),
+            view_distance: self.player.view_distance,
+            chat_mode: self.player.chat_mode.clone(),
+            chat_colors: true,
+            displayed_skin_parts: self.player.displayed_skin_parts,
+            main_hand: self.player.main_hand.clone(),
+        })
+    }
+
+    /// Copies the client settings from the settings, sending them to the server again if any
+    /// changed after joining
+    fn apply_client_settings(&mut self, settings: &Settings) {
+        let view_distance = settings.render_distance.min(i8::MAX as u8) as i8;
+        if self.player.view_distance == view_distance && self.player.locale == settings.locale {
+            return;
+        }
+        self.player.view_distance = view_distance;
+        self.player.locale = settings.locale.clone();
+
+        if self.join_info.is_some() {
+            self.send_client_settings();
         }
     }
 
+    /// Unloads chunks the server has stopped sending once the player moves into a new chunk, in
+    /// case the server doesn't send Unload Chunk for them
+    fn unload_distant_chunks(&mut self) {
+        // The player's position isn't known until the server first teleports them
+        if self.last_teleport_id.is_none() {
+            return;
+        }
+
+        let pos = self.player.get_position();
+        let center = IVec2::new(
+            (pos.x.floor() as i32).div_floor(16),
+            (pos.z.floor() as i32).div_floor(16),
+        );
+        if self.center_chunk == Some(center) {
+            return;
+        }
+        self.center_chunk = Some(center);
+
+        // Chunks are sent out to the server's view distance, keep one extra ring so chunks at the
+        // edge aren't dropped while crossing a chunk border. 1.16 servers don't use the client's
+        // view distance, so chunks past the render distance are kept and only skipped when
+        // rendering, otherwise they wouldn't be sent again when the player comes back
+        let view_distance = self
+            .join_info
+            .as_ref()
+            .map(|info| info.view_distance)
+            .unwrap_or(self.player.view_distance as i32);
+        let unloaded = self.world.unload_outside(center, view_distance + 1);
+        if unloaded > 0 {
+            debug!("Unloaded {} distant chunks", unloaded);
+        }
+    }
+
+    /// Shows the progress of connecting while the server hasn't been joined yet
+    fn render_connection_state(&self, gui_ctx: &egui::Context) {
+        let (state, since) = self.connection_state;
+        let text = match state {
+            ConnectionState::Connecting(1) => String::from("Connecting..."),
+            ConnectionState::Connecting(attempt) => format!("Connecting... (attempt {})", attempt),
+            ConnectionState::Retrying(delay) => format!(
+                "Connection failed, retrying in {}s",
+                delay.saturating_sub(since.elapsed()).as_secs_f32().ceil()
+            ),
+            ConnectionState::Connected => String::from("Logging in..."),
+            ConnectionState::Failed => String::from("Failed to connect"),
+        };
+
+        egui::Window::new("Connecting")
+            .resizable(false)
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(gui_ctx, |ui| {
+                ui.label(self.network_destination.as_str());
+                ui.label(text);
+            });
+    }
+
     pub fn update(&mut self, ctx: &Context, delta: f32, settings: &mut Settings) {
         self.world.generate_meshes(&ctx.dis, true);
+        self.apply_client_settings(settings);
+        self.unload_distant_chunks();
 
         // Update entities
         for ent in self.entities.values_mut() {
             ent.update(delta);
         }
+        self.particles.update(delta);
+
+        // The death screen stays up over anything other than menus until the player respawns
+        if self.is_dead()
+            && matches!(
+                self.input_state,
+                InputState::Playing | InputState::ShowingInfo | InputState::InteractingInfo
+            )
+        {
+            self.input_state = InputState::Dead;
+
+            // Servers can turn off the death screen to respawn straight away
+            if !self
+                .join_info
+                .as_ref()
+                .map_or(true, |info| info.enable_respawn_screen)
+            {
+                self.respawn();
+            }
+        } else if self.input_state == InputState::Dead && !self.is_dead() {
+            self.input_state = InputState::Playing;
+        }
+
+        // Digging stops as soon as the player leaves the game for a menu
+        if self.input_state != InputState::Playing {
+            self.stop_digging();
+        }
 
         // Handle input
         match self.input_state {
@@ -236,55 +813,124 @@ impl Server {
             InputState::ShowingInfo => self.handle_show_info_state(ctx, delta, settings),
             InputState::InteractingInfo => self.handle_interact_info_state(ctx, delta, settings),
             InputState::ChatOpen => self.handle_chat_open_state(ctx, delta, settings),
+            InputState::Dead => self.handle_dead_state(ctx),
         }
 
+        self.send_movement(delta);
+
         // Handle messages from the NetworkManager
+        self.sound_events.clear();
+        for event in self.poll_events() {
+            self.handle_event(event, ctx);
+        }
+    }
+
+    /// Takes all of the events sent from the network thread since the last poll, this never blocks
+    /// on network I/O
+    pub fn poll_events(&mut self) -> Vec<ServerEvent> {
+        let mut events = Vec::new();
         loop {
             match self.network.recv.try_recv() {
-                Ok(comm) => self.handle_message(comm, ctx),
-                Err(e) => match e {
-                    std::sync::mpsc::TryRecvError::Empty => break,
-                    std::sync::mpsc::TryRecvError::Disconnected => {
-                        log::error!("Could not communicate with server. Assuming disconnected.");
-                        self.server_disconnect = true;
-                        if self.disconnect_reason.is_none() {
-                            self.disconnect_reason = Some(String::from("Server forced disconnect. (You were probably sending too many connection requests)"));
-                        }
-                        return;
-                    }
-                },
+                Ok(NetworkCommand::ReceivePacket(PacketType::PlayServerPluginMessage(pack))) => {
+                    events.push(ServerEvent::PluginMessage {
+                        channel: pack.channel,
+                        data: pack.data.data,
+                    })
+                }
+                Ok(NetworkCommand::ReceivePacket(packet)) => {
+                    events.push(ServerEvent::Packet(packet))
+                }
+                Ok(NetworkCommand::ReceiveStatus(status)) => {
+                    events.push(ServerEvent::Status(status))
+                }
+                Ok(NetworkCommand::ConnectionState(state)) => {
+                    events.push(ServerEvent::ConnectionState(state))
+                }
+                Ok(NetworkCommand::Error(e)) => events.push(ServerEvent::ConnectionLost(e)),
+                Ok(NetworkCommand::ConnectFailed(e)) => events.push(ServerEvent::ConnectFailed(e)),
+                // What do with these messages ay??
+                Ok(comm) => debug!("Unhandled message: {:?}", comm),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    events.push(ServerEvent::Closed);
+                    break;
+                }
             }
         }
+        events
     }
 
     fn handle_playing_state(&mut self, ctx: &Context, delta: f32, settings: &mut Settings) {
+        let keys = &settings.key_bindings;
         if ctx.keyboard.pressed_this_frame(&VirtualKeyCode::Escape) {
             self.input_state = InputState::Paused;
-        } else if ctx.keyboard.pressed_this_frame(&VirtualKeyCode::T) {
+        } else if ctx.keyboard.pressed_this_frame(&keys.get(Action::OpenChat)) {
             self.input_state = InputState::ChatOpen;
-        } else if ctx.keyboard.pressed_this_frame(&VirtualKeyCode::Slash) {
+        } else if ctx
+            .keyboard
+            .pressed_this_frame(&keys.get(Action::OpenCommand))
+        {
             self.input_state = InputState::ChatOpen;
             self.chat.set_current_message(String::from("/"));
-        } else if ctx.keyboard.pressed_this_frame(&VirtualKeyCode::Tab) {
+        } else if ctx
+            .keyboard
+            .pressed_this_frame(&keys.get(Action::PlayerList))
+        {
             self.input_state = InputState::ShowingInfo;
+        } else if ctx
+            .keyboard
+            .pressed_this_frame(&keys.get(Action::ToggleDebug))
+        {
+            self.show_debug = !self.show_debug;
+        } else if ctx
+            .keyboard
+            .pressed_this_frame(&keys.get(Action::ToggleCamera))
+        {
+            self.toggle_camera_mode();
+        } else if ctx
+            .keyboard
+            .pressed_this_frame(&keys.get(Action::ToggleChunkBorders))
+        {
+            settings.show_chunk_borders = !settings.show_chunk_borders;
+        } else if ctx
+            .keyboard
+            .pressed_this_frame(&keys.get(Action::ToggleWireframe))
+        {
+            settings.wireframe = !settings.wireframe;
+        } else if ctx
+            .keyboard
+            .pressed_this_frame(&keys.get(Action::ToggleMinimap))
+        {
+            settings.show_minimap = !settings.show_minimap;
         }
 
+        self.handle_hotbar_keys(ctx, settings);
+
         self.handle_keyboard_movement(ctx, delta, settings);
         self.handle_mouse_movement(ctx, delta, settings);
+        self.handle_digging(ctx, delta);
+        self.handle_placing(ctx);
+    }
+
+    fn handle_dead_state(&mut self, ctx: &Context) {
+        if ctx.keyboard.pressed_this_frame(&VirtualKeyCode::Escape) {
+            self.input_state = InputState::Paused;
+        }
     }
 
-    fn handle_paused_state(&mut self, ctx: &Context, delta: f32, settings: &mut Settings) {
+    fn handle_paused_state(&mut self, ctx: &Context, _delta: f32, _settings: &mut Settings) {
         if ctx.keyboard.pressed_this_frame(&VirtualKeyCode::Escape) {
             self.input_state = InputState::Playing;
         }
     }
 
     fn handle_show_info_state(&mut self, ctx: &Context, delta: f32, settings: &mut Settings) {
+        let player_list = settings.key_bindings.get(Action::PlayerList);
         if ctx.keyboard.pressed_this_frame(&VirtualKeyCode::Escape) {
             self.input_state = InputState::Paused;
         } else if ctx.mouse.pressed_this_frame(0) {
             self.input_state = InputState::InteractingInfo;
-        } else if ctx.keyboard.released_this_frame(&VirtualKeyCode::Tab) {
+        } else if ctx.keyboard.released_this_frame(&player_list) {
             self.input_state = InputState::Playing;
         }
 
@@ -293,93 +939,249 @@ impl Server {
     }
 
     fn handle_interact_info_state(&mut self, ctx: &Context, delta: f32, settings: &mut Settings) {
+        let player_list = settings.key_bindings.get(Action::PlayerList);
         if ctx.keyboard.pressed_this_frame(&VirtualKeyCode::Escape) {
             self.input_state = InputState::Paused;
-        } else if ctx.keyboard.released_this_frame(&VirtualKeyCode::Tab) {
+        } else if ctx.keyboard.released_this_frame(&player_list) {
             self.input_state = InputState::Playing;
         }
 
         self.handle_keyboard_movement(ctx, delta, settings);
     }
 
-    fn handle_chat_open_state(&mut self, ctx: &Context, delta: f32, settings: &mut Settings) {
+    fn handle_chat_open_state(&mut self, ctx: &Context, _delta: f32, _settings: &mut Settings) {
         if ctx.keyboard.pressed_this_frame(&VirtualKeyCode::Escape) {
             self.input_state = InputState::Playing;
         } else if ctx.keyboard.pressed_this_frame(&VirtualKeyCode::Return) {
-            let text = self.chat.get_current_message_and_clear();
-            if !text.is_empty() {
-                self.send_packet(encode(PacketType::PlayClientChatMessage(
-                    PlayClientChatMessageSpec { message: text },
-                )));
-            }
+            self.submit_chat();
             self.input_state = InputState::Playing;
+        } else if ctx.keyboard.pressed_this_frame(&VirtualKeyCode::Up) {
+            self.chat.previous_sent();
+        } else if ctx.keyboard.pressed_this_frame(&VirtualKeyCode::Down) {
+            self.chat.next_sent();
         }
     }
 
-    pub fn handle_mouse_movement(&mut self, ctx: &Context, delta: f32, settings: &mut Settings) {
-        let off = ctx.mouse.get_delta();
-        self.player.get_orientation_mut().rotate(
-            off.0 as f32 * 0.05 * settings.mouse_sensitivity,
-            off.1 as f32 * 0.05 * settings.mouse_sensitivity,
-        );
+    /// Sends the message typed into chat and clears the input, nothing is sent if it's empty
+    fn submit_chat(&mut self) {
+        if let Some(text) = self.chat.submit_current_message() {
+            // Commands don't show up in chat unless the server replies, so echo them
+            if text.starts_with('/') {
+                self.chat
+                    .add_message(ChatEvent::local(text.clone()), self.world.get_world_age());
+            }
+            self.send_chat(text);
+        }
     }
 
-    pub fn handle_keyboard_movement(&mut self, ctx: &Context, delta: f32, settings: &mut Settings) {
-        let vel = 14.0 * delta;
+    /// Breaks the targeted block while the left mouse button is held. Blocks that break instantly
+    /// are finished straight away, others once they have been dug for long enough. Broken blocks
+    /// are removed straight away, the server's block change replaces them if it disagrees
+    fn handle_digging(&mut self, ctx: &Context, delta: f32) {
+        if !ctx.mouse.is_pressed(0) {
+            self.stop_digging();
+            return;
+        }
 
-        if ctx.keyboard.is_pressed(&VirtualKeyCode::W) {
-            let mut dir = self.player.get_orientation().get_look_vector();
-            dir.y = 0.0;
-            dir = dir.normalize();
-            dir *= vel;
-            self.player.get_position_mut().add_assign(dir);
+        match self.get_targeted_block() {
+            Some(target) => self.dig(target.block_pos, target.face, delta),
+            None => self.stop_digging(),
         }
+    }
 
-        if ctx.keyboard.is_pressed(&VirtualKeyCode::S) {
-            let mut dir = self.player.get_orientation().get_look_vector();
-            dir.y = 0.0;
-            dir = dir.normalize();
-            dir *= -vel;
-            self.player.get_position_mut().add_assign(dir);
+    /// Keeps breaking a block for a frame, starting on it if it's a different block or face to the
+    /// one being broken. Blocks that break instantly are finished in the same frame
+    fn dig(&mut self, pos: WorldCoords, face: BlockFace, delta: f32) {
+        let same_target = self
+            .digging
+            .map_or(false, |d| d.pos == pos && d.face == face);
+        if !same_target {
+            self.stop_digging();
+
+            let creative = self
+                .join_info
+                .as_ref()
+                .map_or(false, |info| matches!(info.gamemode, GameMode::Creative));
+            let time = match self
+                .world
+                .block_at(&pos)
+                .and_then(|b| break_time(b, creative))
+            {
+                Some(time) => time,
+                None => return,
+            };
+
+            self.send_digging(PlayerDiggingStatus::Started, &pos, face);
+            self.digging = Some(Digging::new(pos, face, time));
         }
 
-        if ctx.keyboard.is_pressed(&VirtualKeyCode::A) {
-            let mut dir = self.player.get_orientation().get_look_vector();
-            dir.y = 0.0;
-            dir = dir.normalize();
-            dir *= -vel;
-            dir.y = dir.x; // Just using this value as temp to swap x and z
-            dir.x = -dir.z;
-            dir.z = dir.y;
-            dir.y = 0.0;
-            self.player.get_position_mut().add_assign(dir);
+        if let Some(digging) = &mut self.digging {
+            if digging.advance(delta) {
+                let digging = *digging;
+                self.digging = None;
+
+                self.send_digging(PlayerDiggingStatus::Finished, &digging.pos, digging.face);
+                self.world.set_block(&digging.pos, AIR as BlockIndex);
+            }
         }
+    }
 
-        if ctx.keyboard.is_pressed(&VirtualKeyCode::D) {
-            let mut dir = self.player.get_orientation().get_look_vector();
-            dir.y = 0.0;
-            dir = dir.normalize();
-            dir *= vel;
-            dir.y = dir.x; // Just using this value as temp to swap x and z
-            dir.x = -dir.z;
-            dir.z = dir.y;
-            dir.y = 0.0;
-            self.player.get_position_mut().add_assign(dir);
+    /// Selects a hotbar slot with the number keys
+    fn handle_hotbar_keys(&mut self, ctx: &Context, settings: &Settings) {
+        for (slot, action) in Action::HOTBAR.iter().enumerate() {
+            let key = settings.key_bindings.get(*action);
+            if ctx.keyboard.pressed_this_frame(&key) && slot != self.player.inventory.get_selected()
+            {
+                self.player.inventory.select(slot);
+                self.send_command(NetworkCommand::HeldItemChange(slot as i16));
+            }
         }
+    }
 
-        if ctx.keyboard.is_pressed(&VirtualKeyCode::Space) {
-            self.player
-                .get_position_mut()
-                .add_assign(Vec3::new(0.0, vel, 0.0));
+    /// Uses the held item on the targeted face when right clicking. Blocks are placed locally
+    /// straight away unless they would end up inside the player
+    fn handle_placing(&mut self, ctx: &Context) {
+        if !ctx.mouse.pressed_this_frame(1) {
+            return;
+        }
+        let target = match self.get_targeted_block() {
+            Some(hit) => hit,
+            None => return,
+        };
+
+        let place_pos = target.get_place_pos();
+        let place_box = Aabb::FULL_CUBE.offset(place_pos.as_vec3());
+        if place_box.intersects(&self.player.get_bounding_box()) {
+            return;
         }
 
-        if ctx.keyboard.is_pressed(&VirtualKeyCode::LShift) {
-            self.player
-                .get_position_mut()
-                .add_assign(Vec3::new(0.0, -vel, 0.0));
+        // Clicked in the middle of the face
+        let cursor = Vec3::splat(0.5) + target.face.normal().as_vec3() * 0.5;
+        self.send_command(NetworkCommand::UseItemOn {
+            hand: Hand::MainHand,
+            position: IntPosition {
+                x: target.block_pos.x,
+                y: target.block_pos.y as i16,
+                z: target.block_pos.z,
+            },
+            face: digging_face(target.face),
+            cursor_x: cursor.x,
+            cursor_y: cursor.y,
+            cursor_z: cursor.z,
+            inside_block: false,
+        });
+
+        // Show the block straight away, the server sends a block change if it was rejected
+        let replaceable = self
+            .world
+            .block_at(&place_pos)
+            .map_or(false, |b| b.is_air());
+        let held_block = self
+            .player
+            .inventory
+            .held_item()
+            .and_then(ItemStack::get_block);
+        if let (Some(block), true) = (held_block, replaceable) {
+            self.world.set_block(&place_pos, block);
         }
     }
 
+    /// Cancels breaking the block the player was digging, if they were digging one
+    fn stop_digging(&mut self) {
+        if let Some(digging) = self.digging.take() {
+            self.send_digging(PlayerDiggingStatus::Cancelled, &digging.pos, digging.face);
+        }
+    }
+
+    fn send_digging(&self, status: PlayerDiggingStatus, pos: &WorldCoords, face: BlockFace) {
+        self.send_command(NetworkCommand::PlayerDigging {
+            status,
+            position: IntPosition {
+                x: pos.x,
+                y: pos.y as i16,
+                z: pos.z,
+            },
+            face: digging_face(face),
+        });
+    }
+
+    /// Returns the block the player is breaking, if any
+    pub fn get_digging(&self) -> Option<&Digging> {
+        self.digging.as_ref()
+    }
+
+    pub fn handle_mouse_movement(&mut self, ctx: &Context, _delta: f32, settings: &mut Settings) {
+        let off = ctx.mouse.get_delta();
+        let scale = MOUSE_DEGREES_PER_PIXEL * settings.mouse_sensitivity;
+        self.player
+            .get_orientation_mut()
+            .rotate(off.0 as f32 * scale, off.1 as f32 * scale);
+    }
+
+    pub fn handle_keyboard_movement(&mut self, ctx: &Context, delta: f32, settings: &mut Settings) {
+        if self.camera_mode == CameraMode::FreeFly {
+            self.handle_free_fly(ctx, delta, settings);
+            return;
+        }
+
+        // Hold the player in place until the chunk they are in loads, so they don't wander off
+        // into the void before the world arrives
+        if !self.is_player_chunk_loaded() {
+            return;
+        }
+
+        let keys = &settings.key_bindings;
+        let speed = if ctx.keyboard.is_pressed(&keys.get(Action::Sprint)) {
+            settings.sprint_speed
+        } else {
+            settings.walk_speed
+        };
+
+        // Taken from the yaw alone so looking straight up or down doesn't stop the player
+        let yaw = self.player.get_orientation().get_yaw().to_radians();
+        let forward = Vec3::new(-yaw.sin(), 0.0, yaw.cos());
+        let right = Vec3::new(-forward.z, 0.0, forward.x);
+        let walk = movement_keys(ctx, keys, forward, right).normalize_or_zero() * speed;
+
+        let jump = ctx.keyboard.is_pressed(&keys.get(Action::Jump));
+        self.player.walk(&self.world, walk, jump, delta);
+    }
+
+    /// Flies the free camera where it is looking, straight through blocks. Jump and fly down move
+    /// straight up and down, and sprint speeds it up
+    fn handle_free_fly(&mut self, ctx: &Context, delta: f32, settings: &Settings) {
+        let keys = &settings.key_bindings;
+        let ori = self.player.get_orientation();
+        let forward = ori.get_look_vector();
+        let yaw = ori.get_yaw().to_radians();
+        let right = Vec3::new(-yaw.cos(), 0.0, -yaw.sin());
+
+        let mut dir = movement_keys(ctx, keys, forward, right);
+        if ctx.keyboard.is_pressed(&keys.get(Action::Jump)) {
+            dir += Vec3::Y;
+        }
+        if ctx.keyboard.is_pressed(&keys.get(Action::FlyDown)) {
+            dir -= Vec3::Y;
+        }
+
+        let mut speed = settings.fly_speed;
+        if ctx.keyboard.is_pressed(&keys.get(Action::Sprint)) {
+            speed *= FAST_FLY_MULTIPLIER;
+        }
+        self.fly(dir, speed, delta);
+    }
+
+    /// Moves the free camera straight through any blocks in the way
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Direction to fly in, doesn't have to be normalised
+    /// * `speed` - Blocks per second
+    /// * `delta` - Seconds since the last frame
+    fn fly(&mut self, dir: Vec3, speed: f32, delta: f32) {
+        self.free_camera += dir.normalize_or_zero() * speed * delta;
+    }
+
     pub fn disconnect(&mut self) {
         info!("Disconnecting from server.");
         self.network
@@ -390,12 +1192,10 @@ impl Server {
     }
 
     /// Handles a message from the NetworkManager
-    fn handle_message(&mut self, comm: NetworkCommand, ctx: &Context) {
-        use NetworkCommand::*;
-
-        match comm {
+    fn handle_event(&mut self, event: ServerEvent, _ctx: &Context) {
+        match event {
             // Handles any incoming packets
-            ReceivePacket(packet) => {
+            ServerEvent::Packet(packet) => {
                 match packet {
                     PacketType::PlayServerDifficulty(pack) => {
                         self.difficulty = pack.difficulty;
@@ -403,9 +1203,21 @@ impl Server {
                         info!("Changed difficulty: {}", pack.locked);
                     }
 
+                    PacketType::PlayChangeGameState(pack) => {
+                        if !self.world.handle_change_game_state(&pack) {
+                            match pack.reason {
+                                GameChangeReason::ChangeGameMode(gamemode) => {
+                                    if let Some(info) = self.join_info.as_mut() {
+                                        info.gamemode = gamemode;
+                                    }
+                                }
+                                reason => debug!("Unhandled game state change: {:?}", reason),
+                            }
+                        }
+                    }
+
                     PacketType::PlayTimeUpdate(pack) => {
-                        self.world_time = pack.world_age;
-                        self.day_time = pack.time_of_day;
+                        self.world.handle_time_update(pack);
                     }
 
                     PacketType::PlayUpdatehealth(pack) => {
@@ -414,105 +1226,52 @@ impl Server {
                         self.player.saturation = pack.saturation;
                     }
 
+                    PacketType::PlayRespawn(pack) => self.handle_respawn(&pack),
+
                     PacketType::PlayDisconnect(pack) => {
-                        self.disconnect_reason = pack.reason.to_traditional();
+                        self.disconnect_reason = Some(component_text(&pack.reason));
                         info!("Disconnected from server: {:?}", self.disconnect_reason);
                         self.server_disconnect = true;
                     }
 
-                    PacketType::LoginSuccess(pack) => {
+                    PacketType::LoginSuccess(_) => {
                         info!("Successfully Logged in!");
+                        self.logged_in = true;
                     }
 
                     PacketType::LoginDisconnect(pack) => {
                         info!("Disconnected during login");
                         self.server_disconnect = true;
-                        self.disconnect_reason = pack.message.to_traditional();
-                    }
-
-                    PacketType::PlayJoinGame(id) => {
-                        self.join_game(id.entity_id);
-                        self.send_packet(encode(PacketType::PlayClientSettings(
-                            PlayClientSettingsSpec {
-                                locale: self.player.locale.clone(),
-                                view_distance: (self.player.view_distance),
-                                chat_mode: self.player.chat_mode.clone(),
-                                chat_colors: (false),
-                                displayed_skin_parts: self.player.displayed_skin_parts,
-                                main_hand: self.player.main_hand.clone(),
-                            },
-                        )));
-                        self.send_packet(encode(PacketType::PlayClientStatus(
-                            PlayClientStatusSpec {
-                                action: ClientStatusAction::PerformRespawn,
-                            },
-                        )));
+                        let message = component_text(&pack.message);
+                        // Explain a version mismatch the same way as one found from the status
+                        self.disconnect_reason = Some(if version::is_outdated_message(&message) {
+                            ConnectError::ProtocolMismatch {
+                                server: version::version_in_message(&message)
+                                    .unwrap_or_else(|| String::from("a different version")),
+                                client: self.protocol_version.name().to_string(),
+                            }
+                            .to_string()
+                        } else {
+                            message
+                        });
+                    }
+
+                    PacketType::PlayJoinGame(pack) => {
+                        self.join_game(JoinGameInfo::from_packet(&pack));
+                        self.send_client_settings();
+                        self.respawn();
                     }
 
                     PacketType::PlaySpawnPlayer(pack) => {
-                        self.entities.insert(
-                            pack.entity_id.0,
-                            Entity::new_with_values(
-                                pack.entity_id.0,
-                                pack.uuid,
-                                PLAYER_INDEX as u32,
-                                0,
-                                pack.location.position.x as f32,
-                                pack.location.position.y as f32,
-                                pack.location.position.z as f32,
-                                pack.location.rotation.yaw.value as f32 / 255.0,
-                                pack.location.rotation.pitch.value as f32 / 255.0,
-                                pack.location.rotation.pitch.value as f32 / 255.0,
-                                0.0,
-                                0.0,
-                                0.0,
-                            ),
-                        );
+                        self.spawn_entity(Entity::from_spawn_player(&pack))
                     }
 
                     PacketType::PlaySpawnLivingEntity(pack) => {
-                        match self.entities.insert(
-                            pack.entity_id.0,
-                            Entity::new_with_values(
-                                pack.entity_id.0,
-                                pack.entity_uuid,
-                                pack.entity_type.0 as u32,
-                                0,
-                                pack.location.position.x as f32,
-                                pack.location.position.y as f32,
-                                pack.location.position.z as f32,
-                                pack.location.rotation.yaw.value as f32 / 255.0,
-                                pack.location.rotation.pitch.value as f32 / 255.0,
-                                pack.head_pitch.value as f32 / 255.0,
-                                pack.velocity.x as f32 / 400.0,
-                                pack.velocity.y as f32 / 400.0,
-                                pack.velocity.z as f32 / 400.0,
-                            ),
-                        ) {
-                            Some(_) => {}
-                            None => {}
-                        }
+                        self.spawn_entity(Entity::from_spawn_living(&pack))
                     }
 
                     PacketType::PlaySpawnEntity(pack) => {
-                        self.entities.insert(
-                            pack.entity_id.0,
-                            Entity::new_with_values(
-                                pack.entity_id.0,
-                                pack.object_uuid,
-                                pack.entity_type.0 as u32,
-                                pack.data,
-                                pack.position.x as f32,
-                                pack.position.y as f32,
-                                pack.position.z as f32,
-                                pack.yaw.value as f32 / 255.0,
-                                pack.pitch.value as f32 / 255.0,
-                                0.0,
-                                pack.velocity.x as f32 / 400.0,
-                                pack.velocity.y as f32 / 400.0,
-                                pack.velocity.z as f32 / 400.0,
-                            ),
-                        );
+                        self.spawn_entity(Entity::from_spawn_entity(&pack))
                     }
 
                     PacketType::PlayDestroyEntities(pack) => {
@@ -522,211 +1281,188 @@ impl Server {
                     }
 
                     PacketType::PlayEntityPosition(pack) => {
-                        match self.entities.get_mut(&pack.entity_id.0) {
-                            Some(ent) => {
-                                let new_pos = ent.last_pos
-                                    + Vec3::new(
-                                        (pack.delta.x as f32) / 4096.0,
-                                        (pack.delta.y as f32) / 4096.0,
-                                        (pack.delta.z as f32) / 4096.0,
-                                    );
-                                ent.pos = new_pos;
-                                ent.last_pos = new_pos;
-                            }
-                            None => {}
+                        if let Some(ent) = self.entities.get_mut(&pack.entity_id.0) {
+                            ent.move_relative(pack.delta.x, pack.delta.y, pack.delta.z);
+                            ent.on_ground = pack.on_ground;
                         }
                     }
 
                     PacketType::PlayEntityPositionAndRotation(pack) => {
-                        match self.entities.get_mut(&pack.entity_id.0) {
-                            Some(ent) => {
-                                let new_pos = ent.last_pos
-                                    + Vec3::new(
-                                        (pack.delta.position.x as f32) / 4096.0,
-                                        (pack.delta.position.y as f32) / 4096.0,
-                                        (pack.delta.position.z as f32) / 4096.0,
-                                    );
-                                ent.pos = new_pos;
-                                ent.last_pos = new_pos;
-                                ent.ori.set(
-                                    pack.delta.rotation.yaw.value as f32 / 256.0,
-                                    pack.delta.rotation.pitch.value as f32 / 256.0,
-                                );
-                                ent.on_ground = pack.on_ground;
-                            }
-                            None => {}
+                        if let Some(ent) = self.entities.get_mut(&pack.entity_id.0) {
+                            let delta = &pack.delta.position;
+                            ent.move_relative(delta.x, delta.y, delta.z);
+                            ent.ori.set(
+                                angle_to_degrees(pack.delta.rotation.yaw.value),
+                                angle_to_degrees(pack.delta.rotation.pitch.value),
+                            );
+                            ent.on_ground = pack.on_ground;
                         }
                     }
 
                     PacketType::PlayEntityRotation(pack) => {
-                        match self.entities.get_mut(&pack.entity_id.0) {
-                            Some(ent) => {
-                                ent.ori.set(
-                                    pack.rotation.yaw.value as f32 / 256.0,
-                                    pack.rotation.pitch.value as f32 / 256.0,
-                                );
-                                ent.on_ground = pack.on_ground;
-                            }
-                            None => {}
+                        if let Some(ent) = self.entities.get_mut(&pack.entity_id.0) {
+                            ent.ori.set(
+                                angle_to_degrees(pack.rotation.yaw.value),
+                                angle_to_degrees(pack.rotation.pitch.value),
+                            );
+                            ent.on_ground = pack.on_ground;
                         }
                     }
 
                     PacketType::PlayEntityHeadLook(pack) => {
-                        match self.entities.get_mut(&pack.entity_id.0) {
-                            Some(ent) => {
-                                ent.ori_head.set(
-                                    pack.head_yaw.value as f32 / 256.0,
-                                    ent.ori_head.get_pitch(),
-                                );
-                            }
-                            None => {}
+                        if let Some(ent) = self.entities.get_mut(&pack.entity_id.0) {
+                            let pitch = ent.ori_head.get_pitch();
+                            ent.ori_head
+                                .set(angle_to_degrees(pack.head_yaw.value), pitch);
                         }
                     }
 
                     PacketType::PlayEntityVelocity(pack) => {
-                        match self.entities.get_mut(&pack.entity_id.0) {
-                            Some(ent) => {
-                                ent.vel = Vec3::new(
-                                    pack.velocity.x as f32 / 400.0,
-                                    pack.velocity.y as f32 / 400.0,
-                                    pack.velocity.z as f32 / 400.0,
-                                );
-                            }
-                            None => {}
+                        if let Some(ent) = self.entities.get_mut(&pack.entity_id.0) {
+                            ent.vel = Vec3::new(
+                                velocity_to_blocks(pack.velocity.x),
+                                velocity_to_blocks(pack.velocity.y),
+                                velocity_to_blocks(pack.velocity.z),
+                            );
                         }
                     }
 
                     PacketType::PlayEntityTeleport(pack) => {
-                        match self.entities.get_mut(&pack.entity_id.0) {
-                            Some(ent) => {
-                                ent.pos = Vec3::new(
-                                    pack.location.position.x as f32,
-                                    pack.location.position.y as f32,
-                                    pack.location.position.z as f32,
-                                );
-                                ent.ori.set(
-                                    pack.location.rotation.yaw.value as f32 / 256.0,
-                                    pack.location.rotation.pitch.value as f32 / 256.0,
-                                );
-                                ent.on_ground = pack.on_ground;
-                            }
-                            None => {}
+                        if let Some(ent) = self.entities.get_mut(&pack.entity_id.0) {
+                            ent.teleport(Vec3::new(
+                                pack.location.position.x as f32,
+                                pack.location.position.y as f32,
+                                pack.location.position.z as f32,
+                            ));
+                            ent.ori.set(
+                                angle_to_degrees(pack.location.rotation.yaw.value),
+                                angle_to_degrees(pack.location.rotation.pitch.value),
+                            );
+                            ent.on_ground = pack.on_ground;
                         }
                     }
 
                     PacketType::PlayServerPlayerPositionAndLook(pack) => {
-                        debug!("Player position updated!");
-
-                        self.player.set_position(Vec3::new(
-                            pack.location.position.x as f32,
-                            pack.location.position.y as f32,
-                            pack.location.position.z as f32,
-                        ));
-                        self.player
-                            .get_orientation_mut()
-                            .set(pack.location.rotation.yaw, pack.location.rotation.pitch);
-
-                        self.send_packet(encode(PacketType::PlayTeleportConfirm(
-                            PlayTeleportConfirmSpec {
-                                teleport_id: pack.teleport_id,
-                            },
-                        )));
-
-                        let px = self.player.get_position().x;
-                        let py = self.player.get_position().y;
-                        let pz = self.player.get_position().z;
-
-                        self.send_packet(encode(PacketType::PlayClientPlayerPositionAndRotation(
-                            PlayClientPlayerPositionAndRotationSpec {
-                                on_ground: (true),
-                                feet_location: EntityLocation {
-                                    position: types::Vec3 {
-                                        x: px as f64,
-                                        y: py as f64,
-                                        z: pz as f64,
-                                    },
-                                    rotation: pack.location.rotation,
-                                },
-                            },
-                        )));
+                        self.handle_teleport(&pack)
+                    }
+
+                    PacketType::PlaySpawnPosition(pack) => {
+                        let spawn =
+                            IVec3::new(pack.location.x, pack.location.y as i32, pack.location.z);
+                        self.spawn_position = Some(spawn);
+
+                        // The spawn is sent before the first teleport, so the camera starts
+                        // there rather than at the origin until the server places the player
+                        if self.last_teleport_id.is_none() {
+                            self.player.set_position(spawn.as_vec3());
+                        }
                     }
 
                     PacketType::PlayServerChatMessage(chat) => {
-                        self.chat.add_message(chat, self.world_time);
+                        self.chat
+                            .add_message(ChatEvent::from_packet(chat), self.world.get_world_age());
                     }
 
                     PacketType::PlayChunkData(cd) => {
-                        self.world.insert_chunk(Chunk::new(&cd.data));
+                        self.world.load_chunk(cd.data);
+                    }
+
+                    PacketType::PlayUpdateLight(pack) => {
+                        self.world.handle_update_light(pack);
                     }
 
                     PacketType::PlayUnloadChunk(pack) => {
                         self.world
-                            .get_chunks_mut()
-                            .remove(&IVec2::new(pack.position.x, pack.position.z));
+                            .unload_chunk(&IVec2::new(pack.position.x, pack.position.z));
+                    }
+
+                    PacketType::PlayWindowItems(pack) => {
+                        if pack.window_id as i8 == PLAYER_WINDOW {
+                            self.player
+                                .inventory
+                                .set_slots(pack.slots.iter().map(ItemStack::from_slot).collect());
+                        }
+                    }
+
+                    PacketType::PlaySetSlot(pack) => {
+                        // Window -1 is the item held by the cursor, which isn't tracked
+                        if pack.window_id as i8 == PLAYER_WINDOW
+                            && !self
+                                .player
+                                .inventory
+                                .set_slot(pack.slow as usize, ItemStack::from_slot(&pack.slot_data))
+                        {
+                            warn!("Got item for invalid inventory slot {}", pack.slow);
+                        }
+                    }
+
+                    PacketType::PlayServerHeldItemChange(pack) => {
+                        if !self.player.inventory.select(pack.slot as usize) {
+                            warn!("Server selected invalid hotbar slot {}", pack.slot);
+                        }
                     }
 
                     PacketType::PlayBlockChange(pack) => {
                         self.world.handle_block_change(pack);
                     }
 
+                    // Blocks broken locally are put back if the server didn't allow it
+                    PacketType::PlayAcknowledgePlayerDigging(pack) => {
+                        if !pack.successful {
+                            let pos = IVec3::new(
+                                pack.location.x,
+                                pack.location.y.into(),
+                                pack.location.z,
+                            );
+                            self.world.set_block(&pos, pack.block.0 as BlockIndex);
+                        }
+                    }
+
                     PacketType::PlayMultiBlockChange(pack) => {
                         self.world.handle_multi_block_change(pack);
                     }
 
-                    PacketType::PlayPlayerInfo(pack) => {
-                        use mcproto_rs::v1_16_3::PlayerInfoActionList;
-                        match pack.actions {
-                            PlayerInfoActionList::Add(players) => {
-                                for player in players.iter() {
-                                    self.players.insert(
-                                        player.uuid,
-                                        RemotePlayer {
-                                            uuid: player.uuid,
-                                            name: player.action.name.clone(),
-                                            gamemode: player.action.game_mode.clone(),
-                                            ping: player.action.ping_ms.0,
-                                            display_name: player
-                                                .action
-                                                .display_name
-                                                .clone()
-                                                .map(|dn| dn.to_traditional())
-                                                .unwrap_or(None),
-                                        },
-                                    );
-                                }
-                            }
-                            PlayerInfoActionList::UpdateGameMode(players) => {
-                                let players: Vec<PlayerInfoAction<GameMode>> = From::from(players);
-                                for player in players {
-                                    if let Some(p) = self.players.get_mut(&player.uuid) {
-                                        p.gamemode = player.action;
-                                    }
-                                }
-                            }
-                            PlayerInfoActionList::UpdateLatency(players) => {
-                                let players: Vec<PlayerInfoAction<VarInt>> = From::from(players);
-                                for player in players {
-                                    if let Some(p) = self.players.get_mut(&player.uuid) {
-                                        p.ping = player.action.into();
-                                    }
-                                }
-                            }
-                            PlayerInfoActionList::UpdateDisplayName(players) => {
-                                for player in players.iter() {
-                                    if let Some(p) = self.players.get_mut(&player.uuid) {
-                                        p.display_name = player.action.clone().map(|chat| {
-                                            chat.to_traditional().unwrap_or_else(|| {
-                                                "Failed to parse name".to_string()
-                                            })
-                                        });
-                                    }
-                                }
-                            }
-                            PlayerInfoActionList::Remove(players) => {
-                                for player in players.iter() {
-                                    self.players.remove(player);
-                                }
-                            }
+                    PacketType::PlayPlayerInfo(pack) => self.players.apply(pack.actions),
+
+                    PacketType::PlayBossBar(pack) => self.boss_bars.handle_packet(&pack),
+
+                    PacketType::PlayScoreboardObjective(pack) => {
+                        self.scoreboard.handle_objective(&pack);
+                    }
+
+                    PacketType::PlayUpdateScore(pack) => self.scoreboard.handle_score(&pack),
+
+                    PacketType::PlayDisplayScoreboard(pack) => {
+                        self.scoreboard.handle_display(&pack);
+                    }
+
+                    PacketType::PlayParticle(pack) => {
+                        self.particles.spawn(&Particle::from_packet(&pack));
+                    }
+
+                    PacketType::PlayNamedSoundEffect(pack) => {
+                        self.play_sound(SoundEvent::from_named(&pack));
+                    }
+
+                    PacketType::PlaySoundEffect(pack) => {
+                        self.play_sound(SoundEvent::from_id(&pack));
+                    }
+
+                    PacketType::PlayEntitySoundEffect(pack) => {
+                        // Sounds from the player or an entity play wherever it is
+                        let position = if pack.entity_id.0 == self.player.id {
+                            Some(*self.player.get_position())
+                        } else {
+                            self.entities.get(&pack.entity_id.0).map(|e| e.pos)
+                        };
+                        if let Some(position) = position {
+                            self.play_sound(SoundEvent {
+                                name: sound::sound_name(pack.sound_id.0),
+                                category: pack.sound_category.clone(),
+                                position,
+                                volume: pack.volume,
+                                pitch: pack.pitch,
+                            });
                         }
                     }
 
@@ -743,10 +1479,351 @@ impl Server {
                 }
             }
 
-            // What do with these messages ay??
-            _ => {
-                debug!("Unhandled message: {:?}", comm);
+            // The connection was lost without the server giving a reason
+            ServerEvent::ConnectionLost(e) => {
+                error!("Lost connection to server: {}", e);
+                self.server_disconnect = true;
+                if self.disconnect_reason.is_none() {
+                    self.disconnect_reason = Some(format!("Connection lost: {}", e));
+                }
+            }
+
+            ServerEvent::ConnectFailed(e) => {
+                error!("Couldn't connect to server: {}", e);
+                self.server_disconnect = true;
+                self.disconnect_reason = Some(e.to_string());
+            }
+
+            ServerEvent::Closed => {
+                log::error!("Could not communicate with server. Assuming disconnected.");
+                self.server_disconnect = true;
+                if self.disconnect_reason.is_none() {
+                    self.disconnect_reason = Some(String::from("Server forced disconnect. (You were probably sending too many connection requests)"));
+                }
+            }
+
+            ServerEvent::ConnectionState(state) => {
+                self.connection_state = (state, Instant::now());
+            }
+
+            ServerEvent::PluginMessage { channel, data } => {
+                self.handle_plugin_message(&channel, &data);
             }
+
+            ServerEvent::Status(_) => {}
+        }
+    }
+}
+
+/// Returns the sum of the directions of the held movement keys, which is zero if opposite keys
+/// are held or none are
+fn movement_keys(ctx: &Context, keys: &KeyBindings, forward: Vec3, right: Vec3) -> Vec3 {
+    let mut dir = Vec3::ZERO;
+    if ctx.keyboard.is_pressed(&keys.get(Action::Forward)) {
+        dir += forward;
+    }
+    if ctx.keyboard.is_pressed(&keys.get(Action::Back)) {
+        dir -= forward;
+    }
+    if ctx.keyboard.is_pressed(&keys.get(Action::Right)) {
+        dir += right;
+    }
+    if ctx.keyboard.is_pressed(&keys.get(Action::Left)) {
+        dir -= right;
+    }
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{self, Receiver};
+
+    use mcproto_rs::{
+        protocol::{Id, PacketDirection, RawPacket, State},
+        types::{EntityLocation, EntityRotation, VarInt, Vec3 as ProtoVec3},
+        v1_16_3::PositionAndLookFlags,
+    };
+
+    use crate::{
+        network::RawPacketType,
+        player::EYE_HEIGHT,
+        world::chunks::{MIN_SECTION, SECTIONS_PER_CHUNK},
+    };
+
+    use super::*;
+
+    /// Makes a server whose commands to the network thread are returned instead of being sent
+    fn test_server() -> (Server, Receiver<NetworkCommand>) {
+        let (send, commands) = mpsc::channel();
+        let (_, recv) = mpsc::channel();
+        let server = Server::new(String::from("test"), NetworkChannel { send, recv });
+        (server, commands)
+    }
+
+    #[test]
+    fn teleport_confirm_echoes_id() {
+        let (mut server, commands) = test_server();
+        let pack = PlayServerPlayerPositionAndLookSpec {
+            location: EntityLocation {
+                position: ProtoVec3 {
+                    x: 8.5,
+                    y: 70.0,
+                    z: -3.5,
+                },
+                rotation: EntityRotation {
+                    yaw: 90.0,
+                    pitch: 0.0,
+                },
+            },
+            flags: PositionAndLookFlags(0),
+            teleport_id: VarInt(1234),
+        };
+        server.handle_teleport(&pack);
+
+        assert!(matches!(
+            commands.try_recv(),
+            Ok(NetworkCommand::TeleportConfirm(1234))
+        ));
+        assert!(matches!(
+            commands.try_recv(),
+            Ok(NetworkCommand::PlayerPositionAndLook { x, y, z, .. })
+                if x == 8.5 && y == 70.0 && z == -3.5
+        ));
+        assert_eq!(server.get_last_teleport_id(), Some(1234));
+    }
+
+    #[test]
+    fn submitting_chat_clears_input_and_sends() {
+        let (mut server, commands) = test_server();
+
+        server
+            .get_chat_mut()
+            .set_current_message(String::from("  hello world "));
+        server.submit_chat();
+        assert!(server.get_chat().get_current_message().is_empty());
+        assert!(matches!(
+            commands.try_recv(),
+            Ok(NetworkCommand::SendChat(text)) if text == "hello world"
+        ));
+        assert!(server.get_chat().get_history().is_empty());
+
+        // Commands are echoed into the scrollback as well as sent
+        server
+            .get_chat_mut()
+            .set_current_message(String::from("/time set day"));
+        server.submit_chat();
+        assert!(matches!(
+            commands.try_recv(),
+            Ok(NetworkCommand::SendChat(text)) if text == "/time set day"
+        ));
+        assert_eq!(server.get_chat().get_history().len(), 1);
+
+        // Empty input sends nothing
+        server.submit_chat();
+        assert!(commands.try_recv().is_err());
+    }
+
+    #[test]
+    fn server_brand_is_answered() {
+        let (mut server, commands) = test_server();
+
+        server.handle_plugin_message(network::BRAND_CHANNEL, &network::encode_brand("Paper"));
+        assert_eq!(server.get_server_brand(), Some(&String::from("Paper")));
+        assert!(matches!(
+            commands.try_recv(),
+            Ok(NetworkCommand::PluginMessage { channel, data })
+                if channel == network::BRAND_CHANNEL
+                    && data == network::encode_brand(network::CLIENT_BRAND)
+        ));
+
+        // Nothing is answered on other or invalid channels
+        server.handle_plugin_message("example:other", &[1, 2, 3]);
+        server.handle_plugin_message("Bad Channel", &[]);
+        assert!(commands.try_recv().is_err());
+    }
+
+    /// Makes a test server with a block placed in an otherwise empty chunk at the origin
+    fn server_with_block(pos: WorldCoords, name: &str) -> (Server, Receiver<NetworkCommand>) {
+        let (mut server, commands) = test_server();
+        server
+            .world
+            .insert_chunk(Chunk::empty(IVec2::ZERO, MIN_SECTION, SECTIONS_PER_CHUNK));
+        server
+            .world
+            .set_block(&pos, resources::state_id(name, &[]) as BlockIndex);
+        (server, commands)
+    }
+
+    #[test]
+    fn instant_break_finishes_straight_away() {
+        let pos = IVec3::new(3, 64, 5);
+        let (mut server, commands) = server_with_block(pos, "Torch");
+
+        server.dig(pos, BlockFace::Up, 0.0);
+        assert!(matches!(
+            commands.try_recv(),
+            Ok(NetworkCommand::PlayerDigging { status: PlayerDiggingStatus::Started, position, .. })
+                if position.x == 3 && position.y == 64 && position.z == 5
+        ));
+        assert!(matches!(
+            commands.try_recv(),
+            Ok(NetworkCommand::PlayerDigging {
+                status: PlayerDiggingStatus::Finished,
+                ..
+            })
+        ));
+        assert!(server.get_digging().is_none());
+        assert!(server.world.block_at(&pos).unwrap().is_air());
+    }
+
+    #[test]
+    fn timed_break_waits_for_break_time() {
+        let pos = IVec3::new(3, 64, 5);
+        let (mut server, commands) = server_with_block(pos, "Dirt");
+
+        server.dig(pos, BlockFace::Up, 0.0);
+        assert!(matches!(
+            commands.try_recv(),
+            Ok(NetworkCommand::PlayerDigging {
+                status: PlayerDiggingStatus::Started,
+                ..
+            })
+        ));
+        assert!(commands.try_recv().is_err());
+        assert!(server.get_digging().is_some());
+
+        // Dirt takes 0.75 seconds by hand
+        server.dig(pos, BlockFace::Up, 0.5);
+        assert!(commands.try_recv().is_err());
+        server.dig(pos, BlockFace::Up, 0.25);
+        assert!(matches!(
+            commands.try_recv(),
+            Ok(NetworkCommand::PlayerDigging {
+                status: PlayerDiggingStatus::Finished,
+                ..
+            })
+        ));
+        assert!(server.world.block_at(&pos).unwrap().is_air());
+    }
+
+    #[test]
+    fn free_fly_ignores_collision() {
+        let wall = IVec3::new(3, 63, 5);
+        let (mut server, commands) = server_with_block(wall, "Stone");
+        server.player.set_position(Vec3::new(3.5, 62.0, 3.5));
+        let start = *server.player.get_position();
+
+        server.toggle_camera_mode();
+        assert_eq!(server.get_camera_mode(), CameraMode::FreeFly);
+        assert_eq!(server.get_camera_position(), start + Vec3::Y * EYE_HEIGHT);
+
+        // Straight through the stone in 4 steps of half a block
+        for _ in 0..4 {
+            server.fly(Vec3::Z, 5.0, 0.1);
+        }
+        let camera = server.get_camera_position();
+        assert!((camera - Vec3::new(3.5, 62.0 + EYE_HEIGHT, 5.5)).length() < 1e-4);
+        assert_eq!(block_coords(&camera), wall);
+
+        // The player stays where they were without anything sent to the server
+        assert_eq!(*server.player.get_position(), start);
+        assert!(commands.try_recv().is_err());
+
+        server.toggle_camera_mode();
+        assert_eq!(server.get_camera_mode(), CameraMode::Walking);
+        assert_eq!(server.get_camera_position(), start + Vec3::Y * EYE_HEIGHT);
+    }
+
+    #[test]
+    fn respawn_sends_perform_respawn() {
+        let (mut server, commands) = test_server();
+        server.logged_in = true;
+        server.player.health = 0.0;
+        assert!(server.is_dead());
+
+        server.respawn();
+        assert!(matches!(
+            commands.try_recv(),
+            Ok(NetworkCommand::ClientStatus(
+                ClientStatusAction::PerformRespawn
+            ))
+        ));
+        assert!(commands.try_recv().is_err());
+    }
+
+    fn overworld() -> JoinGameInfo {
+        JoinGameInfo {
+            entity_id: 1,
+            hardcore: false,
+            gamemode: GameMode::Survival,
+            world_name: String::from("minecraft:overworld"),
+            dimension: join_game::DimensionType {
+                min_y: -64,
+                height: 384,
+                ..Default::default()
+            },
+            biomes: HashMap::new(),
+            hashed_seed: 0,
+            max_players: 20,
+            view_distance: 10,
+            reduced_debug_info: false,
+            enable_respawn_screen: true,
+            is_debug: false,
+            is_flat: false,
+        }
+    }
+
+    /// Decodes a respawn packet into a dimension that is 256 blocks tall from y = 0
+    fn respawn_into(world_name: &str) -> PlayRespawnSpec {
+        // Dimension type compound holding the bounds as ints
+        let mut body = vec![0x0a, 0x00, 0x00];
+        for (name, value) in [("min_y", 0i32), ("height", 256)] {
+            body.push(0x03);
+            body.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            body.extend_from_slice(name.as_bytes());
+            body.extend_from_slice(&value.to_be_bytes());
+        }
+        body.push(0x00);
+
+        body.push(world_name.len() as u8);
+        body.extend_from_slice(world_name.as_bytes());
+        body.extend_from_slice(&0i64.to_be_bytes());
+        // Game mode, previous game mode, debug, flat and copy metadata
+        body.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x01]);
+
+        let id = Id {
+            id: 0x39,
+            state: State::Play,
+            direction: PacketDirection::ClientBound,
+        };
+        match RawPacketType::create(id, &body)
+            .unwrap()
+            .deserialize()
+            .unwrap()
+        {
+            PacketType::PlayRespawn(pack) => pack,
+            other => panic!("Decoded the wrong packet: {:?}", other),
         }
     }
+
+    #[test]
+    fn dimension_change_empties_chunks() {
+        let (mut server, _commands) = test_server();
+        server.join_game(overworld());
+        assert_eq!(server.world.get_min_section(), -4);
+        for x in 0..3 {
+            server
+                .world
+                .insert_chunk(Chunk::empty(IVec2::new(x, 0), -4, 24));
+        }
+
+        // Respawning in the same dimension keeps the chunks
+        server.handle_respawn(&respawn_into("minecraft:overworld"));
+        assert_eq!(server.world.get_chunks().len(), 3);
+
+        server.handle_respawn(&respawn_into("minecraft:the_nether"));
+        assert!(server.world.get_chunks().is_empty());
+        assert_eq!(server.world.get_min_section(), 0);
+        assert_eq!(server.world.get_section_count(), 16);
+    }
 }
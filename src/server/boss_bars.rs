@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+
+use mcproto_rs::{
+    types::Chat,
+    uuid::UUID4,
+    v1_16_3::{BossBarAction, BossBarColor, BossBarDivision, PlayBossBarSpec},
+};
+
+use crate::chat::component_text;
+
+// Flags sent with a boss bar
+const DARKEN_SKY: u8 = 0x1;
+const PLAY_MUSIC: u8 = 0x2;
+const CREATE_FOG: u8 = 0x4;
+
+/// A bar shown at the top of the screen, normally for a boss's health or a minigame's timer
+#[derive(Debug, Clone)]
+pub struct BossBar {
+    pub title: String,
+    // From 0 to 1
+    pub health: f32,
+    pub colour: BossBarColor,
+    pub division: BossBarDivision,
+    flags: u8,
+}
+
+impl BossBar {
+    pub fn darkens_sky(&self) -> bool {
+        self.flags & DARKEN_SKY != 0
+    }
+
+    pub fn plays_music(&self) -> bool {
+        self.flags & PLAY_MUSIC != 0
+    }
+
+    pub fn creates_fog(&self) -> bool {
+        self.flags & CREATE_FOG != 0
+    }
+
+    /// Returns the RGB colour the bar is filled with
+    pub fn get_rgb(&self) -> [u8; 3] {
+        match self.colour {
+            BossBarColor::Pink => [236, 0, 184],
+            BossBarColor::Blue => [0, 183, 236],
+            BossBarColor::Red => [236, 53, 0],
+            BossBarColor::Green => [78, 236, 0],
+            BossBarColor::Yellow => [236, 226, 0],
+            BossBarColor::Purple => [123, 0, 236],
+            BossBarColor::White => [236, 236, 236],
+        }
+    }
+
+    /// Returns how many segments the bar is split into, 1 for a solid bar
+    pub fn get_segments(&self) -> u32 {
+        match self.division {
+            BossBarDivision::NoDivision => 1,
+            BossBarDivision::SixNotches => 6,
+            BossBarDivision::TenNotches => 10,
+            BossBarDivision::TwelveNotches => 12,
+            BossBarDivision::TwentyNotches => 20,
+        }
+    }
+}
+
+/// Boss bars the server has added, in the order they were added like the vanilla client shows them
+#[derive(Default)]
+pub struct BossBars {
+    bars: HashMap<UUID4, BossBar>,
+    order: Vec<UUID4>,
+}
+
+impl BossBars {
+    pub fn new() -> BossBars {
+        BossBars {
+            bars: HashMap::new(),
+            order: Vec::new(),
+        }
+    }
+
+    /// Adds, removes or updates a bar from a Boss Bar packet
+    pub fn handle_packet(&mut self, pack: &PlayBossBarSpec) {
+        match &pack.action {
+            BossBarAction::Add(add) => {
+                if !self.bars.contains_key(&pack.uuid) {
+                    self.order.push(pack.uuid);
+                }
+                self.bars.insert(
+                    pack.uuid,
+                    BossBar {
+                        title: component_text(&add.title),
+                        health: add.health.clamp(0.0, 1.0),
+                        colour: add.color.clone(),
+                        division: add.division.clone(),
+                        flags: add.flags.0,
+                    },
+                );
+            }
+            BossBarAction::Remove => {
+                self.bars.remove(&pack.uuid);
+                self.order.retain(|uuid| *uuid != pack.uuid);
+            }
+            action => {
+                let bar = match self.bars.get_mut(&pack.uuid) {
+                    Some(bar) => bar,
+                    None => {
+                        log::warn!("Update for unknown boss bar {:?}", pack.uuid);
+                        return;
+                    }
+                };
+                match action {
+                    BossBarAction::UpdateHealth(update) => {
+                        bar.health = update.health.clamp(0.0, 1.0)
+                    }
+                    BossBarAction::UpdateTitle(update) => bar.title = json_text(&update.title),
+                    BossBarAction::UpdateStyle(style) => {
+                        bar.colour = style.color.clone();
+                        bar.division = style.dividers.clone();
+                    }
+                    BossBarAction::UpdateFlags(update) => bar.flags = update.flags.0,
+                    BossBarAction::Add(_) | BossBarAction::Remove => {}
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, uuid: &UUID4) -> Option<&BossBar> {
+        self.bars.get(uuid)
+    }
+
+    /// Returns every bar from the first added to the last
+    pub fn get_bars(&self) -> impl Iterator<Item = &BossBar> {
+        self.order
+            .iter()
+            .filter_map(move |uuid| self.bars.get(uuid))
+    }
+
+    /// Returns true if any bar asks for the sky to be darkened, like the wither does
+    pub fn darkens_sky(&self) -> bool {
+        self.bars.values().any(BossBar::darkens_sky)
+    }
+
+    pub fn clear(&mut self) {
+        self.bars.clear();
+        self.order.clear();
+    }
+}
+
+/// Flattens a title sent as a json chat component, mcproto-rs leaves the title of an update as the
+/// raw json. Titles that aren't valid json are shown as they are
+fn json_text(json: &str) -> String {
+    match serde_json::from_str::<Chat>(json) {
+        Ok(chat) => component_text(&chat),
+        Err(_) => json.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mcproto_rs::protocol::{Id, PacketDirection, RawPacket, State};
+
+    use crate::network::{PacketType, RawPacketType};
+
+    use super::*;
+
+    /// Decodes a boss bar packet for the same bar
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - Id of the action
+    /// * `data` - Data of the action after its id
+    fn boss_bar(action: u8, data: &[u8]) -> PlayBossBarSpec {
+        let mut body = vec![0x5a; 16];
+        body.push(action);
+        body.extend_from_slice(data);
+
+        let id = Id {
+            id: 0x0c,
+            state: State::Play,
+            direction: PacketDirection::ClientBound,
+        };
+        match RawPacketType::create(id, &body)
+            .unwrap()
+            .deserialize()
+            .unwrap()
+        {
+            PacketType::PlayBossBar(pack) => pack,
+            other => panic!("Decoded the wrong packet: {:?}", other),
+        }
+    }
+
+    fn add_wither() -> PlayBossBarSpec {
+        let title = br#"{"text":"Wither"}"#;
+        let mut data = vec![title.len() as u8];
+        data.extend_from_slice(title);
+        data.extend_from_slice(&1.0f32.to_be_bytes());
+        // Purple, split into 6 and darkening the sky
+        data.extend_from_slice(&[0x05, 0x01, DARKEN_SKY]);
+        boss_bar(0, &data)
+    }
+
+    #[test]
+    fn add_bar() {
+        let mut bars = BossBars::new();
+        let add = add_wither();
+        bars.handle_packet(&add);
+
+        let bar = bars.get(&add.uuid).unwrap();
+        assert_eq!(bar.title, "Wither");
+        assert_eq!(bar.health, 1.0);
+        assert_eq!(bar.get_rgb(), [123, 0, 236]);
+        assert_eq!(bar.get_segments(), 6);
+        assert!(bar.darkens_sky());
+        assert!(!bar.plays_music());
+        assert!(bars.darkens_sky());
+        assert_eq!(bars.get_bars().count(), 1);
+    }
+
+    #[test]
+    fn update_health() {
+        let mut bars = BossBars::new();
+        let add = add_wither();
+        bars.handle_packet(&add);
+
+        bars.handle_packet(&boss_bar(2, &0.25f32.to_be_bytes()));
+        assert_eq!(bars.get(&add.uuid).unwrap().health, 0.25);
+
+        // Out of range health is clamped
+        bars.handle_packet(&boss_bar(2, &1.5f32.to_be_bytes()));
+        assert_eq!(bars.get(&add.uuid).unwrap().health, 1.0);
+
+        bars.handle_packet(&boss_bar(1, &[]));
+        assert!(bars.get(&add.uuid).is_none());
+        assert_eq!(bars.get_bars().count(), 0);
+    }
+}
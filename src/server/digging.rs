@@ -0,0 +1,92 @@
+use mcproto_rs::v1_16_3::DiggingFace;
+
+use crate::{
+    resources::BlockState,
+    world::{raycast::BlockFace, WorldCoords},
+};
+
+// Breaking progress made each tick is divided by the hardness and this, or by
+// TOOL_REQUIRED_DIVISOR for blocks that need a tool. There are no tools yet so everything is
+// broken by hand
+const HAND_DIVISOR: f32 = 30.0;
+const TOOL_REQUIRED_DIVISOR: f32 = 100.0;
+const TICKS_PER_SECOND: f32 = 20.0;
+
+/// A block the player is part way through breaking
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Digging {
+    pub pos: WorldCoords,
+    pub face: BlockFace,
+    // Seconds spent digging so far, and how long the block takes to break
+    progress: f32,
+    break_time: f32,
+}
+
+impl Digging {
+    pub fn new(pos: WorldCoords, face: BlockFace, break_time: f32) -> Digging {
+        Digging {
+            pos,
+            face,
+            progress: 0.0,
+            break_time,
+        }
+    }
+
+    /// Advances digging by the time since the last frame, returns true once the block has broken
+    pub fn advance(&mut self, delta: f32) -> bool {
+        self.progress += delta;
+        self.is_finished()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.progress >= self.break_time
+    }
+
+    /// Returns how far through breaking the block is, in 0 to 1
+    pub fn get_progress(&self) -> f32 {
+        if self.break_time <= 0.0 {
+            1.0
+        } else {
+            (self.progress / self.break_time).min(1.0)
+        }
+    }
+}
+
+/// Returns how many seconds a block takes to break by hand, 0 for blocks that break instantly and
+/// None for blocks that can't be broken
+///
+/// # Arguments
+///
+/// * `block` - The block being broken
+/// * `creative` - Whether the player is in creative mode, where every block breaks instantly
+pub fn break_time(block: &BlockState, creative: bool) -> Option<f32> {
+    if block.is_air() {
+        return None;
+    }
+    if creative {
+        return Some(0.0);
+    }
+    if block.hardness < 0.0 {
+        return None;
+    }
+
+    let divisor = if block.requires_tool {
+        TOOL_REQUIRED_DIVISOR
+    } else {
+        HAND_DIVISOR
+    };
+    let ticks = (block.hardness * divisor).ceil();
+    Some(ticks / TICKS_PER_SECOND)
+}
+
+/// Converts a face of a block to the face sent in a player digging packet
+pub fn digging_face(face: BlockFace) -> DiggingFace {
+    match face {
+        BlockFace::Up => DiggingFace::Top,
+        BlockFace::Down => DiggingFace::Bottom,
+        BlockFace::North => DiggingFace::North,
+        BlockFace::East => DiggingFace::East,
+        BlockFace::South => DiggingFace::South,
+        BlockFace::West => DiggingFace::West,
+    }
+}
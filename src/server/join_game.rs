@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use mcproto_rs::{
+    nbt,
+    v1_16_3::{GameMode, PlayJoinGameSpec, PlayRespawnSpec},
+};
+
+// Bounds of dimensions from before 1.17, which don't include them in the dimension type
+const DEFAULT_MIN_Y: i32 = 0;
+const DEFAULT_HEIGHT: i32 = 256;
+
+/// Information about the world the player has joined, sent by the server in the Join Game packet
+#[derive(Debug, Clone)]
+pub struct JoinGameInfo {
+    pub entity_id: i32,
+    pub hardcore: bool,
+    pub gamemode: GameMode,
+    pub world_name: String,
+    pub dimension: DimensionType,
+    // Biome names by id, from the dimension codec
+    pub biomes: HashMap<i32, String>,
+    pub hashed_seed: i64,
+    pub max_players: i32,
+    pub view_distance: i32,
+    pub reduced_debug_info: bool,
+    pub enable_respawn_screen: bool,
+    pub is_debug: bool,
+    pub is_flat: bool,
+}
+
+impl JoinGameInfo {
+    pub fn from_packet(pack: &PlayJoinGameSpec) -> JoinGameInfo {
+        JoinGameInfo {
+            entity_id: pack.entity_id,
+            hardcore: pack.is_hardcore,
+            gamemode: pack.gamemode.clone(),
+            world_name: pack.world_name.clone(),
+            dimension: DimensionType::from_nbt(&pack.dimension.root.payload),
+            biomes: read_registry(
+                &pack.dimension_codec.root.payload,
+                "minecraft:worldgen/biome",
+            ),
+            hashed_seed: pack.hashed_seed,
+            max_players: pack.max_players.0,
+            view_distance: pack.view_distance.0,
+            reduced_debug_info: pack.reduced_debug_info,
+            enable_respawn_screen: pack.enable_respawn_screen,
+            is_debug: pack.is_debug,
+            is_flat: pack.is_flat,
+        }
+    }
+
+    /// Updates the world the player is in from a Respawn packet, returns true if they have moved
+    /// to a different dimension
+    pub fn respawn(&mut self, pack: &PlayRespawnSpec) -> bool {
+        let changed = self.world_name != pack.world_name;
+
+        self.gamemode = pack.gamemode.clone();
+        self.world_name = pack.world_name.clone();
+        self.dimension = DimensionType::from_nbt(&pack.dimension.root.payload);
+        self.hashed_seed = pack.hashed_seed;
+        self.is_debug = pack.is_debug;
+        self.is_flat = pack.is_flat;
+
+        changed
+    }
+}
+
+/// Properties of a dimension the client needs to know about
+#[derive(Debug, Clone, PartialEq)]
+pub struct DimensionType {
+    pub min_y: i32,
+    pub height: i32,
+    pub has_skylight: bool,
+    pub ambient_light: f32,
+}
+
+impl DimensionType {
+    /// Reads a dimension type from its NBT compound, using the pre 1.17 bounds for any that are
+    /// missing
+    pub fn from_nbt(tag: &nbt::Tag) -> DimensionType {
+        DimensionType {
+            min_y: get_int(tag, "min_y").unwrap_or(DEFAULT_MIN_Y),
+            height: get_int(tag, "height").unwrap_or(DEFAULT_HEIGHT),
+            has_skylight: get_byte(tag, "has_skylight").map_or(true, |b| b != 0),
+            ambient_light: match get(tag, "ambient_light") {
+                Some(nbt::Tag::Float(f)) => *f,
+                _ => 0.0,
+            },
+        }
+    }
+}
+
+impl Default for DimensionType {
+    fn default() -> Self {
+        DimensionType {
+            min_y: DEFAULT_MIN_Y,
+            height: DEFAULT_HEIGHT,
+            has_skylight: true,
+            ambient_light: 0.0,
+        }
+    }
+}
+
+/// Reads the names of the entries of a registry in the dimension codec by their id
+fn read_registry(codec: &nbt::Tag, registry: &str) -> HashMap<i32, String> {
+    let mut entries = HashMap::new();
+
+    let values = match get(codec, registry).and_then(|r| get(r, "value")) {
+        Some(nbt::Tag::List(values)) => values,
+        _ => {
+            log::warn!("Dimension codec is missing the {} registry", registry);
+            return entries;
+        }
+    };
+
+    for value in values {
+        if let (Some(id), Some(nbt::Tag::String(name))) = (get_int(value, "id"), get(value, "name"))
+        {
+            entries.insert(id, name.clone());
+        }
+    }
+
+    entries
+}
+
+/// Gets a tag from a compound by name
+fn get<'a>(tag: &'a nbt::Tag, name: &str) -> Option<&'a nbt::Tag> {
+    match tag {
+        nbt::Tag::Compound(tags) => tags.iter().find(|t| t.name == name).map(|t| &t.payload),
+        _ => None,
+    }
+}
+
+fn get_int(tag: &nbt::Tag, name: &str) -> Option<i32> {
+    match get(tag, name) {
+        Some(nbt::Tag::Int(i)) => Some(*i),
+        _ => None,
+    }
+}
+
+fn get_byte(tag: &nbt::Tag, name: &str) -> Option<i8> {
+    match get(tag, name) {
+        Some(nbt::Tag::Byte(b)) => Some(*b),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compound(tags: Vec<(&str, nbt::Tag)>) -> nbt::Tag {
+        nbt::Tag::Compound(
+            tags.into_iter()
+                .map(|(name, payload)| nbt::NamedTag {
+                    name: name.to_string(),
+                    payload,
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn dimension_bounds_from_nbt() {
+        // The 1.18 overworld
+        let overworld = compound(vec![
+            ("min_y", nbt::Tag::Int(-64)),
+            ("height", nbt::Tag::Int(384)),
+            ("has_skylight", nbt::Tag::Byte(1)),
+            ("ambient_light", nbt::Tag::Float(0.0)),
+        ]);
+        let dimension = DimensionType::from_nbt(&overworld);
+        assert_eq!(dimension.min_y, -64);
+        assert_eq!(dimension.height, 384);
+        assert!(dimension.has_skylight);
+
+        let nether = compound(vec![
+            ("has_skylight", nbt::Tag::Byte(0)),
+            ("ambient_light", nbt::Tag::Float(0.1)),
+        ]);
+        let dimension = DimensionType::from_nbt(&nether);
+        assert_eq!(dimension.min_y, DEFAULT_MIN_Y);
+        assert_eq!(dimension.height, DEFAULT_HEIGHT);
+        assert!(!dimension.has_skylight);
+        assert_eq!(dimension.ambient_light, 0.1);
+    }
+
+    #[test]
+    fn biome_registry_from_codec() {
+        let biome = |id: i32, name: &str| {
+            compound(vec![
+                ("name", nbt::Tag::String(name.to_string())),
+                ("id", nbt::Tag::Int(id)),
+                ("element", compound(vec![])),
+            ])
+        };
+        let codec = compound(vec![(
+            "minecraft:worldgen/biome",
+            compound(vec![
+                (
+                    "type",
+                    nbt::Tag::String(String::from("minecraft:worldgen/biome")),
+                ),
+                (
+                    "value",
+                    nbt::Tag::List(vec![
+                        biome(0, "minecraft:ocean"),
+                        biome(1, "minecraft:plains"),
+                    ]),
+                ),
+            ]),
+        )]);
+
+        let biomes = read_registry(&codec, "minecraft:worldgen/biome");
+        assert_eq!(biomes.len(), 2);
+        assert_eq!(biomes[&1], "minecraft:plains");
+        assert!(read_registry(&codec, "minecraft:dimension_type").is_empty());
+    }
+}
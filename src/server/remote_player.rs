@@ -1,4 +1,10 @@
-use mcproto_rs::{uuid::UUID4, v1_16_3::GameMode};
+use std::collections::HashMap;
+
+use mcproto_rs::{
+    types::{Chat, VarInt},
+    uuid::UUID4,
+    v1_16_3::{GameMode, PlayerInfoAction, PlayerInfoActionList},
+};
 
 pub struct RemotePlayer {
     pub uuid: UUID4,
@@ -7,3 +13,167 @@ pub struct RemotePlayer {
     pub ping: i32,
     pub display_name: Option<String>,
 }
+
+impl RemotePlayer {
+    /// Returns the name to show for the player, their display name if the server has set one
+    pub fn get_shown_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// Everyone the server lists as online, kept up to date from Player Info packets
+#[derive(Default)]
+pub struct PlayerList {
+    players: HashMap<UUID4, RemotePlayer>,
+}
+
+impl PlayerList {
+    pub fn new() -> PlayerList {
+        PlayerList {
+            players: HashMap::new(),
+        }
+    }
+
+    /// Adds, removes or updates players from the actions in a Player Info packet
+    pub fn apply(&mut self, actions: PlayerInfoActionList) {
+        match actions {
+            PlayerInfoActionList::Add(players) => {
+                for player in players.iter() {
+                    self.players.insert(
+                        player.uuid,
+                        RemotePlayer {
+                            uuid: player.uuid,
+                            name: player.action.name.clone(),
+                            gamemode: player.action.game_mode.clone(),
+                            ping: player.action.ping_ms.0,
+                            display_name: player
+                                .action
+                                .display_name
+                                .as_ref()
+                                .and_then(Chat::to_traditional),
+                        },
+                    );
+                }
+            }
+            PlayerInfoActionList::UpdateGameMode(players) => {
+                let players: Vec<PlayerInfoAction<GameMode>> = From::from(players);
+                for player in players {
+                    if let Some(p) = self.players.get_mut(&player.uuid) {
+                        p.gamemode = player.action;
+                    }
+                }
+            }
+            PlayerInfoActionList::UpdateLatency(players) => {
+                let players: Vec<PlayerInfoAction<VarInt>> = From::from(players);
+                for player in players {
+                    if let Some(p) = self.players.get_mut(&player.uuid) {
+                        p.ping = player.action.into();
+                    }
+                }
+            }
+            PlayerInfoActionList::UpdateDisplayName(players) => {
+                for player in players.iter() {
+                    if let Some(p) = self.players.get_mut(&player.uuid) {
+                        p.display_name = player.action.clone().map(|chat| {
+                            chat.to_traditional()
+                                .unwrap_or_else(|| "Failed to parse name".to_string())
+                        });
+                    }
+                }
+            }
+            PlayerInfoActionList::Remove(players) => {
+                for player in players.iter() {
+                    self.players.remove(player);
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, uuid: &UUID4) -> Option<&RemotePlayer> {
+        self.players.get(uuid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+
+    /// Returns every player sorted by name, ignoring case like the vanilla tab list
+    pub fn get_sorted(&self) -> Vec<&RemotePlayer> {
+        let mut players: Vec<&RemotePlayer> = self.players.values().collect();
+        players.sort_by_key(|p| p.name.to_lowercase());
+        players
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mcproto_rs::protocol::{Id, PacketDirection, RawPacket, State};
+
+    use crate::network::{PacketType, RawPacketType};
+
+    use super::*;
+
+    const UUID: [u8; 16] = [
+        0x06, 0x9a, 0x79, 0xf4, 0x44, 0xe9, 0x4b, 0x2b, 0x8c, 0x1e, 0x9c, 0x0e, 0x19, 0x6d, 0x2b,
+        0x1f,
+    ];
+
+    /// Decodes the actions of a player info packet for the player with `UUID`
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - Id of the action
+    /// * `data` - Data of the action after the player's UUID
+    fn player_info(action: u8, data: &[u8]) -> PlayerInfoActionList {
+        let mut body = vec![action, 0x01];
+        body.extend_from_slice(&UUID);
+        body.extend_from_slice(data);
+
+        let id = Id {
+            id: 0x32,
+            state: State::Play,
+            direction: PacketDirection::ClientBound,
+        };
+        match RawPacketType::create(id, &body)
+            .unwrap()
+            .deserialize()
+            .unwrap()
+        {
+            PacketType::PlayPlayerInfo(pack) => pack.actions,
+            other => panic!("Decoded the wrong packet: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn add_player_then_update_latency() {
+        let mut players = PlayerList::new();
+
+        // Name, no properties, creative, 35ms and no display name
+        let mut add = vec![5];
+        add.extend_from_slice(b"Notch");
+        add.extend_from_slice(&[0x00, 0x01, 35, 0x00]);
+        players.apply(player_info(0, &add));
+
+        assert_eq!(players.len(), 1);
+        let notch = players.get_sorted()[0];
+        let uuid = notch.uuid;
+        assert_eq!(notch.name, "Notch");
+        assert_eq!(notch.get_shown_name(), "Notch");
+        assert!(matches!(notch.gamemode, GameMode::Creative));
+        assert_eq!(notch.ping, 35);
+
+        // 300ms as a VarInt
+        players.apply(player_info(2, &[0xac, 0x02]));
+        assert_eq!(players.len(), 1);
+        assert_eq!(players.get(&uuid).unwrap().ping, 300);
+        assert_eq!(players.get(&uuid).unwrap().name, "Notch");
+
+        // Removing has nothing after the UUID
+        players.apply(player_info(4, &[]));
+        assert!(players.is_empty());
+    }
+}
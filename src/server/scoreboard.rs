@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use mcproto_rs::v1_16_3::{
+    PlayDisplayScoreboardSpec, PlayScoreboardObjectiveSpec, PlayUpdateScoreSpec,
+    ScoreboardObjectiveAction, ScoreboardPosition, TeamMember, UpdateScoreAction,
+};
+
+use crate::chat::component_text;
+
+/// An objective players and other names have scores in
+#[derive(Debug, Clone, Default)]
+pub struct Objective {
+    pub title: String,
+    // Scores by the name of who they belong to, which isn't always a player
+    pub scores: HashMap<String, i32>,
+}
+
+impl Objective {
+    /// Returns the scores highest first, ties are sorted by name like the vanilla sidebar
+    pub fn get_sorted_scores(&self) -> Vec<(&str, i32)> {
+        let mut scores: Vec<(&str, i32)> =
+            self.scores.iter().map(|(n, s)| (n.as_str(), *s)).collect();
+        scores.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        scores
+    }
+}
+
+/// Objectives and scores from the server's scoreboard, and which objective is shown in the
+/// sidebar
+#[derive(Default)]
+pub struct Scoreboard {
+    objectives: HashMap<String, Objective>,
+    // Name of the objective shown on the right of the screen
+    sidebar: Option<String>,
+}
+
+impl Scoreboard {
+    pub fn new() -> Scoreboard {
+        Scoreboard {
+            objectives: HashMap::new(),
+            sidebar: None,
+        }
+    }
+
+    /// Creates, removes or renames an objective from a Scoreboard Objective packet
+    pub fn handle_objective(&mut self, pack: &PlayScoreboardObjectiveSpec) {
+        match &pack.action {
+            ScoreboardObjectiveAction::Create(spec) => {
+                self.objectives.insert(
+                    pack.objective_name.clone(),
+                    Objective {
+                        title: component_text(&spec.text),
+                        scores: HashMap::new(),
+                    },
+                );
+            }
+            ScoreboardObjectiveAction::UpdateText(spec) => {
+                if let Some(objective) = self.objectives.get_mut(&pack.objective_name) {
+                    objective.title = component_text(&spec.text);
+                }
+            }
+            ScoreboardObjectiveAction::Remove => {
+                self.objectives.remove(&pack.objective_name);
+                if self.sidebar.as_ref() == Some(&pack.objective_name) {
+                    self.sidebar = None;
+                }
+            }
+        }
+    }
+
+    /// Sets or removes a score from an Update Score packet
+    pub fn handle_score(&mut self, pack: &PlayUpdateScoreSpec) {
+        let name = member_name(&pack.entity_name);
+        let objective_name = &pack.update.objective_name;
+        match &pack.update.action {
+            UpdateScoreAction::Upsert(value) => {
+                if let Some(objective) = self.objectives.get_mut(objective_name) {
+                    objective.scores.insert(name, value.0);
+                }
+            }
+            UpdateScoreAction::Remove => self.remove_score(&name, objective_name),
+        }
+    }
+
+    /// Removes a name's score from an objective, or from every objective if the objective name
+    /// is empty
+    pub fn remove_score(&mut self, name: &str, objective_name: &str) {
+        if objective_name.is_empty() {
+            for objective in self.objectives.values_mut() {
+                objective.scores.remove(name);
+            }
+        } else if let Some(objective) = self.objectives.get_mut(objective_name) {
+            objective.scores.remove(name);
+        }
+    }
+
+    /// Shows an objective in the sidebar from a Display Scoreboard packet, other positions aren't
+    /// shown yet
+    pub fn handle_display(&mut self, pack: &PlayDisplayScoreboardSpec) {
+        if let ScoreboardPosition::Sidebar = pack.position {
+            self.sidebar = if pack.score_name.is_empty() {
+                None
+            } else {
+                Some(pack.score_name.clone())
+            };
+        }
+    }
+
+    pub fn get_objective(&self, name: &str) -> Option<&Objective> {
+        self.objectives.get(name)
+    }
+
+    /// Returns the objective shown in the sidebar, None if there isn't one
+    pub fn get_sidebar(&self) -> Option<&Objective> {
+        self.sidebar
+            .as_ref()
+            .and_then(|name| self.objectives.get(name))
+    }
+
+    /// Returns true if no objective has any scores
+    pub fn is_empty(&self) -> bool {
+        self.objectives.values().all(|o| o.scores.is_empty())
+    }
+
+    pub fn clear(&mut self) {
+        self.objectives.clear();
+        self.sidebar = None;
+    }
+}
+
+/// Returns the name a score is kept under, entities are named by their UUID
+fn member_name(member: &TeamMember) -> String {
+    match member {
+        TeamMember::Player(name) => name.clone(),
+        TeamMember::Entity(uuid) => uuid.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mcproto_rs::protocol::{Id, PacketDirection, RawPacket, State};
+
+    use crate::network::{PacketType, RawPacketType};
+
+    use super::*;
+
+    fn decode(id: i32, body: &[u8]) -> PacketType {
+        let id = Id {
+            id,
+            state: State::Play,
+            direction: PacketDirection::ClientBound,
+        };
+        RawPacketType::create(id, body)
+            .unwrap()
+            .deserialize()
+            .unwrap()
+    }
+
+    /// Appends a string prefixed with its length, which has to fit in a byte
+    fn push_str(body: &mut Vec<u8>, s: &str) {
+        body.push(s.len() as u8);
+        body.extend_from_slice(s.as_bytes());
+    }
+
+    fn create_objective(name: &str, title: &str) -> PlayScoreboardObjectiveSpec {
+        let mut body = Vec::new();
+        push_str(&mut body, name);
+        body.push(0x00);
+        push_str(&mut body, &format!(r#"{{"text":"{}"}}"#, title));
+        // Shown as an integer
+        body.push(0x00);
+        match decode(0x4a, &body) {
+            PacketType::PlayScoreboardObjective(pack) => pack,
+            other => panic!("Decoded the wrong packet: {:?}", other),
+        }
+    }
+
+    /// Decodes an update score packet, which removes the score if there is no value
+    fn update_score(name: &str, objective: &str, value: Option<u8>) -> PlayUpdateScoreSpec {
+        let mut body = Vec::new();
+        push_str(&mut body, name);
+        body.push(if value.is_some() { 0x00 } else { 0x01 });
+        push_str(&mut body, objective);
+        body.extend(value);
+        match decode(0x4d, &body) {
+            PacketType::PlayUpdateScore(pack) => pack,
+            other => panic!("Decoded the wrong packet: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn update_then_remove_score() {
+        let mut scoreboard = Scoreboard::new();
+        scoreboard.handle_objective(&create_objective("kills", "Kills"));
+        assert!(scoreboard.is_empty());
+
+        scoreboard.handle_score(&update_score("Notch", "kills", Some(12)));
+        assert!(!scoreboard.is_empty());
+        assert_eq!(
+            scoreboard.get_objective("kills").unwrap().scores["Notch"],
+            12
+        );
+
+        scoreboard.handle_score(&update_score("Notch", "kills", None));
+        assert!(scoreboard.is_empty());
+        assert_eq!(scoreboard.get_objective("kills").unwrap().title, "Kills");
+    }
+
+    #[test]
+    fn sidebar_sorted_descending() {
+        let mut scoreboard = Scoreboard::new();
+        scoreboard.handle_objective(&create_objective("kills", "Kills"));
+        for (name, score) in [("b", 3), ("a", 3), ("c", 10), ("d", 1)] {
+            scoreboard.handle_score(&update_score(name, "kills", Some(score)));
+        }
+
+        // Shown in the sidebar, position 1
+        let mut body = vec![0x01];
+        push_str(&mut body, "kills");
+        match decode(0x43, &body) {
+            PacketType::PlayDisplayScoreboard(pack) => scoreboard.handle_display(&pack),
+            other => panic!("Decoded the wrong packet: {:?}", other),
+        }
+
+        let sidebar = scoreboard.get_sidebar().unwrap();
+        assert_eq!(
+            sidebar.get_sorted_scores(),
+            vec![("c", 10), ("a", 3), ("b", 3), ("d", 1)]
+        );
+
+        // Removing from every objective, then the objective itself
+        scoreboard.remove_score("c", "");
+        assert_eq!(scoreboard.get_sidebar().unwrap().scores.len(), 3);
+        let mut body = Vec::new();
+        push_str(&mut body, "kills");
+        body.push(0x01);
+        match decode(0x4a, &body) {
+            PacketType::PlayScoreboardObjective(pack) => scoreboard.handle_objective(&pack),
+            other => panic!("Decoded the wrong packet: {:?}", other),
+        }
+        assert!(scoreboard.get_sidebar().is_none());
+    }
+}
@@ -1,57 +1,392 @@
-use std::path::Path;
+use std::{fs, io::Write, path::Path, time::Duration};
 
-use glam::Vec3;
 use serde::{Deserialize, Serialize};
 
-use crate::gui::main_menu::SavedServer;
+use crate::{
+    auth::Profile,
+    gui::main_menu::SavedServer,
+    network::{version::ProtocolVersion, RetryPolicy},
+};
 
-#[derive(Serialize, Deserialize, Debug)]
+use self::key_bindings::KeyBindings;
+
+pub mod key_bindings;
+
+// File settings are saved to, relative to the working directory
+pub const SETTINGS_FILE: &str = "settings.json";
+// File the logged in profile and its tokens are saved to, next to the settings file. It's only
+// readable by the current user on Unix, elsewhere it relies on the permissions of the directory
+pub const CREDENTIALS_FILE: &str = "credentials.json";
+
+// Bounds of the render distance in chunks, the same as the vanilla client
+pub const MIN_RENDER_DISTANCE: u8 = 2;
+pub const MAX_RENDER_DISTANCE: u8 = 32;
+
+// Smallest size in logical pixels the window can be at each GUI scale when picking one
+// automatically, the same as the vanilla client
+const MIN_SCALED_WIDTH: u32 = 320;
+const MIN_SCALED_HEIGHT: u32 = 240;
+
+/// How big the GUI is drawn, as physical pixels per logical pixel
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuiScale {
+    // Picks the largest scale that fits the window
+    Auto,
+    X1,
+    X2,
+    X3,
+    X4,
+}
+
+impl GuiScale {
+    pub const ALL: [GuiScale; 5] = [
+        GuiScale::Auto,
+        GuiScale::X1,
+        GuiScale::X2,
+        GuiScale::X3,
+        GuiScale::X4,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GuiScale::Auto => "Auto",
+            GuiScale::X1 => "1x",
+            GuiScale::X2 => "2x",
+            GuiScale::X3 => "3x",
+            GuiScale::X4 => "4x",
+        }
+    }
+
+    /// Returns the pixels per point to draw the GUI at for a window size in physical pixels
+    pub fn pixels_per_point(&self, width: u32, height: u32) -> f32 {
+        match self {
+            GuiScale::Auto => auto_gui_scale(width, height) as f32,
+            GuiScale::X1 => 1.0,
+            GuiScale::X2 => 2.0,
+            GuiScale::X3 => 3.0,
+            GuiScale::X4 => 4.0,
+        }
+    }
+}
+
+/// Returns the largest scale from 1 to 4 that leaves the window at least 320x240 logical pixels,
+/// like the vanilla client's auto GUI scale
+pub fn auto_gui_scale(width: u32, height: u32) -> u32 {
+    (1..=4)
+        .rev()
+        .find(|scale| width / scale >= MIN_SCALED_WIDTH && height / scale >= MIN_SCALED_HEIGHT)
+        .unwrap_or(1)
+}
+
+/// Settings saved between runs of the client. Fields missing from the saved file, such as ones
+/// added since it was written, are given their default values
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct Settings {
     pub direct_connection: String,
     pub show_fps: bool,
+    pub gui_scale: GuiScale,
+    // Highest frame rate to draw at, 0 for unlimited
+    pub max_fps: u32,
+    // Keeps the frame rate at or under the monitor's refresh rate
+    pub vsync: bool,
 
     pub mouse_sensitivity: f32,
+    pub key_bindings: KeyBindings,
+    pub fov: f32,
+    // Speeds the player walks and sprints at in blocks per second
+    pub walk_speed: f32,
+    pub sprint_speed: f32,
+    // Speed of the free camera in blocks per second
+    pub fly_speed: f32,
+    // Radius of chunks around the player that are loaded and rendered
+    pub render_distance: u8,
+    // Skip drawing sections hidden behind others, which costs GPU queries so can be slower on
+    // some GPUs
+    pub occlusion_culling: bool,
+    // Debug views, drawing faces as outlines and the edges of the chunk the player is in
+    pub wireframe: bool,
+    pub show_chunk_borders: bool,
+    // Top down map in the corner of the screen and how many points each block takes up on it
+    pub show_minimap: bool,
+    pub minimap_zoom: f32,
 
     pub online_play: bool,
     pub name: String,
+    // Language sent to the server, which it may use for translating messages
+    pub locale: String,
+    pub ms_client_id: String,
+    // Saved to the credentials file rather than with the other settings so the tokens aren't
+    // readable by anyone who can read the settings
+    #[serde(skip)]
+    pub profile: Option<Profile>,
     pub saved_servers: Vec<SavedServer>,
+    pub protocol_version: ProtocolVersion,
+
+    // Connection attempts made before giving up, and the backoff between them in seconds
+    pub retry_attempts: u32,
+    pub retry_delay: f32,
+    pub retry_max_delay: f32,
+    // Seconds each connection attempt waits for the server to answer
+    pub connect_timeout: f32,
 
     pub day_colour: [f32; 3],
-    pub fog_near: f32,
-    pub fog_far: f32,
+
+    // Volume every sound is played at, from 0 to 1
+    pub master_volume: f32,
+    // Directory with sounds.json and the sounds it refers to, laid out like assets/minecraft in a
+    // resource pack
+    pub sound_dir: String,
+
+    // Directory screenshots are saved in, relative to the working directory
+    pub screenshot_dir: String,
 }
 
-impl Settings {
-    pub fn default() -> Settings {
+impl Default for Settings {
+    fn default() -> Settings {
         Settings {
             direct_connection: String::new(),
             show_fps: true,
+            gui_scale: GuiScale::Auto,
+            max_fps: 0,
+            vsync: false,
 
             mouse_sensitivity: 1.0,
+            key_bindings: KeyBindings::default(),
+            fov: 90.0,
+            walk_speed: 4.317,
+            sprint_speed: 5.612,
+            fly_speed: 10.0,
+            render_distance: 8,
+            occlusion_culling: false,
+            wireframe: false,
+            show_chunk_borders: false,
+            show_minimap: true,
+            minimap_zoom: 2.0,
 
             online_play: false,
             name: String::from("Harry"),
+            locale: String::from("en_GB"),
+            ms_client_id: String::new(),
+            profile: None,
             saved_servers: Vec::new(),
+            protocol_version: ProtocolVersion::default(),
+
+            retry_attempts: 3,
+            retry_delay: 1.0,
+            retry_max_delay: 30.0,
+            connect_timeout: 5.0,
 
             day_colour: [0.2, 0.5, 0.9],
-            fog_near: 5.0,
-            fog_far: 320.0,
+
+            master_volume: 1.0,
+            sound_dir: String::from("assets"),
+
+            screenshot_dir: String::from("screenshots"),
+        }
+    }
+}
+
+impl Settings {
+    /// Returns the policy for retrying failed connections to a server
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.retry_attempts.max(1),
+            base_delay: Duration::from_secs_f32(self.retry_delay.max(0.0)),
+            max_delay: Duration::from_secs_f32(self.retry_max_delay.max(0.0)),
         }
     }
 
-    /// Save settings in json format to the specified file
+    /// Returns how long each connection attempt waits for the server to answer
+    pub fn connect_timeout(&self) -> Duration {
+        // A zero timeout is an error for TcpStream::connect_timeout
+        Duration::from_secs_f32(self.connect_timeout.max(0.1))
+    }
+
+    /// Save settings in json format to the specified file, and the profile to the credentials
+    /// file next to it
     pub fn save<P: AsRef<Path>>(&self, file: P) -> Result<(), Box<dyn std::error::Error>> {
-        let contents = serde_json::to_string(&self)?;
-        std::fs::write(file, &contents)?;
+        let contents = serde_json::to_string_pretty(&self)?;
+        fs::write(&file, contents)?;
+
+        let credentials = file.as_ref().with_file_name(CREDENTIALS_FILE);
+        match &self.profile {
+            Some(profile) => write_private(&credentials, &serde_json::to_vec_pretty(profile)?)?,
+            None if credentials.exists() => fs::remove_file(credentials)?,
+            None => {}
+        }
         Ok(())
     }
 
-    /// Read settings from json format from the specified file
-    /// Saved settings in json must have the same structure as the struct trying to load othewise it will fail
-    /// Maybe in the future I will improve this but I can't be bothered for now since I don't have many settings to save yet
+    /// Read settings from json format from the specified file, any settings missing from the file
+    /// are set to their defaults. The profile is read from the credentials file next to it if
+    /// there is one
     pub fn load<P: AsRef<Path>>(file: P) -> Result<Settings, Box<dyn std::error::Error>> {
-        let contents = std::fs::read_to_string(file)?;
-        let set = serde_json::from_str::<Settings>(&contents)?;
+        let contents = fs::read_to_string(&file)?;
+        let mut set = serde_json::from_str::<Settings>(&contents)?;
+
+        let credentials = file.as_ref().with_file_name(CREDENTIALS_FILE);
+        if credentials.exists() {
+            match fs::read(&credentials)
+                .map_err(|e| e.to_string())
+                .and_then(|c| serde_json::from_slice::<Profile>(&c).map_err(|e| e.to_string()))
+            {
+                Ok(profile) => set.profile = Some(profile),
+                Err(e) => log::error!("Couldn't load saved login, logging out: {}", e),
+            }
+        }
         Ok(set)
     }
+
+    /// Loads the settings file, falling back to the defaults if it doesn't exist or can't be read
+    pub fn load_or_default<P: AsRef<Path>>(file: P) -> Settings {
+        if !file.as_ref().exists() {
+            log::info!("No settings file found, using the defaults");
+            return Settings::default();
+        }
+
+        match Settings::load(file) {
+            Ok(s) => s,
+            Err(e) => {
+                log::error!("Couldn't load settings, using the defaults: {:?}", e);
+                Settings::default()
+            }
+        }
+    }
+}
+
+/// Writes a file that only the current user can read or write
+#[cfg(unix)]
+fn write_private(file: &Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let mut f = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(file)?;
+    // The mode is only used when creating the file, so files from before are restricted too
+    f.set_permissions(fs::Permissions::from_mode(0o600))?;
+    f.write_all(contents)
+}
+
+/// Writes a file, which can be read by whoever can read the directory it's in
+#[cfg(not(unix))]
+fn write_private(file: &Path, contents: &[u8]) -> std::io::Result<()> {
+    fs::File::create(file)?.write_all(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Returns the path of a settings file in an empty directory of its own
+    fn temp_settings_file(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mince-raft-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(SETTINGS_FILE)
+    }
+
+    fn server(name: &str, ip: &str) -> SavedServer {
+        SavedServer {
+            ip: ip.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn saved_servers_round_trip() {
+        let file = temp_settings_file("servers");
+        let mut settings = Settings::default();
+
+        settings.saved_servers.push(server("Local", "localhost"));
+        settings
+            .saved_servers
+            .push(server("Hypixel", "mc.hypixel.net"));
+        settings.saved_servers.push(server("IPv6", "[::1]:30000"));
+        settings.saved_servers.remove(1);
+        settings.save(&file).unwrap();
+
+        let loaded = Settings::load(&file).unwrap();
+        assert_eq!(
+            loaded.saved_servers,
+            vec![server("Local", "localhost"), server("IPv6", "[::1]:30000")]
+        );
+
+        fs::remove_dir_all(file.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn missing_settings_are_defaults() {
+        let file = temp_settings_file("defaults");
+        fs::write(&file, r#"{"name": "Notch"}"#).unwrap();
+
+        let loaded = Settings::load(&file).unwrap();
+        assert_eq!(loaded.name, "Notch");
+        assert!(loaded.saved_servers.is_empty());
+
+        fs::remove_dir_all(file.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let file = temp_settings_file("round-trip");
+        let mut settings = Settings::default();
+        settings.render_distance = 12;
+        settings.fov = 95.0;
+        settings.name = String::from("Steve");
+        settings.mouse_sensitivity = 0.35;
+        settings.vsync = !settings.vsync;
+        settings.save(&file).unwrap();
+
+        let loaded = Settings::load(&file).unwrap();
+        assert_eq!(loaded.render_distance, 12);
+        assert_eq!(loaded.fov, 95.0);
+        assert_eq!(loaded.name, "Steve");
+        assert_eq!(loaded.mouse_sensitivity, 0.35);
+        assert_eq!(loaded.vsync, settings.vsync);
+
+        fs::remove_dir_all(file.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn corrupt_settings_fall_back_to_defaults() {
+        let file = temp_settings_file("corrupt");
+        fs::write(&file, "{\"render_distance\": 12,").unwrap();
+
+        assert!(Settings::load(&file).is_err());
+        let loaded = Settings::load_or_default(&file);
+        assert_eq!(loaded.render_distance, Settings::default().render_distance);
+
+        // And so does a file that isn't there
+        fs::remove_file(&file).unwrap();
+        assert_eq!(
+            Settings::load_or_default(&file).name,
+            Settings::default().name
+        );
+
+        fs::remove_dir_all(file.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn auto_gui_scale_fits_window() {
+        assert_eq!(auto_gui_scale(320, 240), 1);
+        assert_eq!(auto_gui_scale(200, 100), 1);
+        assert_eq!(auto_gui_scale(800, 600), 2);
+        assert_eq!(auto_gui_scale(1280, 720), 3);
+        assert_eq!(auto_gui_scale(1366, 768), 3);
+        assert_eq!(auto_gui_scale(1920, 1080), 4);
+        assert_eq!(auto_gui_scale(3840, 2160), 4);
+
+        // Limited by whichever side is shorter
+        assert_eq!(auto_gui_scale(3840, 480), 2);
+        assert_eq!(auto_gui_scale(700, 2160), 2);
+
+        assert_eq!(GuiScale::Auto.pixels_per_point(1920, 1080), 4.0);
+        assert_eq!(GuiScale::X2.pixels_per_point(1920, 1080), 2.0);
+    }
 }
@@ -0,0 +1,374 @@
+use std::collections::BTreeMap;
+
+use egui_winit::winit::event::VirtualKeyCode;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Things the player can do with a key, each bound to one key
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Action {
+    Forward,
+    Back,
+    Left,
+    Right,
+    Jump,
+    Sprint,
+    // Moves the free camera down, jump moves it up
+    FlyDown,
+    OpenChat,
+    OpenCommand,
+    PlayerList,
+    ToggleDebug,
+    ToggleCamera,
+    ToggleChunkBorders,
+    ToggleWireframe,
+    ToggleMinimap,
+    Screenshot,
+    Hotbar1,
+    Hotbar2,
+    Hotbar3,
+    Hotbar4,
+    Hotbar5,
+    Hotbar6,
+    Hotbar7,
+    Hotbar8,
+    Hotbar9,
+}
+
+impl Action {
+    pub const ALL: [Action; 25] = [
+        Action::Forward,
+        Action::Back,
+        Action::Left,
+        Action::Right,
+        Action::Jump,
+        Action::Sprint,
+        Action::FlyDown,
+        Action::OpenChat,
+        Action::OpenCommand,
+        Action::PlayerList,
+        Action::ToggleDebug,
+        Action::ToggleCamera,
+        Action::ToggleChunkBorders,
+        Action::ToggleWireframe,
+        Action::ToggleMinimap,
+        Action::Screenshot,
+        Action::Hotbar1,
+        Action::Hotbar2,
+        Action::Hotbar3,
+        Action::Hotbar4,
+        Action::Hotbar5,
+        Action::Hotbar6,
+        Action::Hotbar7,
+        Action::Hotbar8,
+        Action::Hotbar9,
+    ];
+
+    pub const HOTBAR: [Action; 9] = [
+        Action::Hotbar1,
+        Action::Hotbar2,
+        Action::Hotbar3,
+        Action::Hotbar4,
+        Action::Hotbar5,
+        Action::Hotbar6,
+        Action::Hotbar7,
+        Action::Hotbar8,
+        Action::Hotbar9,
+    ];
+
+    /// Returns the key the action is bound to unless the player changes it
+    pub fn default_key(&self) -> VirtualKeyCode {
+        match self {
+            Action::Forward => VirtualKeyCode::W,
+            Action::Back => VirtualKeyCode::S,
+            Action::Left => VirtualKeyCode::A,
+            Action::Right => VirtualKeyCode::D,
+            Action::Jump => VirtualKeyCode::Space,
+            Action::Sprint => VirtualKeyCode::LControl,
+            Action::FlyDown => VirtualKeyCode::LShift,
+            Action::OpenChat => VirtualKeyCode::T,
+            Action::OpenCommand => VirtualKeyCode::Slash,
+            Action::PlayerList => VirtualKeyCode::Tab,
+            Action::ToggleDebug => VirtualKeyCode::F3,
+            Action::ToggleCamera => VirtualKeyCode::F4,
+            Action::ToggleChunkBorders => VirtualKeyCode::F6,
+            Action::ToggleWireframe => VirtualKeyCode::F7,
+            Action::ToggleMinimap => VirtualKeyCode::M,
+            Action::Screenshot => VirtualKeyCode::F2,
+            Action::Hotbar1 => VirtualKeyCode::Key1,
+            Action::Hotbar2 => VirtualKeyCode::Key2,
+            Action::Hotbar3 => VirtualKeyCode::Key3,
+            Action::Hotbar4 => VirtualKeyCode::Key4,
+            Action::Hotbar5 => VirtualKeyCode::Key5,
+            Action::Hotbar6 => VirtualKeyCode::Key6,
+            Action::Hotbar7 => VirtualKeyCode::Key7,
+            Action::Hotbar8 => VirtualKeyCode::Key8,
+            Action::Hotbar9 => VirtualKeyCode::Key9,
+        }
+    }
+
+    /// Returns the name shown for the action in the settings
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Action::Forward => "Walk forwards",
+            Action::Back => "Walk backwards",
+            Action::Left => "Strafe left",
+            Action::Right => "Strafe right",
+            Action::Jump => "Jump",
+            Action::Sprint => "Sprint",
+            Action::FlyDown => "Fly down",
+            Action::OpenChat => "Open chat",
+            Action::OpenCommand => "Open command",
+            Action::PlayerList => "List players",
+            Action::ToggleDebug => "Debug overlay",
+            Action::ToggleCamera => "Free camera",
+            Action::ToggleChunkBorders => "Chunk borders",
+            Action::ToggleWireframe => "Wireframe",
+            Action::ToggleMinimap => "Minimap",
+            Action::Screenshot => "Take screenshot",
+            Action::Hotbar1 => "Hotbar slot 1",
+            Action::Hotbar2 => "Hotbar slot 2",
+            Action::Hotbar3 => "Hotbar slot 3",
+            Action::Hotbar4 => "Hotbar slot 4",
+            Action::Hotbar5 => "Hotbar slot 5",
+            Action::Hotbar6 => "Hotbar slot 6",
+            Action::Hotbar7 => "Hotbar slot 7",
+            Action::Hotbar8 => "Hotbar slot 8",
+            Action::Hotbar9 => "Hotbar slot 9",
+        }
+    }
+}
+
+/// A key saved by its name, e.g. `W` or `LControl`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key(pub VirtualKeyCode);
+
+impl Serialize for Key {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&key_name(self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Key, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        key_from_name(&name)
+            .map(Key)
+            .ok_or_else(|| de::Error::custom(format!("Unknown key: {}", name)))
+    }
+}
+
+/// Which key each action is bound to. Actions missing from the saved settings use their default
+/// key
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeyBindings {
+    keys: BTreeMap<Action, Key>,
+}
+
+impl KeyBindings {
+    pub fn get(&self, action: Action) -> VirtualKeyCode {
+        self.keys
+            .get(&action)
+            .map(|k| k.0)
+            .unwrap_or_else(|| action.default_key())
+    }
+
+    pub fn set(&mut self, action: Action, key: VirtualKeyCode) {
+        self.keys.insert(action, Key(key));
+    }
+
+    /// Returns every pair of actions that are bound to the same key
+    pub fn get_conflicts(&self) -> Vec<(Action, Action)> {
+        let mut conflicts = Vec::new();
+        for (i, a) in Action::ALL.iter().enumerate() {
+            for b in Action::ALL[i + 1..].iter() {
+                if self.get(*a) == self.get(*b) {
+                    conflicts.push((*a, *b));
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Returns true if another action is bound to the same key as this one
+    pub fn is_conflicting(&self, action: Action) -> bool {
+        self.get_conflicts()
+            .iter()
+            .any(|(a, b)| *a == action || *b == action)
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> KeyBindings {
+        KeyBindings {
+            keys: Action::ALL
+                .iter()
+                .map(|a| (*a, Key(a.default_key())))
+                .collect(),
+        }
+    }
+}
+
+// Keys that can be bound, anything else can't be saved or read back
+const BINDABLE_KEYS: [VirtualKeyCode; 70] = [
+    VirtualKeyCode::A,
+    VirtualKeyCode::B,
+    VirtualKeyCode::C,
+    VirtualKeyCode::D,
+    VirtualKeyCode::E,
+    VirtualKeyCode::F,
+    VirtualKeyCode::G,
+    VirtualKeyCode::H,
+    VirtualKeyCode::I,
+    VirtualKeyCode::J,
+    VirtualKeyCode::K,
+    VirtualKeyCode::L,
+    VirtualKeyCode::M,
+    VirtualKeyCode::N,
+    VirtualKeyCode::O,
+    VirtualKeyCode::P,
+    VirtualKeyCode::Q,
+    VirtualKeyCode::R,
+    VirtualKeyCode::S,
+    VirtualKeyCode::T,
+    VirtualKeyCode::U,
+    VirtualKeyCode::V,
+    VirtualKeyCode::W,
+    VirtualKeyCode::X,
+    VirtualKeyCode::Y,
+    VirtualKeyCode::Z,
+    VirtualKeyCode::Key0,
+    VirtualKeyCode::Key1,
+    VirtualKeyCode::Key2,
+    VirtualKeyCode::Key3,
+    VirtualKeyCode::Key4,
+    VirtualKeyCode::Key5,
+    VirtualKeyCode::Key6,
+    VirtualKeyCode::Key7,
+    VirtualKeyCode::Key8,
+    VirtualKeyCode::Key9,
+    VirtualKeyCode::F1,
+    VirtualKeyCode::F2,
+    VirtualKeyCode::F3,
+    VirtualKeyCode::F4,
+    VirtualKeyCode::F5,
+    VirtualKeyCode::F6,
+    VirtualKeyCode::F7,
+    VirtualKeyCode::F8,
+    VirtualKeyCode::F9,
+    VirtualKeyCode::F10,
+    VirtualKeyCode::F11,
+    VirtualKeyCode::F12,
+    VirtualKeyCode::Space,
+    VirtualKeyCode::Tab,
+    VirtualKeyCode::Return,
+    VirtualKeyCode::Back,
+    VirtualKeyCode::Insert,
+    VirtualKeyCode::Delete,
+    VirtualKeyCode::Home,
+    VirtualKeyCode::End,
+    VirtualKeyCode::PageUp,
+    VirtualKeyCode::PageDown,
+    VirtualKeyCode::Up,
+    VirtualKeyCode::Down,
+    VirtualKeyCode::Left,
+    VirtualKeyCode::Right,
+    VirtualKeyCode::LShift,
+    VirtualKeyCode::RShift,
+    VirtualKeyCode::LControl,
+    VirtualKeyCode::RControl,
+    VirtualKeyCode::LAlt,
+    VirtualKeyCode::RAlt,
+    VirtualKeyCode::Slash,
+    VirtualKeyCode::Grave,
+];
+
+/// Returns the name a key is saved and shown as
+pub fn key_name(key: VirtualKeyCode) -> String {
+    format!("{:?}", key)
+}
+
+/// Returns the key with a name, None if it isn't one that can be bound
+pub fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+    BINDABLE_KEYS.iter().copied().find(|k| key_name(*k) == name)
+}
+
+/// Converts a key pressed in the GUI to the key the game reads, None for keys that can't be bound.
+/// Modifier keys aren't sent as key presses by egui so they are read from the held modifiers
+pub fn from_egui_key(key: egui::Key) -> Option<VirtualKeyCode> {
+    use egui::Key as E;
+
+    let name = match key {
+        E::Num0 => "Key0",
+        E::Num1 => "Key1",
+        E::Num2 => "Key2",
+        E::Num3 => "Key3",
+        E::Num4 => "Key4",
+        E::Num5 => "Key5",
+        E::Num6 => "Key6",
+        E::Num7 => "Key7",
+        E::Num8 => "Key8",
+        E::Num9 => "Key9",
+        E::Enter => "Return",
+        E::Backspace => "Back",
+        E::ArrowUp => "Up",
+        E::ArrowDown => "Down",
+        E::ArrowLeft => "Left",
+        E::ArrowRight => "Right",
+        // Letters, function keys and the rest share names
+        other => return key_from_name(&format!("{:?}", other)),
+    };
+    key_from_name(name)
+}
+
+/// Returns the modifier key being held, for binding keys like shift that egui only reports as
+/// modifiers
+pub fn from_egui_modifiers(modifiers: &egui::Modifiers) -> Option<VirtualKeyCode> {
+    if modifiers.shift {
+        Some(VirtualKeyCode::LShift)
+    } else if modifiers.ctrl {
+        Some(VirtualKeyCode::LControl)
+    } else if modifiers.alt {
+        Some(VirtualKeyCode::LAlt)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conflicting_bindings() {
+        let mut keys = KeyBindings::default();
+        assert!(keys.get_conflicts().is_empty());
+
+        keys.set(Action::Jump, VirtualKeyCode::W);
+        assert_eq!(keys.get_conflicts(), vec![(Action::Forward, Action::Jump)]);
+        assert!(keys.is_conflicting(Action::Forward));
+        assert!(keys.is_conflicting(Action::Jump));
+        assert!(!keys.is_conflicting(Action::Back));
+
+        keys.set(Action::Forward, VirtualKeyCode::Up);
+        assert!(keys.get_conflicts().is_empty());
+    }
+
+    #[test]
+    fn custom_binding_round_trip() {
+        let mut keys = KeyBindings::default();
+        keys.set(Action::OpenChat, VirtualKeyCode::Return);
+        keys.set(Action::Sprint, VirtualKeyCode::RControl);
+
+        let json = serde_json::to_string(&keys).unwrap();
+        assert!(json.contains(r#""OpenChat":"Return""#));
+        let loaded: KeyBindings = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, keys);
+        assert_eq!(loaded.get(Action::OpenChat), VirtualKeyCode::Return);
+
+        // Actions that weren't saved use their default key, and unknown keys aren't read
+        let loaded: KeyBindings = serde_json::from_str(r#"{"keys": {"Jump": "J"}}"#).unwrap();
+        assert_eq!(loaded.get(Action::Jump), VirtualKeyCode::J);
+        assert_eq!(loaded.get(Action::Forward), VirtualKeyCode::W);
+        assert!(serde_json::from_str::<KeyBindings>(r#"{"keys": {"Jump": "Pause"}}"#).is_err());
+    }
+}
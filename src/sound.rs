@@ -0,0 +1,143 @@
+use glam::Vec3;
+use mcproto_rs::{
+    types::{self, FixedInt},
+    v1_16_3::{PlayNamedSoundEffectSpec, PlaySoundEffectSpec, SoundCategory},
+};
+
+use crate::resources::SOUNDS;
+
+// Sound positions are sent as fixed point numbers with 3 fractional bits
+const POSITION_FRACTION_BITS: usize = 3;
+
+/// A sound the server has asked to be played somewhere in the world
+#[derive(Debug, Clone)]
+pub struct SoundEvent {
+    // Name of the sound event, e.g. `minecraft:block.stone.break`
+    pub name: String,
+    // Which volume slider the sound is controlled by
+    pub category: SoundCategory,
+    pub position: Vec3,
+    // 1.0 is full volume, higher values can be heard from further away
+    pub volume: f32,
+    // 1.0 is normal speed, between 0.5 and 2.0
+    pub pitch: f32,
+}
+
+impl SoundEvent {
+    pub fn from_named(pack: &PlayNamedSoundEffectSpec) -> SoundEvent {
+        SoundEvent {
+            name: pack.sound_name.clone(),
+            category: pack.sound_category.clone(),
+            position: decode_position(&pack.position),
+            volume: pack.volume,
+            pitch: pack.pitch,
+        }
+    }
+
+    pub fn from_id(pack: &PlaySoundEffectSpec) -> SoundEvent {
+        SoundEvent {
+            name: sound_name(pack.sound_id.0),
+            category: pack.sound_category.clone(),
+            position: decode_position(&pack.position),
+            volume: pack.volume,
+            pitch: pack.pitch,
+        }
+    }
+}
+
+/// Converts a fixed point sound position from a packet to world coordinates
+pub fn decode_position(pos: &types::Vec3<FixedInt>) -> Vec3 {
+    Vec3::new(
+        pos.x.into_float(POSITION_FRACTION_BITS) as f32,
+        pos.y.into_float(POSITION_FRACTION_BITS) as f32,
+        pos.z.into_float(POSITION_FRACTION_BITS) as f32,
+    )
+}
+
+/// Returns the name of a sound event from its registry id, or the id if it isn't in the registry
+pub fn sound_name(id: i32) -> String {
+    SOUNDS
+        .get(&(id as u32))
+        .cloned()
+        .unwrap_or_else(|| format!("Unknown ({})", id))
+}
+
+#[cfg(test)]
+mod tests {
+    use mcproto_rs::{
+        protocol::{HasPacketBody, HasPacketId, RawPacket},
+        types::{BytesSerializer, VarInt},
+    };
+
+    use crate::network::{PacketType, RawPacketType};
+
+    use super::*;
+
+    /// Serializes a packet and reads it back the same way the network thread does
+    fn round_trip(packet: PacketType) -> PacketType {
+        let id = packet.id();
+        let mut serializer = BytesSerializer::default();
+        packet.mc_serialize_body(&mut serializer).unwrap();
+        let body = serializer.into_bytes();
+        RawPacketType::create(id, &body)
+            .unwrap()
+            .deserialize()
+            .unwrap()
+    }
+
+    /// Packs a position into eighths of a block the way the server sends it
+    fn encode_position(x: f64, y: f64, z: f64) -> types::Vec3<FixedInt> {
+        types::Vec3 {
+            x: FixedInt::new(x, POSITION_FRACTION_BITS),
+            y: FixedInt::new(y, POSITION_FRACTION_BITS),
+            z: FixedInt::new(z, POSITION_FRACTION_BITS),
+        }
+    }
+
+    #[test]
+    fn decode_sound_effect() {
+        let pack = PlaySoundEffectSpec {
+            sound_id: VarInt(7),
+            sound_category: SoundCategory::Block,
+            position: encode_position(-12.5, 64.5, 0.5),
+            volume: 0.8,
+            pitch: 1.5,
+        };
+        let pack = match round_trip(PacketType::PlaySoundEffect(pack)) {
+            PacketType::PlaySoundEffect(pack) => pack,
+            other => panic!("Decoded the wrong packet: {:?}", other),
+        };
+
+        let sound = SoundEvent::from_id(&pack);
+        assert_eq!(sound.position, Vec3::new(-12.5, 64.5, 0.5));
+        assert_eq!(sound.pitch, 1.5);
+        assert_eq!(sound.volume, 0.8);
+        assert!(matches!(sound.category, SoundCategory::Block));
+        assert_eq!(sound.name, sound_name(7));
+    }
+
+    #[test]
+    fn decode_named_sound_effect() {
+        let pack = PlayNamedSoundEffectSpec {
+            sound_name: String::from("minecraft:entity.player.levelup"),
+            sound_category: SoundCategory::Player,
+            position: encode_position(10.375, -2.5, 0.0),
+            volume: 1.0,
+            pitch: 0.5,
+        };
+        let pack = match round_trip(PacketType::PlayNamedSoundEffect(pack)) {
+            PacketType::PlayNamedSoundEffect(pack) => pack,
+            other => panic!("Decoded the wrong packet: {:?}", other),
+        };
+
+        let sound = SoundEvent::from_named(&pack);
+        assert_eq!(sound.name, "minecraft:entity.player.levelup");
+        assert_eq!(sound.position, Vec3::new(10.375, -2.5, 0.0));
+        assert_eq!(sound.pitch, 0.5);
+    }
+
+    #[test]
+    fn unknown_sound_ids_are_named_by_id() {
+        assert_eq!(sound_name(-1), "Unknown (-1)");
+    }
+}
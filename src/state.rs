@@ -3,38 +3,72 @@ use std::collections::HashMap;
 use egui_extras::RetainedImage;
 use glium::Display;
 
-use mcproto_rs::status;
-
-use crate::{renderer::Renderer, server::Server, settings::Settings};
+#[cfg(feature = "audio")]
+use crate::audio::AudioPlayer;
+use crate::{
+    auth::LoginFlow,
+    network::{ConnectError, ServerStatus},
+    renderer::Renderer,
+    server::Server,
+    settings::{Settings, SETTINGS_FILE},
+};
 
 pub struct State {
     pub rend: Renderer,
 
     pub settings: Settings,
     pub server: Option<Server>,
+    pub login: Option<LoginFlow>,
+    pub login_error: Option<String>,
+    // Why the last attempt to join a server failed, shown in the main menu
+    pub connect_error: Option<ConnectError>,
 
     pub outstanding_server_pings: HashMap<String, Server>,
-    pub server_pings: HashMap<String, status::StatusSpec>,
+    // Status of each server that has been pinged, or why the ping failed
+    pub server_pings: HashMap<String, Result<ServerStatus, String>>,
     pub icon_handles: HashMap<String, RetainedImage>,
+
+    // Plays sounds from the server, None if there's no audio device or sounds couldn't be read
+    #[cfg(feature = "audio")]
+    pub audio: Option<AudioPlayer>,
 }
 
 impl State {
     pub fn new(dis: &Display) -> State {
+        let settings = Settings::load_or_default(SETTINGS_FILE);
+        // Saved access tokens only last a day, so the profile is logged in again in the background
+        let login = settings
+            .profile
+            .as_ref()
+            .and_then(|p| p.refresh_token.clone())
+            .map(|token| LoginFlow::refresh(settings.ms_client_id.clone(), token));
+        let mut rend = Renderer::new(dis);
+        rend.cam.set_fov(settings.fov);
+
+        #[cfg(feature = "audio")]
+        let audio = match AudioPlayer::new(&settings.sound_dir) {
+            Ok(audio) => Some(audio),
+            Err(e) => {
+                log::warn!("Sounds won't be played: {}", e);
+                None
+            }
+        };
+
         State {
-            rend: Renderer::new(dis),
-
-            settings: match Settings::load("settings.json") {
-                Ok(s) => s,
-                Err(e) => {
-                    log::error!("Couldn't load settings: {:?}", e);
-                    Settings::default()
-                }
-            },
+            rend,
+
+            settings,
             server: None,
+            login,
+            login_error: None,
+            connect_error: None,
 
             outstanding_server_pings: HashMap::new(),
             server_pings: HashMap::new(),
             icon_handles: HashMap::new(),
+
+            #[cfg(feature = "audio")]
+            audio,
         }
     }
 }
@@ -1,21 +1,36 @@
-use std::{collections::HashMap, convert::TryInto, sync::mpsc::TryRecvError};
+use std::{collections::HashMap, convert::TryInto, f32::consts::PI, sync::mpsc::TryRecvError};
 
 use glam::{IVec2, IVec3, Vec3, Vec3Swizzles};
 use glium::Display;
-use mcproto_rs::v1_16_3::{PlayBlockChangeSpec, PlayMultiBlockChangeSpec};
+use mcproto_rs::v1_16_3::{
+    ChunkData, LightingUpdateSpec, PlayBlockChangeSpec, PlayChangeGameStateSpec,
+    PlayMultiBlockChangeSpec, PlayTimeUpdateSpec, PlayUpdateLightSpec,
+};
 
-use crate::resources::{BlockState, BLOCKS};
+use crate::{network::PROTOCOL, resources::BlockState};
 
 use self::{
     chunk_builder::ChunkBuilder,
+    chunk_decoder::ChunkDecoder,
     chunks::{
-        block_pos_to_index, BlockIndex, Chunk, ChunkSection, WrappedChunkSection, MAX_SECTION,
-        MIN_SECTION,
+        BlockIndex, Chunk, ChunkSection, PackingMode, WrappedChunkSection, MIN_SECTION,
+        SECTIONS_PER_CHUNK,
     },
+    mesh::SectionNeighbourhood,
+    weather::Weather,
 };
 
+pub mod aabb;
 pub mod chunk_builder;
+pub mod chunk_decoder;
 pub mod chunks;
+pub mod coords;
+pub mod fluids;
+pub mod map_colours;
+pub mod mesh;
+pub mod palette;
+pub mod raycast;
+pub mod weather;
 
 pub type WorldCoords = IVec3;
 pub type ChunkCoords = IVec3;
@@ -23,6 +38,9 @@ pub type SectionCoords = IVec3;
 pub type ChunkLocation = IVec2;
 pub type SectionLocation = IVec3;
 
+// Most sections queued by block changes that are rebuilt in one frame
+const SECTION_REBUILDS_PER_FRAME: usize = 16;
+
 trait Directional {
     fn north() -> Self;
     fn east() -> Self;
@@ -35,6 +53,24 @@ pub struct World {
     chunks_to_generate: Vec<ChunkLocation>,
     sections_to_generate: Vec<SectionLocation>,
     builder: ChunkBuilder,
+    decoder: ChunkDecoder,
+
+    // Latest decode job for each chunk still being decoded, results from older jobs are discarded
+    decoding: HashMap<ChunkLocation, u64>,
+
+    // Light updates for chunks that haven't been loaded yet
+    pending_light: HashMap<ChunkLocation, LightingUpdateSpec>,
+
+    min_section: i32,
+    section_count: usize,
+    packing: PackingMode,
+
+    // Ticks since the world was created, and the time of day which is negative when the daylight
+    // cycle is stopped
+    world_age: i64,
+    time_of_day: i64,
+
+    weather: Weather,
 }
 
 impl World {
@@ -44,11 +80,179 @@ impl World {
             chunks_to_generate: Vec::new(),
             sections_to_generate: Vec::new(),
             builder: ChunkBuilder::new(),
+            decoder: ChunkDecoder::new(),
+
+            decoding: HashMap::new(),
+
+            pending_light: HashMap::new(),
+
+            min_section: MIN_SECTION,
+            section_count: SECTIONS_PER_CHUNK,
+            packing: PackingMode::for_protocol(PROTOCOL),
+
+            world_age: 0,
+            time_of_day: 0,
+
+            weather: Weather::new(),
+        }
+    }
+
+    pub fn handle_time_update(&mut self, pack: PlayTimeUpdateSpec) {
+        self.world_age = pack.world_age;
+        self.time_of_day = pack.time_of_day;
+    }
+
+    /// Updates the weather from a Change Game State packet, returns false if the packet isn't
+    /// about the weather
+    pub fn handle_change_game_state(&mut self, pack: &PlayChangeGameStateSpec) -> bool {
+        self.weather.handle_game_state(&pack.reason)
+    }
+
+    pub fn get_weather(&self) -> &Weather {
+        &self.weather
+    }
+
+    pub fn get_world_age(&self) -> i64 {
+        self.world_age
+    }
+
+    /// Returns the time of day in ticks from 0 to 24000, where 0 is sunrise and 6000 is midday
+    pub fn get_time_of_day(&self) -> i64 {
+        // The server negates the time when the doDaylightCycle gamerule is off
+        self.time_of_day.abs() % 24_000
+    }
+
+    /// Returns false if the time of day isn't changing because the daylight cycle is stopped
+    pub fn is_daylight_cycle_enabled(&self) -> bool {
+        self.time_of_day >= 0
+    }
+
+    /// Returns how far the sun has rotated around the world from 0 to 1, where 0 is midday and
+    /// 0.5 is midnight. The sun moves faster around sunrise and sunset like in vanilla
+    pub fn get_sun_angle(&self) -> f32 {
+        let f = (self.get_time_of_day() as f32 / 24_000.0 - 0.25).rem_euclid(1.0);
+        let smoothed = 1.0 - ((f * PI).cos() + 1.0) / 2.0;
+        f + (smoothed - f) / 3.0
+    }
+
+    /// Returns how bright sky light is at the current time of day from 0 at night to 1 during the
+    /// day
+    pub fn sky_brightness(&self) -> f32 {
+        let darkness = 1.0 - ((self.get_sun_angle() * PI * 2.0).cos() * 2.0 + 0.5);
+        1.0 - darkness.clamp(0.0, 1.0)
+    }
+
+    /// Sets the vertical bounds of the world from the dimension's minimum y level and height,
+    /// only affects chunks that are loaded after this is called
+    pub fn set_height(&mut self, min_y: i32, height: i32) {
+        self.min_section = ChunkSection::section_containing_height(min_y);
+        self.section_count = (height.max(0) as usize).div_ceil(16);
+    }
+
+    /// Sets how chunk data is packed, only affects chunks that are loaded after this is called
+    pub fn set_packing(&mut self, packing: PackingMode) {
+        self.packing = packing;
+    }
+
+    /// Returns the index of the lowest section in the world
+    pub fn get_min_section(&self) -> i32 {
+        self.min_section
+    }
+
+    /// Returns the index of the highest section in the world
+    pub fn get_max_section(&self) -> i32 {
+        self.min_section + self.section_count as i32 - 1
+    }
+
+    /// Returns the number of sections in each chunk of the world
+    pub fn get_section_count(&self) -> usize {
+        self.section_count
+    }
+
+    /// Queues chunk data to be decoded off the render thread, the chunk is inserted into the world
+    /// once it has been decoded
+    pub fn load_chunk(&mut self, data: ChunkData) {
+        let loc = ChunkLocation::new(data.position.x, data.position.z);
+        let job = self
+            .decoder
+            .decode(data, self.min_section, self.section_count, self.packing);
+        self.decoding.insert(loc, job);
+    }
+
+    /// Removes a chunk from the world, including one that is still being decoded. The chunk's
+    /// buffers are dropped with it, sections still held by the chunk builder only produce vertices
+    /// which are discarded once the chunk is gone
+    pub fn unload_chunk(&mut self, loc: &ChunkLocation) {
+        self.chunks.remove(loc);
+        self.decoding.remove(loc);
+        self.pending_light.remove(loc);
+        self.chunks_to_generate.retain(|l| l != loc);
+        self.sections_to_generate
+            .retain(|l| l.x != loc.x || l.z != loc.y);
+    }
+
+    /// Unloads every chunk along with any waiting to be decoded, lit or meshed, and clears the
+    /// weather
+    pub fn clear(&mut self) {
+        self.weather = Weather::new();
+        self.chunks.clear();
+        self.decoding.clear();
+        self.pending_light.clear();
+        self.chunks_to_generate.clear();
+        self.sections_to_generate.clear();
+    }
+
+    /// Unloads every chunk further than the view distance from the center chunk, measured along
+    /// either axis like the server does
+    ///
+    /// # Returns
+    ///
+    /// * The number of chunks that were unloaded
+    pub fn unload_outside(&mut self, center: ChunkLocation, view_distance: i32) -> usize {
+        let outside = |loc: &ChunkLocation| !in_view_distance(center, *loc, view_distance);
+
+        let distant: Vec<ChunkLocation> = self
+            .chunks
+            .keys()
+            .chain(self.decoding.keys())
+            .chain(self.pending_light.keys())
+            .filter(|loc| outside(loc))
+            .cloned()
+            .collect();
+
+        let count = distant
+            .iter()
+            .filter(|loc| self.chunks.contains_key(loc))
+            .count();
+        for loc in distant {
+            self.unload_chunk(&loc);
         }
+        count
     }
 
-    pub fn insert_chunk(&mut self, chunk: Chunk) {
+    /// Inserts chunks that have finished decoding into the world
+    fn insert_decoded_chunks(&mut self) {
+        let decoded: Vec<_> = self.decoder.get_decoded_chunks().try_iter().collect();
+
+        for (loc, job, result) in decoded {
+            // Skip chunks that were unloaded or reloaded while being decoded
+            if self.decoding.get(&loc) != Some(&job) {
+                continue;
+            }
+            self.decoding.remove(&loc);
+
+            match result {
+                Ok(chunk) => self.insert_chunk(chunk.into()),
+                Err(e) => log::error!("Skipping chunk {}, {}: {}", loc.x, loc.y, e),
+            }
+        }
+    }
+
+    pub fn insert_chunk(&mut self, mut chunk: Chunk) {
         let chunk_coords = *chunk.get_coords();
+        if let Some(light) = self.pending_light.remove(&chunk_coords) {
+            chunk.apply_light(&light);
+        }
         self.chunks.insert(chunk_coords, chunk);
         self.queue_chunk_mesh(chunk_coords);
     }
@@ -67,53 +271,41 @@ impl World {
         self.chunks_to_generate.push(location);
     }
 
+    /// Returns true if the chunk and all 8 chunks around it are loaded, which are needed to mesh
+    /// and shade the edges of the chunk
     fn are_chunk_neighbours_loaded(&self, loc: &ChunkLocation) -> bool {
-        let chunk = self.get_chunk(loc);
-        let north = self.get_chunk(&ChunkLocation::new(loc.x, loc.y - 1));
-        let east = self.get_chunk(&ChunkLocation::new(loc.x + 1, loc.y));
-        let south = self.get_chunk(&ChunkLocation::new(loc.x, loc.y + 1));
-        let west = self.get_chunk(&ChunkLocation::new(loc.x - 1, loc.y));
-        chunk.is_some() && north.is_some() && east.is_some() && south.is_some() && west.is_some()
+        (-1..=1).all(|x| (-1..=1).all(|z| self.is_chunk_loaded(&(*loc + IVec2::new(x, z)))))
     }
 
-    fn generate_section_mesh(&mut self, loc: &SectionLocation, dis: &Display, threaded: bool) {
-        let chunk = self.get_section(loc);
+    /// Returns the section at the location and the 26 sections surrounding it, indexed with
+    /// `mesh::neighbour_index`. The sections above and below come from the same chunk, so faces
+    /// on the seams between sections are culled against the neighbouring section's edge layer
+    /// like any other face. Sections above or below the world are None and treated as air
+    fn get_section_neighbourhood(&self, loc: &SectionLocation) -> SectionNeighbourhood {
+        std::array::from_fn(|i| {
+            let offset = IVec3::new(i as i32 % 3, i as i32 / 9, (i as i32 / 3) % 3) - IVec3::ONE;
+            let neighbour = *loc + offset;
+            if neighbour.y < self.min_section || neighbour.y > self.get_max_section() {
+                None
+            } else {
+                self.get_section(&neighbour)
+            }
+        })
+    }
+
+    fn generate_section_mesh(&self, loc: &SectionLocation, threaded: bool) {
         // Discard chunk sections that are empty
-        if chunk.is_none() {
+        if self.get_section(loc).is_none() {
             return;
         }
-        let chunk = chunk.unwrap();
-
-        // Can unwrap because we checked above that they are all valid
-        let north = self.get_section(&SectionLocation::new(loc.x, loc.y, loc.z - 1));
-        let east = self.get_section(&SectionLocation::new(loc.x + 1, loc.y, loc.z));
-        let south = self.get_section(&SectionLocation::new(loc.x, loc.y, loc.z + 1));
-        let west = self.get_section(&SectionLocation::new(loc.x - 1, loc.y, loc.z));
-        let above = if loc.y >= MAX_SECTION {
-            None
-        } else {
-            self.get_section(&SectionLocation::new(loc.x, loc.y + 1, loc.z))
-        };
-        let below = if loc.y <= MIN_SECTION {
-            None
-        } else {
-            self.get_section(&SectionLocation::new(loc.x, loc.y - 1, loc.z))
-        };
 
-        self.builder.generate_chunk_section(
-            chunk,
-            loc.clone(),
-            above,
-            below,
-            north,
-            east,
-            south,
-            west,
-            threaded,
-        );
+        self.builder
+            .generate_chunk_section(self.get_section_neighbourhood(loc), *loc, threaded);
     }
 
     pub fn generate_meshes(&mut self, dis: &Display, threaded: bool) {
+        self.insert_decoded_chunks();
+
         // Chunks
         let mut temp = Vec::new();
         std::mem::swap(&mut self.chunks_to_generate, &mut temp);
@@ -123,25 +315,21 @@ impl World {
         std::mem::swap(&mut self.chunks_to_generate, &mut temp);
 
         for loc in ready_chunks {
-            for y in MIN_SECTION..=MAX_SECTION {
-                self.generate_section_mesh(&SectionLocation::new(loc.x, y, loc.y), dis, threaded);
-                self.builder.generate_chunk(
-                    self.get_chunk(&loc).unwrap(),
-                    self.get_chunk(&(loc + IVec2::north())).unwrap(),
-                    self.get_chunk(&(loc + IVec2::east())).unwrap(),
-                    self.get_chunk(&(loc + IVec2::south())).unwrap(),
-                    self.get_chunk(&(loc + IVec2::west())).unwrap(),
-                    threaded,
-                )
+            for y in self.min_section..=self.get_max_section() {
+                self.generate_section_mesh(&SectionLocation::new(loc.x, y, loc.y), threaded);
             }
         }
 
-        // Chunk sections
+        // Chunk sections, limited each frame so large edits don't all get rebuilt at once
         let mut temp = Vec::new();
         std::mem::swap(&mut temp, &mut self.sections_to_generate);
+        let mut rebuilt = 0;
         temp.retain(|loc| {
-            // Retain chunks that don't have all their neighbouring chunks
-            if !self.are_chunk_neighbours_loaded(&ChunkLocation::new(loc.x, loc.z)) {
+            // Retain chunks that don't have all their neighbouring chunks, and any left over once
+            // this frame's budget is used
+            if rebuilt >= SECTION_REBUILDS_PER_FRAME
+                || !self.are_chunk_neighbours_loaded(&ChunkLocation::new(loc.x, loc.z))
+            {
                 return true;
             }
 
@@ -150,33 +338,8 @@ impl World {
                 return false;
             }
 
-            let sect = self.get_section(loc).unwrap();
-            let above = if loc.y < MAX_SECTION {
-                self.get_section(&(*loc + IVec3::Y))
-            } else {
-                None
-            };
-            let below = if loc.y > MIN_SECTION {
-                self.get_section(&(*loc - IVec3::Y))
-            } else {
-                None
-            };
-            let north = self.get_section(&(*loc + IVec3::north()));
-            let east = self.get_section(&(*loc + IVec3::east()));
-            let south = self.get_section(&(*loc + IVec3::south()));
-            let west = self.get_section(&(*loc + IVec3::west()));
-
-            self.builder.generate_chunk_section(
-                sect,
-                loc.clone(),
-                above,
-                below,
-                north,
-                east,
-                south,
-                west,
-                threaded,
-            );
+            self.generate_section_mesh(loc, threaded);
+            rebuilt += 1;
 
             false
         });
@@ -201,10 +364,12 @@ impl World {
             i += 1;
         }
 
-        for (loc, verts) in new_meshes {
-            self.get_chunk_mut(&loc.xz())
-                .map(|c| c.load_mesh(dis, verts, loc.y));
+        for (loc, mesh) in new_meshes {
+            if let Some(c) = self.get_chunk_mut(&loc.xz()) {
+                c.load_mesh(dis, mesh.opaque, mesh.translucent, loc.y);
+            }
         }
+        self.builder.log_throughput();
     }
 
     pub fn get_chunks(&self) -> &HashMap<IVec2, Chunk> {
@@ -215,6 +380,11 @@ impl World {
         &mut self.chunks
     }
 
+    /// Returns every chunk that has been decoded and is in the world, in no particular order
+    pub fn loaded_chunks(&self) -> impl Iterator<Item = (&ChunkLocation, &Chunk)> {
+        self.chunks.iter()
+    }
+
     pub fn get_chunk(&self, location: &ChunkLocation) -> Option<&Chunk> {
         self.chunks.get(location)
     }
@@ -261,90 +431,43 @@ impl World {
         self.chunks.get(location).is_some()
     }
 
-    pub fn block_at(&self, coords: &WorldCoords) -> Option<&BlockState> {
-        self.chunks
-            .get(&Chunk::chunk_containing(coords))
-            .map(|c| c.block_at(&Chunk::map_from_world_coords(coords)))
-            .unwrap_or(None)
+    /// Get the block at the provided WorldCoords from the chunk containing it
+    ///
+    /// # Returns
+    ///
+    /// * `None` - If the chunk isn't loaded or the y level is outside of the world
+    pub fn block_at(&self, coords: &WorldCoords) -> Option<&'static BlockState> {
+        self.get_chunk_containing(coords)
+            .and_then(|c| c.block_at(&Chunk::map_from_world_coords(coords)))
+    }
+
+    /// Applies light data to a chunk, or holds onto it until the chunk is loaded since the server
+    /// usually sends light data before the chunk itself
+    pub fn handle_update_light(&mut self, pack: PlayUpdateLightSpec) {
+        let loc = ChunkLocation::new(pack.chunk.x.0, pack.chunk.z.0);
+        match self.chunks.get_mut(&loc) {
+            Some(chunk) => {
+                chunk.apply_light(&pack.update);
+                // Meshes are lit from the light data so need rebuilding
+                self.queue_chunk_mesh(loc);
+            }
+            None => {
+                self.pending_light.insert(loc, pack.update);
+            }
+        }
     }
 
     pub fn handle_block_change(&mut self, pack: PlayBlockChangeSpec) {
-        if pack.block_id.0 < 0 || pack.block_id.0 >= BLOCKS.len() as i32 {
+        // Ids missing from the block data are kept and shown as unknown blocks
+        if pack.block_id.0 < 0 || pack.block_id.0 > BlockIndex::MAX as i32 {
             log::error!("Got block change with invalid block ID");
             return;
         }
 
         let coords = IVec3::new(pack.location.x, pack.location.y.into(), pack.location.z);
-        let section_loc = ChunkSection::section_containing(&coords);
-        let mut sections_to_regenerate = Vec::new();
-
-        if let Some(chunk) = self.get_chunk_containing_mut(&coords) {
-            if !chunk.is_section_present(section_loc.y) {
-                chunk.put_section(ChunkSection {
-                    y: ChunkSection::section_containing_height(section_loc.y),
-                    blocks: [0; 4096],
-                });
-            }
-
-            let section = chunk
-                .get_section(section_loc.y)
-                .expect("Couldn't get chunk section that was just emplaced in the Chunk");
-            let mut section = section.write().unwrap();
-            let local_coords = ChunkSection::map_from_world_coords(&coords);
-
-            section.blocks[block_pos_to_index(&local_coords)] = pack.block_id.0 as BlockIndex;
-            sections_to_regenerate.push(section_loc);
-
-            // Regenerate neighbouring chunks if necessary
-            if local_coords.x == 0 {
-                sections_to_regenerate.push(IVec3::new(
-                    section_loc.x - 1,
-                    section_loc.y,
-                    section_loc.z,
-                ));
-            }
-            if local_coords.x == 15 {
-                sections_to_regenerate.push(IVec3::new(
-                    section_loc.x + 1,
-                    section_loc.y,
-                    section_loc.z,
-                ));
-            }
-            if local_coords.y == 0 {
-                sections_to_regenerate.push(IVec3::new(
-                    section_loc.x,
-                    section_loc.y - 1,
-                    section_loc.z,
-                ));
-            }
-            if local_coords.y == 15 {
-                sections_to_regenerate.push(IVec3::new(
-                    section_loc.x,
-                    section_loc.y + 1,
-                    section_loc.z,
-                ));
-            }
-            if local_coords.z == 0 {
-                sections_to_regenerate.push(IVec3::new(
-                    section_loc.x,
-                    section_loc.y,
-                    section_loc.z - 1,
-                ));
-            }
-            if local_coords.z == 15 {
-                sections_to_regenerate.push(IVec3::new(
-                    section_loc.x,
-                    section_loc.y,
-                    section_loc.z + 1,
-                ));
-            }
-        } else {
+        if !self.set_block(&coords, pack.block_id.0 as BlockIndex) {
             log::warn!("Block change in unloaded chunk");
         }
-
-        for coords in sections_to_regenerate {
-            self.queue_chunk_section_mesh(coords);
-        }
     }
 
     pub fn handle_multi_block_change(&mut self, pack: PlayMultiBlockChangeSpec) {
@@ -355,22 +478,15 @@ impl World {
             return;
         }
 
-        // Create new chunk section if this one was empty
-        if !self.is_section_present(&loc) {
-            self.get_chunk_mut(&loc.xz())
-                .unwrap()
-                .put_section(ChunkSection {
-                    y: loc.y,
-                    blocks: [0; 4096],
-                })
-        }
-
-        self.queue_chunk_section_mesh(loc);
         for change in pack.blocks.iter() {
-            if change.block_id >= BLOCKS.len().try_into().unwrap() {
-                log::error!("Got block change with invalid block ID");
-                continue;
-            }
+            // Ids missing from the block data are kept and shown as unknown blocks
+            let id: BlockIndex = match change.block_id.try_into() {
+                Ok(id) => id,
+                Err(_) => {
+                    log::error!("Got block change with invalid block ID");
+                    continue;
+                }
+            };
 
             // For some reason the y and z coordinates are the wrong way around??
             let local_pos = IVec3::new(
@@ -379,30 +495,50 @@ impl World {
                 change.rel_position.y.into(),
             );
 
-            self.get_section(&loc).unwrap().write().unwrap().blocks
-                [block_pos_to_index(&local_pos)] = change.block_id.try_into().unwrap();
+            self.set_block(&(loc * 16 + local_pos), id);
+        }
+    }
+
+    /// Sets a block in the world and queues the meshes of every section it affects to be rebuilt
+    ///
+    /// # Returns
+    ///
+    /// * False if the chunk containing the block isn't loaded, in which case nothing changes
+    pub fn set_block(&mut self, coords: &WorldCoords, state: BlockIndex) -> bool {
+        match self.get_chunk_containing_mut(coords) {
+            Some(chunk) => chunk.set_block(&Chunk::map_from_world_coords(coords), state),
+            None => return false,
+        }
 
-            // Update adjacent chunk sections
+        for loc in Self::sections_touching(coords) {
             self.queue_chunk_section_mesh(loc);
-            if local_pos.x == 0 {
-                self.queue_chunk_section_mesh(IVec3::new(loc.x - 1, loc.y, loc.z));
-            }
-            if local_pos.x == 15 {
-                self.queue_chunk_section_mesh(IVec3::new(loc.x + 1, loc.y, loc.z));
-            }
-            if local_pos.y == 0 {
-                self.queue_chunk_section_mesh(IVec3::new(loc.x, loc.y - 1, loc.z));
-            }
-            if local_pos.y == 15 {
-                self.queue_chunk_section_mesh(IVec3::new(loc.x, loc.y + 1, loc.z));
-            }
-            if local_pos.z == 0 {
-                self.queue_chunk_section_mesh(IVec3::new(loc.x, loc.y, loc.z - 1));
-            }
-            if local_pos.z == 15 {
-                self.queue_chunk_section_mesh(IVec3::new(loc.x, loc.y, loc.z + 1));
+        }
+        true
+    }
+
+    /// Returns the section containing a block and the sections next to it that the block touches.
+    /// Diagonal sections are included since ambient occlusion and smooth light on their edges depend
+    /// on the block too
+    pub fn sections_touching(coords: &WorldCoords) -> Vec<SectionLocation> {
+        let section = ChunkSection::section_containing(coords);
+        let local = ChunkSection::map_from_world_coords(coords);
+
+        // Directions along each axis the block is on the edge of the section in
+        let offsets = |l: i32| match l {
+            0 => vec![0, -1],
+            15 => vec![0, 1],
+            _ => vec![0],
+        };
+
+        let mut sections = Vec::new();
+        for x in offsets(local.x) {
+            for y in offsets(local.y) {
+                for z in offsets(local.z) {
+                    sections.push(section + IVec3::new(x, y, z));
+                }
             }
         }
+        sections
     }
 }
 
@@ -412,6 +548,12 @@ impl Default for World {
     }
 }
 
+/// Returns true if a chunk is within a view distance of the center chunk, which is measured along
+/// either axis so the chunks in view make up a square
+pub fn in_view_distance(center: ChunkLocation, loc: ChunkLocation, view_distance: i32) -> bool {
+    (loc.x - center.x).abs() <= view_distance && (loc.y - center.y).abs() <= view_distance
+}
+
 /// Returns the block coordinates of the given position
 pub fn block_coords(pos: &Vec3) -> IVec3 {
     IVec3::new(
@@ -456,3 +598,212 @@ impl Directional for IVec3 {
         IVec3::new(-1, 0, 0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::resources;
+
+    use super::*;
+
+    /// Makes a world with empty chunks loaded at the locations
+    fn world_with_chunks(locations: impl IntoIterator<Item = ChunkLocation>) -> World {
+        let mut world = World::new();
+        for loc in locations {
+            world.insert_chunk(Chunk::empty(loc, MIN_SECTION, SECTIONS_PER_CHUNK));
+        }
+        world
+    }
+
+    /// Locations of the chunks in a square around the origin
+    fn square(radius: i32) -> Vec<ChunkLocation> {
+        (-radius..=radius)
+            .flat_map(|x| (-radius..=radius).map(move |z| ChunkLocation::new(x, z)))
+            .collect()
+    }
+
+    #[test]
+    fn chunks_within_render_distance() {
+        let center = ChunkLocation::new(-3, 5);
+        let within: Vec<ChunkLocation> = square(6)
+            .into_iter()
+            .map(|loc| loc + center)
+            .filter(|loc| in_view_distance(center, *loc, 2))
+            .collect();
+
+        // A 5x5 square, corners included
+        assert_eq!(within.len(), 25);
+        assert!(within.contains(&ChunkLocation::new(-5, 3)));
+        assert!(within.contains(&ChunkLocation::new(-1, 7)));
+        assert!(!in_view_distance(center, ChunkLocation::new(0, 5), 2));
+        assert!(!in_view_distance(center, ChunkLocation::new(-3, 2), 2));
+        assert!(in_view_distance(center, center, 0));
+    }
+
+    #[test]
+    fn unload_chunks_outside_view_distance() {
+        let mut world = world_with_chunks(square(2));
+        assert_eq!(world.get_chunks().len(), 25);
+
+        // A 3x3 square around (1, 0) is kept, which covers 9 of the 25 chunks
+        assert_eq!(world.unload_outside(ChunkLocation::new(1, 0), 1), 16);
+        assert_eq!(world.get_chunks().len(), 9);
+        for loc in square(2) {
+            assert_eq!(
+                world.is_chunk_loaded(&loc),
+                in_view_distance(ChunkLocation::new(1, 0), loc, 1),
+                "{}",
+                loc
+            );
+        }
+
+        // Nothing more to unload from the same place
+        assert_eq!(world.unload_outside(ChunkLocation::new(1, 0), 1), 0);
+    }
+
+    #[test]
+    fn unload_chunk_drops_queued_meshes() {
+        let mut world = world_with_chunks(square(1));
+        let loc = ChunkLocation::new(1, 1);
+        world.queue_chunk_section_mesh(SectionLocation::new(1, 0, 1));
+
+        world.unload_chunk(&loc);
+        assert!(!world.is_chunk_loaded(&loc));
+        assert!(!world.chunks_to_generate.contains(&loc));
+        assert!(world.sections_to_generate.is_empty());
+    }
+
+    #[test]
+    fn block_at_across_chunk_boundary() {
+        let stone = resources::state_id("Stone", &[]) as BlockIndex;
+        let dirt = resources::state_id("Dirt", &[]) as BlockIndex;
+        let mut world = world_with_chunks(square(1));
+
+        // Either side of the boundary between chunk -1 and chunk 0
+        world
+            .get_chunk_mut(&ChunkLocation::new(-1, 0))
+            .unwrap()
+            .set_block(&IVec3::new(15, 64, 3), stone);
+        world
+            .get_chunk_mut(&ChunkLocation::new(0, 0))
+            .unwrap()
+            .set_block(&IVec3::new(0, 64, 3), dirt);
+
+        let id_at = |x, z| world.block_at(&IVec3::new(x, 64, z)).map(|b| b.id);
+        assert_eq!(id_at(-1, 3), Some(stone as u32));
+        assert_eq!(id_at(0, 3), Some(dirt as u32));
+        assert!(world.block_at(&IVec3::new(-16, 64, 3)).unwrap().is_air());
+
+        // Negative z and chunks that aren't loaded
+        assert!(world.block_at(&IVec3::new(-1, 64, -1)).unwrap().is_air());
+        assert_eq!(id_at(-17, 3), None);
+        assert_eq!(id_at(32, 3), None);
+    }
+
+    #[test]
+    fn set_block_on_section_boundary_queues_both_sections() {
+        let stone = resources::state_id("Stone", &[]) as BlockIndex;
+        let mut world = world_with_chunks(square(1));
+        world.sections_to_generate.clear();
+
+        // On the east edge of section (0, 4, 0), so the section across the chunk boundary too
+        assert!(world.set_block(&IVec3::new(15, 68, 3), stone));
+        assert_eq!(
+            world.sections_to_generate,
+            vec![SectionLocation::new(0, 4, 0), SectionLocation::new(1, 4, 0)]
+        );
+
+        // Setting it again doesn't queue the sections twice
+        assert!(world.set_block(&IVec3::new(15, 68, 3), 0));
+        assert_eq!(world.sections_to_generate.len(), 2);
+
+        // Blocks away from the edges only affect their own section
+        world.sections_to_generate.clear();
+        assert!(world.set_block(&IVec3::new(8, 72, 8), stone));
+        assert_eq!(
+            world.sections_to_generate,
+            vec![SectionLocation::new(0, 4, 0)]
+        );
+
+        // A corner touches 8 sections, and chunks that aren't loaded can't be changed
+        assert_eq!(World::sections_touching(&IVec3::new(16, 64, -1)).len(), 8);
+        assert!(!world.set_block(&IVec3::new(40, 64, 0), stone));
+    }
+
+    fn world_at_time(time_of_day: i64) -> World {
+        let mut world = World::new();
+        world.handle_time_update(PlayTimeUpdateSpec {
+            world_age: 0,
+            time_of_day,
+        });
+        world
+    }
+
+    #[test]
+    fn sky_brightness_over_a_day() {
+        for (time, brightness) in [
+            (6_000, 1.0),
+            (18_000, 0.0),
+            // Sunrise and sunset are part way between
+            (0, 0.930),
+            (12_000, 0.930),
+            // Days after the first wrap around
+            (30_000, 1.0),
+        ] {
+            let world = world_at_time(time);
+            assert!(
+                (world.sky_brightness() - brightness).abs() < 0.001,
+                "{} gave {}",
+                time,
+                world.sky_brightness()
+            );
+        }
+    }
+
+    #[test]
+    fn stopped_daylight_cycle() {
+        let world = world_at_time(-18_000);
+        assert_eq!(world.get_time_of_day(), 18_000);
+        assert!(!world.is_daylight_cycle_enabled());
+        assert_eq!(world.sky_brightness(), 0.0);
+
+        assert!(world_at_time(18_000).is_daylight_cycle_enabled());
+    }
+
+    /// Meshes a section with its neighbours from the world, returning the area of its opaque faces
+    fn opaque_area(world: &World, loc: SectionLocation) -> f32 {
+        let snapshots: Vec<_> = world
+            .get_section_neighbourhood(&loc)
+            .iter()
+            .map(|s| s.as_ref().map(|s| s.read().unwrap().snapshot()))
+            .collect();
+        let mesh = mesh::build_section_mesh(&snapshots).opaque;
+        mesh.indices
+            .chunks_exact(3)
+            .map(|tri| {
+                let [a, b, c] =
+                    [0, 1, 2].map(|i| Vec3::from(mesh.vertices[tri[i] as usize].position));
+                (b - a).cross(c - a).length() / 2.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn column_across_sections_has_no_seam_faces() {
+        let mut world = world_with_chunks([ChunkLocation::ZERO]);
+        let stone = resources::state_id("Stone", &[]) as BlockIndex;
+        // y = 10 to 21, crossing from section 0 into section 1 at y = 16
+        for y in 10..22 {
+            world.set_block(&IVec3::new(5, y, 5), stone);
+        }
+
+        // 6 blocks in each section with 4 sides each, and only the very bottom and top faces
+        assert_eq!(opaque_area(&world, IVec3::new(0, 0, 0)), 25.0);
+        assert_eq!(opaque_area(&world, IVec3::new(0, 1, 0)), 25.0);
+
+        // Taking the bottom half away shows the face on the seam
+        for y in 10..16 {
+            world.set_block(&IVec3::new(5, y, 5), 0);
+        }
+        assert_eq!(opaque_area(&world, IVec3::new(0, 1, 0)), 26.0);
+    }
+}
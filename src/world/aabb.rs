@@ -0,0 +1,46 @@
+use glam::Vec3;
+
+/// Axis aligned bounding box, used for block collision shapes in block space where a full block is
+/// 0 to 1 on every axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Bounding box of a full block
+    pub const FULL_CUBE: Aabb = Aabb {
+        min: Vec3::ZERO,
+        max: Vec3::ONE,
+    };
+
+    pub fn new(min: Vec3, max: Vec3) -> Aabb {
+        Aabb {
+            min: min.min(max),
+            max: min.max(max),
+        }
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    /// Returns the box moved by the offset, such as from block space to the position of a block in
+    /// the world
+    pub fn offset(&self, offset: Vec3) -> Aabb {
+        Aabb {
+            min: self.min + offset,
+            max: self.max + offset,
+        }
+    }
+
+    /// Returns true if the boxes overlap, boxes that are only touching don't intersect
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.cmplt(other.max).all() && self.max.cmpgt(other.min).all()
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        self.min.cmple(point).all() && self.max.cmpge(point).all()
+    }
+}
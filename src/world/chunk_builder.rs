@@ -1,36 +1,45 @@
-use std::sync::{
-    mpsc::{channel, Receiver, Sender},
-    RwLockReadGuard,
+use std::{
+    cell::Cell,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    time::Instant,
 };
 
-use glam::IVec3;
 use threadpool::ThreadPool;
 
-use crate::{
-    renderer::BlockVertex,
-    resources::{block_models::BlockModel, BLOCKS, BLOCK_MODELS_PARSED},
-};
-
 use super::{
-    chunks::{
-        block_index_to_pos, block_pos_to_index, Chunk, ChunkSection, WrappedChunkSection,
-        MAX_SECTION, MIN_SECTION,
-    },
+    mesh::{self, SectionMesh, SectionNeighbourhood},
     SectionLocation,
 };
 
 pub struct ChunkBuilder {
-    incoming: Receiver<(SectionLocation, Vec<BlockVertex>)>,
-    outgoing: Sender<(SectionLocation, Vec<BlockVertex>)>,
+    incoming: Receiver<(SectionLocation, SectionMesh)>,
+    outgoing: Sender<(SectionLocation, SectionMesh)>,
     pool: ThreadPool,
+
+    // Sections queued that haven't been meshed yet
+    pending: Arc<AtomicUsize>,
+    // When the builder last started working after being idle and how many sections it has been
+    // given since, for logging how fast bursts of new chunks are meshed
+    burst: Cell<Option<(Instant, usize)>>,
+}
+
+impl Default for ChunkBuilder {
+    fn default() -> Self {
+        ChunkBuilder::new()
+    }
 }
 
 impl ChunkBuilder {
     pub fn new() -> ChunkBuilder {
         let (send, recv) = channel();
 
+        // Sections are meshed in parallel, leaving a core each for the render and network threads
         let mut threads: usize = std::thread::available_parallelism().unwrap().into();
-        threads = threads.saturating_sub(4);
+        threads = threads.saturating_sub(2);
         threads = threads.max(1);
 
         ChunkBuilder {
@@ -40,204 +49,201 @@ impl ChunkBuilder {
                 .num_threads(threads)
                 .thread_name("ChunkBuilderPool".to_string())
                 .build(),
+
+            pending: Arc::new(AtomicUsize::new(0)),
+            burst: Cell::new(None),
         }
     }
 
-    pub fn get_incoming_meshes(&self) -> &Receiver<(SectionLocation, Vec<BlockVertex>)> {
-        &self.incoming
+    /// Returns the number of sections waiting to be meshed
+    pub fn get_pending(&self) -> usize {
+        self.pending.load(Ordering::Acquire)
     }
 
-    pub fn generate_chunk(
-        &self,
-        chunk: &Chunk,
-        north: &Chunk,
-        east: &Chunk,
-        south: &Chunk,
-        west: &Chunk,
-        threaded: bool,
-    ) {
-        for sec in chunk.get_sections() {
-            if sec.is_none() {
-                continue;
-            }
-            let sec = sec.unwrap();
-            let sec_read = sec.read().unwrap();
-            let loc = IVec3::new(chunk.get_coords().x, sec_read.y, chunk.get_coords().y);
-
-            let above = if loc.y < MAX_SECTION {
-                chunk.get_section(loc.y + 1)
-            } else {
-                None
-            };
-            let below = if loc.y > MIN_SECTION {
-                chunk.get_section(loc.y - 1)
-            } else {
-                None
-            };
-            let north = north.get_section(loc.y);
-            let east = east.get_section(loc.y);
-            let south = south.get_section(loc.y);
-            let west = west.get_section(loc.y);
-
-            self.generate_chunk_section(
-                sec.clone(),
-                loc,
-                above,
-                below,
-                north,
-                east,
-                south,
-                west,
-                threaded,
+    /// Logs how quickly sections were meshed once the builder runs out of work
+    pub fn log_throughput(&self) {
+        if self.get_pending() > 0 {
+            return;
+        }
+        if let Some((start, sections)) = self.burst.take() {
+            let elapsed = start.elapsed();
+            log::debug!(
+                "Meshed {} sections in {:.1}ms ({:.0} per second)",
+                sections,
+                elapsed.as_secs_f64() * 1000.0,
+                sections as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
             );
         }
     }
 
+    pub fn get_incoming_meshes(&self) -> &Receiver<(SectionLocation, SectionMesh)> {
+        &self.incoming
+    }
+
+    /// Queues the mesh of a section to be generated
+    ///
+    /// # Arguments
+    ///
+    /// * `sections` - The section to mesh and the sections around it, which are needed to cull
+    /// faces and shade vertices on the edges of the section
+    /// * `loc` - Location of the section, sent back with the mesh
+    /// * `threaded` - Generate the mesh on the thread pool rather than immediately
     pub fn generate_chunk_section(
         &self,
-        sect: WrappedChunkSection,
+        sections: SectionNeighbourhood,
         loc: SectionLocation,
-        above: Option<WrappedChunkSection>,
-        below: Option<WrappedChunkSection>,
-        north: Option<WrappedChunkSection>,
-        east: Option<WrappedChunkSection>,
-        south: Option<WrappedChunkSection>,
-        west: Option<WrappedChunkSection>,
         threaded: bool,
     ) {
         let outgoing = self.outgoing.clone();
+        let pending = self.pending.clone();
+
+        let burst = match self.burst.get() {
+            Some((start, sections)) => (start, sections + 1),
+            None => (Instant::now(), 1),
+        };
+        self.burst.set(Some(burst));
+        pending.fetch_add(1, Ordering::AcqRel);
 
+        // The sections are copied and their locks released before meshing, so blocks changed by
+        // the network thread don't wait for the whole mesh to be built
         let run = move || {
-            let above = above.as_ref();
-            let below = below.as_ref();
-            let north = north.as_ref();
-            let south = south.as_ref();
-            let east = east.as_ref();
-            let west = west.as_ref();
-            outgoing
-                .send((
-                    loc,
-                    Self::generate_mesh(
-                        sect.read().unwrap(),
-                        above.map(|s| s.read().unwrap()),
-                        below.map(|s| s.read().unwrap()),
-                        north.map(|s| s.read().unwrap()),
-                        east.map(|s| s.read().unwrap()),
-                        south.map(|s| s.read().unwrap()),
-                        west.map(|s| s.read().unwrap()),
-                    ),
-                ))
-                .ok();
+            let snapshots: Vec<_> = sections
+                .iter()
+                .map(|s| s.as_ref().map(|s| s.read().unwrap().snapshot()))
+                .collect();
+            let mesh = mesh::build_section_mesh(&snapshots);
+
+            outgoing.send((loc, mesh)).ok();
+            pending.fetch_sub(1, Ordering::AcqRel);
         };
 
         if threaded {
-            self.pool.execute(move || run());
+            self.pool.execute(run);
         } else {
             run();
         }
     }
+}
 
-    pub fn generate_mesh(
-        section: RwLockReadGuard<ChunkSection>,
-        above: Option<RwLockReadGuard<ChunkSection>>,
-        below: Option<RwLockReadGuard<ChunkSection>>,
-        north: Option<RwLockReadGuard<ChunkSection>>,
-        east: Option<RwLockReadGuard<ChunkSection>>,
-        south: Option<RwLockReadGuard<ChunkSection>>,
-        west: Option<RwLockReadGuard<ChunkSection>>,
-    ) -> Vec<BlockVertex> {
-        let mut verts: Vec<BlockVertex> = Vec::new();
-
-        let value = section.blocks;
-        for (i, b) in value.iter().enumerate() {
-            let block = BLOCKS.get(&((*b).into()));
-            if block.is_none() {
-                continue;
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::RwLock,
+        time::{Duration, Instant},
+    };
+
+    use glam::IVec3;
+
+    use crate::{
+        resources::state_id,
+        world::{
+            chunks::{block_index_to_pos, ChunkSection, WrappedChunkSection},
+            mesh::neighbour_index,
+        },
+    };
+
+    use super::*;
+
+    /// Sections along the ground of a square of freshly loaded chunks, with uneven stone in each
+    /// so the meshes are all different
+    fn terrain(chunks: i32) -> HashMap<SectionLocation, WrappedChunkSection> {
+        let stone = state_id("Stone", &[]) as u16;
+        let mut sections = HashMap::new();
+        for x in 0..chunks {
+            for z in 0..chunks {
+                let mut blocks = [0; 4096];
+                for (i, id) in blocks.iter_mut().enumerate() {
+                    let pos = block_index_to_pos(i);
+                    if pos.y <= (pos.x * 7 + pos.z * 13 + x * 3 + z * 5) % 11 {
+                        *id = stone;
+                    }
+                }
+                let section = ChunkSection::new(0, blocks);
+                sections.insert(
+                    SectionLocation::new(x, 0, z),
+                    Arc::new(RwLock::new(section)),
+                );
             }
-            let block = block.unwrap();
+        }
+        sections
+    }
 
-            if block.models.is_none() {
-                continue;
-            }
-            if block.models.as_ref().unwrap().len() == 0 {
-                continue;
+    fn neighbourhood(
+        sections: &HashMap<SectionLocation, WrappedChunkSection>,
+        loc: SectionLocation,
+    ) -> SectionNeighbourhood {
+        let mut neighbourhood: SectionNeighbourhood = Default::default();
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    let offset = IVec3::new(x, y, z);
+                    neighbourhood[neighbour_index(offset)] = sections.get(&(loc + offset)).cloned();
+                }
             }
+        }
+        neighbourhood
+    }
 
-            let model_key = block.models.as_ref().unwrap().get(0).unwrap();
-            let model: Option<&BlockModel> = BLOCK_MODELS_PARSED.get(model_key);
-            if model.is_none() {
-                log::error!(
-                    "Couldn't find model {}",
-                    block.models.as_ref().unwrap().get(0).unwrap()
-                );
-                continue;
-            }
-            let model = model.unwrap();
-
-            let pos = block_index_to_pos(i);
-
-            // Top Face
-            let n = pos + IVec3::new(0, 1, 0);
-            let ni = block_pos_to_index(&n);
-            let b_above = if pos.y == 15 {
-                above.as_ref().map(|cs| cs.blocks[ni]).unwrap_or(0)
-            } else {
-                section.blocks[ni]
-            };
-
-            // Bottom Face
-            let n = pos + IVec3::new(0, -1, 0);
-            let ni = block_pos_to_index(&n);
-            let b_below = if pos.y == 0 {
-                below.as_ref().map(|cs| cs.blocks[ni]).unwrap_or(0)
-            } else {
-                section.blocks[ni]
-            };
-
-            // North Face
-            let n = pos + IVec3::new(0, 0, -1);
-            let ni = block_pos_to_index(&n);
-            let b_north = if pos.z == 0 {
-                north.as_ref().map(|cs| cs.blocks[ni]).unwrap_or(0)
-            } else {
-                section.blocks[ni]
-            };
-
-            // South Face
-            let n = pos + IVec3::new(0, 0, 1);
-            let ni = block_pos_to_index(&n);
-            let b_south = if pos.z == 15 {
-                south.as_ref().map(|cs| cs.blocks[ni]).unwrap_or(0)
-            } else {
-                section.blocks[ni]
-            };
-
-            // East Face
-            let n = pos + IVec3::new(1, 0, 0);
-            let ni = block_pos_to_index(&n);
-            let b_east = if pos.x == 15 {
-                east.as_ref().map(|cs| cs.blocks[ni]).unwrap_or(0)
-            } else {
-                section.blocks[ni]
-            };
-
-            // West Face
-            let n = pos + IVec3::new(-1, 0, 0);
-            let ni = block_pos_to_index(&n);
-            let b_west = if pos.x == 0 {
-                west.as_ref().map(|cs| cs.blocks[ni]).unwrap_or(0)
-            } else {
-                section.blocks[ni]
-            };
-
-            for mut vert in model.generate_mesh(b_above, b_below, b_north, b_east, b_south, b_west)
-            {
-                vert.position[0] += pos.x as f32;
-                vert.position[1] += pos.y as f32;
-                vert.position[2] += pos.z as f32;
-                verts.push(vert);
-            }
+    /// Meshes every section, returning the meshes by location since they can arrive in any order
+    fn build_all(
+        builder: &ChunkBuilder,
+        sections: &HashMap<SectionLocation, WrappedChunkSection>,
+        threaded: bool,
+    ) -> HashMap<SectionLocation, SectionMesh> {
+        for loc in sections.keys() {
+            builder.generate_chunk_section(neighbourhood(sections, *loc), *loc, threaded);
+        }
+        (0..sections.len())
+            .map(|_| {
+                builder
+                    .get_incoming_meshes()
+                    .recv_timeout(Duration::from_secs(30))
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn threaded_and_unthreaded_meshes_match() {
+        let sections = terrain(10);
+        let builder = ChunkBuilder::new();
+
+        let threaded = build_all(&builder, &sections, true);
+        let unthreaded = build_all(&builder, &sections, false);
+        assert_eq!(builder.get_pending(), 0);
+        assert_eq!(threaded.len(), 100);
+        assert_eq!(unthreaded.len(), 100);
+
+        for (loc, mesh) in &threaded {
+            let other = &unthreaded[loc];
+            assert!(!mesh.opaque.is_empty(), "{}", loc);
+            assert_eq!(mesh.opaque.vertices, other.opaque.vertices, "{}", loc);
+            assert_eq!(mesh.opaque.indices, other.opaque.indices, "{}", loc);
+            assert_eq!(mesh.translucent.vertices, other.translucent.vertices);
+        }
+    }
+
+    /// Prints how fast a burst of 100 freshly loaded chunks is meshed on the thread pool and on a
+    /// single thread, run with `cargo test bench_ -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_chunk_burst() {
+        let sections = terrain(10);
+        let builder = ChunkBuilder::new();
+
+        for threaded in [true, false] {
+            let start = Instant::now();
+            let meshes = build_all(&builder, &sections, threaded);
+            let elapsed = start.elapsed();
+
+            println!(
+                "{} sections {}: {:.1}ms ({:.0} per second)",
+                meshes.len(),
+                if threaded { "threaded" } else { "unthreaded" },
+                elapsed.as_secs_f64() * 1000.0,
+                meshes.len() as f64 / elapsed.as_secs_f64()
+            );
         }
-        verts
     }
 }
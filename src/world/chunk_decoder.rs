@@ -0,0 +1,75 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use mcproto_rs::v1_16_3::ChunkData;
+use threadpool::ThreadPool;
+
+use super::{
+    chunks::{ChunkDecodeError, DecodedChunk, PackingMode},
+    ChunkLocation,
+};
+
+/// Result of decoding a chunk, tagged with the location and id of the job it came from
+pub type DecodeResult = (ChunkLocation, u64, Result<DecodedChunk, ChunkDecodeError>);
+
+/// Decodes chunk data packets on a thread pool so large bursts of chunks don't stall the render
+/// thread. Completed chunks are polled from `get_decoded_chunks` and still need to be turned into
+/// a Chunk on the render thread
+pub struct ChunkDecoder {
+    incoming: Receiver<DecodeResult>,
+    outgoing: Sender<DecodeResult>,
+    pool: ThreadPool,
+    next_job: u64,
+}
+
+impl ChunkDecoder {
+    pub fn new() -> ChunkDecoder {
+        let (send, recv) = channel();
+
+        ChunkDecoder {
+            incoming: recv,
+            outgoing: send,
+            pool: threadpool::Builder::new()
+                .num_threads(2)
+                .thread_name("ChunkDecoderPool".to_string())
+                .build(),
+            next_job: 0,
+        }
+    }
+
+    pub fn get_decoded_chunks(&self) -> &Receiver<DecodeResult> {
+        &self.incoming
+    }
+
+    /// Queues chunk data to be decoded, returns the id of the job which is sent back with the
+    /// decoded chunk
+    pub fn decode(
+        &mut self,
+        data: ChunkData,
+        min_section: i32,
+        section_count: usize,
+        packing: PackingMode,
+    ) -> u64 {
+        let job = self.next_job;
+        self.next_job += 1;
+
+        let outgoing = self.outgoing.clone();
+        self.pool.execute(move || {
+            let loc = ChunkLocation::new(data.position.x, data.position.z);
+            outgoing
+                .send((
+                    loc,
+                    job,
+                    DecodedChunk::try_new(&data, min_section, section_count, packing),
+                ))
+                .ok();
+        });
+
+        job
+    }
+}
+
+impl Default for ChunkDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
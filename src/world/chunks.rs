@@ -1,108 +1,369 @@
 use std::{
     convert::TryInto,
-    io::{Cursor, Read},
+    io::{self, Cursor, ErrorKind, Read},
     sync::{Arc, RwLock},
 };
 
-use glam::{IVec2, IVec3};
-use glium::{Display, VertexBuffer};
+use glam::{IVec2, IVec3, Vec3};
+use glium::{index::PrimitiveType, Display, IndexBuffer, VertexBuffer};
 use log::debug;
-use mcproto_rs::{nbt, v1_16_3::ChunkData};
+use mcproto_rs::{
+    nbt,
+    types::VarInt,
+    v1_16_3::{ChunkData, LightingUpdateSpec},
+};
 
 use crate::{
     network::read_varint,
     renderer::BlockVertex,
-    resources::{BlockState, BLOCKS},
+    resources::{self, BlockState},
 };
 
-use super::{ChunkCoords, ChunkLocation, SectionCoords, SectionLocation, WorldCoords};
+use super::{
+    aabb::Aabb,
+    coords,
+    map_colours::{self, SurfaceColours},
+    mesh::IndexedMesh,
+    palette::PalettedContainer,
+    ChunkCoords, ChunkLocation, SectionCoords, SectionLocation, World, WorldCoords,
+};
 
 // Base 2 Log of number of state ids in the game
 const MAX_BITS_PER_BLOCK: u32 = 15;
+// Default section range of a chunk, used until the server tells us the dimension height
 pub const SECTIONS_PER_CHUNK: usize = 16;
 pub const MAX_SECTION: i32 = 15;
 pub const MIN_SECTION: i32 = 0;
 pub type BlockIndex = u16;
+// State id of air, used for sections the server didn't send
+pub const AIR: u32 = 0;
 pub type ChunkArray = [BlockIndex; 4096];
+// Index into the biome registry the server sent, which can have more than 256 biomes
+pub type BiomeId = u32;
+// Biomes are stored in 4x4x4 cells, giving 64 biomes per chunk section
+pub const BIOMES_PER_SECTION: usize = 64;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ChunkSection {
     pub y: i32,
-    pub blocks: ChunkArray,
+    // Shared with snapshots of the section, and copied when changed while a snapshot holds them
+    pub blocks: Arc<BlockStorage>,
+    pub light: Option<Arc<SectionLight>>,
+}
+
+/// Blocks of a chunk section. Sections with few distinct blocks are paletted to save memory, and
+/// the rest are kept as a flat array
+#[derive(Debug, Clone)]
+pub enum BlockStorage {
+    Flat(Box<ChunkArray>),
+    Paletted(PalettedContainer),
+}
+
+impl BlockStorage {
+    /// Stores blocks in whichever form suits how many distinct blocks there are
+    pub fn from_array(blocks: ChunkArray) -> BlockStorage {
+        match PalettedContainer::from_array(&blocks) {
+            Some(container) => BlockStorage::Paletted(container),
+            None => BlockStorage::Flat(Box::new(blocks)),
+        }
+    }
+
+    /// Returns the state of the block at an index into the section
+    pub fn get(&self, i: usize) -> BlockIndex {
+        match self {
+            BlockStorage::Flat(blocks) => blocks[i],
+            BlockStorage::Paletted(container) => container.get(i),
+        }
+    }
+
+    /// Sets the block at an index into the section, switching to a flat array once the palette
+    /// gets too big
+    pub fn set(&mut self, i: usize, state: BlockIndex) {
+        match self {
+            BlockStorage::Flat(blocks) => blocks[i] = state,
+            BlockStorage::Paletted(container) => {
+                if !container.set(i, state) {
+                    let mut blocks = Box::new(container.to_array());
+                    blocks[i] = state;
+                    *self = BlockStorage::Flat(blocks);
+                }
+            }
+        }
+    }
+
+    pub fn is_paletted(&self) -> bool {
+        matches!(self, BlockStorage::Paletted(_))
+    }
+
+    /// Copies the blocks out into a flat array
+    pub fn to_array(&self) -> ChunkArray {
+        match self {
+            BlockStorage::Flat(blocks) => **blocks,
+            BlockStorage::Paletted(container) => container.to_array(),
+        }
+    }
+}
+
+/// Sky and block light levels for a chunk section, packed as 4 bits per block in the same order
+/// as the blocks of the section
+#[derive(Debug, Clone)]
+pub struct SectionLight {
+    pub sky: [u8; 2048],
+    pub block: [u8; 2048],
+}
+
+impl SectionLight {
+    /// Creates light data with full sky light and no block light
+    pub fn new() -> SectionLight {
+        SectionLight {
+            sky: [0xff; 2048],
+            block: [0; 2048],
+        }
+    }
+
+    /// Returns the (sky light, block light) levels of the block at the provided SectionCoords
+    pub fn light_at(&self, coords: &SectionCoords) -> (u8, u8) {
+        let i = block_pos_to_index(coords);
+        (get_nibble(&self.sky, i), get_nibble(&self.block, i))
+    }
+}
+
+impl Default for SectionLight {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ChunkSection {
     pub fn new(y: i32, blocks: ChunkArray) -> ChunkSection {
-        ChunkSection { y, blocks }
+        ChunkSection {
+            y,
+            blocks: Arc::new(BlockStorage::from_array(blocks)),
+            light: None,
+        }
+    }
+
+    /// Shares the blocks and light of this section so they can be read after the lock on the
+    /// section is released. Nothing is copied until the section changes while the snapshot is
+    /// still held
+    pub fn snapshot(&self) -> ChunkSection {
+        self.clone()
+    }
+
+    /// Returns the (sky light, block light) levels in 0..=15 of the block at the provided
+    /// SectionCoords. Sections that haven't received any light data are assumed to be fully lit
+    /// by the sky
+    pub fn light_at(&self, coords: &SectionCoords) -> (u8, u8) {
+        match &self.light {
+            Some(light) => light.light_at(coords),
+            None => (15, 0),
+        }
     }
 
     /// Convert block coordinates from within a chunk to the chunk section
     pub fn map_from_chunk_coords(coords: &ChunkCoords) -> SectionCoords {
-        IVec3::new(coords.x, coords.y.rem_euclid(16), coords.z)
+        coords::chunk_to_section(coords)
     }
 
     /// Convert block coordinsate from within this chunk section to the entire chunk
     pub fn map_to_chunk_coords(&self, coords: &SectionCoords) -> ChunkCoords {
-        IVec3::new(coords.x, self.y * 16 + coords.y, coords.z)
+        coords::section_to_chunk(self.y, coords)
     }
 
     pub fn map_from_world_coords(coords: &WorldCoords) -> SectionCoords {
-        Self::map_from_chunk_coords(&Chunk::map_from_world_coords(coords))
+        coords::world_to_section(coords)
+    }
+
+    /// Iterates over the non-air blocks in this chunk section along with their SectionCoords
+    pub fn iter_blocks(&self) -> impl Iterator<Item = (SectionCoords, &'static BlockState)> + '_ {
+        (0..4096).filter_map(move |i| {
+            let state = resources::block_state(self.blocks.get(i).into());
+            (!state.is_air()).then(|| (block_index_to_pos(i), state))
+        })
     }
 
     /// Get the block at the provided SectionCoords within this chunk section
     pub fn block_at(&self, coords: &SectionCoords) -> Option<&'static BlockState> {
-        BLOCKS.get(&self.blocks[block_pos_to_index(coords)].into())
+        Some(resources::block_state(self.block_id_at(coords).into()))
+    }
+
+    /// Get the state id of the block at the provided SectionCoords within this chunk section
+    pub fn block_id_at(&self, coords: &SectionCoords) -> BlockIndex {
+        self.blocks.get(block_pos_to_index(coords))
+    }
+
+    /// Set the block at the provided SectionCoords within this chunk section
+    pub fn set_block(&mut self, coords: &SectionCoords, state: BlockIndex) {
+        Arc::make_mut(&mut self.blocks).set(block_pos_to_index(coords), state);
     }
 
     /// Get the chunk section index of the section containing the provided y level
     pub fn section_containing_height(y: i32) -> i32 {
-        y / 16
+        coords::section_containing_height(y)
     }
 
     pub fn section_containing(coords: &WorldCoords) -> SectionLocation {
-        IVec3::new(
-            coords.x.div_floor(16),
-            coords.y.div_floor(16),
-            coords.z.div_floor(16),
-        )
+        coords::section_containing(coords)
     }
 }
 
 pub type WrappedChunkSection = Arc<RwLock<ChunkSection>>;
-pub type VBO = VertexBuffer<BlockVertex>;
+/// Vertex and index buffers of a section mesh on the GPU
+pub struct SectionBuffers {
+    pub vertices: VertexBuffer<BlockVertex>,
+    pub indices: IndexBuffer<u32>,
+}
+// A section with the buffers of its opaque and translucent faces
+pub type SectionEntry = Option<(
+    WrappedChunkSection,
+    Option<SectionBuffers>,
+    Option<SectionBuffers>,
+)>;
 pub struct Chunk {
     pos: ChunkLocation,
     heightmap: [u16; 256],
-    sections: [Option<(WrappedChunkSection, Option<VBO>)>; SECTIONS_PER_CHUNK],
+    biomes: Vec<BiomeId>,
+    min_section: i32,
+    sections: Vec<SectionEntry>,
+    // Map colours of the top blocks, worked out when first asked for and cleared when the surface
+    // changes
+    surface_colours: RwLock<Option<SurfaceColours>>,
 }
 
-impl Chunk {
-    pub fn new(data: &ChunkData) -> Chunk {
+/// How values are packed into the long arrays of chunk data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PackingMode {
+    /// Values don't span multiple longs, leaving unused bits at the top of each long (1.16+)
+    Padded,
+    /// Values are packed end to end and can span two longs (1.15 and earlier). The client can't
+    /// join servers older than 1.16.3, so only data decoded outside a connection uses this
+    Tight,
+}
+
+impl PackingMode {
+    /// Returns the packing mode used by a protocol version, which is only ever 1.16 or later while
+    /// connected to a server
+    pub fn for_protocol(protocol: i32) -> PackingMode {
+        // 1.16 (20w17a)
+        if protocol >= 735 {
+            PackingMode::Padded
+        } else {
+            PackingMode::Tight
+        }
+    }
+}
+
+/// Chunk data decoded from a chunk data packet without any GPU resources, so chunks can be decoded
+/// on another thread and turned into a Chunk on the render thread
+pub struct DecodedChunk {
+    pos: ChunkLocation,
+    heightmap: [u16; 256],
+    biomes: Vec<BiomeId>,
+    min_section: i32,
+    sections: Vec<Option<ChunkSection>>,
+}
+
+impl DecodedChunk {
+    /// Decodes a chunk from chunk data, where the chunk spans `section_count` sections starting
+    /// from the section at index `min_section`. Fails if the chunk data is truncated or malformed
+    pub fn try_new(
+        data: &ChunkData,
+        min_section: i32,
+        section_count: usize,
+        packing: PackingMode,
+    ) -> Result<DecodedChunk, ChunkDecodeError> {
         debug!("Processing chunk data");
 
-        Chunk {
+        Ok(DecodedChunk {
             pos: IVec2::new(data.position.x, data.position.z),
 
-            heightmap: process_heightmap(data),
-            sections: process_sections(data),
+            heightmap: process_heightmap(&data.heightmaps.root.payload),
+            biomes: process_biomes(data.biomes.as_ref().map(|b| b.as_slice()), section_count),
+            min_section,
+            sections: process_sections(
+                data.primary_bit_mask.0,
+                &data.data,
+                min_section,
+                section_count,
+                packing,
+            )?,
+        })
+    }
+
+    pub fn get_coords(&self) -> &ChunkLocation {
+        &self.pos
+    }
+}
+
+impl From<DecodedChunk> for Chunk {
+    fn from(decoded: DecodedChunk) -> Chunk {
+        Chunk {
+            pos: decoded.pos,
+            heightmap: decoded.heightmap,
+            biomes: decoded.biomes,
+            min_section: decoded.min_section,
+            sections: decoded
+                .sections
+                .into_iter()
+                .map(|s| s.map(|s| (Arc::new(RwLock::new(s)), None, None)))
+                .collect(),
+            surface_colours: RwLock::new(None),
         }
     }
+}
+
+impl Chunk {
+    /// Decodes a chunk from chunk data on the current thread, see DecodedChunk::try_new
+    pub fn try_new(
+        data: &ChunkData,
+        min_section: i32,
+        section_count: usize,
+        packing: PackingMode,
+    ) -> Result<Chunk, ChunkDecodeError> {
+        DecodedChunk::try_new(data, min_section, section_count, packing).map(Chunk::from)
+    }
+
+    /// Creates a chunk with no blocks in it, for tests to fill with the blocks they need
+    #[cfg(test)]
+    pub fn empty(pos: ChunkLocation, min_section: i32, section_count: usize) -> Chunk {
+        Chunk::from(DecodedChunk {
+            pos,
+            heightmap: [0; 256],
+            biomes: Vec::new(),
+            min_section,
+            sections: (0..section_count).map(|_| None).collect(),
+        })
+    }
+
+    /// Returns the index of the lowest section in this chunk
+    pub fn get_min_section(&self) -> i32 {
+        self.min_section
+    }
+
+    /// Returns the index of the highest section in this chunk
+    pub fn get_max_section(&self) -> i32 {
+        self.min_section + self.sections.len() as i32 - 1
+    }
 
     /// Returns true if there are any non-air blocks within a section of the chunk
     pub fn is_section_present(&self, y: i32) -> bool {
-        self.sections.get(section_to_index(y)).is_some()
+        self.section_to_index(y)
+            .map(|i| self.sections[i].is_some())
+            .unwrap_or(false)
     }
 
     pub fn is_section_containing_present(&self, y: i32) -> bool {
-        self.sections
-            .get(section_to_index(ChunkSection::section_containing_height(y)))
-            .is_some()
+        self.is_section_present(ChunkSection::section_containing_height(y))
     }
 
     pub fn put_section(&mut self, section: ChunkSection) {
-        let index = section_to_index(section.y);
-        *self.sections.get_mut(index).unwrap() = Some((Arc::new(RwLock::new(section)), None));
+        match self.section_to_index(section.y) {
+            Some(index) => {
+                self.sections[index] = Some((Arc::new(RwLock::new(section)), None, None));
+                self.invalidate_surface();
+            }
+            None => log::warn!("Tried to put chunk section outside of chunk: {}", section.y),
+        }
     }
 
     pub fn get_sections(&self) -> Vec<Option<WrappedChunkSection>> {
@@ -112,26 +373,52 @@ impl Chunk {
             .collect()
     }
 
-    /// Returns an option containing a reference to the request section of this chunk
+    /// Returns an option containing a reference to the request section of this chunk, None if the
+    /// section is empty or outside of the chunk, which neighbour lookups rely on
     pub fn get_section(&self, y: i32) -> Option<WrappedChunkSection> {
-        self.sections
-            .get(section_to_index(y))
-            .unwrap_or(&None)
-            .as_ref()
-            .map(|(s, _)| s.clone())
+        self.section_to_index(y)
+            .and_then(|i| self.sections[i].as_ref())
+            .map(|(s, _, _)| s.clone())
     }
 
-    pub fn get_section_vbo(&self, y: i32) -> Option<&VertexBuffer<BlockVertex>> {
-        self.sections
-            .get(section_to_index(y))
-            .unwrap_or(&None)
-            .as_ref()
-            .map(|(_, vbo)| vbo.as_ref())
-            .unwrap_or(None)
+    /// Returns the buffer of solid and cut out faces of a section, None if it hasn't been meshed or
+    /// has no faces to draw
+    pub fn get_section_opaque(&self, y: i32) -> Option<&SectionBuffers> {
+        self.checked_section_index(y)
+            .and_then(|i| self.sections[i].as_ref())
+            .and_then(|(_, opaque, _)| opaque.as_ref())
+    }
+
+    /// Returns the buffer of faces of a section that are blended with what is behind them
+    pub fn get_section_translucent(&self, y: i32) -> Option<&SectionBuffers> {
+        self.checked_section_index(y)
+            .and_then(|i| self.sections[i].as_ref())
+            .and_then(|(_, _, translucent)| translucent.as_ref())
+    }
+
+    /// Returns the box a section of this chunk takes up in the world, whether or not it is loaded
+    pub fn section_aabb(&self, y: i32) -> Aabb {
+        let min = Vec3::new(
+            (self.pos.x * 16) as f32,
+            (y * 16) as f32,
+            (self.pos.y * 16) as f32,
+        );
+        Aabb::new(min, min + Vec3::splat(16.0))
     }
 
+    /// Returns the section containing a y level, None if the section is empty or the y level is
+    /// above or below the chunk
     pub fn get_section_containing(&self, y: i32) -> Option<WrappedChunkSection> {
-        self.get_section(ChunkSection::section_containing_height(y))
+        self.section_index_containing(y)
+            .and_then(|i| self.sections[i].as_ref())
+            .map(|(s, _, _)| s.clone())
+    }
+
+    /// Returns the index into this chunk's section list of the section containing a y level, None
+    /// if the y level is above or below the chunk. Negative y levels round down to the section
+    /// below so y = -1 is in section -1
+    pub fn section_index_containing(&self, y: i32) -> Option<usize> {
+        self.section_to_index(ChunkSection::section_containing_height(y))
     }
 
     pub fn get_coords(&self) -> &ChunkLocation {
@@ -140,52 +427,253 @@ impl Chunk {
 
     /// Converts a coordinates of a block from the world to the coordinates within the chunk
     pub fn map_from_world_coords(coords: &WorldCoords) -> ChunkCoords {
-        IVec3::new(coords.x.rem_euclid(16), coords.y, coords.z.rem_euclid(16))
+        coords::world_to_chunk(coords)
     }
 
     /// Converts a coordinates of a block within this chunk to a position in the world
     pub fn map_to_world_coords(&self, coords: &ChunkCoords) -> WorldCoords {
-        assert!(coords.x >= 0 && coords.x < 16);
-        assert!(coords.z >= 0 && coords.z < 16);
-        IVec3::new(
-            self.pos.x * 16 + coords.x,
-            coords.y,
-            self.pos.y * 16 + coords.z,
-        )
+        coords::chunk_to_world(&self.pos, coords)
     }
 
     pub fn chunk_containing(coords: &WorldCoords) -> ChunkLocation {
-        IVec2::new(coords.x.div_floor(16), coords.z.div_floor(16))
+        coords::chunk_containing(coords)
+    }
+
+    /// Uploads the mesh of a section into separate buffers for its opaque and translucent faces, no
+    /// buffer is made for an empty list
+    pub fn load_mesh(
+        &mut self,
+        dis: &Display,
+        opaque: IndexedMesh,
+        translucent: IndexedMesh,
+        section: i32,
+    ) {
+        let upload = |mesh: IndexedMesh| match mesh.is_empty() {
+            true => None,
+            false => Some(SectionBuffers {
+                vertices: VertexBuffer::new(dis, &mesh.vertices).unwrap(),
+                indices: IndexBuffer::new(dis, PrimitiveType::TrianglesList, &mesh.indices)
+                    .unwrap(),
+            }),
+        };
+
+        if let Some(Some(cs)) = self
+            .checked_section_index(section)
+            .map(|i| &mut self.sections[i])
+        {
+            cs.1 = upload(opaque);
+            cs.2 = upload(translucent);
+        }
+    }
+
+    /// Sets the block at the provided ChunkCoords, creating the section if it was empty. The old mesh
+    /// of the section is kept and drawn until the world has meshed the section again, so it doesn't
+    /// disappear for the frames in between
+    pub fn set_block(&mut self, coords: &ChunkCoords, state: BlockIndex) {
+        let y = ChunkSection::section_containing_height(coords.y);
+        let index = match self.section_to_index(y) {
+            Some(i) => i,
+            None => {
+                log::warn!("Tried to set block outside of chunk: {}", coords.y);
+                return;
+            }
+        };
+
+        if self.sections[index].is_none() {
+            self.put_section(ChunkSection::new(y, [0; 4096]));
+        }
+        if let Some((section, _, _)) = &self.sections[index] {
+            section
+                .write()
+                .unwrap()
+                .set_block(&ChunkSection::map_from_chunk_coords(coords), state);
+        }
+
+        // Only blocks at or above the old top of the column can change what the map shows
+        let top = self.get_highest_block(IVec2::new(coords.x, coords.z)) - 1;
+        self.update_heightmap(coords, state);
+        if coords.y >= top {
+            self.invalidate_surface();
+        }
+    }
+
+    /// Updates the heightmap after the block at the provided ChunkCoords has been set
+    fn update_heightmap(&mut self, coords: &ChunkCoords, state: BlockIndex) {
+        let i = coords.z as usize * 16 + coords.x as usize;
+        let bottom = self.min_section * 16;
+        // Heightmaps store the height above the highest block
+        let height = (coords.y - bottom + 1) as u16;
+
+        let is_air = resources::block_state(state.into()).is_air();
+
+        if !is_air {
+            self.heightmap[i] = self.heightmap[i].max(height);
+        } else if height == self.heightmap[i] {
+            // The highest block was removed so look for the next highest one
+            self.heightmap[i] = self.column_height_below(coords.x, coords.z, coords.y);
+        }
+    }
+
+    /// Recalculates the heightmap of a column by scanning down from the top of the chunk for the
+    /// highest block that isn't air, for when the heightmap may not match the blocks
+    pub fn recompute_column_height(&mut self, x: i32, z: i32) {
+        let top = (self.get_max_section() + 1) * 16;
+        self.heightmap[z as usize * 16 + x as usize] = self.column_height_below(x, z, top);
     }
 
-    pub fn load_mesh(&mut self, dis: &Display, verts: Vec<BlockVertex>, section: i32) {
-        self.sections.get_mut(section_to_index(section)).map(|cs| {
-            cs.as_mut()
-                .map(|cs| cs.1 = Some(VertexBuffer::new(dis, &verts).unwrap()))
-        });
+    /// Returns the heightmap value of a column counting only the blocks below a y level
+    fn column_height_below(&self, x: i32, z: i32, y: i32) -> u16 {
+        let bottom = self.min_section * 16;
+        let mut y = y - 1;
+        while y >= bottom
+            && self
+                .block_at(&IVec3::new(x, y, z))
+                .map(|b| b.is_air())
+                .unwrap_or(true)
+        {
+            y -= 1;
+        }
+        (y - bottom + 1) as u16
     }
 
+    /// Get the block at the provided ChunkCoords, None if the y level is outside of the chunk
     pub fn block_at(&self, coords: &ChunkCoords) -> Option<&'static BlockState> {
-        self.get_section(ChunkSection::section_containing_height(coords.y))
-            .map(|s| {
-                s.read()
-                    .unwrap()
-                    .block_at(&ChunkSection::map_from_chunk_coords(coords))
-            })
-            .unwrap_or(None)
+        match &self.sections[self.section_index_containing(coords.y)?] {
+            Some((s, _, _)) => s
+                .read()
+                .unwrap()
+                .block_at(&ChunkSection::map_from_chunk_coords(coords)),
+            // Sections that are entirely air aren't sent by the server
+            None => Some(resources::block_state(AIR)),
+        }
+    }
+
+    /// Get the block at the provided ChunkCoords, which may lie outside of this chunk in which case
+    /// the block is looked up in the neighbouring chunk from the world
+    pub fn neighbor_block(
+        &self,
+        world: &World,
+        coords: &ChunkCoords,
+    ) -> Option<&'static BlockState> {
+        if (0..16).contains(&coords.x) && (0..16).contains(&coords.z) {
+            self.block_at(coords)
+        } else {
+            world.block_at(&IVec3::new(
+                self.pos.x * 16 + coords.x,
+                coords.y,
+                self.pos.y * 16 + coords.z,
+            ))
+        }
     }
 
     /// Returns the y value of the highest block at the x/z position provided in this chunk
     pub fn get_highest_block(&self, coords: IVec2) -> i32 {
-        self.heightmap[coords.y as usize * 16 + coords.x as usize] as i32
+        self.heightmap[coords.y as usize * 16 + coords.x as usize] as i32 + self.min_section * 16
+    }
+
+    /// Returns the map colour of the highest block in each column, indexed by z then x. The colours
+    /// are kept until a block on the surface changes
+    pub fn top_colours(&self) -> SurfaceColours {
+        if let Some(colours) = *self.surface_colours.read().unwrap() {
+            return colours;
+        }
+
+        let mut colours = [[map_colours::EMPTY; 16]; 16];
+        for (z, row) in colours.iter_mut().enumerate() {
+            for (x, colour) in row.iter_mut().enumerate() {
+                let (x, z) = (x as i32, z as i32);
+                // The heightmap is the height above the highest block
+                let y = self.get_highest_block(IVec2::new(x, z)) - 1;
+                if let Some(block) = self.block_at(&IVec3::new(x, y, z)) {
+                    *colour = map_colours::block_colour(block);
+                }
+            }
+        }
+        *self.surface_colours.write().unwrap() = Some(colours);
+        colours
+    }
+
+    /// Forgets the cached map colours so they're worked out again next time they're asked for
+    fn invalidate_surface(&mut self) {
+        *self.surface_colours.get_mut().unwrap() = None;
+    }
+
+    /// Updates the light levels of the sections in this chunk. Light data for sections that
+    /// aren't present in the chunk is discarded
+    pub fn apply_light(&mut self, data: &LightingUpdateSpec) {
+        // The light arrays start from the section below the bottom of the chunk
+        for (i, (sky, block)) in data
+            .skylight_data
+            .data
+            .iter()
+            .zip(data.blocklight_data.data.iter())
+            .enumerate()
+        {
+            if sky.is_none() && block.is_none() {
+                continue;
+            }
+
+            if let Some(section) = self.get_section(self.min_section + i as i32 - 1) {
+                let mut section = section.write().unwrap();
+                let light = Arc::make_mut(
+                    section
+                        .light
+                        .get_or_insert_with(|| Arc::new(SectionLight::new())),
+                );
+                if let Some(sky) = sky {
+                    light.sky.copy_from_slice(&sky[..]);
+                }
+                if let Some(block) = block {
+                    light.block.copy_from_slice(&block[..]);
+                }
+            }
+        }
+    }
+
+    /// Returns the biome at the provided ChunkCoords, or None if the chunk was sent without biome
+    /// data or the coordinates are outside of the chunk
+    pub fn biome_at(&self, coords: &ChunkCoords) -> Option<BiomeId> {
+        let y = coords.y - self.min_section * 16;
+        if y < 0 {
+            return None;
+        }
+
+        self.biomes
+            .get(biome_pos_to_index(&IVec3::new(coords.x, y, coords.z)))
+            .copied()
+    }
+
+    /// Converts a section y index to an index into this chunk's section list, returns None if
+    /// the section lies outside of the chunk
+    fn section_to_index(&self, y: i32) -> Option<usize> {
+        let index: usize = (y - self.min_section).try_into().ok()?;
+        if index < self.sections.len() {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    /// Same as section_to_index, for callers that should only ever use sections inside the chunk.
+    /// Debug builds panic on sections outside the chunk rather than letting the call do nothing
+    fn checked_section_index(&self, y: i32) -> Option<usize> {
+        let index = self.section_to_index(y);
+        debug_assert!(
+            index.is_some(),
+            "Section {} is outside of the chunk, which has sections {} to {}",
+            y,
+            self.get_min_section(),
+            self.get_max_section()
+        );
+        index
     }
 }
 
-/// Extracts the heightmap from chunk data
-fn process_heightmap(data: &ChunkData) -> [u16; 256] {
+/// Extracts the heightmap from the heightmaps compound of chunk data
+fn process_heightmap(heightmaps: &nbt::Tag) -> [u16; 256] {
     let mut map = [0u16; 256];
 
-    if let nbt::Tag::Compound(heightmaps) = &data.heightmaps.root.payload {
+    if let nbt::Tag::Compound(heightmaps) = heightmaps {
         if heightmaps.len() != 2 {
             log::error!(
                 "Got unexpected number of heightmap compound elements, expected 2 got {}",
@@ -205,11 +693,11 @@ fn process_heightmap(data: &ChunkData) -> [u16; 256] {
                 }
 
                 let vals_per_long: usize = 7;
-                for i in 0..256usize {
-                    let long = 1 / vals_per_long;
+                for (i, height) in map.iter_mut().enumerate() {
+                    let long = i / vals_per_long;
                     let offset = (i % vals_per_long) * 9;
 
-                    map[i] = ((longs[long] >> offset) & 0x1ff) as u16;
+                    *height = ((longs[long] >> offset) & 0x1ff) as u16;
                 }
             }
         }
@@ -221,107 +709,280 @@ fn process_heightmap(data: &ChunkData) -> [u16; 256] {
     map
 }
 
-/// Builds a list of chunk sections from chunk data
+/// Extracts the biomes from chunk data, returns an empty list if the chunk has no biome data
+fn process_biomes(biomes: Option<&[VarInt]>, section_count: usize) -> Vec<BiomeId> {
+    match biomes {
+        Some(biomes) => {
+            let mut out: Vec<BiomeId> = biomes.iter().map(|b| b.0.max(0) as BiomeId).collect();
+            if out.len() != section_count * BIOMES_PER_SECTION {
+                log::warn!(
+                    "Got unexpected number of biomes, expected {} got {}",
+                    section_count * BIOMES_PER_SECTION,
+                    out.len()
+                );
+            }
+            out.resize(section_count * BIOMES_PER_SECTION, 0);
+            out
+        }
+        None => Vec::new(),
+    }
+}
+
+/// Reads a paletted biome container for a single chunk section, as sent after the block states
+/// of each section from 1.18 onwards. Unused until packets from 1.18 servers can be decoded
+#[allow(dead_code)]
+fn read_biome_container<R: Read>(r: &mut R) -> io::Result<[BiomeId; BIOMES_PER_SECTION]> {
+    let mut buf = [0u8; 1];
+    r.read_exact(&mut buf)?;
+    let bits_per_biome: u32 = buf[0].into();
+
+    let mut biomes = [0 as BiomeId; BIOMES_PER_SECTION];
+
+    // Single valued palette, every cell holds the same biome and the data array is empty
+    if bits_per_biome == 0 {
+        let biome = read_varint(r)?;
+        let array_len = read_varint(r)?;
+        for _ in 0..array_len {
+            r.read_exact(&mut [0u8; 8])?;
+        }
+        biomes.fill(biome.max(0) as BiomeId);
+        return Ok(biomes);
+    }
+
+    // Biomes use an indirect palette for up to 3 bits per entry
+    let palette = if bits_per_biome <= 3 {
+        let palette_len = read_varint(r)?;
+        let mut palette = Vec::new();
+        for _ in 0..palette_len {
+            palette.push(read_varint(r)?);
+        }
+        Some(palette)
+    } else {
+        None
+    };
+
+    let array_len = read_varint(r)?;
+    let mut array = Vec::new();
+    for _ in 0..array_len {
+        let mut buf = [0u8; 8];
+        r.read_exact(&mut buf)?;
+        array.push(i64::from_be_bytes(buf));
+    }
+
+    let mask = 2i64.pow(bits_per_biome) - 1;
+    let biomes_per_long = (64 / bits_per_biome) as usize;
+
+    for (i, biome) in biomes.iter_mut().enumerate() {
+        let long = array.get(i / biomes_per_long).ok_or_else(|| {
+            io::Error::new(ErrorKind::UnexpectedEof, "Biome data array too short")
+        })?;
+        let value = (long >> ((i % biomes_per_long) as u32 * bits_per_biome)) & mask;
+
+        *biome = match &palette {
+            Some(pal) => (*pal.get(value as usize).unwrap_or(&0)).max(0) as BiomeId,
+            None => value as BiomeId,
+        };
+    }
+
+    Ok(biomes)
+}
+
+/// Error decoding the chunk sections in a chunk data packet
+#[derive(Debug)]
+pub struct ChunkDecodeError {
+    /// The y index of the section that failed to decode
+    pub section: i32,
+    /// The offset into the chunk data where decoding failed
+    pub offset: u64,
+    pub source: io::Error,
+}
+
+impl std::fmt::Display for ChunkDecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Couldn't decode chunk section {} at byte {}: {}",
+            self.section, self.offset, self.source
+        )
+    }
+}
+
+impl std::error::Error for ChunkDecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Builds a list of chunk sections from chunk data, where only the sections in the primary bit
+/// mask are sent
 fn process_sections(
-    data: &ChunkData,
-) -> [Option<(Arc<RwLock<ChunkSection>>, Option<VertexBuffer<BlockVertex>>)>; 16] {
+    primary_bit_mask: i32,
+    data: &[u8],
+    min_section: i32,
+    section_count: usize,
+    packing: PackingMode,
+) -> Result<Vec<Option<ChunkSection>>, ChunkDecodeError> {
     // Check bit mask for which chunk sections are present
-    let mut chunk_sections_present = [false; SECTIONS_PER_CHUNK];
-    for i in 0..SECTIONS_PER_CHUNK {
-        if data.primary_bit_mask.0 & 0b1 << i != 0 {
-            chunk_sections_present[i] = true;
+    let mut chunk_sections_present = vec![false; section_count];
+    for (i, present) in chunk_sections_present.iter_mut().take(32).enumerate() {
+        if primary_bit_mask & 0b1 << i != 0 {
+            *present = true;
         }
     }
 
-    const INIT: Option<(Arc<RwLock<ChunkSection>>, Option<VertexBuffer<BlockVertex>>)> = None;
-    let mut sections = [INIT; SECTIONS_PER_CHUNK];
+    let mut sections = Vec::with_capacity(section_count);
+    sections.resize_with(section_count, || None);
 
     // Decode data array
-    let mut cur = Cursor::new(&*data.data);
-    for i in 0..SECTIONS_PER_CHUNK {
+    let mut cur = Cursor::new(data);
+    for i in 0..section_count {
         if !chunk_sections_present[i] {
             continue;
         }
 
-        let mut buf = [0u8; 2];
-        cur.read_exact(&mut buf).unwrap();
-        let block_count = i16::from_ne_bytes(buf);
+        let y = min_section + i as i32;
+        let (_, blocks) = read_section_blocks(&mut cur, packing).map_err(|e| ChunkDecodeError {
+            section: y,
+            offset: cur.position(),
+            source: e,
+        })?;
 
-        let mut buf = [0u8; 1];
-        cur.read_exact(&mut buf).unwrap();
-        let mut bits_per_block = buf[0].into();
+        sections[i] = Some(ChunkSection::new(y, blocks));
+    }
+    Ok(sections)
+}
 
-        if bits_per_block <= 4 {
-            bits_per_block = 4;
-        }
-        if bits_per_block >= 9 {
-            bits_per_block = MAX_BITS_PER_BLOCK;
+/// Reads the number of non-air blocks and the blocks of a single chunk section from chunk data
+fn read_section_blocks<R: Read>(
+    cur: &mut R,
+    packing: PackingMode,
+) -> io::Result<(i16, ChunkArray)> {
+    let mut buf = [0u8; 2];
+    cur.read_exact(&mut buf)?;
+    let block_count = i16::from_be_bytes(buf);
+
+    let mut buf = [0u8; 1];
+    cur.read_exact(&mut buf)?;
+    let mut bits_per_block = buf[0].into();
+
+    // Sections made of a single block have a single palette entry and no block data
+    if bits_per_block == 0 {
+        let value = read_varint(cur)?;
+        let array_len = read_varint(cur)?;
+        for _ in 0..array_len {
+            let mut buf = [0u8; 8];
+            cur.read_exact(&mut buf)?;
         }
+        return Ok((block_count, [value as u16; 4096]));
+    }
 
-        let palette: Option<Vec<i32>>;
+    if bits_per_block <= 4 {
+        bits_per_block = 4;
+    }
+    // Widths of 9 and above index the global palette directly using their actual width
+    if bits_per_block > MAX_BITS_PER_BLOCK {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Invalid bits per block: {}", bits_per_block),
+        ));
+    }
 
-        // Construct palette or no palette
-        if bits_per_block < 9 {
-            let palette_len = read_varint(&mut cur).unwrap();
-            log::debug!("Got chunk with pallete of {} elements.", palette_len);
-            let mut palette_vec: Vec<i32> = Vec::new();
+    // Construct palette or no palette
+    let palette: Option<Vec<i32>> = if bits_per_block < 9 {
+        let palette_len = read_varint(cur)?;
+        log::debug!("Got chunk with pallete of {} elements.", palette_len);
+        let mut palette_vec: Vec<i32> = Vec::new();
 
-            for _ in 0..palette_len as usize {
-                palette_vec.push(read_varint(&mut cur).unwrap());
-            }
-            palette = Some(palette_vec);
-        } else {
-            palette = None;
+        for _ in 0..palette_len as usize {
+            palette_vec.push(read_varint(cur)?);
         }
+        Some(palette_vec)
+    } else {
+        None
+    };
 
-        // Get long array of blocks
-        let array_len = read_varint(&mut cur).unwrap();
-        let mut array = Vec::new();
-        for _ in 0..array_len as usize {
-            let mut buf = [0u8; 8];
-            cur.read_exact(&mut buf).unwrap();
-            array.push(i64::from_be_bytes(buf));
+    // Get long array of blocks
+    let array_len = read_varint(cur)?;
+    let mut array = Vec::new();
+    for _ in 0..array_len as usize {
+        let mut buf = [0u8; 8];
+        cur.read_exact(&mut buf)?;
+        array.push(i64::from_be_bytes(buf));
+    }
+
+    let mut blocks = [0u16; 4096];
+
+    // Extract blocks
+    for j in 0..4096 {
+        // Get block id / palette index from long array
+        let block = extract_packed(&array, j, bits_per_block, packing).ok_or_else(|| {
+            io::Error::new(ErrorKind::UnexpectedEof, "Block data array too short")
+        })?;
+
+        // Get block from palette
+        match &palette {
+            Some(pal) => {
+                blocks[j as usize] = *pal.get(block as usize).ok_or_else(|| {
+                    io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Palette index {} out of bounds", block),
+                    )
+                })? as u16;
+            }
+            None => {
+                blocks[j as usize] = block as u16;
+            }
         }
+    }
 
-        // Bit mask depending on bits per block
-        let mask = 2i64.pow(bits_per_block) - 1;
-        let blocks_per_long = 64 / bits_per_block;
+    Ok((block_count, blocks))
+}
 
-        let mut blocks = [0u16; 4096];
+/// Converts a block position to an index within a chunk section array
+pub fn block_pos_to_index(pos: &IVec3) -> usize {
+    ((pos.y.rem_euclid(16)) * 16 * 16 + pos.z.rem_euclid(16) * 16 + pos.x.rem_euclid(16)) as usize
+}
 
-        // Extract blocks
-        for j in 0..4096 {
-            let long = j / blocks_per_long;
-            let start = (j % blocks_per_long) * bits_per_block;
+/// Reads the `index`th value of `bits` bits from a long array packed with the given mode
+fn extract_packed(array: &[i64], index: u32, bits: u32, packing: PackingMode) -> Option<u64> {
+    let mask = (1u64 << bits) - 1;
 
-            // Get block id / palette index from long
-            let block = (array[long as usize] >> start) & mask;
+    match packing {
+        PackingMode::Padded => {
+            let values_per_long = 64 / bits;
+            let long = *array.get((index / values_per_long) as usize)? as u64;
+            let start = (index % values_per_long) * bits;
+            Some((long >> start) & mask)
+        }
+        PackingMode::Tight => {
+            let bit = index as usize * bits as usize;
+            let start = (bit % 64) as u32;
+            let mut value = *array.get(bit / 64)? as u64 >> start;
 
-            // Get block from palette
-            match &palette {
-                Some(pal) => {
-                    blocks[j as usize] = pal[block as usize] as u16;
-                }
-                None => {
-                    blocks[j as usize] = block as u16;
-                }
+            // Value continues into the low bits of the next long
+            if start + bits > 64 {
+                value |= (*array.get(bit / 64 + 1)? as u64) << (64 - start);
             }
+            Some(value & mask)
         }
+    }
+}
 
-        sections[i] = Some((
-            Arc::new(RwLock::new(ChunkSection {
-                y: i as i32,
-                blocks,
-            })),
-            None,
-        ));
+/// Reads the 4 bit value at the index provided from a nibble array, where even indices are stored
+/// in the low bits of each byte
+pub fn get_nibble(array: &[u8], i: usize) -> u8 {
+    let byte = array[i / 2];
+    if i % 2 == 0 {
+        byte & 0xf
+    } else {
+        byte >> 4
     }
-    sections
 }
 
-/// Converts a block position to an index within a chunk section array
-pub fn block_pos_to_index(pos: &IVec3) -> usize {
-    ((pos.y.rem_euclid(16)) * 16 * 16 + pos.z.rem_euclid(16) * 16 + pos.x.rem_euclid(16)) as usize
+/// Converts a block position within a chunk (relative to the bottom of the chunk) to an index
+/// within the chunk's biome array
+pub fn biome_pos_to_index(pos: &IVec3) -> usize {
+    ((pos.y >> 2) * 16 + (pos.z.rem_euclid(16) >> 2) * 4 + (pos.x.rem_euclid(16) >> 2)) as usize
 }
 
 /// Converts an index within a chunk section array to a 3d block pos
@@ -333,6 +994,487 @@ pub fn block_index_to_pos(i: usize) -> IVec3 {
     IVec3::new(x as i32, y as i32, z as i32)
 }
 
-fn section_to_index(loc: i32) -> usize {
-    loc.try_into().unwrap()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::write_varint;
+
+    /// Packs values into longs without letting them span two longs, as 1.16+ does
+    fn pack_padded(values: &[u64], bits: u32) -> Vec<i64> {
+        let per_long = (64 / bits) as usize;
+        values
+            .chunks(per_long)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0u64, |long, (i, v)| long | v << (i as u32 * bits)) as i64
+            })
+            .collect()
+    }
+
+    /// Packs values end to end so they can span two longs, as 1.15 and earlier do
+    fn pack_tight(values: &[u64], bits: u32) -> Vec<i64> {
+        let mut longs = vec![0u64; (values.len() * bits as usize + 63) / 64];
+        for (i, v) in values.iter().enumerate() {
+            let bit = i * bits as usize;
+            let start = (bit % 64) as u32;
+            longs[bit / 64] |= v << start;
+            if start + bits > 64 {
+                longs[bit / 64 + 1] |= v >> (64 - start);
+            }
+        }
+        longs.into_iter().map(|l| l as i64).collect()
+    }
+
+    fn heightmaps(motion_blocking: Vec<i64>) -> nbt::Tag {
+        nbt::Tag::Compound(vec![
+            nbt::NamedTag {
+                name: String::from("MOTION_BLOCKING"),
+                payload: nbt::Tag::LongArray(motion_blocking),
+            },
+            nbt::NamedTag {
+                name: String::from("WORLD_SURFACE"),
+                payload: nbt::Tag::LongArray(vec![0; 37]),
+            },
+        ])
+    }
+
+    /// Decoded data of a chunk with no blocks in it, spanning `section_count` sections from
+    /// `min_section`
+    fn empty_decoded_chunk(min_section: i32, section_count: usize) -> DecodedChunk {
+        DecodedChunk {
+            pos: IVec2::ZERO,
+            heightmap: [0; 256],
+            biomes: Vec::new(),
+            min_section,
+            sections: (0..section_count).map(|_| None).collect(),
+        }
+    }
+
+    fn empty_chunk(min_section: i32, section_count: usize) -> Chunk {
+        Chunk::from(empty_decoded_chunk(min_section, section_count))
+    }
+
+    #[test]
+    fn extended_height_section_indices() {
+        // The overworld from 1.18, y = -64 to 319
+        let chunk = empty_chunk(-4, 24);
+        assert_eq!(chunk.get_min_section(), -4);
+        assert_eq!(chunk.get_max_section(), 19);
+
+        assert_eq!(chunk.section_index_containing(-64), Some(0));
+        assert_eq!(chunk.section_index_containing(-1), Some(3));
+        assert_eq!(chunk.section_index_containing(0), Some(4));
+        assert_eq!(chunk.section_index_containing(319), Some(23));
+
+        assert_eq!(chunk.section_index_containing(-65), None);
+        assert_eq!(chunk.section_index_containing(320), None);
+    }
+
+    #[test]
+    fn out_of_world_heights_have_no_section() {
+        // A pre 1.18 world, y = 0 to 255
+        let mut chunk = empty_chunk(0, 16);
+        let stone = resources::state_id("Stone", &[]) as u16;
+        chunk.set_block(&IVec3::new(1, 100, 2), stone);
+
+        assert_eq!(chunk.section_index_containing(-1), None);
+        assert_eq!(chunk.section_index_containing(256), None);
+        assert!(chunk.get_section_containing(-1).is_none());
+        assert!(chunk.get_section_containing(256).is_none());
+        assert!(chunk.block_at(&IVec3::new(1, -1, 2)).is_none());
+        assert!(chunk.block_at(&IVec3::new(1, 256, 2)).is_none());
+
+        assert_eq!(chunk.section_index_containing(0), Some(0));
+        assert_eq!(chunk.section_index_containing(100), Some(6));
+        assert_eq!(chunk.section_index_containing(255), Some(15));
+        assert!(chunk.get_section_containing(100).is_some());
+        assert_eq!(
+            chunk.block_at(&IVec3::new(1, 100, 2)).unwrap().name,
+            "Stone"
+        );
+        assert!(chunk.block_at(&IVec3::new(1, 255, 2)).unwrap().is_air());
+    }
+
+    #[test]
+    fn section_buffers_in_range() {
+        let mut chunk = empty_chunk(-4, 24);
+        chunk.set_block(&IVec3::new(0, -64, 0), 1);
+
+        // Sections that haven't been meshed or are empty have no buffers
+        for y in [-4, 0, 19] {
+            assert!(chunk.get_section_opaque(y).is_none());
+            assert!(chunk.get_section_translucent(y).is_none());
+        }
+        assert!(chunk.get_section(-4).is_some());
+        assert!(chunk.get_section(0).is_none());
+
+        // Neighbour lookups go past the top and bottom of the chunk, so those aren't mistakes
+        assert!(chunk.get_section(-5).is_none());
+        assert!(chunk.get_section(20).is_none());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "outside of the chunk")]
+    fn section_buffers_above_chunk() {
+        empty_chunk(-4, 24).get_section_opaque(20);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "outside of the chunk")]
+    fn section_buffers_below_chunk() {
+        empty_chunk(-4, 24).get_section_translucent(-5);
+    }
+
+    /// A chunk with no blocks in it and the biomes given in the 1.16 chunk data format
+    fn chunk_with_biomes(biomes: &[i32]) -> Chunk {
+        let biomes: Vec<VarInt> = biomes.iter().map(|b| VarInt(*b)).collect();
+        Chunk::from(DecodedChunk {
+            biomes: process_biomes(Some(biomes.as_slice()), 16),
+            ..empty_decoded_chunk(0, 16)
+        })
+    }
+
+    #[test]
+    fn single_biome_chunk() {
+        let chunk = chunk_with_biomes(&[1; 1024]);
+        for coords in [
+            IVec3::new(0, 0, 0),
+            IVec3::new(15, 255, 15),
+            IVec3::new(7, 64, 3),
+        ] {
+            assert_eq!(chunk.biome_at(&coords), Some(1));
+        }
+    }
+
+    #[test]
+    fn multi_biome_chunk() {
+        // Biome ids above 255 have to survive too
+        let biomes: Vec<i32> = (0..1024).map(|i| i % 7 + 250).collect();
+        let chunk = chunk_with_biomes(&biomes);
+
+        // Each biome covers a 4x4x4 cell, ordered by y then z then x
+        assert_eq!(chunk.biome_at(&IVec3::new(0, 0, 0)), Some(250));
+        assert_eq!(chunk.biome_at(&IVec3::new(3, 3, 3)), Some(250));
+        assert_eq!(chunk.biome_at(&IVec3::new(4, 0, 0)), Some(251));
+        // Cell (1, 4, 2) is 4 * 16 + 2 * 4 + 1 = 73
+        assert_eq!(chunk.biome_at(&IVec3::new(5, 17, 9)), Some(73 % 7 + 250));
+        assert_eq!(
+            chunk.biome_at(&IVec3::new(15, 255, 15)),
+            Some(1023 % 7 + 250)
+        );
+    }
+
+    #[test]
+    fn chunk_without_biomes() {
+        let chunk = Chunk::from(empty_decoded_chunk(0, 16));
+        assert_eq!(chunk.biome_at(&IVec3::new(0, 0, 0)), None);
+    }
+
+    #[test]
+    fn single_valued_biome_container() {
+        let mut data = vec![0u8];
+        write_varint(&mut data, 300).unwrap();
+        write_varint(&mut data, 0).unwrap();
+
+        let biomes = read_biome_container(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(biomes, [300; BIOMES_PER_SECTION]);
+    }
+
+    #[test]
+    fn paletted_biome_container() {
+        let palette = [3, 40, 500];
+        let indices: Vec<u64> = (0..BIOMES_PER_SECTION as u64).map(|i| i % 3).collect();
+
+        let mut data = vec![2u8];
+        write_varint(&mut data, palette.len() as i32).unwrap();
+        for biome in palette {
+            write_varint(&mut data, biome).unwrap();
+        }
+        let longs = pack_padded(&indices, 2);
+        write_varint(&mut data, longs.len() as i32).unwrap();
+        for long in longs {
+            data.extend_from_slice(&long.to_be_bytes());
+        }
+
+        let biomes = read_biome_container(&mut Cursor::new(&data)).unwrap();
+        for (i, biome) in biomes.iter().enumerate() {
+            assert_eq!(*biome, palette[i % 3] as BiomeId);
+        }
+    }
+
+    #[test]
+    fn nibbles_are_low_bits_first() {
+        let array = [0x21, 0xf0, 0x7a];
+        let nibbles: Vec<u8> = (0..6).map(|i| get_nibble(&array, i)).collect();
+        assert_eq!(nibbles, [1, 2, 0, 15, 10, 7]);
+    }
+
+    #[test]
+    fn section_light_levels() {
+        let mut light = SectionLight::new();
+        // Block 0 has sky 3, block 1 sky 12
+        light.sky[0] = 0xc3;
+        // Block 256 is at y = 1, with block light 14
+        light.block[128] = 0x0e;
+
+        assert_eq!(light.light_at(&IVec3::new(0, 0, 0)), (3, 0));
+        assert_eq!(light.light_at(&IVec3::new(1, 0, 0)), (12, 0));
+        assert_eq!(light.light_at(&IVec3::new(0, 1, 0)), (15, 14));
+        assert_eq!(light.light_at(&IVec3::new(15, 15, 15)), (15, 0));
+    }
+
+    #[test]
+    fn unlit_section_is_lit_by_sky() {
+        let section = ChunkSection::new(0, [0; 4096]);
+        assert_eq!(section.light_at(&IVec3::new(4, 4, 4)), (15, 0));
+    }
+
+    #[test]
+    fn snapshot_unaffected_by_set_block() {
+        let stone = resources::state_id("Stone", &[]) as u16;
+        let dirt = resources::state_id("Dirt", &[]) as u16;
+        let pos = IVec3::new(4, 5, 6);
+        let section: WrappedChunkSection =
+            Arc::new(RwLock::new(ChunkSection::new(2, [stone; 4096])));
+
+        let snapshot = section.read().unwrap().snapshot();
+        // Nothing is copied until the section changes
+        assert!(Arc::ptr_eq(
+            &snapshot.blocks,
+            &section.read().unwrap().blocks
+        ));
+
+        section.write().unwrap().set_block(&pos, dirt);
+        assert_eq!(section.read().unwrap().block_id_at(&pos), dirt);
+        assert_eq!(snapshot.block_id_at(&pos), stone);
+        assert_eq!(snapshot.y, 2);
+        assert_eq!(snapshot.iter_blocks().count(), 4096);
+    }
+
+    #[test]
+    fn iter_blocks_skips_air() {
+        let stone = resources::state_id("Stone", &[]) as u16;
+        let cave_air = resources::state_id("Cave Air", &[]) as u16;
+        let mut blocks = [cave_air; 4096];
+        blocks[0] = stone;
+        blocks[block_pos_to_index(&IVec3::new(3, 9, 14))] = stone;
+
+        let section = ChunkSection::new(0, blocks);
+        let found: Vec<(IVec3, u32)> = section.iter_blocks().map(|(p, b)| (p, b.id)).collect();
+        assert_eq!(
+            found,
+            vec![
+                (IVec3::ZERO, stone as u32),
+                (IVec3::new(3, 9, 14), stone as u32)
+            ]
+        );
+        assert_eq!(ChunkSection::new(0, [0; 4096]).iter_blocks().count(), 0);
+    }
+
+    #[test]
+    fn set_block_reads_back() {
+        let stone = resources::state_id("Stone", &[]) as u16;
+        let mut chunk = empty_chunk(-4, 24);
+
+        // Creates the empty section at the bottom of the world
+        let pos = IVec3::new(5, -60, 11);
+        chunk.set_block(&pos, stone);
+        assert_eq!(chunk.block_at(&pos).map(|b| b.id), Some(stone as u32));
+        assert!(chunk.is_section_present(-4));
+        assert_eq!(chunk.get_highest_block(IVec2::new(5, 11)), -59);
+
+        // Removing it again leaves the column empty
+        chunk.set_block(&pos, 0);
+        assert!(chunk.block_at(&pos).unwrap().is_air());
+        assert_eq!(chunk.get_highest_block(IVec2::new(5, 11)), -64);
+
+        // Outside of the chunk is ignored
+        chunk.set_block(&IVec3::new(0, 320, 0), stone);
+        assert!(chunk.block_at(&IVec3::new(0, 320, 0)).is_none());
+    }
+
+    #[test]
+    fn heightmap_follows_top_of_column() {
+        let stone = resources::state_id("Stone", &[]) as u16;
+        let mut chunk = empty_chunk(-4, 24);
+        let column = IVec2::new(2, 13);
+        let at = |y| IVec3::new(2, y, 13);
+
+        chunk.set_block(&at(60), stone);
+        chunk.set_block(&at(64), stone);
+        assert_eq!(chunk.get_highest_block(column), 65);
+
+        // Placing below the top or removing a block under it doesn't change the height
+        chunk.set_block(&at(62), stone);
+        chunk.set_block(&at(62), 0);
+        assert_eq!(chunk.get_highest_block(column), 65);
+
+        // Removing the top block finds the next one down, in a section further down too
+        chunk.set_block(&at(64), 0);
+        assert_eq!(chunk.get_highest_block(column), 61);
+        chunk.set_block(&at(-30), stone);
+        chunk.set_block(&at(60), 0);
+        assert_eq!(chunk.get_highest_block(column), -29);
+
+        // Other columns are left alone
+        assert_eq!(chunk.get_highest_block(IVec2::new(3, 13)), -64);
+
+        chunk.recompute_column_height(2, 13);
+        assert_eq!(chunk.get_highest_block(column), -29);
+    }
+
+    #[test]
+    fn flat_grass_is_uniformly_grass_coloured() {
+        let grass = resources::state_id("Grass Block", &[]) as u16;
+        let dirt = resources::state_id("Dirt", &[]) as u16;
+        let grass_colour = [127, 178, 56, 255];
+        let mut chunk = empty_chunk(0, 16);
+        assert_eq!(chunk.top_colours(), [[map_colours::EMPTY; 16]; 16]);
+
+        for x in 0..16 {
+            for z in 0..16 {
+                chunk.set_block(&IVec3::new(x, 62, z), dirt);
+                chunk.set_block(&IVec3::new(x, 63, z), grass);
+            }
+        }
+        assert_eq!(chunk.top_colours(), [[grass_colour; 16]; 16]);
+
+        // Covering a column changes its colour, and uncovering it changes it back
+        let stone = resources::state_id("Stone", &[]) as u16;
+        chunk.set_block(&IVec3::new(4, 64, 9), stone);
+        let colours = chunk.top_colours();
+        assert_ne!(colours[9][4], grass_colour);
+        assert_eq!(colours[4][9], grass_colour);
+
+        chunk.set_block(&IVec3::new(4, 64, 9), 0);
+        assert_eq!(chunk.top_colours(), [[grass_colour; 16]; 16]);
+    }
+
+    /// Encodes a chunk section the way it's sent in chunk data
+    fn encode_section(bits: u8, palette: Option<&[i32]>, longs: &[i64]) -> Vec<u8> {
+        let mut data = 4096i16.to_be_bytes().to_vec();
+        data.push(bits);
+        match palette {
+            // A single valued palette has no length
+            Some(palette) if bits == 0 => write_varint(&mut data, palette[0]).unwrap(),
+            Some(palette) => {
+                write_varint(&mut data, palette.len() as i32).unwrap();
+                for id in palette {
+                    write_varint(&mut data, *id).unwrap();
+                }
+            }
+            None => {}
+        }
+        write_varint(&mut data, longs.len() as i32).unwrap();
+        for long in longs {
+            data.extend_from_slice(&long.to_be_bytes());
+        }
+        data
+    }
+
+    /// Decodes the blocks of a single section at the bottom of a chunk
+    fn decode_blocks(data: &[u8], packing: PackingMode) -> ChunkArray {
+        let sections = process_sections(1, data, 0, 16, packing).unwrap();
+        sections[0].as_ref().unwrap().blocks.to_array()
+    }
+
+    #[test]
+    fn single_valued_section() {
+        let section = encode_section(0, Some(&[9]), &[]);
+        assert_eq!(decode_blocks(&section, PackingMode::Padded), [9; 4096]);
+    }
+
+    #[test]
+    fn paletted_section_of_5_bits() {
+        let palette: Vec<i32> = (0..32).map(|i| i * 3 + 100).collect();
+        let indices: Vec<u64> = (0..4096).map(|i| (i * 7) % 32).collect();
+        let section = encode_section(5, Some(&palette), &pack_padded(&indices, 5));
+
+        let blocks = decode_blocks(&section, PackingMode::Padded);
+        for (block, index) in blocks.iter().zip(&indices) {
+            assert_eq!(*block as i32, palette[*index as usize]);
+        }
+    }
+
+    #[test]
+    fn direct_sections_use_their_own_width() {
+        for bits in [9, 13] {
+            let ids: Vec<u64> = (0..4096).map(|i| (i * 37) % (1 << bits)).collect();
+            let section = encode_section(bits as u8, None, &pack_padded(&ids, bits));
+
+            let blocks = decode_blocks(&section, PackingMode::Padded);
+            let blocks: Vec<u64> = blocks.iter().map(|b| *b as u64).collect();
+            assert_eq!(blocks, ids, "{} bits per block", bits);
+        }
+    }
+
+    #[test]
+    fn padded_and_tight_packing_decode_the_same_blocks() {
+        // 5 and 13 bits don't divide 64, so tightly packed values span two longs
+        for bits in [4, 5, 13] {
+            let palette: Vec<i32> = (0..1 << bits.min(8)).collect();
+            let palette = (bits < 9).then_some(palette.as_slice());
+            let values: Vec<u64> = (0..4096).map(|i| (i * 37) % (1 << bits.min(8))).collect();
+
+            let padded = encode_section(bits as u8, palette, &pack_padded(&values, bits));
+            let tight = encode_section(bits as u8, palette, &pack_tight(&values, bits));
+
+            let padded = decode_blocks(&padded, PackingMode::Padded);
+            let tight = decode_blocks(&tight, PackingMode::Tight);
+            assert_eq!(padded, tight, "{} bits per block", bits);
+            assert!(padded.iter().zip(&values).all(|(b, v)| *b as u64 == *v));
+        }
+    }
+
+    #[test]
+    fn truncated_section_is_an_error() {
+        let indices: Vec<u64> = (0..4096).map(|i| i % 2).collect();
+        let section = encode_section(4, Some(&[1, 2]), &pack_padded(&indices, 4));
+
+        // Cut off in the palette, in the length of the data array and part way through it
+        for len in [0, 4, 6, 100, section.len() - 1] {
+            let result = process_sections(1, &section[..len], 0, 16, PackingMode::Padded);
+            match result {
+                Err(e) => assert_eq!(e.section, 0),
+                Ok(_) => panic!("Decoded section cut off at {} bytes", len),
+            }
+        }
+
+        assert!(process_sections(1, &section, 0, 16, PackingMode::Padded).is_ok());
+    }
+
+    #[test]
+    fn truncated_second_section_reports_its_height() {
+        let section = encode_section(0, Some(&[1]), &[]);
+        let mut data = section.clone();
+        data.extend_from_slice(&section[..3]);
+
+        // Sections 2 and 5 of a chunk starting at section -4
+        let e = process_sections(0b100100, &data, -4, 24, PackingMode::Padded).unwrap_err();
+        assert_eq!(e.section, 1);
+        assert_eq!(e.offset as usize, data.len());
+    }
+
+    #[test]
+    fn heightmap_decodes_every_column() {
+        // Different in every column and using all 9 bits, so a value read from the wrong long or
+        // offset shows up
+        let heights: Vec<u64> = (0..256).map(|i| (i * 7 + 300) % 512).collect();
+        let longs = pack_padded(&heights, 9);
+        assert_eq!(longs.len(), 37);
+
+        let map = process_heightmap(&heightmaps(longs));
+        for (i, height) in heights.iter().enumerate() {
+            assert_eq!(map[i] as u64, *height, "column {}", i);
+        }
+    }
+
+    #[test]
+    fn heightmap_missing_compound_is_flat() {
+        assert_eq!(process_heightmap(&nbt::Tag::Int(0)), [0; 256]);
+    }
 }
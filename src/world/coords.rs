@@ -0,0 +1,131 @@
+// Conversions between the coordinate spaces blocks are addressed in:
+//
+// * `WorldCoords` - Position of a block in the world
+// * `ChunkCoords` - Position within a chunk, x and z are 0 to 15 and y is the same as the world
+// * `SectionCoords` - Position within a chunk section, every axis is 0 to 15
+//
+// Negative world coordinates round down to the chunk or section below, so x = -1 is the last
+// block of chunk -1 rather than a block in chunk 0.
+
+use glam::{IVec2, IVec3};
+
+use super::{ChunkCoords, ChunkLocation, SectionCoords, SectionLocation, WorldCoords};
+
+/// Converts the coordinates of a block in the world to coordinates within the chunk containing it
+pub fn world_to_chunk(coords: &WorldCoords) -> ChunkCoords {
+    IVec3::new(coords.x.rem_euclid(16), coords.y, coords.z.rem_euclid(16))
+}
+
+/// Converts the coordinates of a block within a chunk to coordinates in the world
+///
+/// # Arguments
+///
+/// * `chunk` - Location of the chunk the block is in
+/// * `coords` - Coordinates within the chunk, x and z should be from 0 to 15. Only checked in
+/// debug builds, world coordinates passed by mistake end up in a different chunk
+pub fn chunk_to_world(chunk: &ChunkLocation, coords: &ChunkCoords) -> WorldCoords {
+    debug_assert!(
+        (0..16).contains(&coords.x) && (0..16).contains(&coords.z),
+        "Chunk coordinates out of range: {}",
+        coords
+    );
+    IVec3::new(chunk.x * 16 + coords.x, coords.y, chunk.y * 16 + coords.z)
+}
+
+/// Converts the coordinates of a block within a chunk to coordinates within its section
+pub fn chunk_to_section(coords: &ChunkCoords) -> SectionCoords {
+    IVec3::new(coords.x, coords.y.rem_euclid(16), coords.z)
+}
+
+/// Converts the coordinates of a block within a section to coordinates within its chunk
+///
+/// # Arguments
+///
+/// * `section_y` - Index of the section in the chunk, negative for sections below y = 0
+/// * `coords` - Coordinates within the section, y should be from 0 to 15. Only checked in debug
+/// builds
+pub fn section_to_chunk(section_y: i32, coords: &SectionCoords) -> ChunkCoords {
+    debug_assert!(
+        (0..16).contains(&coords.y),
+        "Section coordinates out of range: {}",
+        coords
+    );
+    IVec3::new(coords.x, section_y * 16 + coords.y, coords.z)
+}
+
+/// Converts the coordinates of a block in the world to coordinates within its section
+pub fn world_to_section(coords: &WorldCoords) -> SectionCoords {
+    chunk_to_section(&world_to_chunk(coords))
+}
+
+/// Returns the location of the chunk containing a block
+pub fn chunk_containing(coords: &WorldCoords) -> ChunkLocation {
+    IVec2::new(coords.x.div_floor(16), coords.z.div_floor(16))
+}
+
+/// Returns the index of the section containing a y level
+pub fn section_containing_height(y: i32) -> i32 {
+    y.div_floor(16)
+}
+
+/// Returns the location of the section containing a block
+pub fn section_containing(coords: &WorldCoords) -> SectionLocation {
+    IVec3::new(
+        coords.x.div_floor(16),
+        section_containing_height(coords.y),
+        coords.z.div_floor(16),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn world_to_chunk_at_edges() {
+        // (world x, chunk, x in chunk)
+        for (x, chunk, local) in [
+            (-1, -1, 15),
+            (-16, -1, 0),
+            (-17, -2, 15),
+            (0, 0, 0),
+            (15, 0, 15),
+            (16, 1, 0),
+        ] {
+            let world = IVec3::new(x, 70, x);
+            assert_eq!(chunk_containing(&world), IVec2::new(chunk, chunk));
+            assert_eq!(world_to_chunk(&world), IVec3::new(local, 70, local));
+            assert_eq!(
+                chunk_to_world(&chunk_containing(&world), &world_to_chunk(&world)),
+                world
+            );
+        }
+    }
+
+    #[test]
+    fn sections_at_edges() {
+        for (y, section, local) in [
+            (-1, -1, 15),
+            (-16, -1, 0),
+            (-64, -4, 0),
+            (15, 0, 15),
+            (16, 1, 0),
+        ] {
+            let world = IVec3::new(-1, y, 15);
+            assert_eq!(section_containing_height(y), section);
+            assert_eq!(section_containing(&world), IVec3::new(-1, section, 0));
+            assert_eq!(world_to_section(&world), IVec3::new(15, local, 15));
+
+            let chunk = world_to_chunk(&world);
+            assert_eq!(chunk_to_section(&chunk), world_to_section(&world));
+            assert_eq!(section_to_chunk(section, &chunk_to_section(&chunk)), chunk);
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Chunk coordinates out of range")]
+    fn world_coords_passed_as_chunk_coords() {
+        chunk_to_world(&IVec2::new(-1, 0), &IVec3::new(-1, 64, 0));
+    }
+}
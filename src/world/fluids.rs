@@ -0,0 +1,173 @@
+use glam::IVec3;
+
+use crate::resources::{BlockState, RenderLayer};
+
+// Levels from this up are fluid falling down, which fills the whole block
+const FALLING: u8 = 8;
+
+/// Liquids that flow between blocks, drawn lower the further they are from their source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fluid {
+    Water,
+    Lava,
+}
+
+impl Fluid {
+    /// Returns the fluid in a block and its level, 0 for a source and 1 to 7 for fluid flowing
+    /// away from it. Waterlogged blocks hold a water source
+    pub fn from_block(block: &BlockState) -> Option<(Fluid, u8)> {
+        let fluid = match block.name.as_str() {
+            "Water" => Fluid::Water,
+            "Lava" => Fluid::Lava,
+            _ => {
+                return match block.property("waterlogged") {
+                    Some("true") => Some((Fluid::Water, 0)),
+                    _ => None,
+                }
+            }
+        };
+        let level = block
+            .property("level")
+            .and_then(|l| l.parse().ok())
+            .unwrap_or(0);
+        Some((fluid, level))
+    }
+
+    /// Returns true if a block is nothing but fluid, rather than a block with water in it
+    pub fn is_fluid_block(block: &BlockState) -> bool {
+        matches!(block.name.as_str(), "Water" | "Lava")
+    }
+
+    /// Returns the name of the texture the fluid is drawn with
+    pub fn texture(&self) -> &'static str {
+        match self {
+            Fluid::Water => "minecraft:block/water_still",
+            Fluid::Lava => "minecraft:block/lava_still",
+        }
+    }
+
+    /// Returns how the faces of the fluid are drawn, water can be seen through but lava can't
+    pub fn render_layer(&self) -> RenderLayer {
+        match self {
+            Fluid::Water => RenderLayer::Translucent,
+            Fluid::Lava => RenderLayer::Solid,
+        }
+    }
+}
+
+/// Returns how much of its block fluid of a level fills, from 0 to 1
+pub fn fluid_height(level: u8) -> f32 {
+    if level >= FALLING {
+        1.0
+    } else {
+        (8 - level) as f32 / 9.0
+    }
+}
+
+/// Returns the heights of the top corners of fluid in a block, ordered (0, 0), (1, 0), (0, 1) and
+/// (1, 1) on x and z. Each corner is the average height of the same fluid in the 4 blocks around
+/// it, so flowing fluid slopes down away from its source. Corners next to fluid with more of the
+/// same fluid above it are full height so they join up with the fluid falling into them
+///
+/// # Arguments
+///
+/// * `fluid` - Fluid in the block
+/// * `pos` - Position of the block
+/// * `fluid_at` - Returns the fluid and its level in the block at a position
+pub fn corner_heights(
+    fluid: Fluid,
+    pos: IVec3,
+    fluid_at: impl Fn(IVec3) -> Option<(Fluid, u8)>,
+) -> [f32; 4] {
+    let level_at = |pos: IVec3| match fluid_at(pos) {
+        Some((f, level)) if f == fluid => Some(level),
+        _ => None,
+    };
+
+    [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(cx, cz)| {
+        let mut total = 0.0;
+        let mut count = 0;
+        for dx in cx - 1..=cx {
+            for dz in cz - 1..=cz {
+                let column = pos + IVec3::new(dx, 0, dz);
+                if level_at(column + IVec3::Y).is_some() {
+                    return 1.0;
+                }
+                if let Some(level) = level_at(column) {
+                    total += fluid_height(level);
+                    count += 1;
+                }
+            }
+        }
+        // The block itself is always one of the 4
+        total / count.max(1) as f32
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::resources::{self, BLOCKS};
+
+    use super::*;
+
+    #[test]
+    fn source_next_to_level_7() {
+        // Source at the origin with flowing water at level 7 to its east
+        let fluid_at = |pos: IVec3| match (pos.x, pos.y, pos.z) {
+            (0, 0, 0) => Some((Fluid::Water, 0)),
+            (1, 0, 0) => Some((Fluid::Water, 7)),
+            _ => None,
+        };
+        let source = fluid_height(0);
+        assert_eq!(source, 8.0 / 9.0);
+
+        let heights = corner_heights(Fluid::Water, IVec3::ZERO, fluid_at);
+        // Corners shared with the flowing block are halfway between the two
+        assert_eq!(heights, [source, 0.5, source, 0.5]);
+
+        // Lava doesn't mix with the water around it
+        let lava_at = |pos: IVec3| {
+            if pos == IVec3::ZERO {
+                Some((Fluid::Lava, 0))
+            } else {
+                fluid_at(pos)
+            }
+        };
+        assert_eq!(
+            corner_heights(Fluid::Lava, IVec3::ZERO, lava_at),
+            [source; 4]
+        );
+    }
+
+    #[test]
+    fn fluid_above_fills_corners() {
+        let fluid_at = |pos: IVec3| match (pos.x, pos.y, pos.z) {
+            (0, 0, 0) => Some((Fluid::Water, 3)),
+            // Falling into the block to the north west
+            (-1, 0, -1) => Some((Fluid::Water, 8)),
+            (-1, 1, -1) => Some((Fluid::Water, 0)),
+            _ => None,
+        };
+        let heights = corner_heights(Fluid::Water, IVec3::ZERO, fluid_at);
+        assert_eq!(heights[0], 1.0);
+        assert_eq!(heights[3], fluid_height(3));
+    }
+
+    #[test]
+    fn fluids_from_block_states() {
+        let water = resources::block_state(resources::state_id("Water", &[("level", "5")]));
+        assert_eq!(Fluid::from_block(water), Some((Fluid::Water, 5)));
+        assert!(Fluid::is_fluid_block(water));
+
+        let stairs = BLOCKS
+            .values()
+            .find(|b| b.name == "Oak Stairs" && b.property("waterlogged") == Some("true"))
+            .unwrap();
+        assert_eq!(Fluid::from_block(stairs), Some((Fluid::Water, 0)));
+        assert!(!Fluid::is_fluid_block(stairs));
+
+        let stone = resources::block_state(resources::state_id("Stone", &[]));
+        assert_eq!(Fluid::from_block(stone), None);
+        assert_eq!(fluid_height(8), 1.0);
+    }
+}
@@ -0,0 +1,60 @@
+use crate::resources::BlockState;
+
+/// A colour with red, green, blue and alpha from 0 to 255
+pub type Rgba = [u8; 4];
+/// Colour of the top block of each column in a chunk, indexed by z then x
+pub type SurfaceColours = [[Rgba; 16]; 16];
+
+// Shown for columns with no blocks at all
+pub const EMPTY: Rgba = [0, 0, 0, 0];
+// Shown for blocks that don't match any of the names below
+const DEFAULT: Rgba = [112, 112, 112, 255];
+
+// Parts of block names and the colour a map shows them as, the first match is used so more
+// specific names have to come before general ones, e.g. "Grass Block" before "Grass"
+const COLOURS: &[(&str, Rgba)] = &[
+    ("Water", [63, 118, 228, 255]),
+    ("Kelp", [63, 118, 228, 255]),
+    ("Seagrass", [63, 118, 228, 255]),
+    ("Lava", [207, 92, 20, 255]),
+    ("Grass Block", [127, 178, 56, 255]),
+    ("Leaves", [0, 124, 0, 255]),
+    ("Grass", [127, 178, 56, 255]),
+    ("Fern", [127, 178, 56, 255]),
+    ("Vine", [0, 124, 0, 255]),
+    ("Lily Pad", [0, 124, 0, 255]),
+    ("Mycelium", [127, 63, 178, 255]),
+    ("Podzol", [129, 86, 49, 255]),
+    ("Sandstone", [247, 233, 163, 255]),
+    ("Red Sand", [216, 127, 51, 255]),
+    ("Sand", [247, 233, 163, 255]),
+    ("Snow", [255, 255, 255, 255]),
+    ("Ice", [160, 160, 255, 255]),
+    ("Clay", [164, 168, 184, 255]),
+    ("Terracotta", [209, 177, 161, 255]),
+    ("Farmland", [151, 109, 77, 255]),
+    ("Path", [151, 109, 77, 255]),
+    ("Dirt", [151, 109, 77, 255]),
+    ("Gravel", [112, 112, 112, 255]),
+    ("Log", [102, 76, 51, 255]),
+    ("Wood", [102, 76, 51, 255]),
+    ("Planks", [143, 119, 72, 255]),
+    ("Netherrack", [112, 2, 0, 255]),
+    ("Nether", [112, 2, 0, 255]),
+    ("End Stone", [247, 233, 163, 255]),
+    ("Obsidian", [25, 25, 25, 255]),
+    ("Deepslate", [100, 100, 100, 255]),
+];
+
+/// Returns the colour a block is shown as on a map, air is transparent
+pub fn block_colour(block: &BlockState) -> Rgba {
+    if block.is_air() {
+        return EMPTY;
+    }
+
+    COLOURS
+        .iter()
+        .find(|(name, _)| block.name.contains(name))
+        .map(|(_, colour)| *colour)
+        .unwrap_or(DEFAULT)
+}
@@ -0,0 +1,936 @@
+use glam::{IVec3, Vec2, Vec3};
+
+use crate::resources::{
+    block_models::{push_face, texture_index, BlockModel},
+    block_state, RenderLayer,
+};
+
+use super::{
+    chunks::{block_pos_to_index, ChunkSection, WrappedChunkSection},
+    fluids::{self, Fluid},
+};
+
+// Brightness of a vertex for each ambient occlusion level, 0 being the most occluded
+const AO_BRIGHTNESS: [f32; 4] = [0.45, 0.65, 0.82, 1.0];
+
+/// Vertex of a block face. Implements glium's Vertex in the renderer, so meshes can be built
+/// without touching the GPU
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BlockVertex {
+    pub position: [f32; 3],
+    pub tex_coords: [f32; 3],
+    // Multiplier for the colour of the texture, from ambient occlusion
+    pub brightness: f32,
+    // Sky and block light in 0 to 1, the sky light is scaled by the time of day when rendering
+    pub light: [f32; 2],
+}
+
+/// Ambient occlusion and smooth light of a corner of a face
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CornerShade {
+    // 0 when the corner is fully occluded up to 3 when nothing is blocking it
+    occlusion: u8,
+    // Average sky and block light of the blocks touching the corner, in 0 to 1
+    sky: f32,
+    block: f32,
+}
+
+/// Block id, texture and the shading of each corner of a visible face
+type MaskFace = (u16, f32, [CornerShade; 4]);
+
+/// A section and the 26 sections around it, indexed with `neighbour_index`
+pub type SectionNeighbourhood = [Option<WrappedChunkSection>; 27];
+
+/// Index into a SectionNeighbourhood of the section at an offset of -1 to 1 on each axis from the
+/// center section
+pub fn neighbour_index(offset: IVec3) -> usize {
+    ((offset.x + 1) + (offset.z + 1) * 3 + (offset.y + 1) * 9) as usize
+}
+
+/// Vertices of faces with the indices of the triangles they make up, so the corners shared by the
+/// two triangles of a quad are only stored once
+#[derive(Debug, Default)]
+pub struct IndexedMesh {
+    pub vertices: Vec<BlockVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl IndexedMesh {
+    /// Indexes a list of quads which are each 2 triangles of 3 vertices, giving 4 vertices and 6
+    /// indices per quad. The triangles keep their order, so quads are still split along the same
+    /// diagonal
+    pub fn from_quads(quads: &[BlockVertex]) -> IndexedMesh {
+        let mut mesh = IndexedMesh {
+            vertices: Vec::with_capacity(quads.len() / 6 * 4),
+            indices: Vec::with_capacity(quads.len()),
+        };
+
+        for quad in quads.chunks(6) {
+            let base = mesh.vertices.len();
+            for vert in quad {
+                let i = match mesh.vertices[base..].iter().position(|v| v == vert) {
+                    Some(i) => base + i,
+                    None => {
+                        mesh.vertices.push(*vert);
+                        mesh.vertices.len() - 1
+                    }
+                };
+                mesh.indices.push(i as u32);
+            }
+        }
+
+        mesh
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+}
+
+/// Mesh of a section, split so translucent faces can be drawn after everything else
+#[derive(Debug, Default)]
+pub struct SectionMesh {
+    // Solid and cut out faces
+    pub opaque: IndexedMesh,
+    pub translucent: IndexedMesh,
+}
+
+/// Quads of a section while it is being meshed, each quad is 6 vertices until they are indexed
+#[derive(Default)]
+struct SectionQuads {
+    opaque: Vec<BlockVertex>,
+    translucent: Vec<BlockVertex>,
+}
+
+impl SectionQuads {
+    /// Returns the vertex list faces in a render layer are added to
+    fn verts_for(&mut self, layer: RenderLayer) -> &mut Vec<BlockVertex> {
+        match layer {
+            RenderLayer::Solid | RenderLayer::Cutout => &mut self.opaque,
+            RenderLayer::Translucent => &mut self.translucent,
+        }
+    }
+
+    fn into_mesh(self) -> SectionMesh {
+        SectionMesh {
+            opaque: IndexedMesh::from_quads(&self.opaque),
+            translucent: IndexedMesh::from_quads(&self.translucent),
+        }
+    }
+}
+
+/// Generates the mesh of the center section of a neighbourhood. This only works with blocks and
+/// vertices so it can run on any thread, uploading the mesh to the GPU is left to the caller
+pub fn build_section_mesh(sections: &[Option<ChunkSection>]) -> SectionMesh {
+    let section = match &sections[neighbour_index(IVec3::ZERO)] {
+        Some(section) => section,
+        None => return SectionMesh::default(),
+    };
+
+    // Block id at a position relative to this section, looking into neighbouring sections
+    let block_id = |pos: IVec3| {
+        let offset = IVec3::new(
+            pos.x.div_floor(16),
+            pos.y.div_floor(16),
+            pos.z.div_floor(16),
+        );
+        let local = pos - offset * 16;
+        sections[neighbour_index(offset)]
+            .as_ref()
+            .map(|cs| cs.block_id_at(&local))
+            .unwrap_or(0)
+    };
+
+    // Sky and block light at a position relative to this section
+    let light_at = |pos: IVec3| {
+        let offset = IVec3::new(
+            pos.x.div_floor(16),
+            pos.y.div_floor(16),
+            pos.z.div_floor(16),
+        );
+        sections[neighbour_index(offset)]
+            .as_ref()
+            .map(|cs| cs.light_at(&(pos - offset * 16)))
+            .unwrap_or((15, 0))
+    };
+
+    // Fluid and its level at a position relative to this section
+    let fluid_at = |pos: IVec3| Fluid::from_block(block_state(block_id(pos).into()));
+
+    let mut mesh = SectionQuads::default();
+
+    // Full cube blocks are meshed together afterwards so their faces can be merged
+    let mut full_cubes: Vec<Option<&BlockModel>> = vec![None; 4096];
+
+    for (pos, block) in section.iter_blocks() {
+        // Waterlogged blocks have their water meshed as well as their model
+        if let Some((fluid, _)) = Fluid::from_block(block) {
+            mesh_fluid(fluid, pos, block_id, light_at, fluid_at, &mut mesh);
+        }
+        if Fluid::is_fluid_block(block) {
+            continue;
+        }
+
+        let model = match block.get_model() {
+            Some(model) => model,
+            None => continue,
+        };
+
+        if model.is_full_cube() {
+            full_cubes[block_pos_to_index(&pos)] = Some(model);
+            continue;
+        }
+
+        let b_above = block_id(pos + IVec3::Y);
+        let b_below = block_id(pos - IVec3::Y);
+        let b_north = block_id(pos - IVec3::Z);
+        let b_south = block_id(pos + IVec3::Z);
+        let b_east = block_id(pos + IVec3::X);
+        let b_west = block_id(pos - IVec3::X);
+
+        let verts = mesh.verts_for(block.render_layer());
+        let first_vert = verts.len();
+        for mut vert in model.generate_mesh(b_above, b_below, b_north, b_east, b_south, b_west) {
+            vert.position[0] += pos.x as f32;
+            vert.position[1] += pos.y as f32;
+            vert.position[2] += pos.z as f32;
+            verts.push(vert);
+        }
+
+        for quad in verts[first_vert..].chunks_exact_mut(6) {
+            match quad_normal(quad) {
+                Some(normal) => shade_quad(quad, |corner, center| {
+                    let mut shade = corner_shade(&block_id, &light_at, corner, center, normal);
+                    if !model.has_ambient_occlusion() {
+                        shade.occlusion = 3;
+                    }
+                    shade
+                }),
+                // Faces of rotated elements aren't lined up with the neighbouring blocks so
+                // are lit by the block itself
+                None => {
+                    let (sky, block) = light_at(pos);
+                    for vert in quad {
+                        vert.light = [sky as f32 / 15.0, block as f32 / 15.0];
+                    }
+                }
+            }
+        }
+    }
+
+    generate_greedy_faces(&full_cubes, block_id, light_at, &mut mesh);
+
+    mesh.into_mesh()
+}
+
+/// Meshes the fluid in a block with its top at the height of the fluid at each corner. Faces
+/// against the same fluid are left out, as are faces against opaque blocks other than the top
+/// which can be lower than the block above
+fn mesh_fluid(
+    fluid: Fluid,
+    pos: IVec3,
+    block_id: impl Fn(IVec3) -> u16,
+    light_at: impl Fn(IVec3) -> (u8, u8),
+    fluid_at: impl Fn(IVec3) -> Option<(Fluid, u8)>,
+    mesh: &mut SectionQuads,
+) {
+    let faces = [
+        ("up", IVec3::Y),
+        ("down", -IVec3::Y),
+        ("north", -IVec3::Z),
+        ("east", IVec3::X),
+        ("south", IVec3::Z),
+        ("west", -IVec3::X),
+    ];
+
+    let heights = fluids::corner_heights(fluid, pos, &fluid_at);
+    let texture = texture_index(fluid.texture());
+    let (sky, block) = light_at(pos);
+    let verts = mesh.verts_for(fluid.render_layer());
+
+    for (face, normal) in faces {
+        let next = pos + normal;
+        if matches!(fluid_at(next), Some((f, _)) if f == fluid) {
+            continue;
+        }
+        if face != "up" && is_opaque_id(block_id(next)) {
+            continue;
+        }
+
+        let first_vert = verts.len();
+        let from = pos.as_vec3();
+        push_face(
+            verts,
+            face,
+            from,
+            from + Vec3::ONE,
+            (Vec2::ZERO, Vec2::ONE),
+            texture,
+        );
+        for vert in &mut verts[first_vert..] {
+            // Lower the top corners to the height of the fluid
+            if vert.position[1] > from.y {
+                let x = (vert.position[0] - from.x) as usize;
+                let z = (vert.position[2] - from.z) as usize;
+                vert.position[1] = from.y + heights[z * 2 + x];
+            }
+            vert.light = [sky as f32 / 15.0, block as f32 / 15.0];
+        }
+    }
+}
+
+/// Meshes the faces of full cube blocks, merging neighbouring faces with the same block,
+/// texture, ambient occlusion and light into larger quads. The texture coordinates of merged quads
+/// extend past 1 so the texture repeats once per block
+fn generate_greedy_faces(
+    full_cubes: &[Option<&BlockModel>],
+    block_id: impl Fn(IVec3) -> u16,
+    light_at: impl Fn(IVec3) -> (u8, u8),
+    mesh: &mut SectionQuads,
+) {
+    // Face, normal, and the axes the texture's u and v run along
+    let faces = [
+        ("up", IVec3::Y, 0, 2),
+        ("down", -IVec3::Y, 0, 2),
+        ("north", -IVec3::Z, 0, 1),
+        ("east", IVec3::X, 2, 1),
+        ("south", IVec3::Z, 0, 1),
+        ("west", -IVec3::X, 2, 1),
+    ];
+
+    let render_layer = |id: u16| block_state(id.into()).render_layer();
+
+    for (face, normal, u_axis, v_axis) in faces {
+        let axis = 3 - u_axis - v_axis;
+
+        for d in 0..16 {
+            // Visible faces in this layer with the shading of their corners, indexed by [v][u]
+            let mut mask: [[Option<MaskFace>; 16]; 16] = [[None; 16]; 16];
+            for (v, row) in mask.iter_mut().enumerate() {
+                for (u, cell) in row.iter_mut().enumerate() {
+                    let mut pos = [0; 3];
+                    pos[axis] = d;
+                    pos[u_axis] = u as i32;
+                    pos[v_axis] = v as i32;
+                    let pos = IVec3::from(pos);
+
+                    let i = block_pos_to_index(&pos);
+                    let model = match full_cubes[i] {
+                        Some(m) => m,
+                        None => continue,
+                    };
+                    if is_opaque_id(block_id(pos + normal)) {
+                        continue;
+                    }
+                    let center = pos.as_vec3() + Vec3::splat(0.5) + normal.as_vec3() * 0.5;
+                    let mut tangent_u = IVec3::ZERO;
+                    let mut tangent_v = IVec3::ZERO;
+                    tangent_u[u_axis] = 1;
+                    tangent_v[v_axis] = 1;
+                    let shading = [(-1, -1), (1, -1), (-1, 1), (1, 1)].map(|(du, dv)| {
+                        let corner = center + (tangent_u * du + tangent_v * dv).as_vec3() * 0.5;
+                        corner_shade(&block_id, &light_at, corner, center, normal)
+                    });
+                    *cell = model
+                        .face_texture(face)
+                        .map(|t| (block_id(pos), t, shading));
+                }
+            }
+
+            for v in 0..16 {
+                let mut u = 0;
+                while u < 16 {
+                    let current = match mask[v][u] {
+                        Some(c) => c,
+                        None => {
+                            u += 1;
+                            continue;
+                        }
+                    };
+
+                    // Widen along u, then extend along v while the whole row matches
+                    let mut w = 1;
+                    while u + w < 16 && mask[v][u + w] == Some(current) {
+                        w += 1;
+                    }
+                    let mut h = 1;
+                    while v + h < 16 && mask[v + h][u..u + w].iter().all(|m| *m == Some(current)) {
+                        h += 1;
+                    }
+
+                    for row in mask.iter_mut().skip(v).take(h) {
+                        for m in row.iter_mut().skip(u).take(w) {
+                            *m = None;
+                        }
+                    }
+
+                    let mut from = [0.0; 3];
+                    let mut to = [0.0; 3];
+                    from[axis] = d as f32;
+                    to[axis] = d as f32 + 1.0;
+                    from[u_axis] = u as f32;
+                    to[u_axis] = (u + w) as f32;
+                    from[v_axis] = v as f32;
+                    to[v_axis] = (v + h) as f32;
+
+                    let verts = mesh.verts_for(render_layer(current.0));
+                    push_face(
+                        verts,
+                        face,
+                        Vec3::from(from),
+                        Vec3::from(to),
+                        (Vec2::ZERO, Vec2::new(w as f32, h as f32)),
+                        current.1,
+                    );
+                    let first_vert = verts.len() - 6;
+                    shade_quad(&mut verts[first_vert..], |corner, center| {
+                        corner_shade(&block_id, &light_at, corner, center, normal)
+                    });
+
+                    u += w;
+                }
+            }
+        }
+    }
+}
+
+/// Returns the direction a quad faces from the winding of its first triangle, None if it doesn't
+/// face along an axis
+fn quad_normal(quad: &[BlockVertex]) -> Option<IVec3> {
+    let a = Vec3::from(quad[0].position);
+    let b = Vec3::from(quad[1].position);
+    let c = Vec3::from(quad[2].position);
+    let normal = (b - a).cross(c - a).normalize_or_zero();
+
+    let rounded = normal.round();
+    if rounded.abs().max_element() == 1.0 && (normal - rounded).length() < 0.01 {
+        Some(rounded.as_ivec3())
+    } else {
+        None
+    }
+}
+
+/// Returns true if the block with the id hides the faces next to it. Every kind of air is empty,
+/// not just id 0, so faces next to cave air inside the ground are still culled
+fn is_opaque_id(id: u16) -> bool {
+    let block = block_state(id.into());
+    !block.is_air() && block.is_opaque()
+}
+
+/// Calculates the ambient occlusion and smooth light of a corner of a face. The occlusion level
+/// comes from the blocks beside and diagonal to the corner, and the light is the average of the
+/// light of the transparent blocks touching the corner
+///
+/// # Arguments
+///
+/// * `block_id` - Looks up the block at a position relative to the section
+/// * `light_at` - Looks up the sky and block light at a position relative to the section
+/// * `corner` - Position of the corner relative to the section
+/// * `center` - Center of the face the corner belongs to
+/// * `normal` - Direction the face is facing
+fn corner_shade(
+    block_id: &impl Fn(IVec3) -> u16,
+    light_at: &impl Fn(IVec3) -> (u8, u8),
+    corner: Vec3,
+    center: Vec3,
+    normal: IVec3,
+) -> CornerShade {
+    let is_opaque = |pos: IVec3| is_opaque_id(block_id(pos));
+
+    // Direction from the center of the face towards the corner along each side of the face
+    let along_face = IVec3::ONE - normal * normal;
+    let towards = (corner - center).signum().as_ivec3() * along_face;
+    let (mut side1, mut side2) = (IVec3::ZERO, IVec3::ZERO);
+    for axis in 0..3 {
+        if towards[axis] == 0 {
+            continue;
+        }
+        if side1 == IVec3::ZERO {
+            side1[axis] = towards[axis];
+        } else {
+            side2[axis] = towards[axis];
+        }
+    }
+
+    // Block in front of the face that the corner belongs to
+    let front = (corner + normal.as_vec3() * 0.5 - towards.as_vec3() * 0.5)
+        .floor()
+        .as_ivec3();
+    let side1 = front + side1;
+    let side2 = front + side2;
+    let diagonal = front + towards;
+
+    let side1_opaque = is_opaque(side1);
+    let side2_opaque = is_opaque(side2);
+    let diagonal_opaque = is_opaque(diagonal);
+
+    let occlusion = if side1_opaque && side2_opaque {
+        0
+    } else {
+        3 - side1_opaque as u8 - side2_opaque as u8 - diagonal_opaque as u8
+    };
+
+    // Light can't reach the diagonal block through two opaque sides
+    let mut lit = vec![front];
+    if !side1_opaque {
+        lit.push(side1);
+    }
+    if !side2_opaque {
+        lit.push(side2);
+    }
+    if !diagonal_opaque && (!side1_opaque || !side2_opaque) {
+        lit.push(diagonal);
+    }
+    let (sky, block) = lit.iter().fold((0.0, 0.0), |(sky, block), pos| {
+        let (s, b) = light_at(*pos);
+        (sky + s as f32, block + b as f32)
+    });
+    let count = lit.len() as f32 * 15.0;
+
+    CornerShade {
+        occlusion,
+        sky: sky / count,
+        block: block / count,
+    }
+}
+
+/// Sets the brightness and light of each corner of a quad, then splits the quad along the
+/// diagonal between its less occluded corners so the occlusion looks the same no matter which way
+/// the quad was split
+fn shade_quad(quad: &mut [BlockVertex], shade_at: impl Fn(Vec3, Vec3) -> CornerShade) {
+    let pos = |v: &BlockVertex| Vec3::from(v.position);
+    let (first, second) = quad.split_at(3);
+
+    // The corner of each triangle that isn't on the diagonal they share
+    let lone = match (0..3).find(|&i| second.iter().all(|v| pos(v) != pos(&first[i]))) {
+        Some(i) => i,
+        None => return,
+    };
+    let other = match second
+        .iter()
+        .find(|v| first.iter().all(|f| pos(f) != pos(v)))
+    {
+        Some(v) => *v,
+        None => return,
+    };
+
+    // Corners in winding order
+    let mut corners = [
+        first[lone],
+        first[(lone + 1) % 3],
+        other,
+        first[(lone + 2) % 3],
+    ];
+    let center = corners.iter().fold(Vec3::ZERO, |sum, c| sum + pos(c)) / 4.0;
+    let shading = corners.map(|c| shade_at(pos(&c), center));
+    for (corner, shade) in corners.iter_mut().zip(shading) {
+        corner.brightness = AO_BRIGHTNESS[shade.occlusion as usize];
+        corner.light = [shade.sky, shade.block];
+    }
+    let levels = shading.map(|s| s.occlusion);
+
+    let order = if levels[0] + levels[2] > levels[1] + levels[3] {
+        [0, 1, 2, 0, 2, 3]
+    } else {
+        [1, 2, 3, 1, 3, 0]
+    };
+    for (vert, i) in quad.iter_mut().zip(order) {
+        *vert = corners[i];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use crate::{resources::state_id, world::chunks::block_index_to_pos};
+
+    use super::*;
+
+    /// Makes a section with the block at each position given by a function
+    fn section_of(y: i32, block: impl Fn(IVec3) -> u16) -> ChunkSection {
+        let mut blocks = [0; 4096];
+        for (i, id) in blocks.iter_mut().enumerate() {
+            *id = block(block_index_to_pos(i));
+        }
+        ChunkSection::new(y, blocks)
+    }
+
+    /// A section with nothing around it, so every neighbour is treated as air
+    fn alone(section: ChunkSection) -> Vec<Option<ChunkSection>> {
+        let mut sections = vec![None; 27];
+        sections[neighbour_index(IVec3::ZERO)] = Some(section);
+        sections
+    }
+
+    /// Three layers of stone with dirt on top, like the bottom of a superflat world
+    fn flat_section() -> ChunkSection {
+        let stone = state_id("Stone", &[]) as u16;
+        let dirt = state_id("Dirt", &[]) as u16;
+        section_of(0, |pos| match pos.y {
+            0..=2 => stone,
+            3 => dirt,
+            _ => 0,
+        })
+    }
+
+    /// Total area of the triangles in a mesh
+    fn area(mesh: &IndexedMesh) -> f32 {
+        mesh.indices
+            .chunks_exact(3)
+            .map(|tri| {
+                let [a, b, c] =
+                    [0, 1, 2].map(|i| Vec3::from(mesh.vertices[tri[i] as usize].position));
+                (b - a).cross(c - a).length() / 2.0
+            })
+            .sum()
+    }
+
+    /// Number of faces of full cubes in a section that aren't against an opaque block, which is
+    /// the area meshing a face per block would draw
+    fn exposed_faces(section: &ChunkSection) -> usize {
+        let directions = [
+            IVec3::X,
+            -IVec3::X,
+            IVec3::Y,
+            -IVec3::Y,
+            IVec3::Z,
+            -IVec3::Z,
+        ];
+        section
+            .iter_blocks()
+            .map(|(pos, _)| {
+                directions
+                    .iter()
+                    .filter(|d| {
+                        let next = pos + **d;
+                        let inside = next.min_element() >= 0 && next.max_element() < 16;
+                        !(inside && is_opaque_id(section.block_id_at(&next)))
+                    })
+                    .count()
+            })
+            .sum()
+    }
+
+    #[test]
+    fn flat_world_only_meshes_the_surface() {
+        let section = flat_section();
+        let mesh = build_section_mesh(&alone(section.clone()));
+
+        // Top, bottom and the 4 sides of a 16x4x16 slab
+        assert_eq!(area(&mesh.opaque), (256 + 256 + 4 * 64) as f32);
+        assert_eq!(exposed_faces(&section), 256 + 256 + 4 * 64);
+        assert!(mesh.translucent.is_empty());
+    }
+
+    #[test]
+    fn faces_against_neighbouring_sections_are_culled() {
+        let mut sections = alone(flat_section());
+        // The same slab continues into the chunks on every side
+        for offset in [IVec3::X, -IVec3::X, IVec3::Z, -IVec3::Z] {
+            sections[neighbour_index(offset)] = Some(flat_section());
+        }
+        let mesh = build_section_mesh(&sections);
+        assert_eq!(area(&mesh.opaque), 512.0);
+
+        // And a stone floor in the section below hides the bottom
+        let stone = state_id("Stone", &[]) as u16;
+        sections[neighbour_index(-IVec3::Y)] = Some(section_of(-1, |_| stone));
+        let mesh = build_section_mesh(&sections);
+        assert_eq!(area(&mesh.opaque), 256.0);
+    }
+
+    /// Stone with the height of each column picked from its position, so there are steps of
+    /// different sizes on every side
+    fn uneven_section() -> ChunkSection {
+        let stone = state_id("Stone", &[]) as u16;
+        section_of(0, |pos| {
+            if pos.y <= (pos.x * 7 + pos.z * 13) % 5 + (pos.x / 4) {
+                stone
+            } else {
+                0
+            }
+        })
+    }
+
+    #[test]
+    fn greedy_faces_cover_the_same_area() {
+        for section in [flat_section(), uneven_section()] {
+            let mesh = build_section_mesh(&alone(section.clone()));
+            assert_eq!(area(&mesh.opaque), exposed_faces(&section) as f32);
+        }
+    }
+
+    #[test]
+    fn greedy_faces_are_merged() {
+        let section = uneven_section();
+        let mesh = build_section_mesh(&alone(section.clone()));
+        assert!(mesh.opaque.indices.len() / 6 < exposed_faces(&section));
+    }
+
+    #[test]
+    fn unknown_block_is_placeholder_cube() {
+        let section = section_of(0, |pos| {
+            if pos == IVec3::new(8, 8, 8) {
+                u16::MAX
+            } else {
+                0
+            }
+        });
+        let mesh = build_section_mesh(&alone(section));
+
+        // Drawn as a whole cube rather than leaving a hole
+        assert_eq!(mesh.opaque.vertices.len(), 24);
+        assert_eq!(area(&mesh.opaque), 6.0);
+    }
+
+    #[test]
+    fn isolated_block_is_six_quads() {
+        let stone = state_id("Stone", &[]) as u16;
+        let section = section_of(0, |pos| {
+            if pos == IVec3::new(3, 7, 11) {
+                stone
+            } else {
+                0
+            }
+        });
+        let mesh = build_section_mesh(&alone(section));
+
+        // 4 vertices and 2 triangles for each face
+        assert_eq!(mesh.opaque.vertices.len(), 24);
+        assert_eq!(mesh.opaque.indices.len(), 36);
+        assert_eq!(area(&mesh.opaque), 6.0);
+        assert!(mesh.translucent.is_empty());
+    }
+
+    #[test]
+    fn indices_reference_quad_vertices() {
+        let mesh = build_section_mesh(&alone(uneven_section())).opaque;
+        assert_eq!(mesh.vertices.len() % 4, 0);
+        assert_eq!(mesh.indices.len() / 6, mesh.vertices.len() / 4);
+
+        // Each quad's 2 triangles only use its own 4 vertices, and use all of them
+        for (quad, indices) in mesh.indices.chunks_exact(6).enumerate() {
+            let first = quad as u32 * 4;
+            assert!(indices.iter().all(|i| (first..first + 4).contains(i)));
+            for i in first..first + 4 {
+                assert!(indices.contains(&i));
+            }
+        }
+
+        // The triangles without indices have no degenerate ones, and index back to the same mesh
+        let unindexed: Vec<BlockVertex> = mesh
+            .indices
+            .iter()
+            .map(|i| mesh.vertices[*i as usize])
+            .collect();
+        assert!(unindexed.chunks_exact(3).all(|t| {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(t[i].position));
+            (b - a).cross(c - a).length() > 0.0
+        }));
+
+        let reindexed = IndexedMesh::from_quads(&unindexed);
+        assert_eq!(reindexed.vertices, mesh.vertices);
+        assert_eq!(reindexed.indices, mesh.indices);
+    }
+
+    #[test]
+    fn cave_air_is_empty() {
+        let stone = state_id("Stone", &[]) as u16;
+        let cave_air = state_id("Cave Air", &[]) as u16;
+
+        // A stone block in a cave has all its faces drawn, and the cave air none
+        let section = section_of(0, |pos| {
+            if pos == IVec3::splat(8) {
+                stone
+            } else {
+                cave_air
+            }
+        });
+        let mesh = build_section_mesh(&alone(section));
+        assert_eq!(area(&mesh.opaque), 6.0);
+        assert!(mesh.translucent.is_empty());
+    }
+
+    #[test]
+    fn water_is_translucent_and_stone_is_opaque() {
+        let water = state_id("Water", &[("level", "0")]) as u16;
+        let stone = state_id("Stone", &[]) as u16;
+        let section = section_of(0, |pos| match (pos.x, pos.y, pos.z) {
+            (0, 0, 0) => water,
+            (8, 0, 8) => stone,
+            _ => 0,
+        });
+        let mesh = build_section_mesh(&alone(section));
+
+        // Every face of both blocks is in the open, and each lands in its own mesh
+        assert_eq!(area(&mesh.opaque), 6.0);
+        assert!(!mesh.translucent.is_empty());
+        assert!(mesh
+            .opaque
+            .vertices
+            .iter()
+            .all(|v| v.position[0] >= 8.0 && v.position[2] >= 8.0));
+        assert!(mesh
+            .translucent
+            .vertices
+            .iter()
+            .all(|v| v.position[0] <= 1.0 && v.position[2] <= 1.0));
+    }
+
+    /// Shading of the 4 corners of the top face of the block at the origin, in the order (0, 0),
+    /// (1, 0), (0, 1) and (1, 1) on x and z
+    fn top_corner_shades(
+        opaque: &[IVec3],
+        light_at: impl Fn(IVec3) -> (u8, u8),
+    ) -> [CornerShade; 4] {
+        let stone = state_id("Stone", &[]) as u16;
+        let block_id = |pos: IVec3| if opaque.contains(&pos) { stone } else { 0 };
+        let center = Vec3::new(0.5, 1.0, 0.5);
+
+        [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)].map(|(x, z)| {
+            corner_shade(&block_id, &light_at, Vec3::new(x, 1.0, z), center, IVec3::Y)
+        })
+    }
+
+    #[test]
+    fn ambient_occlusion_levels() {
+        // Blocks on top of the layer the face is in, beside and diagonal to its corners
+        let opaque = [
+            IVec3::new(1, 1, 0),
+            IVec3::new(0, 1, 1),
+            IVec3::new(1, 1, -1),
+        ];
+        let levels = top_corner_shades(&opaque, |_| (15, 0)).map(|s| s.occlusion);
+
+        // Open, one side and the diagonal, one side, and both sides which hides the diagonal
+        assert_eq!(levels, [3, 1, 2, 0]);
+        assert_eq!(
+            top_corner_shades(&[], |_| (15, 0)).map(|s| s.occlusion),
+            [3; 4]
+        );
+    }
+
+    #[test]
+    fn smooth_light_is_brighter_near_torch() {
+        // Block light from a torch beside the corner at (0, 0), falling off by 1 per block
+        let torch = IVec3::new(-1, 1, -1);
+        let light_at = |pos: IVec3| {
+            let offset = (pos - torch).abs();
+            let distance = offset.x + offset.y + offset.z;
+            (0, 14u8.saturating_sub(distance as u8))
+        };
+
+        let shades = top_corner_shades(&[], light_at);
+        assert!(shades[0].block > shades[1].block);
+        assert!(shades[0].block > shades[2].block);
+        assert!(shades[1].block > shades[3].block);
+        // Average of the torch, the block above the face and the two between them
+        assert_eq!(shades[0].block, (14.0 + 12.0 + 13.0 + 13.0) / 4.0 / 15.0);
+        assert_eq!(shades[0].sky, 0.0);
+
+        // Walls on both sides keep the torch's light from reaching the corner
+        let walls = [IVec3::new(-1, 1, 0), IVec3::new(0, 1, -1)];
+        let shades = top_corner_shades(&walls, light_at);
+        assert_eq!(shades[0].block, 12.0 / 15.0);
+    }
+
+    #[test]
+    fn quad_is_split_away_from_the_darkest_corner() {
+        let vertex = |x: f32, z: f32| BlockVertex {
+            position: [x, 1.0, z],
+            tex_coords: [0.0; 3],
+            brightness: 1.0,
+            light: [1.0, 0.0],
+        };
+        let dark = Vec3::new(1.0, 1.0, 0.0);
+
+        // Both ways of splitting the same quad
+        for quad in [
+            [
+                (0.0, 0.0),
+                (1.0, 0.0),
+                (1.0, 1.0),
+                (0.0, 0.0),
+                (1.0, 1.0),
+                (0.0, 1.0),
+            ],
+            [
+                (1.0, 0.0),
+                (1.0, 1.0),
+                (0.0, 1.0),
+                (1.0, 0.0),
+                (0.0, 1.0),
+                (0.0, 0.0),
+            ],
+        ] {
+            let mut quad = quad.map(|(x, z)| vertex(x, z));
+            shade_quad(&mut quad, |corner, _| CornerShade {
+                occlusion: if corner == dark { 0 } else { 3 },
+                sky: 1.0,
+                block: 0.0,
+            });
+
+            // The darkest corner isn't on the diagonal shared by both triangles
+            let dark_verts: Vec<&BlockVertex> = quad
+                .iter()
+                .filter(|v| Vec3::from(v.position) == dark)
+                .collect();
+            assert_eq!(dark_verts.len(), 1);
+            assert_eq!(dark_verts[0].brightness, AO_BRIGHTNESS[0]);
+        }
+    }
+
+    /// Prints how many vertices meshing a flat world makes compared to drawing every face of every
+    /// block, run with `cargo test bench_ -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_flat_world_culling() {
+        let sections = alone(flat_section());
+        let blocks = sections[neighbour_index(IVec3::ZERO)]
+            .as_ref()
+            .unwrap()
+            .iter_blocks()
+            .count();
+
+        let runs = 100;
+        let start = Instant::now();
+        let mut mesh = SectionMesh::default();
+        for _ in 0..runs {
+            mesh = build_section_mesh(&sections);
+        }
+        let elapsed = start.elapsed() / runs;
+
+        println!(
+            "Flat section: {} vertices, {} without culling, {:?} per mesh",
+            mesh.opaque.vertices.len(),
+            blocks * 6 * 4,
+            elapsed
+        );
+    }
+
+    /// Prints the vertices of a flat and an uneven section with greedy meshing against one quad
+    /// for every visible face, run with `cargo test bench_ -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_greedy_meshing() {
+        for (name, section) in [("Flat", flat_section()), ("Uneven", uneven_section())] {
+            let sections = alone(section.clone());
+            let runs = 100;
+            let start = Instant::now();
+            let mut mesh = SectionMesh::default();
+            for _ in 0..runs {
+                mesh = build_section_mesh(&sections);
+            }
+            let elapsed = start.elapsed() / runs;
+
+            println!(
+                "{} section: {} vertices greedy, {} one quad per face, {:?} per mesh",
+                name,
+                mesh.opaque.vertices.len(),
+                exposed_faces(&section) * 4,
+                elapsed
+            );
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use super::chunks::{BlockIndex, ChunkArray};
+
+// Palettes needing more bits than this are stored as a flat array, which is only twice the size
+// and doesn't need the lookup
+pub const MAX_PALETTE_BITS: u32 = 8;
+// Fewest bits used per block, like the server sends
+const MIN_PALETTE_BITS: u32 = 4;
+
+/// Blocks of a section stored as indices into a palette of the distinct states in it, packed into
+/// as few bits per block as the palette needs. Indices don't span across longs
+#[derive(Debug, Clone)]
+pub struct PalettedContainer {
+    palette: Vec<BlockIndex>,
+    bits: u32,
+    data: Vec<u64>,
+}
+
+impl PalettedContainer {
+    /// Creates a container with every block set to the same state
+    pub fn new(fill: BlockIndex) -> PalettedContainer {
+        PalettedContainer {
+            palette: vec![fill],
+            bits: MIN_PALETTE_BITS,
+            data: vec![0; longs_needed(MIN_PALETTE_BITS)],
+        }
+    }
+
+    /// Creates a container holding the blocks of an array, None if the array has too many distinct
+    /// blocks to be worth paletting
+    pub fn from_array(blocks: &ChunkArray) -> Option<PalettedContainer> {
+        let mut palette: Vec<BlockIndex> = Vec::new();
+        for block in blocks.iter() {
+            if !palette.contains(block) {
+                palette.push(*block);
+                if palette.len() > 1 << MAX_PALETTE_BITS {
+                    return None;
+                }
+            }
+        }
+
+        let mut container = PalettedContainer {
+            bits: bits_for(palette.len()),
+            palette,
+            data: Vec::new(),
+        };
+        container.data = vec![0; longs_needed(container.bits)];
+        for (i, block) in blocks.iter().enumerate() {
+            let index = container.palette.iter().position(|p| p == block).unwrap();
+            container.write(i, index as u64);
+        }
+        Some(container)
+    }
+
+    /// Returns the state of the block at an index into the section
+    pub fn get(&self, i: usize) -> BlockIndex {
+        self.palette[self.read(i) as usize]
+    }
+
+    /// Sets the block at an index into the section, adding the state to the palette if it isn't
+    /// already in it. Returns false without changing anything if the palette would need more than
+    /// `MAX_PALETTE_BITS` bits
+    pub fn set(&mut self, i: usize, state: BlockIndex) -> bool {
+        let index = match self.palette.iter().position(|p| *p == state) {
+            Some(index) => index,
+            None => {
+                let bits = bits_for(self.palette.len() + 1);
+                if bits > MAX_PALETTE_BITS {
+                    return false;
+                }
+                if bits != self.bits {
+                    self.resize(bits);
+                }
+                self.palette.push(state);
+                self.palette.len() - 1
+            }
+        };
+
+        self.write(i, index as u64);
+        true
+    }
+
+    /// Returns the number of distinct states in the palette, including ones no longer used
+    pub fn palette_len(&self) -> usize {
+        self.palette.len()
+    }
+
+    pub fn get_bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Unpacks the blocks into a flat array
+    pub fn to_array(&self) -> ChunkArray {
+        let mut blocks = [0; 4096];
+        for (i, block) in blocks.iter_mut().enumerate() {
+            *block = self.get(i);
+        }
+        blocks
+    }
+
+    /// Repacks the indices with a different number of bits per block
+    fn resize(&mut self, bits: u32) {
+        let old = std::mem::replace(
+            self,
+            PalettedContainer {
+                palette: Vec::new(),
+                bits,
+                data: vec![0; longs_needed(bits)],
+            },
+        );
+        for i in 0..4096 {
+            self.write(i, old.read(i));
+        }
+        self.palette = old.palette;
+    }
+
+    fn read(&self, i: usize) -> u64 {
+        let per_long = (64 / self.bits) as usize;
+        let start = (i % per_long) as u32 * self.bits;
+        (self.data[i / per_long] >> start) & ((1 << self.bits) - 1)
+    }
+
+    fn write(&mut self, i: usize, value: u64) {
+        let per_long = (64 / self.bits) as usize;
+        let start = (i % per_long) as u32 * self.bits;
+        let mask = ((1 << self.bits) - 1) << start;
+        let long = &mut self.data[i / per_long];
+        *long = (*long & !mask) | (value << start);
+    }
+}
+
+/// Returns the bits per block needed to index a palette of a length
+fn bits_for(len: usize) -> u32 {
+    let bits = usize::BITS - len.saturating_sub(1).leading_zeros();
+    bits.max(MIN_PALETTE_BITS)
+}
+
+/// Returns the number of longs needed to hold the indices of a section with a number of bits
+fn longs_needed(bits: u32) -> usize {
+    let per_long = (64 / bits) as usize;
+    (4096 + per_long - 1) / per_long
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::chunks::BlockStorage;
+
+    use super::*;
+
+    /// Blocks with a number of distinct states spread through the section
+    fn blocks_with_states(states: usize) -> ChunkArray {
+        let mut blocks = [0; 4096];
+        for (i, block) in blocks.iter_mut().enumerate() {
+            *block = ((i * 7919) % states) as BlockIndex + 1;
+        }
+        blocks
+    }
+
+    #[test]
+    fn paletted_and_flat_blocks_match() {
+        for states in [1, 2, 16, 17, 200, 256] {
+            let blocks = blocks_with_states(states);
+            let container = PalettedContainer::from_array(&blocks).unwrap();
+            let storage = BlockStorage::from_array(blocks);
+
+            assert!(storage.is_paletted());
+            assert_eq!(container.get_bits(), bits_for(states));
+            for (i, &block) in blocks.iter().enumerate() {
+                assert_eq!(container.get(i), block, "{} states at {}", states, i);
+                assert_eq!(storage.get(i), block);
+            }
+            assert_eq!(container.to_array(), blocks);
+        }
+
+        // Too many states to be worth paletting
+        let blocks = blocks_with_states(257);
+        assert!(PalettedContainer::from_array(&blocks).is_none());
+        let storage = BlockStorage::from_array(blocks);
+        assert!(!storage.is_paletted());
+        assert_eq!(storage.to_array(), blocks);
+    }
+
+    #[test]
+    fn palette_grows_then_switches_to_flat() {
+        let mut storage = BlockStorage::from_array([0; 4096]);
+        let mut expected = [0; 4096];
+
+        // Air and 15 more states fit in 4 bits, the 17th needs 5 and up to 256 fit in 8
+        for state in 1..=255 {
+            storage.set(state as usize * 13, state);
+            expected[state as usize * 13] = state;
+            if state == 16 {
+                assert!(matches!(&storage, BlockStorage::Paletted(c) if c.get_bits() == 5));
+            }
+        }
+        match &storage {
+            BlockStorage::Paletted(container) => {
+                assert_eq!(container.palette_len(), 256);
+                assert_eq!(container.get_bits(), MAX_PALETTE_BITS);
+            }
+            BlockStorage::Flat(_) => panic!("Switched to a flat array too early"),
+        }
+        assert_eq!(storage.to_array(), expected);
+
+        // The 257th state doesn't fit in a palette
+        storage.set(4000, 1000);
+        expected[4000] = 1000;
+        assert!(!storage.is_paletted());
+        assert_eq!(storage.to_array(), expected);
+    }
+}
@@ -0,0 +1,218 @@
+use glam::{IVec3, Vec3};
+
+use super::{block_coords, World, WorldCoords};
+
+/// Face of a block, named after the direction it faces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockFace {
+    Up,
+    Down,
+    North,
+    East,
+    South,
+    West,
+}
+
+impl BlockFace {
+    /// Returns the direction the face points in, which is the offset to the block touching it
+    pub fn normal(&self) -> IVec3 {
+        match self {
+            BlockFace::Up => IVec3::Y,
+            BlockFace::Down => -IVec3::Y,
+            BlockFace::North => -IVec3::Z,
+            BlockFace::East => IVec3::X,
+            BlockFace::South => IVec3::Z,
+            BlockFace::West => -IVec3::X,
+        }
+    }
+}
+
+/// The block a ray hit and which of its faces it entered through
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RaycastHit {
+    pub block_pos: WorldCoords,
+    pub face: BlockFace,
+    pub distance: f32,
+}
+
+impl RaycastHit {
+    /// Returns the position a block would be placed at against the hit face
+    pub fn get_place_pos(&self) -> WorldCoords {
+        self.block_pos + self.face.normal()
+    }
+}
+
+impl World {
+    /// Finds the first block with collision along a ray by stepping through every block the ray
+    /// passes through. Blocks without collision, like air, plants and water, and unloaded chunks
+    /// are passed through
+    ///
+    /// # Arguments
+    ///
+    /// * `origin` - Where the ray starts in the world
+    /// * `dir` - Direction of the ray, doesn't need to be normalised
+    /// * `max_dist` - How far along the ray to look for a block
+    pub fn raycast(&self, origin: Vec3, dir: Vec3, max_dist: f32) -> Option<RaycastHit> {
+        let dir = dir.normalize_or_zero();
+        if dir == Vec3::ZERO {
+            return None;
+        }
+
+        let mut block = block_coords(&origin);
+        let step = IVec3::new(
+            dir.x.signum() as i32,
+            dir.y.signum() as i32,
+            dir.z.signum() as i32,
+        );
+
+        // Distance along the ray to cross a whole block on each axis
+        let delta = (Vec3::ONE / dir).abs();
+        // Distance along the ray to the first block boundary on each axis
+        let boundary = |pos: f32, block: i32, dir: f32| {
+            if dir > 0.0 {
+                (block as f32 + 1.0 - pos) / dir
+            } else if dir < 0.0 {
+                (pos - block as f32) / -dir
+            } else {
+                f32::INFINITY
+            }
+        };
+        let mut next = Vec3::new(
+            boundary(origin.x, block.x, dir.x),
+            boundary(origin.y, block.y, dir.y),
+            boundary(origin.z, block.z, dir.z),
+        );
+
+        // Faces entered when stepping along each axis in the direction of the ray
+        let faces = [
+            if step.x > 0 {
+                BlockFace::West
+            } else {
+                BlockFace::East
+            },
+            if step.y > 0 {
+                BlockFace::Down
+            } else {
+                BlockFace::Up
+            },
+            if step.z > 0 {
+                BlockFace::North
+            } else {
+                BlockFace::South
+            },
+        ];
+
+        loop {
+            // Step along the axis with the closest boundary
+            let axis = if next.x <= next.y && next.x <= next.z {
+                0
+            } else if next.y <= next.z {
+                1
+            } else {
+                2
+            };
+
+            let distance = next[axis];
+            if distance > max_dist {
+                return None;
+            }
+
+            block[axis] += step[axis];
+            next[axis] += delta[axis];
+
+            if let Some(state) = self.block_at(&block) {
+                if state.has_collision() {
+                    return Some(RaycastHit {
+                        block_pos: block,
+                        face: faces[axis],
+                        distance,
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec2;
+
+    use crate::{
+        resources::state_id,
+        world::chunks::{Chunk, MIN_SECTION, SECTIONS_PER_CHUNK},
+    };
+
+    use super::*;
+
+    /// A world with a stone block at (5, 64, 0) and grass in front of it
+    fn world() -> World {
+        let mut world = World::new();
+        for x in -1..=1 {
+            for z in -1..=1 {
+                world.insert_chunk(Chunk::empty(
+                    IVec2::new(x, z),
+                    MIN_SECTION,
+                    SECTIONS_PER_CHUNK,
+                ));
+            }
+        }
+        world.set_block(&IVec3::new(5, 64, 0), state_id("Stone", &[]) as u16);
+        world.set_block(&IVec3::new(3, 64, 0), state_id("Grass", &[]) as u16);
+        world
+    }
+
+    #[test]
+    fn axis_aligned_ray_hits_block() {
+        let world = world();
+
+        let hit = world
+            .raycast(Vec3::new(0.5, 64.5, 0.5), Vec3::X, 10.0)
+            .unwrap();
+        assert_eq!(hit.block_pos, IVec3::new(5, 64, 0));
+        assert_eq!(hit.face, BlockFace::West);
+        assert_eq!(hit.distance, 4.5);
+        assert_eq!(hit.get_place_pos(), IVec3::new(4, 64, 0));
+
+        let hit = world
+            .raycast(Vec3::new(5.5, 70.5, 0.5), -Vec3::Y * 3.0, 10.0)
+            .unwrap();
+        assert_eq!(hit.block_pos, IVec3::new(5, 64, 0));
+        assert_eq!(hit.face, BlockFace::Up);
+        assert_eq!(hit.distance, 5.5);
+    }
+
+    #[test]
+    fn placing_on_top_face_targets_block_above() {
+        let world = world();
+
+        // Looking down at the top of the stone from the side
+        let hit = world
+            .raycast(Vec3::new(3.5, 66.5, 0.5), Vec3::new(2.0, -1.8, 0.0), 10.0)
+            .unwrap();
+        assert_eq!(hit.block_pos, IVec3::new(5, 64, 0));
+        assert_eq!(hit.face, BlockFace::Up);
+        assert_eq!(hit.get_place_pos(), IVec3::new(5, 65, 0));
+
+        let below = RaycastHit {
+            block_pos: IVec3::new(5, 64, 0),
+            face: BlockFace::Down,
+            distance: 1.0,
+        };
+        assert_eq!(below.get_place_pos(), IVec3::new(5, 63, 0));
+    }
+
+    #[test]
+    fn ray_misses_within_max_distance() {
+        let world = world();
+
+        assert_eq!(world.raycast(Vec3::new(0.5, 64.5, 0.5), Vec3::X, 4.0), None);
+        assert_eq!(
+            world.raycast(Vec3::new(0.5, 64.5, 0.5), -Vec3::X, 10.0),
+            None
+        );
+        assert_eq!(
+            world.raycast(Vec3::new(0.5, 64.5, 0.5), Vec3::ZERO, 10.0),
+            None
+        );
+    }
+}
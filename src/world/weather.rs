@@ -0,0 +1,96 @@
+use mcproto_rs::v1_16_3::GameChangeReason;
+
+/// Whether it's raining and how heavily, from Change Game State packets
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Weather {
+    raining: bool,
+    // From 0 to 1, the server fades these in and out over a few seconds when rain starts or stops
+    rain_level: f32,
+    thunder_level: f32,
+}
+
+impl Weather {
+    pub fn new() -> Weather {
+        Weather {
+            raining: false,
+            rain_level: 0.0,
+            thunder_level: 0.0,
+        }
+    }
+
+    /// Updates the weather from the reason in a Change Game State packet, returns false if the
+    /// reason isn't to do with the weather
+    pub fn handle_game_state(&mut self, reason: &GameChangeReason) -> bool {
+        match reason {
+            GameChangeReason::BeginRaining => self.raining = true,
+            GameChangeReason::EndRaining => {
+                self.raining = false;
+                self.rain_level = 0.0;
+                self.thunder_level = 0.0;
+            }
+            GameChangeReason::RainLevelChange(level) => self.rain_level = level.clamp(0.0, 1.0),
+            GameChangeReason::ThunderLevelChange(level) => {
+                self.thunder_level = level.clamp(0.0, 1.0)
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn is_raining(&self) -> bool {
+        self.raining
+    }
+
+    /// Returns how heavily it's raining from 0 for clear skies to 1
+    pub fn get_rain_level(&self) -> f32 {
+        if self.raining {
+            self.rain_level
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns how stormy it is from 0 to 1, which is never more than the rain level
+    pub fn get_thunder_level(&self) -> f32 {
+        self.thunder_level.min(self.get_rain_level())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mcproto_rs::v1_16_3::GameMode;
+
+    use super::*;
+
+    #[test]
+    fn begin_and_end_raining() {
+        let mut weather = Weather::new();
+        assert!(!weather.is_raining());
+
+        assert!(weather.handle_game_state(&GameChangeReason::BeginRaining));
+        assert!(weather.is_raining());
+        assert!(weather.handle_game_state(&GameChangeReason::RainLevelChange(0.6)));
+        assert_eq!(weather.get_rain_level(), 0.6);
+
+        assert!(weather.handle_game_state(&GameChangeReason::EndRaining));
+        assert!(!weather.is_raining());
+        assert_eq!(weather.get_rain_level(), 0.0);
+    }
+
+    #[test]
+    fn levels_are_clamped_and_thunder_needs_rain() {
+        let mut weather = Weather::new();
+        weather.handle_game_state(&GameChangeReason::ThunderLevelChange(1.0));
+        assert_eq!(weather.get_thunder_level(), 0.0);
+
+        weather.handle_game_state(&GameChangeReason::BeginRaining);
+        weather.handle_game_state(&GameChangeReason::RainLevelChange(3.0));
+        assert_eq!(weather.get_rain_level(), 1.0);
+        assert_eq!(weather.get_thunder_level(), 1.0);
+
+        // Other game state changes are left for the server to handle
+        let before = weather;
+        assert!(!weather.handle_game_state(&GameChangeReason::ChangeGameMode(GameMode::Creative)));
+        assert_eq!(weather, before);
+    }
+}